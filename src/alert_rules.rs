@@ -0,0 +1,226 @@
+use ntex::http::client::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use crate::calendar;
+use crate::config::ConfigError;
+use crate::WeatherData;
+
+/// Comparison a reading must satisfy to count as breaching a rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertOp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl AlertOp {
+    fn breached(self, value: f64, threshold: f64) -> bool {
+        match self {
+            AlertOp::Lt => value < threshold,
+            AlertOp::Le => value <= threshold,
+            AlertOp::Gt => value > threshold,
+            AlertOp::Ge => value >= threshold,
+        }
+    }
+}
+
+/// A threshold on one push field, e.g. `tempf` below 32, with the webhook
+/// notified when a station crosses it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertRule {
+    pub metric: String,
+    pub op: AlertOp,
+    pub threshold: f64,
+    pub webhook_url: String,
+    /// Minimum time between two notifications for the same station, so a
+    /// reading hovering around the threshold doesn't page repeatedly.
+    #[serde(default)]
+    pub cooldown_secs: u64,
+}
+
+impl AlertRule {
+    /// Parse rules from `STORMCAST_ALERT_RULES`, which holds either JSON (one
+    /// rule or an array of them) or the path of a file containing it.
+    pub fn parse_rules(value: &str) -> Result<Vec<AlertRule>, ConfigError> {
+        let json = if value.trim_start().starts_with(['[', '{']) {
+            value.to_string()
+        } else {
+            fs::read_to_string(value).map_err(|source| ConfigError::Read {
+                path: Path::new(value).to_path_buf(),
+                source,
+            })?
+        };
+        let rules = match serde_json::from_str::<Vec<AlertRule>>(&json) {
+            Ok(rules) => rules,
+            Err(_) => vec![serde_json::from_str::<AlertRule>(&json)
+                .map_err(|e| ConfigError::Invalid(format!("STORMCAST_ALERT_RULES: {}", e)))?],
+        };
+        let known = WeatherData::default().fields().map(|(name, _)| name);
+        for rule in &rules {
+            if !known.contains(&rule.metric.as_str()) {
+                return Err(ConfigError::Invalid(format!("alert rule on unknown field {:?}", rule.metric)));
+            }
+            if !rule.threshold.is_finite() {
+                return Err(ConfigError::Invalid(format!("alert rule on {} has a non-finite threshold", rule.metric)));
+            }
+        }
+        Ok(rules)
+    }
+}
+
+/// Where a station stands against one rule.
+#[derive(Debug, Default, Clone)]
+pub struct AlertState {
+    /// Whether the last reading breached the threshold.
+    breached: bool,
+    last_fired: Option<Instant>,
+}
+
+/// A station crossing into breach of a rule.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertEvent {
+    pub rule: usize,
+    pub value: f64,
+}
+
+/// Evaluates alert rules against each reading, firing only on the reading
+/// that crosses a threshold rather than on every reading while breached.
+#[derive(Debug)]
+pub struct AlertEvaluator {
+    rules: Vec<AlertRule>,
+    states: Mutex<HashMap<(usize, String), AlertState>>,
+}
+
+impl AlertEvaluator {
+    pub fn new(rules: Vec<AlertRule>) -> Self {
+        AlertEvaluator {
+            rules,
+            states: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Rules `data` newly breaches for `station`, outside their cooldown.
+    /// Readings without the rule's field leave its state untouched.
+    pub fn evaluate(&self, station: &str, data: &WeatherData, now: Instant) -> Vec<AlertEvent> {
+        let fields = data.fields();
+        let mut states = self.states.lock().unwrap();
+        let mut events = Vec::new();
+        for (index, rule) in self.rules.iter().enumerate() {
+            let Some(value) = fields.iter().find(|(name, _)| *name == rule.metric).and_then(|(_, value)| *value)
+            else {
+                continue;
+            };
+            let state = states.entry((index, station.to_string())).or_default();
+            let breached = rule.op.breached(value, rule.threshold);
+            let crossed = breached && !state.breached;
+            state.breached = breached;
+            if !crossed {
+                continue;
+            }
+            let cooldown = Duration::from_secs(rule.cooldown_secs);
+            if state.last_fired.is_some_and(|at| now.saturating_duration_since(at) < cooldown) {
+                debug!("Alert on {} for station {} is cooling down", rule.metric, station);
+                continue;
+            }
+            state.last_fired = Some(now);
+            events.push(AlertEvent { rule: index, value });
+        }
+        events
+    }
+
+    /// Evaluate `data` and POST every newly fired alert to its rule's webhook
+    /// in the background.
+    pub fn notify(&self, station: &str, data: &WeatherData) {
+        for event in self.evaluate(station, data, Instant::now()) {
+            let rule = &self.rules[event.rule];
+            info!(
+                "Station {} crossed alert threshold {} {:?} {} with {}",
+                station, rule.metric, rule.op, rule.threshold, event.value
+            );
+            let url = rule.webhook_url.clone();
+            let body = json!({
+                "station": station,
+                "metric": rule.metric,
+                "op": rule.op,
+                "threshold": rule.threshold,
+                "value": event.value,
+                "timestamp": data.timestamp().unwrap_or_else(calendar::now),
+            });
+            ntex::rt::spawn(async move {
+                let client = Client::build().timeout(Duration::from_secs(10)).finish();
+                match client.post(&url).send_json(&body).await {
+                    Ok(res) if res.status().is_success() => debug!("Sent alert to {}", url),
+                    Ok(res) => warn!("Alert webhook {} responded with status {}", url, res.status()),
+                    Err(e) => warn!("Failed to send alert to {}: {}", url, e),
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn freezing(cooldown_secs: u64) -> AlertEvaluator {
+        let rules = format!(
+            r#"{{"metric": "tempf", "op": "lt", "threshold": 32, "webhook_url": "http://localhost/hook", "cooldown_secs": {}}}"#,
+            cooldown_secs
+        );
+        AlertEvaluator::new(AlertRule::parse_rules(&rules).unwrap())
+    }
+
+    fn reading(query: &str) -> WeatherData {
+        WeatherData::from_query(query).unwrap()
+    }
+
+    #[test]
+    fn fires_only_when_crossing_the_threshold() {
+        let evaluator = freezing(0);
+        let now = Instant::now();
+        assert!(evaluator.evaluate("s", &reading("tempf=40.0"), now).is_empty());
+        assert_eq!(evaluator.evaluate("s", &reading("tempf=31.0"), now), [AlertEvent { rule: 0, value: 31.0 }]);
+        assert!(evaluator.evaluate("s", &reading("tempf=30.0"), now).is_empty());
+        assert!(evaluator.evaluate("s", &reading("humidity=50"), now).is_empty());
+        assert!(evaluator.evaluate("s", &reading("tempf=33.0"), now).is_empty());
+        assert_eq!(evaluator.evaluate("s", &reading("tempf=31.5"), now).len(), 1);
+        // Stations are tracked separately
+        assert_eq!(evaluator.evaluate("other", &reading("tempf=20.0"), now).len(), 1);
+    }
+
+    #[test]
+    fn cooldown_suppresses_repeat_crossings() {
+        let evaluator = freezing(600);
+        let start = Instant::now();
+        assert_eq!(evaluator.evaluate("s", &reading("tempf=31.0"), start).len(), 1);
+        assert!(evaluator.evaluate("s", &reading("tempf=33.0"), start).is_empty());
+        assert!(evaluator.evaluate("s", &reading("tempf=31.0"), start + Duration::from_secs(60)).is_empty());
+        assert!(evaluator.evaluate("s", &reading("tempf=33.0"), start + Duration::from_secs(60)).is_empty());
+        assert_eq!(evaluator.evaluate("s", &reading("tempf=31.0"), start + Duration::from_secs(601)).len(), 1);
+    }
+
+    #[test]
+    fn operators_compare_inclusively_or_not() {
+        assert!(AlertOp::Le.breached(32.0, 32.0));
+        assert!(!AlertOp::Lt.breached(32.0, 32.0));
+        assert!(AlertOp::Ge.breached(32.0, 32.0));
+        assert!(!AlertOp::Gt.breached(32.0, 32.0));
+    }
+
+    #[test]
+    fn rules_on_unknown_fields_are_rejected() {
+        let rule = r#"[{"metric": "nonsense", "op": "gt", "threshold": 1, "webhook_url": "http://localhost/hook"}]"#;
+        assert!(AlertRule::parse_rules(rule).is_err());
+        assert!(AlertRule::parse_rules("/nonexistent/alert-rules.json").is_err());
+    }
+}
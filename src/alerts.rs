@@ -0,0 +1,125 @@
+use ntex::web;
+use std::fmt::Write;
+use tracing::debug;
+
+use crate::metrics::{metrics, Metrics};
+
+/// How long a battery must report low before the alert fires.
+const BATTERY_ALERT_FOR: &str = "5m";
+/// Voltage below which an AA-powered sensor's battery is low.
+const LOW_BATTERY_VOLTS: f64 = 1.2;
+/// Highest level, on the 0–5 scale some sensors report, counting as low.
+const LOW_BATTERY_LEVEL: u8 = 1;
+
+/// Condition under which a battery gauge reports a low battery, for the
+/// gauge's name without the metric prefix. Stations encode battery state in
+/// several ways, so `None` is returned for gauges of unknown encoding rather
+/// than guessing.
+fn low_battery_condition(name: &str) -> Option<String> {
+    let field = name.strip_prefix("extra_").unwrap_or(name);
+    match field.trim_end_matches(|c: char| c.is_ascii_digit()) {
+        // Ambient Weather: 1 is OK, 0 is low
+        "battout_level" | "battin_level" => Some("== 0".to_string()),
+        // Ecowitt sensor flags: 0 is OK, 1 is low
+        "wh65batt" | "wh25batt" | "wh26batt" | "wh24batt" | "wh32batt" | "batt" => Some("== 1".to_string()),
+        // Ecowitt sensors reporting their battery voltage
+        "soilbatt" | "tf_batt" | "wh40batt" | "wh68batt" | "wh80batt" | "wh90batt" => {
+            Some(format!("< {}", LOW_BATTERY_VOLTS))
+        }
+        // Ecowitt sensors reporting a 0–5 level
+        "pm25batt" | "leakbatt" | "wh57batt" | "co2_batt" => Some(format!("<= {}", LOW_BATTERY_LEVEL)),
+        _ => None,
+    }
+}
+
+/// Battery gauges with the condition meaning low, among every gauge
+/// registered so far, including ones without series yet.
+pub fn battery_gauges(metrics: &Metrics) -> Vec<(String, String)> {
+    metrics
+        .describe()
+        .into_iter()
+        .filter(|descriptor| descriptor.kind == "gauge" && descriptor.name.contains("batt"))
+        .filter_map(|descriptor| {
+            let name = descriptor.name.strip_prefix(metrics.prefix())?.strip_prefix('_')?;
+            match low_battery_condition(name) {
+                Some(condition) => Some((descriptor.name, condition)),
+                None => {
+                    debug!("No battery alert rule for {}: unknown battery encoding", descriptor.name);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Prometheus alerting rules, one `BatteryLow` rule per battery gauge
+/// and the condition under which it reports a low battery.
+pub fn battery_rules(gauges: &[(String, String)]) -> String {
+    let mut yaml = String::from("groups:\n  - name: stormcastrs-battery\n    rules:\n");
+    for (gauge, condition) in gauges {
+        let _ = write!(
+            yaml,
+            "      - alert: BatteryLow\n        \
+             expr: {gauge} {condition}\n        \
+             for: {BATTERY_ALERT_FOR}\n        \
+             labels:\n          \
+             severity: warning\n        \
+             annotations:\n          \
+             summary: \"{gauge} reports a low battery\"\n"
+        );
+    }
+    yaml
+}
+
+/// Generate battery alerting rules for every registered battery gauge.
+pub async fn handle_battery_rules() -> web::HttpResponse {
+    web::HttpResponse::Ok()
+        .content_type("application/yaml")
+        .body(battery_rules(&battery_gauges(metrics())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support, WeatherData};
+
+    /// Rule `expr` lines in `yaml`, in order.
+    fn exprs(yaml: &str) -> Vec<&str> {
+        yaml.lines().filter_map(|line| line.trim().strip_prefix("expr: ")).collect()
+    }
+
+    #[test]
+    fn conditions_follow_each_encoding() {
+        assert_eq!(low_battery_condition("battout_level").as_deref(), Some("== 0"));
+        assert_eq!(low_battery_condition("extra_wh65batt").as_deref(), Some("== 1"));
+        assert_eq!(low_battery_condition("extra_batt3").as_deref(), Some("== 1"));
+        assert_eq!(low_battery_condition("extra_soilbatt2").as_deref(), Some("< 1.2"));
+        assert_eq!(low_battery_condition("extra_pm25batt1").as_deref(), Some("<= 1"));
+        assert_eq!(low_battery_condition("extra_mystery_batt"), None);
+    }
+
+    #[test]
+    fn one_rule_per_battery_gauge() {
+        let config = test_support::config(&[("STORMCAST_REGISTER_EXTRA_METRICS", "true")]);
+        let metrics = Metrics::new(&config).unwrap();
+        let push = "PASSKEY=batteries&tempf=50.0&wh65batt=0&wh25batt=0&batt1=1&soilbatt1=1.4";
+        metrics.update(&WeatherData::from_query(push).unwrap()).unwrap();
+
+        let gauges = battery_gauges(&metrics);
+        let yaml = battery_rules(&gauges);
+        assert_eq!(yaml.matches("alert: BatteryLow").count(), gauges.len());
+        let mut exprs = exprs(&yaml);
+        exprs.sort();
+        assert_eq!(
+            exprs,
+            [
+                "weather_battin_level == 0",
+                "weather_battout_level == 0",
+                "weather_extra_batt1 == 1",
+                "weather_extra_soilbatt1 < 1.2",
+                "weather_extra_wh25batt == 1",
+                "weather_extra_wh65batt == 1",
+            ]
+        );
+    }
+}
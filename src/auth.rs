@@ -0,0 +1,94 @@
+use ntex::http::header::AUTHORIZATION;
+use ntex::web::HttpRequest;
+use std::collections::{HashMap, HashSet};
+
+/// Check the request's `Authorization: Bearer <token>` header against the
+/// configured token. Always fails when no token is configured.
+pub fn has_bearer_token(req: &HttpRequest, expected: Option<&str>) -> bool {
+    let Some(expected) = expected else {
+        return false;
+    };
+    req.headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| token.trim() == expected)
+}
+
+/// Query parameters station firmware sends an API key in, compared
+/// case-insensitively.
+const API_KEY_PARAMS: [&str; 2] = ["key", "apikey"];
+
+/// Whether a push parameter carries an API key.
+pub fn is_api_key_param(name: &str) -> bool {
+    API_KEY_PARAMS.iter().any(|param| name.eq_ignore_ascii_case(param))
+}
+
+/// Remove any API key parameters from a push, returning the key sent.
+pub fn take_api_key(params: &mut HashMap<String, String>) -> Option<String> {
+    let names: Vec<String> = params.keys().filter(|name| is_api_key_param(name)).cloned().collect();
+    names.into_iter().filter_map(|name| params.remove(&name)).next()
+}
+
+/// API key sent in the request's query string, for pushes whose readings
+/// arrive in the body.
+pub fn query_api_key(req: &HttpRequest) -> Option<String> {
+    let mut params = form_urlencoded::parse(req.query_string().as_bytes()).into_owned().collect();
+    take_api_key(&mut params)
+}
+
+/// Proof that a push carried a valid API key, or that none is required.
+/// `ingest` takes one, so no push endpoint can skip the check.
+#[derive(Debug)]
+pub struct Authorized(());
+
+/// Check the API key a push carried. Always passes when no keys are configured.
+pub fn check_api_key(key: Option<&str>, allowed: Option<&HashSet<String>>) -> Option<Authorized> {
+    let valid = match allowed {
+        Some(allowed) => key.is_some_and(|key| allowed.contains(key)),
+        None => true,
+    };
+    valid.then_some(Authorized(()))
+}
+
+/// Why a push was refused by the station allowlist or blocklist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum StationRejection {
+    #[error("station is not in the allowlist")]
+    NotInAllowlist,
+    #[error("station is blocked")]
+    InBlocklist,
+    #[error("a PASSKEY is required")]
+    MissingPasskey,
+}
+
+impl StationRejection {
+    /// Label on `weather_rejected_push_total`.
+    pub fn label(self) -> &'static str {
+        match self {
+            StationRejection::NotInAllowlist => "not_in_allowlist",
+            StationRejection::InBlocklist => "in_blocklist",
+            StationRejection::MissingPasskey => "missing_passkey",
+        }
+    }
+}
+
+/// Check a push's PASSKEY against the blocklist, then the allowlist when
+/// one is configured.
+pub fn check_station(
+    passkey: Option<&str>,
+    allowlist: &HashSet<String>,
+    blocklist: &HashSet<String>,
+) -> Result<(), StationRejection> {
+    if passkey.is_some_and(|passkey| blocklist.contains(passkey)) {
+        return Err(StationRejection::InBlocklist);
+    }
+    if allowlist.is_empty() {
+        return Ok(());
+    }
+    match passkey {
+        None => Err(StationRejection::MissingPasskey),
+        Some(passkey) if !allowlist.contains(passkey) => Err(StationRejection::NotInAllowlist),
+        Some(_) => Ok(()),
+    }
+}
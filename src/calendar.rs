@@ -0,0 +1,168 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const SECS_PER_DAY: i64 = 86_400;
+
+/// Current Unix time in whole seconds.
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+/// A calendar date.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Date {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+/// Number of days in `month` of `year`.
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl Date {
+    /// Date of the given day counted from 1970-01-01.
+    pub fn from_days(days: i64) -> Date {
+        // Howard Hinnant's civil_from_days
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + i64::from(month <= 2);
+        Date { year, month, day }
+    }
+
+    /// Days from 1970-01-01 to this date.
+    pub fn to_days(self) -> i64 {
+        // Howard Hinnant's days_from_civil
+        let year = self.year - i64::from(self.month <= 2);
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let month = i64::from(self.month);
+        let mp = if month > 2 { month - 3 } else { month + 9 };
+        let doy = (153 * mp + 2) / 5 + i64::from(self.day) - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146_097 + doe - 719_468
+    }
+
+    /// Day of the year, starting at 1 on January 1st.
+    pub fn ordinal(self) -> u32 {
+        const CUMULATIVE: [u32; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+        let leap_day = u32::from(self.month > 2 && is_leap_year(self.year));
+        CUMULATIVE[self.month as usize - 1] + self.day + leap_day
+    }
+}
+
+/// Parse a `YYYY-MM-DD HH:MM:SS` UTC timestamp, as sent in `dateutc`, into
+/// Unix seconds.
+pub fn parse_datetime(value: &str) -> Option<i64> {
+    let (date, time) = value.trim().split_once([' ', 'T'])?;
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let mut time = time.splitn(3, ':').map(str::parse::<i64>);
+    let (hour, minute, second) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+    if !(1..=12).contains(&month)
+        || !(1..=i64::from(days_in_month(year, month as u32))).contains(&day)
+        || !(0..24).contains(&hour)
+        || !(0..60).contains(&minute)
+        || !(0..=60).contains(&second)
+    {
+        return None;
+    }
+    let date = Date {
+        year,
+        month: month as u32,
+        day: day as u32,
+    };
+    Some(date.to_days() * SECS_PER_DAY + hour * 3600 + minute * 60 + second)
+}
+
+/// Format Unix seconds as an ISO 8601 UTC timestamp, `YYYY-MM-DDTHH:MM:SSZ`.
+pub fn format_datetime(unix_secs: i64) -> String {
+    let date = Date::from_days(unix_secs.div_euclid(SECS_PER_DAY));
+    let secs = unix_secs.rem_euclid(SECS_PER_DAY);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        date.year,
+        date.month,
+        date.day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i64, month: u32, day: u32) -> Date {
+        Date { year, month, day }
+    }
+
+    #[test]
+    fn days_convert_to_dates_and_back() {
+        assert_eq!(Date::from_days(0), date(1970, 1, 1));
+        assert_eq!(Date::from_days(-1), date(1969, 12, 31));
+        assert_eq!(Date::from_days(19_782), date(2024, 2, 29));
+        assert_eq!(Date::from_days(11_016), date(2000, 2, 29));
+        for days in -800_000..800_000 {
+            assert_eq!(Date::from_days(days).to_days(), days);
+        }
+    }
+
+    #[test]
+    fn ordinal_counts_the_leap_day() {
+        assert_eq!(date(2023, 1, 1).ordinal(), 1);
+        assert_eq!(date(2023, 3, 1).ordinal(), 60);
+        assert_eq!(date(2024, 3, 1).ordinal(), 61);
+        assert_eq!(date(2023, 12, 31).ordinal(), 365);
+        assert_eq!(date(2024, 12, 31).ordinal(), 366);
+        // Centuries are leap years only when divisible by 400
+        assert_eq!(date(1900, 12, 31).ordinal(), 365);
+        assert_eq!(date(2000, 12, 31).ordinal(), 366);
+    }
+
+    #[test]
+    fn leap_years() {
+        assert!(is_leap_year(2024));
+        assert!(is_leap_year(2000));
+        assert!(!is_leap_year(2023));
+        assert!(!is_leap_year(1900));
+    }
+
+    #[test]
+    fn datetimes_parse_to_unix_seconds() {
+        assert_eq!(parse_datetime("1970-01-01 00:00:00"), Some(0));
+        assert_eq!(parse_datetime("2024-02-29 12:30:15"), Some(19_782 * SECS_PER_DAY + 45_015));
+        assert_eq!(parse_datetime("2024-12-31T23:59:59"), Some(20_088 * SECS_PER_DAY + 86_399));
+    }
+
+    #[test]
+    fn days_past_the_end_of_the_month_are_rejected() {
+        for value in ["2023-02-29 00:00:00", "2024-02-30 00:00:00", "1900-02-29 00:00:00", "2024-04-31 00:00:00"] {
+            assert_eq!(parse_datetime(value), None, "{}", value);
+        }
+        for value in ["2000-02-29 00:00:00", "2024-01-31 00:00:00", "2024-06-30 00:00:00"] {
+            assert!(parse_datetime(value).is_some(), "{}", value);
+        }
+        assert_eq!(parse_datetime("2024-13-01 00:00:00"), None);
+        assert_eq!(parse_datetime("2024-01-00 00:00:00"), None);
+    }
+}
@@ -0,0 +1,790 @@
+use ntex::time::Seconds;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use std::{env, fs, io};
+
+use crate::alert_rules::AlertRule;
+use crate::convert::MetricSystem;
+use crate::cwop;
+use crate::fallback::FallbackConfig;
+use crate::geohash;
+use crate::group::{self, Aggregation, StationGroup};
+use crate::middleware::CorsOrigins;
+use crate::openhab::OpenHabConfig;
+use crate::precision::{PrecisionProfile, RoundingConfig};
+use crate::relay::ForwardConfig;
+use crate::rename::FieldRenameConfig;
+use crate::timezone::TimeZone;
+use crate::virtual_sensor::{VirtualSensor, VirtualSensorConfig};
+use crate::WeatherData;
+
+/// Config file read when `STORMCAST_CONFIG` is not set, if it exists.
+const DEFAULT_CONFIG_PATH: &str = "stormcastrs.toml";
+/// Prefix of every environment variable read as a setting.
+const ENV_PREFIX: &str = "STORMCAST_";
+/// Variables read before the config is loaded rather than as settings.
+const READ_ELSEWHERE: [&str; 2] = ["STORMCAST_CONFIG", "STORMCAST_LOG_FORMAT"];
+
+/// Annotated example config file, printed by `--sample-config`.
+pub const SAMPLE_CONFIG: &str = r#"# stormcastrs configuration. Every key is optional; environment variables
+# override the values set here.
+
+# Any STORMCAST_* environment variable, named in lowercase without the prefix.
+[settings]
+bind = "0.0.0.0:8080"
+# metrics_bind = "127.0.0.1:9090"
+metric_prefix = "weather"
+units = "imperial"
+max_stations = 100
+rate_limit_rps = 10
+altitude_meters = 0.0
+history_size = 100
+stale_threshold_secs = 3600
+stale_timeout_secs = 0
+shutdown_grace_secs = 5
+shutdown_timeout_secs = 30
+# station_allowlist = ["ABCDEF0123456789"]
+# station_names = ["ABCDEF0123456789=backyard"]
+# mqtt_url = "mqtt://localhost:1883"
+# mqtt_topic = "weather/#"
+# remote_write_url = "http://localhost:9090/api/v1/write"
+# push_interval_secs = 60
+# state_file = "/var/lib/stormcastrs/state.json"
+
+[histograms]
+temperature_f = [-20.0, 0.0, 20.0, 40.0, 60.0, 80.0, 100.0, 120.0]
+wind_speed_mph = [0.0, 2.0, 5.0, 10.0, 15.0, 20.0, 30.0, 50.0]
+
+# Decimal places per field, for each output format, over the per-type
+# round_temperature, round_wind, round_rain, round_pressure, round_solar,
+# round_visibility and round_air_quality settings.
+[precision.prometheus]
+tempf = 1
+
+# openHAB item names and the push fields they map to, replacing the defaults.
+# [openhab.items]
+# outdoor_temperature = "tempf"
+
+# Non-standard push parameter names and their canonical equivalents.
+# [field_renames.renames]
+# temp_f = "tempf"
+
+# HELP text overrides, keyed by metric name without the prefix.
+# [metric_help]
+# temperature_fahrenheit = "Outdoor temperature in degrees Fahrenheit"
+
+# [[virtual_sensor]]
+# name = "blended_temperature_fahrenheit"
+# expr = "0.6 * tempf + 0.4 * tempinf"
+# help = "Weighted average of outdoor and indoor temperature"
+"#;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("failed to read config file {}: {source}", path.display())]
+    Read { path: PathBuf, source: io::Error },
+    #[error("failed to parse config file {}: {source}", path.display())]
+    Parse { path: PathBuf, source: toml::de::Error },
+    #[error("invalid config: {0}")]
+    Invalid(String),
+}
+
+/// Histogram bucket boundaries, one array per histogram metric.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistogramConfig {
+    pub temperature_f: Vec<f64>,
+    pub wind_speed_mph: Vec<f64>,
+}
+
+impl Default for HistogramConfig {
+    fn default() -> Self {
+        HistogramConfig {
+            temperature_f: vec![-20.0, 0.0, 20.0, 40.0, 60.0, 80.0, 100.0, 120.0],
+            wind_speed_mph: vec![0.0, 2.0, 5.0, 10.0, 15.0, 20.0, 30.0, 50.0],
+        }
+    }
+}
+
+impl HistogramConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        validate_buckets("temperature_f", &self.temperature_f)?;
+        validate_buckets("wind_speed_mph", &self.wind_speed_mph)
+    }
+}
+
+fn validate_buckets(name: &str, buckets: &[f64]) -> Result<(), ConfigError> {
+    if buckets.is_empty() {
+        return Err(ConfigError::Invalid(format!("histogram {} has no buckets", name)));
+    }
+    if buckets.iter().any(|b| !b.is_finite()) {
+        return Err(ConfigError::Invalid(format!("histogram {} has a non-finite bucket", name)));
+    }
+    if buckets.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err(ConfigError::Invalid(format!(
+            "histogram {} buckets must be sorted in strictly increasing order",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Contents of the optional TOML config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    pub histograms: HistogramConfig,
+    pub openhab: OpenHabConfig,
+    /// Decimal places per field and output format, over the built-in ones.
+    pub precision: PrecisionProfile,
+    #[serde(rename = "virtual_sensor")]
+    pub virtual_sensors: Vec<VirtualSensorConfig>,
+    /// HELP text overrides, keyed by metric name without the `weather_` prefix.
+    pub metric_help: HashMap<String, String>,
+    pub field_renames: FieldRenameConfig,
+    /// Values for any `STORMCAST_*` environment variable, keyed by its name
+    /// in lowercase without the prefix, e.g. `units` for `STORMCAST_UNITS`.
+    pub settings: HashMap<String, toml::Value>,
+}
+
+impl ConfigFile {
+    pub fn from_file(path: &Path) -> Result<ConfigFile, ConfigError> {
+        let contents = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        toml::from_str(&contents).map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Config {
+    pub histograms: HistogramConfig,
+    /// Mapping from openHAB item names to push fields.
+    pub openhab: OpenHabConfig,
+    pub precision: PrecisionProfile,
+    /// Gauges computed from other fields after every update.
+    pub virtual_sensors: Vec<VirtualSensor>,
+    pub metric_help: HashMap<String, String>,
+    /// Non-standard push parameter names and their canonical equivalents.
+    pub field_renames: FieldRenameConfig,
+    /// Bearer token required by admin endpoints; they are disabled when unset.
+    pub admin_token: Option<String>,
+    /// WeatherLink Live `current_conditions` URL to poll, if any.
+    pub davis_url: Option<String>,
+    pub davis_poll_interval: Duration,
+    /// Register gauges for numeric fields `WeatherData` doesn't know about.
+    pub register_extra_metrics: bool,
+    /// When set, only these extra fields are registered as gauges.
+    pub extra_metrics_allowlist: Option<HashSet<String>>,
+    /// Station local time, used to detect daily totals resetting at midnight.
+    pub station_timezone: TimeZone,
+    /// Substitutes for readings whose sensor has stopped reporting.
+    pub sensor_fallback: FallbackConfig,
+    /// Groups of nearby stations whose readings are averaged.
+    pub groups: Vec<StationGroup>,
+    /// Body returned for accepted pushes, `ok` unless set, which may be
+    /// empty; `{station_id}` is replaced with the pushing station's ID.
+    pub push_response_body: String,
+    /// How long `/push/` holds back its response to an accepted push.
+    pub push_delay: Duration,
+    /// Minimum time between scrapes from one client; zero disables the limit.
+    pub min_scrape_interval: Duration,
+    /// Directory unparseable push payloads are written to, if any.
+    pub dead_letter_dir: Option<PathBuf>,
+    /// Number of dead-letter files kept before the oldest are deleted.
+    pub max_dead_letters: usize,
+    /// File station metadata is saved to, and loaded from at startup, if any.
+    pub state_file: Option<PathBuf>,
+    /// URL accepted readings are POSTed to as JSON, if any.
+    pub webhook_url: Option<String>,
+    /// Minimum change in any field, in percent, for the webhook to fire.
+    pub webhook_threshold_pct: f64,
+    /// Parse pushes but skip everything after, to measure parsing throughput.
+    pub benchmark_mode: bool,
+    /// Geohash of the station location, added as a label to every metric.
+    pub geohash: Option<String>,
+    /// Fraction of pushes expected to succeed, e.g. 0.999.
+    pub slo_target: f64,
+    /// Prefix of every metric name, so several instances can share a Prometheus.
+    pub metric_prefix: String,
+    /// Number of distinct stations tracked before pushes from new ones are refused.
+    pub max_stations: usize,
+    /// Unit system(s) readings are exported in.
+    pub units: MetricSystem,
+    /// Pushes accepted per second from one client IP; zero disables the limit.
+    pub rate_limit_rps: u32,
+    /// Origins browsers may call the read-only JSON endpoints from; no CORS
+    /// headers are sent when unset.
+    pub cors_origins: Option<CorsOrigins>,
+    /// Clients streaming `/events` at once before new ones are refused.
+    pub max_sse_clients: usize,
+    /// Gzip `/metrics`, `/data` and `/history` for clients that accept it.
+    pub compress_metrics: bool,
+    /// Largest POST push body accepted, in bytes.
+    pub max_body_bytes: usize,
+    /// Connections open at once, across all listeners and workers, before
+    /// new ones are refused with 503; unlimited when zero.
+    pub max_connections: Option<usize>,
+    /// Keys accepted in the `key` or `APIKEY` push parameter; pushes are
+    /// not authenticated when unset.
+    pub api_keys: Option<HashSet<String>>,
+    /// PASSKEYs pushes are accepted from; any station may push when empty.
+    pub station_allowlist: HashSet<String>,
+    /// PASSKEYs whose pushes are always refused.
+    pub station_blocklist: HashSet<String>,
+    /// Names stations are exported under instead of their PASSKEY or
+    /// station ID. With an allowlist the PASSKEY works as a credential, so
+    /// every allowlisted station must have one.
+    pub station_names: HashMap<String, String>,
+    /// Age of the latest update after which `/health` reports unhealthy.
+    pub stale_threshold: Duration,
+    /// Age after which a station's readings are set to NaN; never when unset.
+    pub stale_timeout: Option<Duration>,
+    /// How long requests are still served after a shutdown signal, while
+    /// `/health` fails so load balancers stop routing here.
+    pub shutdown_grace: Duration,
+    /// How long in-flight requests may take to finish after a shutdown signal.
+    pub shutdown_timeout: Seconds,
+    /// Address the server listens on.
+    pub bind: String,
+    /// Separate address `/metrics` is served on instead of `bind`, if any.
+    pub metrics_bind: Option<String>,
+    /// Prometheus remote write endpoint metrics are pushed to, if any.
+    pub remote_write_url: Option<String>,
+    /// Bearer token sent with remote write pushes.
+    pub remote_write_token: Option<String>,
+    /// Time between remote write pushes.
+    pub push_interval: Duration,
+    /// Station altitude, used to derive sea-level pressure when only
+    /// absolute pressure is reported.
+    pub altitude_m: f32,
+    /// Thresholds on push fields that notify a webhook when crossed.
+    pub alert_rules: Vec<AlertRule>,
+    /// Credentials for relaying accepted pushes to other weather services.
+    pub forward: ForwardConfig,
+    /// Number of recent readings kept for `/history`.
+    pub history_size: usize,
+    /// Broker to subscribe to for readings published over MQTT, if any.
+    pub mqtt_url: Option<String>,
+    /// Topic filter subscribed to on the MQTT broker.
+    pub mqtt_topic: String,
+    /// Number of recent pressure readings the barometer trend is computed from.
+    pub pressure_trend_readings: usize,
+    /// How far back pressure readings count towards the barometer trend.
+    pub pressure_trend_window: Duration,
+    /// Span of the rolling averages of temperature, humidity and solar radiation.
+    pub avg_window: Duration,
+}
+
+/// Whether `name` matches the Prometheus metric name format.
+pub fn is_valid_metric_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+/// Parse a comma-separated list, ignoring surrounding whitespace and empty items.
+fn parse_list(value: &str) -> impl Iterator<Item = String> + '_ {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+}
+
+/// Snapshot of the `STORMCAST_*` environment variables.
+#[derive(Debug, Default, Clone)]
+pub struct ConfigEnv {
+    vars: HashMap<String, String>,
+}
+
+impl ConfigEnv {
+    pub fn from_env() -> ConfigEnv {
+        ConfigEnv::from_vars(env::vars().filter(|(name, _)| name.starts_with(ENV_PREFIX)))
+    }
+
+    pub fn from_vars(vars: impl IntoIterator<Item = (String, String)>) -> ConfigEnv {
+        ConfigEnv {
+            vars: vars.into_iter().collect(),
+        }
+    }
+}
+
+/// Setting lookup by environment variable name, preferring the environment
+/// over the config file. Remembers which settings were asked for so
+/// misspelt ones can be reported.
+struct Settings<'a> {
+    env: &'a ConfigEnv,
+    file: HashMap<String, String>,
+    used: RefCell<HashSet<String>>,
+}
+
+impl<'a> Settings<'a> {
+    fn new(env: &'a ConfigEnv, file: &HashMap<String, toml::Value>) -> Result<Settings<'a>, ConfigError> {
+        let file = file
+            .iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    toml::Value::String(value) => value.clone(),
+                    toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Boolean(_) => value.to_string(),
+                    // Lists are accepted wherever the variable takes a comma-separated list
+                    toml::Value::Array(items) => items
+                        .iter()
+                        .map(|item| match item {
+                            toml::Value::String(item) => Ok(item.clone()),
+                            _ => Err(ConfigError::Invalid(format!("settings.{} must be a list of strings", key))),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?
+                        .join(","),
+                    _ => return Err(ConfigError::Invalid(format!("settings.{} has an unsupported value", key))),
+                };
+                Ok((key.clone(), value))
+            })
+            .collect::<Result<_, ConfigError>>()?;
+        Ok(Settings {
+            env,
+            file,
+            used: RefCell::new(HashSet::new()),
+        })
+    }
+
+    /// Value of the environment variable `name`, or of its config file key,
+    /// treating an empty value as unset.
+    fn var(&self, name: &str) -> Option<String> {
+        self.var_or_empty(name).filter(|value| !value.is_empty())
+    }
+
+    /// Like [`Self::var`], for settings where an empty value means something.
+    fn var_or_empty(&self, name: &str) -> Option<String> {
+        let key = name.strip_prefix(ENV_PREFIX).unwrap_or(name).to_ascii_lowercase();
+        let value = self.env.vars.get(name).or_else(|| self.file.get(&key)).cloned();
+        self.used.borrow_mut().insert(key);
+        value
+    }
+
+    /// Parse a setting, rejecting unparseable values.
+    fn parse<T: FromStr>(&self, name: &str) -> Result<Option<T>, ConfigError> {
+        self.var(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| ConfigError::Invalid(format!("{} has invalid value {:?}", name, value)))
+            })
+            .transpose()
+    }
+
+    /// Fail on config file settings and `STORMCAST_*` variables no option
+    /// reads, which are most likely typos or settings this build lacks, such
+    /// as TLS, which would otherwise be ignored without notice.
+    fn check_unused(&self) -> Result<(), ConfigError> {
+        let used = self.used.borrow();
+        let mut unused: Vec<_> = self.file.keys().filter(|key| !used.contains(*key)).collect();
+        unused.sort();
+        if let Some(key) = unused.first() {
+            return Err(ConfigError::Invalid(format!("unknown setting {:?} in config file", key)));
+        }
+        let mut unused: Vec<_> = self
+            .env
+            .vars
+            .keys()
+            .filter(|name| !READ_ELSEWHERE.contains(&name.as_str()))
+            .filter(|name| !used.contains(&name.strip_prefix(ENV_PREFIX).unwrap_or(name).to_ascii_lowercase()))
+            .collect();
+        unused.sort();
+        match unused.first() {
+            Some(name) => Err(ConfigError::Invalid(format!("unknown environment variable {}", name))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Config {
+    /// Load the config file named by `STORMCAST_CONFIG`, falling back to
+    /// `stormcastrs.toml` in the working directory when it exists, and read
+    /// the `STORMCAST_*` environment variables.
+    pub fn load() -> Result<Config, ConfigError> {
+        match env::var_os("STORMCAST_CONFIG") {
+            Some(path) => Config::from_file(Path::new(&path)),
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => Config::from_file(Path::new(DEFAULT_CONFIG_PATH)),
+            None => Config::merge(ConfigFile::default(), ConfigEnv::from_env()),
+        }
+    }
+
+    /// Load the config file at `path`, overridden by the environment.
+    pub fn from_file(path: &Path) -> Result<Config, ConfigError> {
+        Config::merge(ConfigFile::from_file(path)?, ConfigEnv::from_env())
+    }
+
+    /// Combine the config file with the environment. A `STORMCAST_*`
+    /// variable always wins over the same setting in the file's
+    /// `[settings]` table, which in turn wins over the built-in default.
+    pub fn merge(file: ConfigFile, env: ConfigEnv) -> Result<Config, ConfigError> {
+        let settings = Settings::new(&env, &file.settings)?;
+
+        file.histograms.validate()?;
+        if let Some((name, _)) = file.metric_help.iter().find(|(_, help)| help.trim().is_empty()) {
+            return Err(ConfigError::Invalid(format!("metric_help for {} is empty", name)));
+        }
+
+        let extra_metrics_allowlist = settings.var("STORMCAST_EXTRA_METRICS_ALLOWLIST")
+            .map(|value| parse_list(&value).collect::<HashSet<_>>());
+        if let Some(name) = extra_metrics_allowlist
+            .iter()
+            .flatten()
+            .find(|name| !is_valid_metric_name(name))
+        {
+            return Err(ConfigError::Invalid(format!(
+                "STORMCAST_EXTRA_METRICS_ALLOWLIST entry {:?} is not a valid metric name",
+                name
+            )));
+        }
+
+        let sensor_fallback = match settings.var("STORMCAST_SENSOR_FALLBACK") {
+            Some(value) => toml::from_str(&value).map_err(|e| {
+                ConfigError::Invalid(format!("STORMCAST_SENSOR_FALLBACK is not a valid table: {}", e))
+            })?,
+            None => FallbackConfig::default(),
+        };
+
+        let groups: Vec<StationGroup> = match settings.var("STORMCAST_GROUPS") {
+            Some(value) => serde_json::from_str(&value).map_err(|e| {
+                ConfigError::Invalid(format!("STORMCAST_GROUPS is not a valid group list: {}", e))
+            })?,
+            None => Vec::new(),
+        };
+        let fields = WeatherData::default().fields().map(|(field, _)| field);
+        for (i, group) in groups.iter().enumerate() {
+            let invalid = fields
+                .iter()
+                .filter(|field| Aggregation::for_field(field).is_some())
+                .any(|field| !is_valid_metric_name(&group::gauge_name(field, &group.name)));
+            if group.name.is_empty() || invalid {
+                return Err(ConfigError::Invalid(format!(
+                    "STORMCAST_GROUPS name {:?} is not valid in a metric name",
+                    group.name
+                )));
+            }
+            if groups[..i].iter().any(|other| other.name == group.name) {
+                return Err(ConfigError::Invalid(format!("STORMCAST_GROUPS names {:?} twice", group.name)));
+            }
+        }
+
+        let geohash_precision = settings.parse("STORMCAST_GEOHASH_PRECISION")?.unwrap_or(6);
+        if !(1..=geohash::MAX_PRECISION).contains(&geohash_precision) {
+            return Err(ConfigError::Invalid(format!(
+                "STORMCAST_GEOHASH_PRECISION must be 1..={}",
+                geohash::MAX_PRECISION
+            )));
+        }
+        let latitude: Option<f64> = settings.parse("STORMCAST_STATION_LAT")?;
+        let longitude: Option<f64> = settings.parse("STORMCAST_STATION_LON")?;
+        let geohash = match (latitude, longitude) {
+            (Some(lat), Some(lon)) if (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon) => {
+                Some(geohash::encode(lat, lon, geohash_precision))
+            }
+            (None, None) => None,
+            _ => {
+                return Err(ConfigError::Invalid(
+                    "STORMCAST_STATION_LAT and STORMCAST_STATION_LON must both be set to a valid location"
+                        .to_string(),
+                ))
+            }
+        };
+
+        // STORMCAST_API_KEY and STORMCAST_API_KEYS may be combined
+        let api_keys: HashSet<String> = settings.var("STORMCAST_API_KEY")
+            .into_iter()
+            .chain(settings.var("STORMCAST_API_KEYS").iter().flat_map(|keys| parse_list(keys)))
+            .collect();
+        let api_keys = (!api_keys.is_empty()).then_some(api_keys);
+
+        let metric_prefix = settings.var("STORMCAST_METRIC_PREFIX").unwrap_or_else(|| "weather".to_string());
+        if !is_valid_metric_name(&metric_prefix) {
+            return Err(ConfigError::Invalid(format!(
+                "STORMCAST_METRIC_PREFIX {:?} is not a valid metric name prefix",
+                metric_prefix
+            )));
+        }
+
+        let cwop_id = settings.var("STORMCAST_CWOP_ID");
+        let cwop_lat: Option<f64> = settings.parse("STORMCAST_CWOP_LAT")?;
+        let cwop_lon: Option<f64> = settings.parse("STORMCAST_CWOP_LON")?;
+        let cwop_location = match (&cwop_id, cwop_lat, cwop_lon) {
+            (None, _, _) => None,
+            (Some(_), Some(lat), Some(lon)) if (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon) => {
+                Some((lat, lon))
+            }
+            (Some(_), _, _) => {
+                return Err(ConfigError::Invalid(
+                    "STORMCAST_CWOP_LAT and STORMCAST_CWOP_LON must both be set to a valid location".to_string(),
+                ))
+            }
+        };
+
+        let slo_target = settings.parse("STORMCAST_SLO_TARGET")?.unwrap_or(0.999);
+        if !(0.0..1.0).contains(&slo_target) {
+            return Err(ConfigError::Invalid("STORMCAST_SLO_TARGET must be between 0 and 1".to_string()));
+        }
+
+        let rounding = RoundingConfig {
+            temperature: settings.parse("STORMCAST_ROUND_TEMPERATURE")?,
+            wind: settings.parse("STORMCAST_ROUND_WIND")?,
+            rain: settings.parse("STORMCAST_ROUND_RAIN")?,
+            pressure: settings.parse("STORMCAST_ROUND_PRESSURE")?,
+            solar: settings.parse("STORMCAST_ROUND_SOLAR")?,
+            visibility: settings.parse("STORMCAST_ROUND_VISIBILITY")?,
+            air_quality: settings.parse("STORMCAST_ROUND_AIR_QUALITY")?,
+        };
+
+        let station_allowlist: HashSet<String> =
+            settings.var("STORMCAST_STATION_ALLOWLIST").iter().flat_map(|list| parse_list(list)).collect();
+        let mut station_names = HashMap::new();
+        for entry in settings.var("STORMCAST_STATION_NAMES").iter().flat_map(|list| parse_list(list)) {
+            match entry.split_once('=').map(|(id, name)| (id.trim(), name.trim())) {
+                Some((id, name)) if !id.is_empty() && !name.is_empty() => {
+                    if station_names.values().any(|other| other == name) {
+                        return Err(ConfigError::Invalid(format!("STORMCAST_STATION_NAMES names {:?} twice", name)));
+                    }
+                    station_names.insert(id.to_string(), name.to_string());
+                }
+                _ => {
+                    return Err(ConfigError::Invalid(format!(
+                        "STORMCAST_STATION_NAMES entry {:?} is not of the form PASSKEY=name",
+                        entry
+                    )))
+                }
+            }
+        }
+        if station_allowlist.iter().any(|passkey| !station_names.contains_key(passkey)) {
+            return Err(ConfigError::Invalid(
+                "every STORMCAST_STATION_ALLOWLIST entry needs a name in STORMCAST_STATION_NAMES, \
+                 so PASSKEYs aren't exported in labels"
+                    .to_string(),
+            ));
+        }
+
+        let bind = settings.var("STORMCAST_BIND").unwrap_or_else(|| "0.0.0.0:8080".to_string());
+
+        let config = Config {
+            histograms: file.histograms,
+            openhab: file.openhab,
+            precision: PrecisionProfile::with_overrides(&rounding, file.precision)?,
+            virtual_sensors: file
+                .virtual_sensors
+                .into_iter()
+                .map(VirtualSensor::from_config)
+                .collect::<Result<_, _>>()?,
+            metric_help: file.metric_help,
+            field_renames: file.field_renames,
+            admin_token: settings.var("STORMCAST_ADMIN_TOKEN").or(settings.var("STORMCAST_ADMIN_KEY")),
+            davis_url: settings.var("STORMCAST_DAVIS_URL"),
+            davis_poll_interval: Duration::from_secs(settings.parse("STORMCAST_DAVIS_POLL_SECS")?.unwrap_or(60)),
+            register_extra_metrics: settings.parse("STORMCAST_REGISTER_EXTRA_METRICS")?.unwrap_or(false),
+            extra_metrics_allowlist,
+            station_timezone: settings
+                .var("STORMCAST_STATION_TIMEZONE")
+                .map(|value| value.parse::<TimeZone>())
+                .transpose()
+                .map_err(|e| ConfigError::Invalid(format!("STORMCAST_STATION_TIMEZONE: {}", e)))?
+                .unwrap_or_default(),
+            sensor_fallback,
+            groups,
+            push_response_body: settings
+                .var_or_empty("STORMCAST_PUSH_RESPONSE_BODY")
+                .unwrap_or_else(|| "ok".to_string()),
+            push_delay: Duration::from_millis(settings.parse("STORMCAST_PUSH_DELAY_MS")?.unwrap_or(0)),
+            min_scrape_interval: Duration::from_secs(
+                settings.parse("STORMCAST_MIN_SCRAPE_INTERVAL_SECS")?.unwrap_or(0),
+            ),
+            dead_letter_dir: settings.var("STORMCAST_DEAD_LETTER_DIR").map(PathBuf::from),
+            max_dead_letters: settings.parse("STORMCAST_MAX_DEAD_LETTERS")?.unwrap_or(1000),
+            state_file: settings.var("STORMCAST_STATE_FILE").map(PathBuf::from),
+            webhook_url: settings.var("STORMCAST_WEBHOOK_URL"),
+            webhook_threshold_pct: settings.parse("STORMCAST_WEBHOOK_THRESHOLD_PCT")?.unwrap_or(0.0),
+            benchmark_mode: settings.parse("STORMCAST_BENCHMARK_MODE")?.unwrap_or(false),
+            geohash,
+            slo_target,
+            metric_prefix,
+            max_stations: settings.parse("STORMCAST_MAX_STATIONS")?.unwrap_or(100),
+            units: settings.parse("STORMCAST_UNITS")?.unwrap_or_default(),
+            rate_limit_rps: settings.parse("STORMCAST_RATE_LIMIT_RPS")?.unwrap_or(10),
+            cors_origins: settings.var("STORMCAST_CORS_ORIGINS").map(|origins| CorsOrigins::parse(&origins)),
+            max_sse_clients: settings.parse("STORMCAST_MAX_SSE_CLIENTS")?.unwrap_or(100),
+            compress_metrics: settings.parse("STORMCAST_COMPRESS_METRICS")?.unwrap_or(true),
+            max_body_bytes: settings.parse("STORMCAST_MAX_BODY_BYTES")?.unwrap_or(65536),
+            max_connections: Some(settings.parse("STORMCAST_MAX_CONNECTIONS")?.unwrap_or(1000)).filter(|&max| max > 0),
+            api_keys,
+            station_allowlist,
+            station_blocklist: settings.var("STORMCAST_STATION_BLOCKLIST").iter().flat_map(|list| parse_list(list)).collect(),
+            station_names,
+            stale_threshold: Duration::from_secs(settings.parse("STORMCAST_STALE_THRESHOLD_SECS")?.unwrap_or(3600)),
+            stale_timeout: settings
+                .parse("STORMCAST_STALE_TIMEOUT_SECS")?
+                .filter(|&secs| secs > 0)
+                .map(Duration::from_secs),
+            shutdown_grace: Duration::from_secs(settings.parse("STORMCAST_SHUTDOWN_GRACE_SECS")?.unwrap_or(5)),
+            shutdown_timeout: Seconds(settings.parse("STORMCAST_SHUTDOWN_TIMEOUT_SECS")?.unwrap_or(30)),
+            metrics_bind: settings.var("STORMCAST_METRICS_BIND").filter(|metrics_bind| *metrics_bind != bind),
+            bind,
+            remote_write_url: settings.var("STORMCAST_REMOTE_WRITE_URL"),
+            remote_write_token: settings.var("STORMCAST_REMOTE_WRITE_TOKEN"),
+            push_interval: Duration::from_secs(settings.parse("STORMCAST_PUSH_INTERVAL_SECS")?.unwrap_or(60)),
+            altitude_m: settings.parse("STORMCAST_ALTITUDE_METERS")?.unwrap_or(0.0),
+            alert_rules: settings.var("STORMCAST_ALERT_RULES")
+                .map(|value| AlertRule::parse_rules(&value))
+                .transpose()?
+                .unwrap_or_default(),
+            forward: ForwardConfig {
+                wunderground_id: settings.var("STORMCAST_WU_ID"),
+                wunderground_key: settings.var("STORMCAST_WU_KEY"),
+                pwsweather_id: settings.var("STORMCAST_PWS_ID"),
+                pwsweather_key: settings.var("STORMCAST_PWS_KEY"),
+                cwop_id,
+                cwop_location,
+                cwop_server: settings.var("STORMCAST_CWOP_SERVER").unwrap_or_else(|| cwop::DEFAULT_SERVER.to_string()),
+            },
+            history_size: settings.parse("STORMCAST_HISTORY_SIZE")?.unwrap_or(100),
+            mqtt_url: settings.var("STORMCAST_MQTT_URL"),
+            mqtt_topic: settings.var("STORMCAST_MQTT_TOPIC").unwrap_or_else(|| "weather/#".to_string()),
+            pressure_trend_readings: settings.parse("STORMCAST_PRESSURE_TREND_READINGS")?.unwrap_or(3),
+            pressure_trend_window: Duration::from_secs(
+                settings.parse("STORMCAST_PRESSURE_TREND_WINDOW_SECS")?.unwrap_or(3600),
+            ),
+            avg_window: Duration::from_secs(settings.parse("STORMCAST_AVG_WINDOW_SECS")?.unwrap_or(600)),
+        };
+        settings.check_unused()?;
+        Ok(config)
+    }
+
+    /// Name `station` is exported under: the one assigned in
+    /// `STORMCAST_STATION_NAMES`, or its PASSKEY or station ID as is.
+    pub fn station_name<'a>(&'a self, station: &'a str) -> &'a str {
+        self.station_names.get(station).map_or(station, String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge(vars: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        let vars = vars.iter().map(|&(name, value)| (name.to_string(), value.to_string()));
+        Config::merge(ConfigFile::default(), ConfigEnv::from_vars(vars))
+    }
+
+    #[test]
+    fn group_names_must_make_valid_gauge_names() {
+        assert!(merge(&[("STORMCAST_GROUPS", r#"[{"name": "farm_1", "stations": ["s1"]}]"#)]).is_ok());
+        for name in ["north field", "farm-1", ""] {
+            let groups = format!(r#"[{{"name": "{}", "stations": ["s1"]}}]"#, name);
+            assert!(merge(&[("STORMCAST_GROUPS", &groups)]).is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn group_names_must_be_unique() {
+        let groups = r#"[{"name": "farm", "stations": ["s1"]}, {"name": "farm", "stations": ["s2"]}]"#;
+        assert!(merge(&[("STORMCAST_GROUPS", groups)]).is_err());
+    }
+
+    #[test]
+    fn allowlisted_stations_need_names() {
+        let allowlist = ("STORMCAST_STATION_ALLOWLIST", "AAAA,BBBB");
+        assert!(merge(&[allowlist]).is_err());
+        assert!(merge(&[allowlist, ("STORMCAST_STATION_NAMES", "AAAA=front")]).is_err());
+        let config = merge(&[allowlist, ("STORMCAST_STATION_NAMES", "AAAA=front, BBBB=back")]).unwrap();
+        assert_eq!(config.station_name("AAAA"), "front");
+        assert_eq!(config.station_name("CCCC"), "CCCC");
+    }
+
+    #[test]
+    fn station_names_must_be_pairs_of_unique_names() {
+        for names in ["AAAA", "AAAA=", "=front", "AAAA=front,BBBB=front"] {
+            assert!(merge(&[("STORMCAST_STATION_NAMES", names)]).is_err(), "{:?}", names);
+        }
+    }
+
+    fn env(vars: &[(&str, &str)]) -> ConfigEnv {
+        ConfigEnv::from_vars(vars.iter().map(|&(name, value)| (name.to_string(), value.to_string())))
+    }
+
+    const FILE: &str = r#"
+[settings]
+bind = "127.0.0.1:9000"
+max_stations = 5
+rate_limit_rps = 20
+station_blocklist = ["AAAA", "BBBB"]
+
+[metric_help]
+temperature_fahrenheit = "Outside temperature"
+"#;
+
+    #[test]
+    fn settings_are_read_from_the_file() {
+        let config = Config::merge(toml::from_str(FILE).unwrap(), ConfigEnv::default()).unwrap();
+        assert_eq!(config.bind, "127.0.0.1:9000");
+        assert_eq!(config.max_stations, 5);
+        assert_eq!(config.rate_limit_rps, 20);
+        assert_eq!(config.station_blocklist, HashSet::from(["AAAA".to_string(), "BBBB".to_string()]));
+        assert_eq!(config.metric_help["temperature_fahrenheit"], "Outside temperature");
+    }
+
+    #[test]
+    fn environment_overrides_the_file() {
+        let vars = env(&[("STORMCAST_MAX_STATIONS", "50"), ("STORMCAST_STATION_BLOCKLIST", "CCCC")]);
+        let config = Config::merge(toml::from_str(FILE).unwrap(), vars).unwrap();
+        assert_eq!(config.max_stations, 50);
+        assert_eq!(config.station_blocklist, HashSet::from(["CCCC".to_string()]));
+        assert_eq!(config.bind, "127.0.0.1:9000");
+    }
+
+    #[test]
+    fn defaults_apply_without_a_file() {
+        let config = Config::merge(ConfigFile::default(), ConfigEnv::default()).unwrap();
+        assert_eq!(config.bind, "0.0.0.0:8080");
+        assert_eq!(config.max_stations, 100);
+        let missing = Path::new("/nonexistent/stormcastrs.toml");
+        assert!(matches!(ConfigFile::from_file(missing), Err(ConfigError::Read { .. })));
+    }
+
+    #[test]
+    fn misspelt_and_malformed_files_are_rejected() {
+        let file: ConfigFile = toml::from_str("[settings]
+max_station = 5
+").unwrap();
+        assert!(Config::merge(file, ConfigEnv::default()).is_err());
+        assert!(toml::from_str::<ConfigFile>("[unknown_table]
+x = 1
+").is_err());
+        let file: ConfigFile = toml::from_str("[settings]
+max_stations = \"many\"
+").unwrap();
+        assert!(Config::merge(file, ConfigEnv::default()).is_err());
+    }
+
+    #[test]
+    fn unknown_environment_variables_are_rejected() {
+        assert!(merge(&[("STORMCAST_MAX_STATION", "5")]).is_err());
+        // TLS isn't built in, so asking for it must not quietly serve plain HTTP
+        let tls = [("STORMCAST_TLS_CERT", "/etc/stormcast/cert.pem"), ("STORMCAST_TLS_KEY", "/etc/stormcast/key.pem")];
+        let error = merge(&tls).unwrap_err().to_string();
+        assert!(error.contains("STORMCAST_TLS_CERT"), "{}", error);
+        assert!(merge(&[("STORMCAST_CONFIG", "stormcastrs.toml"), ("STORMCAST_LOG_FORMAT", "json")]).is_ok());
+        assert!(merge(&[("STORMCAST_ADMIN_TOKEN", "a"), ("STORMCAST_ADMIN_KEY", "b")]).is_ok());
+    }
+
+    #[test]
+    fn sample_config_is_valid() {
+        Config::merge(toml::from_str(SAMPLE_CONFIG).unwrap(), ConfigEnv::default()).unwrap();
+    }
+}
@@ -0,0 +1,112 @@
+use std::str::FromStr;
+
+pub const MM_PER_INCH: f32 = 25.4;
+pub const KM_PER_MILE: f32 = 1.609344;
+pub const MS_PER_MPH: f32 = 0.44704;
+pub const HPA_PER_INHG: f32 = 33.863_89;
+
+pub fn fahrenheit_to_celsius(f: f32) -> f32 {
+    (f - 32.0) * 5.0 / 9.0
+}
+
+pub fn celsius_to_fahrenheit(c: f32) -> f32 {
+    c * 9.0 / 5.0 + 32.0
+}
+
+pub fn mph_to_ms(mph: f32) -> f32 {
+    mph * MS_PER_MPH
+}
+
+pub fn inches_to_mm(inches: f32) -> f32 {
+    inches * MM_PER_INCH
+}
+
+pub fn inhg_to_hpa(inhg: f32) -> f32 {
+    inhg * HPA_PER_INHG
+}
+
+/// Which unit system gauges are exported in, from `STORMCAST_UNITS`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MetricSystem {
+    /// °F, mph, inHg and inches, as stations push them.
+    #[default]
+    Imperial,
+    /// °C, m/s, hPa and mm.
+    Metric,
+    Both,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid unit system {0:?}, expected \"imperial\", \"metric\" or \"both\"")]
+pub struct MetricSystemError(String);
+
+impl MetricSystem {
+    pub fn imperial(self) -> bool {
+        matches!(self, MetricSystem::Imperial | MetricSystem::Both)
+    }
+
+    pub fn metric(self) -> bool {
+        matches!(self, MetricSystem::Metric | MetricSystem::Both)
+    }
+}
+
+impl FromStr for MetricSystem {
+    type Err = MetricSystemError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "imperial" => Ok(MetricSystem::Imperial),
+            "metric" => Ok(MetricSystem::Metric),
+            "both" => Ok(MetricSystem::Both),
+            _ => Err(MetricSystemError(value.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Metrics;
+    use crate::{test_support, WeatherData};
+
+    fn close(actual: f32, expected: f32) -> bool {
+        (actual - expected).abs() < 1e-3
+    }
+
+    #[test]
+    fn edge_values_convert() {
+        assert!(close(fahrenheit_to_celsius(-459.67), -273.15));
+        assert!(close(fahrenheit_to_celsius(32.0), 0.0));
+        assert!(close(fahrenheit_to_celsius(-40.0), -40.0));
+        assert!(close(celsius_to_fahrenheit(100.0), 212.0));
+        assert_eq!(mph_to_ms(0.0), 0.0);
+        assert!(close(mph_to_ms(10.0), 4.4704));
+        assert_eq!(inches_to_mm(1.0), 25.4);
+        assert!(close(inhg_to_hpa(29.92), 1013.207));
+    }
+
+    #[test]
+    fn unit_system_parses() {
+        assert_eq!(" Metric ".parse::<MetricSystem>().unwrap(), MetricSystem::Metric);
+        assert!("both".parse::<MetricSystem>().unwrap().imperial());
+        assert!(!MetricSystem::Imperial.metric());
+        assert!("kelvin".parse::<MetricSystem>().is_err());
+    }
+
+    #[test]
+    fn metric_gauges_follow_the_unit_system() {
+        let push = WeatherData::from_query("PASSKEY=units&tempf=212.0&windspeedmph=0.0&dailyrainin=1.0").unwrap();
+
+        let metrics = Metrics::new(&test_support::config(&[("STORMCAST_UNITS", "metric")])).unwrap();
+        metrics.update(&push).unwrap();
+        assert_eq!(test_support::series_value(&metrics, "weather_temperature_celsius", "units"), Some(100.0));
+        assert_eq!(test_support::series_value(&metrics, "weather_wind_speed_ms", "units"), Some(0.0));
+        assert_eq!(test_support::series_value(&metrics, "weather_rain_daily_mm", "units"), Some(25.4));
+        assert_eq!(test_support::series_value(&metrics, "weather_temperature_fahrenheit", "units"), None);
+
+        let metrics = Metrics::new(&test_support::config(&[])).unwrap();
+        metrics.update(&push).unwrap();
+        assert_eq!(test_support::series_value(&metrics, "weather_temperature_celsius", "units"), None);
+        assert_eq!(test_support::series_value(&metrics, "weather_temperature_fahrenheit", "units"), Some(212.0));
+    }
+}
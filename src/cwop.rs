@@ -0,0 +1,191 @@
+use std::io;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tracing::{debug, warn};
+
+use crate::calendar::{self, Date, SECS_PER_DAY};
+use crate::metrics::metrics;
+use crate::WeatherData;
+
+/// APRS-IS server reports go to when `STORMCAST_CWOP_SERVER` is unset.
+pub const DEFAULT_SERVER: &str = "cwop.aprs.net:14580";
+/// CWOP asks stations not to report more often than every five minutes.
+const MIN_INTERVAL: Duration = Duration::from_secs(300);
+/// Upper bound on connecting to the server and sending one report.
+const SUBMIT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Tenths of a millibar per inch of mercury.
+const TENTHS_MBAR_PER_INHG: f64 = 338.639;
+
+/// `DDMM.hh` (or `DDDMM.hh` with three degree digits) followed by the
+/// hemisphere, rounded to hundredths of a minute.
+fn format_coordinate(value: f64, degree_digits: usize, positive: char, negative: char) -> String {
+    let hundredths = (value.abs() * 6000.0).round() as u64;
+    let (degrees, minutes) = (hundredths / 6000, hundredths % 6000);
+    format!(
+        "{:0width$}{:02}.{:02}{}",
+        degrees,
+        minutes / 100,
+        minutes % 100,
+        if value < 0.0 { negative } else { positive },
+        width = degree_digits
+    )
+}
+
+/// A three-digit field, or `...` when the station didn't report it.
+fn three_digits(value: Option<f64>) -> String {
+    match value {
+        Some(value) => format!("{:03}", (value.round() as i64).clamp(-99, 999)),
+        None => "...".to_string(),
+    }
+}
+
+/// An APRS positioned weather report for CWOP, e.g.
+/// `CW0001>APRS,TCPIP*:@151200z4903.50N/07201.75W_220/004g005t077r000P012h50b10132`.
+/// Wind and temperature are always present (as `...` when unknown); rain,
+/// humidity, pressure and luminosity are left out when not reported.
+pub fn format_aprs_weather_report(callsign: &str, lat: f64, lon: f64, data: &WeatherData) -> String {
+    let unix_secs = data.timestamp().unwrap_or_else(calendar::now);
+    let date = Date::from_days(unix_secs.div_euclid(SECS_PER_DAY));
+    let secs = unix_secs.rem_euclid(SECS_PER_DAY);
+
+    let mut report = format!(
+        "{}>APRS,TCPIP*:@{:02}{:02}{:02}z{}/{}_{}/{}g{}t{}",
+        callsign,
+        date.day,
+        secs / 3600,
+        secs % 3600 / 60,
+        format_coordinate(lat, 2, 'N', 'S'),
+        format_coordinate(lon, 3, 'E', 'W'),
+        three_digits(data.winddir.map(f64::from)),
+        three_digits(data.windspeedmph.map(f64::from)),
+        three_digits(data.windgustmph.map(f64::from)),
+        three_digits(data.tempf.map(f64::from)),
+    );
+    // Rain is in hundredths of an inch
+    if let Some(rain) = data.hourlyrainin {
+        report.push_str(&format!("r{}", three_digits(Some(f64::from(rain) * 100.0))));
+    }
+    if let Some(rain) = data.dailyrainin {
+        report.push_str(&format!("P{}", three_digits(Some(f64::from(rain) * 100.0))));
+    }
+    // Humidity of 100% is sent as 00
+    if let Some(humidity) = data.humidity {
+        report.push_str(&format!("h{:02}", humidity.min(100) % 100));
+    }
+    if let Some(pressure) = data.baromrelin {
+        report.push_str(&format!("b{:05}", (f64::from(pressure) * TENTHS_MBAR_PER_INHG).round() as u32));
+    }
+    // Luminosity in W/m², with `l` carrying the thousands
+    if let Some(solar) = data.solarradiation {
+        let solar = solar.round().clamp(0.0, 1999.0) as u32;
+        if solar < 1000 {
+            report.push_str(&format!("L{:03}", solar));
+        } else {
+            report.push_str(&format!("l{:03}", solar - 1000));
+        }
+    }
+    report
+}
+
+/// Submits readings to the Citizen Weather Observer Program over APRS-IS,
+/// at most once per `MIN_INTERVAL`.
+#[derive(Debug)]
+pub struct Cwop {
+    callsign: String,
+    lat: f64,
+    lon: f64,
+    server: String,
+    last_sent: Mutex<Option<Instant>>,
+}
+
+impl Cwop {
+    pub fn new(callsign: String, lat: f64, lon: f64, server: String) -> Self {
+        Cwop {
+            callsign,
+            lat,
+            lon,
+            server,
+            last_sent: Mutex::new(None),
+        }
+    }
+
+    /// Send `data` in the background unless a report went out recently.
+    pub fn submit(&self, data: &WeatherData) {
+        let now = Instant::now();
+        {
+            let mut last_sent = self.last_sent.lock().unwrap();
+            if last_sent.is_some_and(|at| now.saturating_duration_since(at) < MIN_INTERVAL) {
+                debug!("Skipping CWOP report: last one was sent less than {:?} ago", MIN_INTERVAL);
+                return;
+            }
+            *last_sent = Some(now);
+        }
+
+        // Stations without an amateur radio licence log in with passcode -1
+        let login = format!("user {} pass -1 vers stormcastrs {}\r\n", self.callsign, env!("CARGO_PKG_VERSION"));
+        let report = format!("{}\r\n", format_aprs_weather_report(&self.callsign, self.lat, self.lon, data));
+        let server = self.server.clone();
+        ntex::rt::spawn(async move {
+            match tokio::time::timeout(SUBMIT_TIMEOUT, send(&server, &login, &report)).await {
+                Ok(Ok(())) => debug!("Sent CWOP report to {}", server),
+                Ok(Err(e)) => {
+                    warn!("Failed to send CWOP report to {}: {}", server, e);
+                    metrics().relay_errors.with_label_values(&["cwop"]).inc();
+                }
+                Err(_) => {
+                    warn!("Timed out sending CWOP report to {}", server);
+                    metrics().relay_errors.with_label_values(&["cwop"]).inc();
+                }
+            }
+        });
+    }
+}
+
+async fn send(server: &str, login: &str, report: &str) -> io::Result<()> {
+    let mut stream = TcpStream::connect(server).await?;
+    stream.write_all(login.as_bytes()).await?;
+    stream.write_all(report.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAT: f64 = 49.0 + 3.5 / 60.0;
+    const LON: f64 = -(72.0 + 1.75 / 60.0);
+
+    fn report(query: &str) -> String {
+        format_aprs_weather_report("CW0001", LAT, LON, &WeatherData::from_query(query).unwrap())
+    }
+
+    #[test]
+    fn full_report() {
+        let query = "dateutc=2024-03-15+12:00:00&winddir=220&windspeedmph=4&windgustmph=5&tempf=77.0\
+                     &hourlyrainin=0&dailyrainin=0.12&humidity=50&baromrelin=29.91&solarradiation=550";
+        assert_eq!(report(query), "CW0001>APRS,TCPIP*:@151200z4903.50N/07201.75W_220/004g005t077r000P012h50b10129L550");
+    }
+
+    #[test]
+    fn missing_fields_are_dotted_or_left_out() {
+        assert_eq!(
+            report("dateutc=2024-03-15+08:05:00&tempf=-5.0"),
+            "CW0001>APRS,TCPIP*:@150805z4903.50N/07201.75W_.../...g...t-05"
+        );
+    }
+
+    #[test]
+    fn saturated_humidity_and_bright_sun() {
+        let report = report("dateutc=2024-03-15+12:00:00&tempf=60.0&humidity=100&solarradiation=1100");
+        assert!(report.ends_with("t060h00l100"), "{}", report);
+    }
+
+    #[test]
+    fn coordinates_use_degrees_and_minutes() {
+        assert_eq!(format_coordinate(-33.8688, 2, 'N', 'S'), "3352.13S");
+        assert_eq!(format_coordinate(151.2093, 3, 'E', 'W'), "15112.56E");
+        assert_eq!(format_coordinate(0.0, 3, 'E', 'W'), "00000.00E");
+    }
+}
@@ -0,0 +1,60 @@
+use ntex::web;
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use serde_json::json;
+use std::sync::RwLock;
+
+use crate::calendar;
+use crate::gzip;
+use crate::{AppState, WeatherData};
+
+/// The most recently accepted push from any station.
+#[derive(Debug, Default)]
+pub struct LatestReading {
+    reading: RwLock<Option<(WeatherData, i64)>>,
+}
+
+impl LatestReading {
+    /// Remember `data`, received at `unix_secs`.
+    pub fn set(&self, data: &WeatherData, unix_secs: i64) {
+        *self.reading.write().unwrap() = Some((data.clone(), unix_secs));
+    }
+
+    /// The latest reading and when it was received, in Unix seconds.
+    pub fn get(&self) -> Option<(WeatherData, i64)> {
+        self.reading.read().unwrap().clone()
+    }
+}
+
+/// A reading as returned by `/data`, with fields in push order.
+struct DataResponse<'a> {
+    station_id: Option<&'a str>,
+    last_updated: String,
+    fields: Vec<(&'static str, f64)>,
+}
+
+impl Serialize for DataResponse<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.fields.len() + 2))?;
+        map.serialize_entry("station_id", &self.station_id)?;
+        map.serialize_entry("last_updated", &self.last_updated)?;
+        for (name, value) in &self.fields {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+/// The latest reading as JSON, rounded like the gauges.
+pub async fn handle_data(req: web::HttpRequest, state: web::types::State<AppState>) -> web::HttpResponse {
+    let latest = state.latest.reading.read().unwrap();
+    let Some((data, received_at)) = latest.as_ref() else {
+        return web::HttpResponse::ServiceUnavailable().json(&json!({ "error": "no data received yet" }));
+    };
+    let response = DataResponse {
+        station_id: data.station_id(),
+        last_updated: calendar::format_datetime(*received_at),
+        fields: data.to_response(&state.config.precision),
+    };
+    gzip::json(&req, state.config.compress_metrics, web::HttpResponse::Ok(), &response)
+}
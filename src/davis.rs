@@ -0,0 +1,252 @@
+use ntex::http::client::Client;
+use ntex::web;
+use serde::Deserialize;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::auth::has_bearer_token;
+use crate::error::AppError;
+use crate::{process_reading, AppState, PushProtocol, WeatherData};
+
+/// Response of the WeatherLink Live `/v1/current_conditions` local API.
+#[derive(Debug, Deserialize)]
+pub struct DavisWeatherLinkData {
+    pub data: DavisConditions,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DavisConditions {
+    pub did: String,
+    pub ts: i64,
+    pub conditions: Vec<DavisCondition>,
+}
+
+/// One sensor record. The gateway reports the ISS, the barometer and the
+/// indoor temperature/humidity sensor as separate records, so every field is
+/// optional and the records are merged into a single reading.
+#[derive(Debug, Default, Deserialize)]
+pub struct DavisCondition {
+    pub temp: Option<f32>,
+    pub hum: Option<f32>,
+    pub wind_speed_last: Option<f32>,
+    pub wind_dir_last: Option<f32>,
+    pub wind_speed_hi_last_10_min: Option<f32>,
+    pub wind_dir_scalar_avg_last_10_min: Option<f32>,
+    pub rain_size: Option<u8>,
+    pub rain_rate_last: Option<f32>,
+    pub rain_storm: Option<f32>,
+    pub rainfall_daily: Option<f32>,
+    pub rainfall_monthly: Option<f32>,
+    pub rainfall_year: Option<f32>,
+    pub solar_rad: Option<f32>,
+    pub uv_index: Option<f32>,
+    pub trans_battery_flag: Option<u8>,
+    pub temp_in: Option<f32>,
+    pub hum_in: Option<f32>,
+    pub bar_sea_level: Option<f32>,
+    pub bar_absolute: Option<f32>,
+}
+
+impl DavisCondition {
+    /// Rain values are reported as bucket tip counts; `rain_size` gives the
+    /// bucket size (1 = 0.01 in, 2 = 0.2 mm, 3 = 0.1 mm, 4 = 0.001 in).
+    fn rain_inches(&self, counts: Option<f32>) -> Option<f32> {
+        let inches_per_count = match self.rain_size.unwrap_or(1) {
+            2 => 0.2 / 25.4,
+            3 => 0.1 / 25.4,
+            4 => 0.001,
+            _ => 0.01,
+        };
+        counts.map(|counts| counts * inches_per_count)
+    }
+}
+
+impl From<DavisWeatherLinkData> for WeatherData {
+    fn from(davis: DavisWeatherLinkData) -> Self {
+        let mut data = WeatherData {
+            stationid: Some(davis.data.did),
+            ..Default::default()
+        };
+
+        for c in &davis.data.conditions {
+            data.tempf = c.temp.or(data.tempf);
+            data.humidity = c.hum.map(|v| v.round() as u8).or(data.humidity);
+            data.windspeedmph = c.wind_speed_last.or(data.windspeedmph);
+            data.windgustmph = c.wind_speed_hi_last_10_min.or(data.windgustmph);
+            data.winddir = c.wind_dir_last.map(|v| v.round() as u16).or(data.winddir);
+            data.winddir_avg10m = c.wind_dir_scalar_avg_last_10_min.map(|v| v.round() as u16).or(data.winddir_avg10m);
+            data.uv = c.uv_index.map(|v| v.round() as u8).or(data.uv);
+            data.solarradiation = c.solar_rad.or(data.solarradiation);
+            data.hourlyrainin = c.rain_inches(c.rain_rate_last).or(data.hourlyrainin);
+            data.eventrainin = c.rain_inches(c.rain_storm).or(data.eventrainin);
+            data.dailyrainin = c.rain_inches(c.rainfall_daily).or(data.dailyrainin);
+            data.monthlyrainin = c.rain_inches(c.rainfall_monthly).or(data.monthlyrainin);
+            data.yearlyrainin = c.rain_inches(c.rainfall_year).or(data.yearlyrainin);
+            // Davis flags a low transmitter battery with 1; the gauge uses 1 for OK
+            data.battout = c.trans_battery_flag.map(|flag| u8::from(flag == 0)).or(data.battout);
+            data.tempinf = c.temp_in.or(data.tempinf);
+            data.humidityin = c.hum_in.map(|v| v.round() as u8).or(data.humidityin);
+            data.baromrelin = c.bar_sea_level.or(data.baromrelin);
+            data.baromabsin = c.bar_absolute.or(data.baromabsin);
+        }
+        data
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DavisError {
+    #[error("request failed: {0}")]
+    Request(String),
+    #[error("unexpected response status {0}")]
+    Status(u16),
+    #[error("invalid response body: {0}")]
+    Parse(String),
+}
+
+/// Fetch current conditions from the gateway.
+pub async fn fetch(url: &str) -> Result<WeatherData, DavisError> {
+    let client = Client::build().timeout(Duration::from_secs(10)).finish();
+    let mut res = client
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| DavisError::Request(e.to_string()))?;
+    if !res.status().is_success() {
+        return Err(DavisError::Status(res.status().as_u16()));
+    }
+    let body = res
+        .body()
+        .await
+        .map_err(|e| DavisError::Request(e.to_string()))?;
+    let davis: DavisWeatherLinkData =
+        serde_json::from_slice(&body).map_err(|e| DavisError::Parse(e.to_string()))?;
+
+    let data = WeatherData::from(davis);
+    info!("Fetched Davis weather data: {:?}", data);
+    Ok(data)
+}
+
+/// Fetch current conditions and feed them through the same checks and
+/// consumers as a pushed reading.
+async fn poll(state: &AppState, url: &str) -> Result<(), AppError> {
+    let data = fetch(url)
+        .await
+        .map_err(|e| AppError::Upstream(format!("failed to fetch Davis data: {}", e)))?;
+    process_reading(state, data, PushProtocol::Davis)
+}
+
+/// Poll the configured gateway forever at the configured interval.
+pub async fn poll_loop(state: AppState) {
+    let Some(url) = state.config.davis_url.clone() else {
+        return;
+    };
+    loop {
+        if let Err(e) = poll(&state, &url).await {
+            warn!("Failed to ingest Davis weather data from {}: {}", url, e);
+        }
+        ntex::time::sleep(state.config.davis_poll_interval).await;
+    }
+}
+
+/// Trigger an immediate poll of the configured WeatherLink gateway.
+/// Requires the admin token.
+pub async fn handle_fetch_davis(
+    req: web::HttpRequest,
+    state: web::types::State<AppState>,
+) -> Result<web::HttpResponse, AppError> {
+    if !has_bearer_token(&req, state.config.admin_token.as_deref()) {
+        warn!("Rejected Davis fetch without a valid admin token");
+        return Err(AppError::Unauthorized);
+    }
+    let Some(url) = state.config.davis_url.as_deref() else {
+        return Err(AppError::NotFound("Davis WeatherLink polling is not configured".to_string()));
+    };
+    if let Err(e) = poll(&state, url).await {
+        warn!("Failed to ingest Davis weather data from {}: {}", url, e);
+        return Err(e);
+    }
+    Ok(web::HttpResponse::Ok().body("Davis data fetched and metrics updated"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::metrics;
+    use crate::test_support;
+    use ntex::http::StatusCode;
+    use ntex::web::test::{call_service, init_service, server, TestRequest};
+    use ntex::web::App;
+
+    const CURRENT_CONDITIONS: &str = r#"{"data": {"did": "001D0A700001", "ts": 1609459200, "conditions": [
+        {"temp": 72.5, "hum": 45.2, "wind_speed_last": 5.5, "wind_dir_last": 270, "rain_size": 1, "rainfall_daily": 12},
+        {"temp_in": 68.0, "hum_in": 40},
+        {"bar_sea_level": 30.01, "bar_absolute": 29.5}
+    ]}}"#;
+
+    #[test]
+    fn conditions_merge_into_one_reading() {
+        let davis: DavisWeatherLinkData = serde_json::from_str(CURRENT_CONDITIONS).unwrap();
+        let data = WeatherData::from(davis);
+        assert_eq!(data.stationid.as_deref(), Some("001D0A700001"));
+        assert_eq!(data.tempf, Some(72.5));
+        assert_eq!(data.humidity, Some(45));
+        assert_eq!(data.winddir, Some(270));
+        assert_eq!(data.dailyrainin, Some(0.12));
+        assert_eq!(data.tempinf, Some(68.0));
+        assert_eq!(data.baromrelin, Some(30.01));
+    }
+
+    #[test]
+    fn metric_rain_buckets_convert_to_inches() {
+        let condition = DavisCondition {
+            rain_size: Some(2),
+            ..Default::default()
+        };
+        let inches = condition.rain_inches(Some(127.0)).unwrap();
+        assert!((inches - 1.0).abs() < 1e-6, "{}", inches);
+    }
+
+    /// Gateway stand-in serving `CURRENT_CONDITIONS`.
+    fn gateway() -> ntex::web::test::TestServer {
+        server(|| {
+            App::new().route(
+                "/v1/current_conditions",
+                web::get().to(|| async {
+                    web::HttpResponse::Ok()
+                        .content_type("application/json")
+                        .body(CURRENT_CONDITIONS)
+                }),
+            )
+        })
+    }
+
+    #[ntex::test]
+    async fn poll_updates_gauges() {
+        let gateway = gateway();
+        let url = gateway.url("/v1/current_conditions");
+        let state = test_support::state(&[("STORMCAST_DAVIS_URL", &url)]);
+
+        poll(&state, &url).await.unwrap();
+        let temp = test_support::series_value(metrics(), "weather_temperature_fahrenheit", "001D0A700001");
+        assert_eq!(temp, Some(72.5));
+        let (latest, _) = state.latest.get().unwrap();
+        assert_eq!(latest.stationid.as_deref(), Some("001D0A700001"));
+    }
+
+    #[ntex::test]
+    async fn fetch_endpoint_requires_admin_token() {
+        let gateway = gateway();
+        let url = gateway.url("/v1/current_conditions");
+        let state = test_support::state(&[("STORMCAST_DAVIS_URL", &url), ("STORMCAST_ADMIN_TOKEN", "secret")]);
+        let app = init_service(App::new().state(state).route("/fetch/davis", web::get().to(handle_fetch_davis))).await;
+
+        let req = TestRequest::get().uri("/fetch/davis").to_request();
+        assert_eq!(call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+        let req = TestRequest::get()
+            .uri("/fetch/davis")
+            .header("Authorization", "Bearer secret")
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), StatusCode::OK);
+    }
+}
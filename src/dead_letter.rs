@@ -0,0 +1,193 @@
+use crate::auth::is_api_key_param;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A push as the station sent it, kept for the dead-letter queue.
+#[derive(Debug, Clone, Copy)]
+pub enum RawPayload<'a> {
+    /// A URL-encoded query string or form body.
+    Form(&'a [u8]),
+    /// A JSON object body.
+    Json(&'a [u8]),
+}
+
+impl RawPayload<'_> {
+    /// The payload with any API key parameters removed, so dead letters
+    /// never hold a credential.
+    pub fn redacted(self) -> String {
+        match self {
+            RawPayload::Form(bytes) => String::from_utf8_lossy(bytes)
+                .split('&')
+                .filter(|pair| {
+                    let name = pair.split('=').next().unwrap_or_default();
+                    !form_urlencoded::parse(name.as_bytes()).any(|(name, _)| is_api_key_param(&name))
+                })
+                .collect::<Vec<_>>()
+                .join("&"),
+            RawPayload::Json(bytes) => match serde_json::from_slice::<serde_json::Value>(bytes) {
+                Ok(serde_json::Value::Object(mut fields)) => {
+                    fields.retain(|name, _| !is_api_key_param(name));
+                    serde_json::Value::Object(fields).to_string()
+                }
+                _ => String::from_utf8_lossy(bytes).into_owned(),
+            },
+        }
+    }
+}
+
+/// Keeps the raw payloads of rejected pushes on disk for debugging, one file
+/// per payload, deleting the oldest once `max_files` is reached.
+#[derive(Debug)]
+pub struct DeadLetterQueue {
+    dir: PathBuf,
+    max_files: usize,
+}
+
+/// Station ID made safe to use in a file name.
+fn file_safe(station: &str) -> String {
+    station
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+impl DeadLetterQueue {
+    pub fn new(dir: PathBuf, max_files: usize) -> Self {
+        DeadLetterQueue { dir, max_files }
+    }
+
+    /// Dead-letter files, oldest first. Names start with a zero-padded
+    /// timestamp, so name order is age order.
+    fn files(&self) -> io::Result<Vec<PathBuf>> {
+        let mut files: Vec<PathBuf> = fs::read_dir(&self.dir)?
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "txt"))
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+
+    /// Write `payload` to `{timestamp}_{station}.txt`, via a temporary file
+    /// so readers never see a partial payload.
+    pub fn write(&self, station: &str, payload: &str) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.dir)?;
+
+        let files = self.files()?;
+        let excess = (files.len() + 1).saturating_sub(self.max_files.max(1));
+        for oldest in &files[..excess.min(files.len())] {
+            fs::remove_file(oldest)?;
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros();
+        let name = format!("{:020}_{}", timestamp, file_safe(station));
+        let tmp = self.dir.join(format!(".{}.tmp", name));
+        let path = self.dir.join(format!("{}.txt", name));
+        fs::write(&tmp, payload)?;
+        fs::rename(&tmp, &path)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use std::path::Path;
+    use ntex::http::StatusCode;
+    use ntex::web::{self, test};
+
+    /// An empty directory for one test to write dead letters to.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("stormcast-dead-letter-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn oldest_files_are_deleted_at_the_limit() {
+        let dir = scratch_dir("limit");
+        let queue = DeadLetterQueue::new(dir.clone(), 2);
+        let first = queue.write("station/1", "first").unwrap();
+        queue.write("station/1", "second").unwrap();
+        queue.write("station/1", "third").unwrap();
+
+        let files = queue.files().unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(!first.exists());
+        assert!(files[0].file_name().unwrap().to_str().unwrap().ends_with("_station_1.txt"));
+        assert_eq!(fs::read_to_string(&files[1]).unwrap(), "third");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// The one dead letter a test wrote to `dir`.
+    fn only_dead_letter(dir: &Path, station: &str) -> String {
+        let files = DeadLetterQueue::new(dir.to_path_buf(), 1000).files().unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].to_str().unwrap().ends_with(&format!("_{}.txt", station)));
+        fs::read_to_string(&files[0]).unwrap()
+    }
+
+    #[test]
+    fn api_keys_are_redacted() {
+        let form = RawPayload::Form(b"PASSKEY=a&key=secret&tempf=warm&APIKEY=secret");
+        assert_eq!(form.redacted(), "PASSKEY=a&tempf=warm");
+        let json = RawPayload::Json(br#"{"PASSKEY":"a","apikey":"secret"}"#);
+        assert_eq!(json.redacted(), r#"{"PASSKEY":"a"}"#);
+    }
+
+    #[ntex::test]
+    async fn unparseable_push_is_written_with_its_raw_query() {
+        let dir = scratch_dir("push");
+        let state = test_support::state(&[("STORMCAST_DEAD_LETTER_DIR", dir.to_str().unwrap())]);
+        let app = test::init_service(
+            web::App::new()
+                .state(state)
+                .route("/push/", web::get().to(crate::handle_weather_data)),
+        )
+        .await;
+
+        let query = "PASSKEY=deadletter&tempf=warm&humidity=40";
+        let req = test::TestRequest::with_uri(&format!("/push/?{}&key=secret", query)).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+
+        assert_eq!(only_dead_letter(&dir, "deadletter"), query);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[ntex::test]
+    async fn unparseable_post_push_is_written_with_its_body() {
+        let dir = scratch_dir("post");
+        let state = test_support::state(&[("STORMCAST_DEAD_LETTER_DIR", dir.to_str().unwrap())]);
+        let app = test::init_service(
+            web::App::new()
+                .state(state)
+                .route("/push/", web::post().to(crate::handle_weather_data_post))
+                .route("/push/json", web::post().to(crate::handle_weather_data_json)),
+        )
+        .await;
+
+        let body = "PASSKEY=deadpost&tempf=warm&humidity=40";
+        let req = test::TestRequest::post()
+            .uri("/push/?key=secret")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .set_payload(format!("apikey=secret&{}", body))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(only_dead_letter(&dir, "deadpost"), body);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let req = test::TestRequest::post()
+            .uri("/push/json")
+            .header("content-type", "application/json")
+            .set_payload(r#"{"PASSKEY":"deadjson","tempf":"warm","key":"secret"}"#)
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(only_dead_letter(&dir, "deadjson"), r#"{"PASSKEY":"deadjson","tempf":"warm"}"#);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
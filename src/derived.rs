@@ -0,0 +1,448 @@
+use crate::convert::{celsius_to_fahrenheit, fahrenheit_to_celsius};
+
+/// Magnus formula coefficients (Alduchov & Eskridge), valid from -45 °C to 60 °C.
+const MAGNUS_A: f32 = 17.62;
+const MAGNUS_B: f32 = 243.12;
+
+/// Dew point in Fahrenheit from temperature and relative humidity, using the
+/// Magnus approximation. Humidity below 1% is treated as 1%, where the
+/// formula would otherwise diverge.
+pub fn dew_point_f(temp_f: f32, humidity: u8) -> f32 {
+    let temp_c = fahrenheit_to_celsius(temp_f);
+    let rh = f32::from(humidity.clamp(1, 100)) / 100.0;
+    let gamma = rh.ln() + MAGNUS_A * temp_c / (MAGNUS_B + temp_c);
+    let dew_point_c = MAGNUS_B * gamma / (MAGNUS_A - gamma);
+    celsius_to_fahrenheit(dew_point_c)
+}
+
+/// Temperature lapse rate of the standard atmosphere in K/m.
+const LAPSE_RATE: f32 = 0.0065;
+/// g·M / (R·L) for dry air in the standard atmosphere.
+const BAROMETRIC_EXPONENT: f32 = 5.257;
+
+/// Sea-level pressure in inHg from absolute (station) pressure, using the
+/// NOAA standard atmosphere barometric formula
+/// `P0 = P · (1 - L·h / (T + L·h + 273.15))^-5.257`, where `h` is the
+/// altitude in metres, `L` the lapse rate and `T` the station temperature
+/// in °C.
+pub fn absolute_to_sea_level_inhg(abs_inhg: f32, altitude_m: f32, temp_f: f32) -> f32 {
+    let temp_c = fahrenheit_to_celsius(temp_f);
+    let drop = LAPSE_RATE * altitude_m;
+    abs_inhg * (1.0 - drop / (temp_c + drop + 273.15)).powf(-BAROMETRIC_EXPONENT)
+}
+
+/// Temperature the standard atmosphere has at `altitude_m`, in Fahrenheit.
+pub fn standard_temp_f(altitude_m: f32) -> f32 {
+    celsius_to_fahrenheit(15.0 - LAPSE_RATE * altitude_m)
+}
+
+/// NOAA Rothfusz regression coefficients, for °F and percent humidity.
+const HI_C1: f32 = -42.379;
+const HI_C2: f32 = 2.049_015_2;
+const HI_C3: f32 = 10.143_332;
+const HI_C4: f32 = -0.224_755_4;
+const HI_C5: f32 = -0.006_837_83;
+const HI_C6: f32 = -0.054_817_17;
+const HI_C7: f32 = 0.001_228_74;
+const HI_C8: f32 = 0.000_852_82;
+const HI_C9: f32 = -0.000_001_99;
+
+/// Heat index in Fahrenheit using the NOAA Rothfusz regression, or `None`
+/// below 80 °F or 40% humidity where the regression doesn't apply.
+pub fn heat_index_f(temp_f: f32, humidity: u8) -> Option<f32> {
+    if temp_f < 80.0 || humidity < 40 {
+        return None;
+    }
+    let t = temp_f;
+    let rh = f32::from(humidity.min(100));
+    Some(
+        HI_C1
+            + HI_C2 * t
+            + HI_C3 * rh
+            + HI_C4 * t * rh
+            + HI_C5 * t * t
+            + HI_C6 * rh * rh
+            + HI_C7 * t * t * rh
+            + HI_C8 * t * rh * rh
+            + HI_C9 * t * t * rh * rh,
+    )
+}
+
+/// Wind chill in Fahrenheit using the NWS 2001 formula, or `None` above
+/// 50 °F or below 3 mph where it isn't defined.
+pub fn wind_chill_f(temp_f: f32, wind_mph: f32) -> Option<f32> {
+    if temp_f > 50.0 || wind_mph < 3.0 {
+        return None;
+    }
+    let v = wind_mph.powf(0.16);
+    Some(35.74 + 0.6215 * temp_f - 35.75 * v + 0.4275 * temp_f * v)
+}
+
+/// Lowest wind speed in mph of Beaufort forces 1 through 12.
+const BEAUFORT_MPH: [f32; 12] = [1.0, 4.0, 8.0, 13.0, 19.0, 25.0, 32.0, 39.0, 47.0, 55.0, 64.0, 73.0];
+
+/// Beaufort force 0 (calm, under 1 mph) to 12 (hurricane, 73 mph and up).
+pub fn beaufort_from_mph(mph: f32) -> u8 {
+    BEAUFORT_MPH.iter().take_while(|&&lower| mph >= lower).count() as u8
+}
+
+/// WHO UV exposure category: 0 low (0-2), 1 moderate (3-5), 2 high (6-7),
+/// 3 very high (8-10) and 4 extreme (11+).
+pub fn uv_risk_level(uv_index: u8) -> u8 {
+    match uv_index {
+        0..=2 => 0,
+        3..=5 => 1,
+        6..=7 => 2,
+        8..=10 => 3,
+        _ => 4,
+    }
+}
+
+/// Indoor temperature, humidity and CO2 at which comfort is ideal.
+const COMFORT_TEMP_F: f32 = 70.0;
+const COMFORT_HUMIDITY: f32 = 50.0;
+const COMFORT_CO2_PPM: f32 = 800.0;
+/// Points lost per °F away from the ideal temperature.
+const COMFORT_TEMP_PENALTY: f32 = 4.0;
+/// Points lost per percent away from the ideal humidity.
+const COMFORT_HUMIDITY_PENALTY: f32 = 1.0;
+/// Scale and e-folding concentration of the CO2 penalty, which grows
+/// exponentially with the excess over the ideal level.
+const COMFORT_CO2_PENALTY: f32 = 10.0;
+const COMFORT_CO2_EFOLD_PPM: f32 = 1000.0;
+
+/// Indoor comfort from 0 (unbearable) to 100 (ideal):
+/// `100 - 4·|T - 70| - |RH - 50| - 10·(e^((CO2 - 800) / 1000) - 1)`, with
+/// no CO2 penalty at or below 800 ppm or when there is no CO2 sensor.
+pub fn indoor_comfort_score(temp_f: f32, humidity: u8, co2_ppm: Option<u16>) -> f32 {
+    let temp_penalty = COMFORT_TEMP_PENALTY * (temp_f - COMFORT_TEMP_F).abs();
+    let humidity_penalty = COMFORT_HUMIDITY_PENALTY * (f32::from(humidity.min(100)) - COMFORT_HUMIDITY).abs();
+    let co2_excess = co2_ppm.map_or(0.0, |ppm| (f32::from(ppm) - COMFORT_CO2_PPM).max(0.0));
+    let co2_penalty = COMFORT_CO2_PENALTY * ((co2_excess / COMFORT_CO2_EFOLD_PPM).exp() - 1.0);
+    (100.0 - temp_penalty - humidity_penalty - co2_penalty).clamp(0.0, 100.0)
+}
+
+/// Latent heat of vaporization of water in MJ/kg (FAO-56 eq. 8).
+const LATENT_HEAT_VAPORIZATION: f32 = 2.45;
+/// Specific heat of air at constant pressure in MJ/kg/°C.
+const SPECIFIC_HEAT_AIR: f32 = 1.013e-3;
+/// Ratio of the molecular weights of water vapour and dry air.
+const MOLECULAR_WEIGHT_RATIO: f32 = 0.622;
+/// Stefan-Boltzmann constant in MJ/K⁴/m²/h.
+const STEFAN_BOLTZMANN: f32 = 2.043e-10;
+/// Albedo of the FAO-56 grass reference crop.
+const ALBEDO: f32 = 0.23;
+/// MJ/m²/h per W/m².
+const MJ_PER_HOUR_PER_WATT: f32 = 0.0036;
+/// Cloudiness factor `1.35 Rs/Rso - 0.35` for net longwave radiation, fixed
+/// at mostly clear skies since clear-sky radiation needs the station's
+/// location and the sun's position.
+const CLOUDINESS_FACTOR: f32 = 0.7;
+
+/// Saturation vapour pressure in kPa at `temp_c` (FAO-56 eq. 11).
+fn saturation_vapour_pressure(temp_c: f32) -> f32 {
+    0.6108 * (17.27 * temp_c / (temp_c + 237.3)).exp()
+}
+
+/// Reference evapotranspiration in mm over one hour under the given
+/// conditions, using the FAO-56 Penman-Monteith hourly equation (eq. 53).
+/// Wind is taken as measured at 2 m and solar radiation is in W/m².
+pub fn evapotranspiration_mm(temp_c: f32, humidity: u8, wind_ms: f32, solar_radiation: f32, altitude_m: f32) -> f32 {
+    let es = saturation_vapour_pressure(temp_c);
+    let ea = es * f32::from(humidity.min(100)) / 100.0;
+    let delta = 4098.0 * es / (temp_c + 237.3).powi(2);
+    let pressure = 101.3 * ((293.0 - 0.0065 * altitude_m) / 293.0).powf(5.26);
+    let gamma = SPECIFIC_HEAT_AIR * pressure / (MOLECULAR_WEIGHT_RATIO * LATENT_HEAT_VAPORIZATION);
+
+    // Net radiation, with soil heat flux a tenth of it by day and half by night
+    let rs = solar_radiation.max(0.0) * MJ_PER_HOUR_PER_WATT;
+    let rns = (1.0 - ALBEDO) * rs;
+    let rnl = STEFAN_BOLTZMANN * (temp_c + 273.16).powi(4) * (0.34 - 0.14 * ea.sqrt()) * CLOUDINESS_FACTOR;
+    let rn = rns - rnl;
+    let g = if rs > 0.0 { 0.1 * rn } else { 0.5 * rn };
+
+    let wind = wind_ms.max(0.0);
+    let et = (0.408 * delta * (rn - g) + gamma * 37.0 / (temp_c + 273.0) * wind * (es - ea))
+        / (delta + gamma * (1.0 + 0.34 * wind));
+    et.max(0.0)
+}
+
+/// EPA PM2.5 breakpoints: concentration range in µg/m³ and the AQI range it maps to.
+static PM25_AQI_BREAKPOINTS: [(f32, f32, u16, u16); 7] = [
+    (0.0, 12.0, 0, 50),
+    (12.1, 35.4, 51, 100),
+    (35.5, 55.4, 101, 150),
+    (55.5, 150.4, 151, 200),
+    (150.5, 250.4, 201, 300),
+    (250.5, 350.4, 301, 400),
+    (350.5, 500.4, 401, 500),
+];
+
+/// US EPA air quality index for a PM2.5 concentration in µg/m³, capped at 500.
+pub fn pm25_to_aqi(pm25: f32) -> u16 {
+    // The EPA truncates concentrations to one decimal place
+    let pm25 = (pm25.max(0.0) * 10.0).floor() / 10.0;
+    PM25_AQI_BREAKPOINTS
+        .iter()
+        .find(|&&(_, c_high, _, _)| pm25 <= c_high)
+        .map_or(500, |&(c_low, c_high, i_low, i_high)| {
+            let aqi = f32::from(i_high - i_low) / (c_high - c_low) * (pm25 - c_low) + f32::from(i_low);
+            aqi.round() as u16
+        })
+}
+
+/// Indoor CO2 comfort level: 1 (good) below 800 ppm, 2 (moderate) up to
+/// 1500 ppm and 3 (poor) above.
+pub fn co2_level(ppm: u16) -> u8 {
+    match ppm {
+        0..800 => 1,
+        800..=1500 => 2,
+        _ => 3,
+    }
+}
+
+/// Leaf wetness risk for fungal disease models: 0 (dry) below 30%,
+/// 1 (moist) up to 70% and 2 (wet) above.
+pub fn leaf_wetness_risk(wetness: u8) -> u8 {
+    match wetness {
+        0..30 => 0,
+        30..=70 => 1,
+        _ => 2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Metrics;
+    use crate::{test_support, WeatherData};
+
+    /// Metrics exported in both unit systems after one push of `query`.
+    fn pushed(query: &str) -> Metrics {
+        let metrics = Metrics::new(&test_support::config(&[("STORMCAST_UNITS", "both")])).unwrap();
+        metrics.update(&WeatherData::from_query(query).unwrap()).unwrap();
+        metrics
+    }
+
+    fn close(actual: f32, expected: f32, tolerance: f32) -> bool {
+        (actual - expected).abs() <= tolerance
+    }
+
+    #[test]
+    fn dew_point_matches_known_values() {
+        let dew_point = dew_point_f(68.0, 50);
+        assert!(close(dew_point, 48.4, 0.5), "{}", dew_point);
+        let dew_point = dew_point_f(86.0, 70);
+        assert!(close(dew_point, 75.2, 0.3), "{}", dew_point);
+    }
+
+    #[test]
+    fn dew_point_edge_humidities() {
+        let saturated = dew_point_f(59.0, 100);
+        assert!(close(saturated, 59.0, 0.01), "{}", saturated);
+        let dry = dew_point_f(68.0, 0);
+        assert!(dry.is_finite() && dry < -20.0, "{}", dry);
+        assert_eq!(dew_point_f(68.0, 0), dew_point_f(68.0, 1));
+    }
+
+    #[test]
+    fn dew_point_gauges_need_temperature_and_humidity() {
+        let metrics = pushed("PASSKEY=dew&tempf=68.0&humidity=50");
+        let fahrenheit = test_support::series_value(&metrics, "weather_dew_point_fahrenheit", "dew").unwrap();
+        let celsius = test_support::series_value(&metrics, "weather_dew_point_celsius", "dew").unwrap();
+        assert!(close(fahrenheit as f32, 48.4, 0.5), "{}", fahrenheit);
+        assert!(close(celsius as f32, 9.3, 0.1), "{}", celsius);
+
+        let metrics = pushed("PASSKEY=nodew&tempf=68.0");
+        assert_eq!(test_support::series_value(&metrics, "weather_dew_point_fahrenheit", "nodew"), None);
+    }
+
+    #[test]
+    fn heat_index_matches_the_noaa_table() {
+        let heat_index = heat_index_f(90.0, 90).unwrap();
+        assert!(close(heat_index, 122.0, 1.0), "{}", heat_index);
+        let heat_index = heat_index_f(80.0, 40).unwrap();
+        assert!(close(heat_index, 80.0, 1.0), "{}", heat_index);
+    }
+
+    #[test]
+    fn heat_index_is_undefined_when_cool_or_dry() {
+        assert_eq!(heat_index_f(79.9, 90), None);
+        assert_eq!(heat_index_f(95.0, 39), None);
+
+        let metrics = pushed("PASSKEY=cool&tempf=70.0&humidity=90");
+        assert_eq!(test_support::series_value(&metrics, "weather_heat_index_fahrenheit", "cool"), None);
+        let metrics = pushed("PASSKEY=hot&tempf=90.0&humidity=90");
+        let heat_index = test_support::series_value(&metrics, "weather_heat_index_fahrenheit", "hot").unwrap();
+        assert!(close(heat_index as f32, 122.0, 1.0), "{}", heat_index);
+    }
+
+    #[test]
+    fn wind_chill_matches_the_nws_table() {
+        let wind_chill = wind_chill_f(30.0, 15.0).unwrap();
+        assert!(close(wind_chill, 19.0, 0.5), "{}", wind_chill);
+        let wind_chill = wind_chill_f(0.0, 30.0).unwrap();
+        assert!(close(wind_chill, -26.0, 0.5), "{}", wind_chill);
+    }
+
+    #[test]
+    fn wind_chill_is_undefined_when_warm_or_calm() {
+        assert_eq!(wind_chill_f(50.1, 15.0), None);
+        assert_eq!(wind_chill_f(30.0, 2.9), None);
+        assert!(wind_chill_f(50.0, 3.0).is_some());
+
+        let metrics = pushed("PASSKEY=chill&tempf=30.0&windspeedmph=15.0");
+        let wind_chill = test_support::series_value(&metrics, "weather_wind_chill_fahrenheit", "chill").unwrap();
+        assert!(close(wind_chill as f32, 19.0, 0.5), "{}", wind_chill);
+        let metrics = pushed("PASSKEY=calm&tempf=30.0&windspeedmph=1.0");
+        assert_eq!(test_support::series_value(&metrics, "weather_wind_chill_fahrenheit", "calm"), None);
+    }
+
+    #[test]
+    fn aqi_at_each_category_boundary() {
+        let boundaries = [
+            (0.0, 0),
+            (12.0, 50),
+            (12.1, 51),
+            (35.4, 100),
+            (35.5, 101),
+            (55.4, 150),
+            (55.5, 151),
+            (150.4, 200),
+            (150.5, 201),
+            (250.4, 300),
+            (250.5, 301),
+            (350.4, 400),
+            (350.5, 401),
+            (500.4, 500),
+        ];
+        for (pm25, aqi) in boundaries {
+            assert_eq!(pm25_to_aqi(pm25), aqi, "{}", pm25);
+        }
+        assert_eq!(pm25_to_aqi(12.09), 50);
+        assert_eq!(pm25_to_aqi(900.0), 500);
+        assert_eq!(pm25_to_aqi(-3.0), 0);
+    }
+
+    #[test]
+    fn particulate_gauges_are_exported() {
+        let metrics = pushed("PASSKEY=air&pm25=35.5&pm25_avg_24h=20.0&pm10=40.0");
+        assert_eq!(test_support::series_value(&metrics, "weather_pm25_ugm3", "air"), Some(35.5));
+        assert_eq!(test_support::series_value(&metrics, "weather_pm25_avg24h_ugm3", "air"), Some(20.0));
+        assert_eq!(test_support::series_value(&metrics, "weather_pm10_ugm3", "air"), Some(40.0));
+        assert_eq!(test_support::series_value(&metrics, "weather_aqi_pm25", "air"), Some(101.0));
+    }
+
+    #[test]
+    fn sea_level_pressure_follows_the_barometric_formula() {
+        assert_eq!(absolute_to_sea_level_inhg(29.5, 0.0, 59.0), 29.5);
+        let sea_level = absolute_to_sea_level_inhg(28.0, 500.0, 59.0);
+        assert!(close(sea_level, 29.70, 0.02), "{}", sea_level);
+        // Colder air is denser, so the same absolute pressure implies more at sea level
+        assert!(absolute_to_sea_level_inhg(28.0, 500.0, 14.0) > sea_level);
+        assert!(close(standard_temp_f(0.0), 59.0, 0.01));
+        assert!(close(standard_temp_f(1000.0), 47.3, 0.01));
+    }
+
+    #[test]
+    fn sea_level_pressure_fills_in_for_stations_without_it() {
+        let config = test_support::config(&[("STORMCAST_ALTITUDE_METERS", "500")]);
+        let metrics = Metrics::new(&config).unwrap();
+        metrics.update(&WeatherData::from_query("PASSKEY=abs&tempf=59.0&baromabsin=28.0").unwrap()).unwrap();
+        let relative = test_support::series_value(&metrics, "weather_barom_relative_in", "abs").unwrap();
+        assert!(close(relative as f32, 29.70, 0.02), "{}", relative);
+
+        // A reported relative pressure is kept as is
+        metrics.update(&WeatherData::from_query("PASSKEY=rel&baromrelin=30.0&baromabsin=28.0").unwrap()).unwrap();
+        assert_eq!(test_support::series_value(&metrics, "weather_barom_relative_in", "rel"), Some(30.0));
+    }
+
+    #[test]
+    fn evapotranspiration_matches_the_fao_example() {
+        // FAO-56 example 19: a hot, dry and sunny afternoon hour in Brazil
+        let et = evapotranspiration_mm(38.0, 52, 3.3, 680.0, 8.0);
+        assert!(close(et, 0.63, 0.1), "{}", et);
+        // More sun, more wind and drier air all evaporate more
+        assert!(evapotranspiration_mm(38.0, 52, 3.3, 900.0, 8.0) > et);
+        assert!(evapotranspiration_mm(38.0, 52, 6.0, 680.0, 8.0) > et);
+        assert!(evapotranspiration_mm(38.0, 30, 3.3, 680.0, 8.0) > et);
+    }
+
+    #[test]
+    fn evapotranspiration_is_never_negative() {
+        assert_eq!(evapotranspiration_mm(10.0, 100, 0.0, 0.0, 0.0), 0.0);
+        assert_eq!(evapotranspiration_mm(5.0, 100, 0.0, -20.0, 0.0), 0.0);
+
+        let metrics = pushed("PASSKEY=et&tempf=80.0&humidity=40&windspeedmph=5.0&solarradiation=500");
+        let daily = test_support::series_value(&metrics, "weather_evapotranspiration_mm_daily", "et").unwrap();
+        assert!((0.0..0.01).contains(&daily), "{}", daily);
+        let metrics = pushed("PASSKEY=noet&tempf=80.0&humidity=40");
+        assert_eq!(test_support::series_value(&metrics, "weather_evapotranspiration_mm_daily", "noet"), None);
+    }
+
+    #[test]
+    fn beaufort_force_at_each_boundary() {
+        assert_eq!(beaufort_from_mph(0.0), 0);
+        assert_eq!(beaufort_from_mph(0.9), 0);
+        for (force, &lower) in BEAUFORT_MPH.iter().enumerate() {
+            let force = force as u8 + 1;
+            assert_eq!(beaufort_from_mph(lower), force, "{} mph", lower);
+            assert_eq!(beaufort_from_mph(lower - 0.1), force - 1, "{} mph", lower - 0.1);
+        }
+        assert_eq!(beaufort_from_mph(150.0), 12);
+
+        let metrics = pushed("PASSKEY=gale&windspeedmph=40.0&windgustmph=56.0");
+        assert_eq!(test_support::series_value(&metrics, "weather_wind_beaufort_force", "gale"), Some(8.0));
+        assert_eq!(test_support::series_value(&metrics, "weather_wind_gust_beaufort_force", "gale"), Some(10.0));
+    }
+
+    #[test]
+    fn uv_risk_level_at_each_boundary() {
+        let boundaries = [(0, 0), (2, 0), (3, 1), (5, 1), (6, 2), (7, 2), (8, 3), (10, 3), (11, 4), (15, 4)];
+        for (uv, level) in boundaries {
+            assert_eq!(uv_risk_level(uv), level, "UV {}", uv);
+        }
+
+        let metrics = pushed("PASSKEY=sunny&uv=8");
+        assert_eq!(test_support::series_value(&metrics, "weather_uv_risk_level", "sunny"), Some(3.0));
+    }
+
+    #[test]
+    fn comfort_score_penalises_each_factor() {
+        assert_eq!(indoor_comfort_score(70.0, 50, None), 100.0);
+        assert_eq!(indoor_comfort_score(70.0, 50, Some(800)), 100.0);
+        assert_eq!(indoor_comfort_score(74.0, 60, None), 74.0);
+        assert_eq!(indoor_comfort_score(66.0, 40, Some(400)), 74.0);
+        let stuffy = indoor_comfort_score(70.0, 50, Some(1800));
+        assert!(close(stuffy, 82.8, 0.1), "{}", stuffy);
+        assert_eq!(indoor_comfort_score(100.0, 95, Some(5000)), 0.0);
+    }
+
+    #[test]
+    fn comfort_gauge_needs_indoor_temperature_and_humidity() {
+        let metrics = pushed("PASSKEY=comfy&tempinf=72.0&humidityin=45&co2=1800");
+        let score = test_support::series_value(&metrics, "weather_indoor_comfort_score", "comfy").unwrap();
+        assert!(close(score as f32, 69.8, 0.1), "{}", score);
+        let metrics = pushed("PASSKEY=outdoors&tempf=72.0&humidity=45");
+        assert_eq!(test_support::series_value(&metrics, "weather_indoor_comfort_score", "outdoors"), None);
+    }
+
+    #[test]
+    fn leaf_wetness_risk_at_each_boundary() {
+        for (wetness, risk) in [(0, 0), (29, 0), (30, 1), (70, 1), (71, 2), (100, 2)] {
+            assert_eq!(leaf_wetness_risk(wetness), risk, "{}%", wetness);
+        }
+    }
+
+    #[test]
+    fn leaf_wetness_is_exported_per_channel() {
+        let metrics = pushed("PASSKEY=leaf&leafwetness1=20&leafwetness3=85");
+        let channel = |name, channel| test_support::sample(&metrics, name, &[("station", "leaf"), ("channel", channel)]);
+        assert_eq!(channel("weather_leaf_wetness_percent", "1"), Some(20.0));
+        assert_eq!(channel("weather_leaf_wetness_risk", "1"), Some(0.0));
+        assert_eq!(channel("weather_leaf_wetness_percent", "3"), Some(85.0));
+        assert_eq!(channel("weather_leaf_wetness_risk", "3"), Some(2.0));
+        assert_eq!(channel("weather_leaf_wetness_percent", "2"), None);
+        assert!(WeatherData::from_query("leafwetness2=101").unwrap().validate().is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use ntex::http::header::RETRY_AFTER;
+use ntex::http::StatusCode;
+use ntex::web::{self, DefaultError, WebResponseError};
+use serde::Serialize;
+use std::time::Duration;
+
+use crate::auth::StationRejection;
+use crate::metrics::TooManyStations;
+use crate::validate::ValidationError;
+
+/// Base of the `type` URI identifying each kind of problem.
+const PROBLEM_TYPE_BASE: &str = "https://stormcastrs.example.com/errors/";
+
+/// Errors returned by request handlers, rendered as RFC 7807 problem details.
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    #[error("{0}")]
+    Parse(String),
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("a valid admin token is required")]
+    Unauthorized,
+    #[error("a valid API key is required")]
+    InvalidApiKey,
+    #[error(transparent)]
+    Forbidden(#[from] StationRejection),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("push rate too high, backing off for another {}s", .0.as_secs())]
+    RateLimited(Duration),
+    #[error("scraped too soon, retry in {}s", .0.as_secs())]
+    ScrapeTooSoon(Duration),
+    #[error("{0}")]
+    Upstream(String),
+    #[error(transparent)]
+    Validation(#[from] ValidationError),
+    #[error(transparent)]
+    TooManyStations(#[from] TooManyStations),
+    #[error("request body of {size} bytes exceeds the limit of {limit} bytes")]
+    RequestTooLarge { size: usize, limit: usize },
+    #[error("already handling the maximum of {0} connections")]
+    TooManyConnections(usize),
+    #[error("{0}")]
+    Storage(String),
+}
+
+impl AppError {
+    /// Last segment of the problem `type` URI.
+    fn kind(&self) -> &'static str {
+        match self {
+            AppError::Parse(_) => "parse",
+            AppError::BadRequest(_) => "bad-request",
+            AppError::Unauthorized => "unauthorized",
+            AppError::InvalidApiKey => "invalid-api-key",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::NotFound(_) => "not-found",
+            AppError::RateLimited(_) => "rate-limited",
+            AppError::ScrapeTooSoon(_) => "scrape-too-soon",
+            AppError::Upstream(_) => "upstream",
+            AppError::Validation(_) => "validation",
+            AppError::TooManyStations(_) => "too-many-stations",
+            AppError::RequestTooLarge { .. } => "request-too-large",
+            AppError::TooManyConnections(_) => "too-many-connections",
+            AppError::Storage(_) => "storage",
+        }
+    }
+}
+
+/// An RFC 7807 `application/problem+json` body.
+#[derive(Debug, Serialize)]
+pub struct ProblemDetail {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+}
+
+impl From<&AppError> for ProblemDetail {
+    fn from(err: &AppError) -> Self {
+        let status = WebResponseError::<DefaultError>::status_code(err);
+        ProblemDetail {
+            problem_type: format!("{}{}", PROBLEM_TYPE_BASE, err.kind()),
+            title: status.canonical_reason().unwrap_or("Error").to_string(),
+            status: status.as_u16(),
+            detail: err.to_string(),
+        }
+    }
+}
+
+impl WebResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::Parse(_) | AppError::BadRequest(_) | AppError::Validation(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized | AppError::InvalidApiKey => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::RequestTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::RateLimited(_) | AppError::ScrapeTooSoon(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            AppError::TooManyStations(_) | AppError::TooManyConnections(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self, _: &web::HttpRequest) -> web::HttpResponse {
+        let mut res = web::HttpResponse::build(WebResponseError::<DefaultError>::status_code(self));
+        if let AppError::RateLimited(retry_after) | AppError::ScrapeTooSoon(retry_after) = self {
+            // Round up so a client never retries before the backoff ends
+            let secs = (retry_after.as_secs_f64().ceil() as u64).max(1);
+            res.header(RETRY_AFTER, secs.to_string());
+        }
+        res.content_type("application/problem+json")
+            .json(&ProblemDetail::from(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use ntex::http::header::CONTENT_TYPE;
+    use ntex::web::test::{call_service, init_service, read_body, TestRequest};
+    use ntex::web::App;
+
+    #[ntex::test]
+    async fn parse_errors_are_problem_details() {
+        let app = init_service(
+            App::new()
+                .state(test_support::state(&[]))
+                .route("/push/", web::get().to(crate::handle_weather_data)),
+        )
+        .await;
+        let req = TestRequest::with_uri("/push/?PASSKEY=problem&tempf=warm").to_request();
+        let res = call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(res.headers().get(CONTENT_TYPE).unwrap(), "application/problem+json");
+        let body: serde_json::Value = serde_json::from_slice(&read_body(res).await).unwrap();
+        assert_eq!(body["status"], 400);
+        assert_eq!(body["title"], "Bad Request");
+        assert_eq!(body["type"], "https://stormcastrs.example.com/errors/parse");
+        assert!(!body["detail"].as_str().unwrap().is_empty());
+    }
+
+    #[test]
+    fn problem_detail_follows_the_status() {
+        let problem = ProblemDetail::from(&AppError::NotFound("no metadata for station x".to_string()));
+        assert_eq!(problem.status, 404);
+        assert_eq!(problem.title, "Not Found");
+        assert_eq!(problem.problem_type, "https://stormcastrs.example.com/errors/not-found");
+        assert_eq!(problem.detail, "no metadata for station x");
+    }
+}
@@ -0,0 +1,188 @@
+use ntex::http::header::CACHE_CONTROL;
+use ntex::util::{Bytes, Stream};
+use ntex::web;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{Instant, Interval};
+use tracing::{debug, info, warn};
+
+use crate::error::AppError;
+use crate::history::HistoryEntry;
+use crate::precision::PrecisionProfile;
+use crate::{AppState, WeatherData};
+
+/// Readings buffered per client before a slow one starts missing them.
+const CHANNEL_CAPACITY: usize = 64;
+/// Comment lines sent this often keep proxies from closing idle streams.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+/// How long browsers wait before reconnecting a dropped stream.
+const RETRY_MS: u64 = 5000;
+
+/// Fans accepted readings out to the clients streaming `/events`.
+#[derive(Debug)]
+pub struct EventStream {
+    sender: broadcast::Sender<String>,
+    clients: AtomicUsize,
+    max_clients: usize,
+}
+
+impl EventStream {
+    pub fn new(max_clients: usize) -> Self {
+        EventStream {
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+            clients: AtomicUsize::new(0),
+            max_clients,
+        }
+    }
+
+    /// Send `data`, received at `unix_secs` from the station named `name`,
+    /// to every connected client.
+    pub fn publish(&self, data: &WeatherData, unix_secs: i64, name: Option<String>, precision: &PrecisionProfile) {
+        if self.sender.receiver_count() == 0 {
+            return;
+        }
+        match serde_json::to_string(&HistoryEntry::new(data, unix_secs, name, precision)) {
+            Ok(event) => {
+                let _ = self.sender.send(event);
+            }
+            Err(e) => warn!("Failed to serialize reading for /events: {}", e),
+        }
+    }
+
+    /// Claim a client slot, unless `max_clients` are already connected.
+    fn connect(self: &Arc<Self>) -> Option<ClientSlot> {
+        if self.clients.fetch_add(1, Ordering::AcqRel) >= self.max_clients {
+            self.clients.fetch_sub(1, Ordering::AcqRel);
+            return None;
+        }
+        Some(ClientSlot(self.clone()))
+    }
+}
+
+/// A connected client, released when its stream ends.
+struct ClientSlot(Arc<EventStream>);
+
+impl Drop for ClientSlot {
+    fn drop(&mut self) {
+        self.0.clients.fetch_sub(1, Ordering::AcqRel);
+        info!("/events client disconnected");
+    }
+}
+
+type NextReading = Pin<Box<dyn Future<Output = (Result<String, RecvError>, broadcast::Receiver<String>)>>>;
+
+fn next_reading(mut readings: broadcast::Receiver<String>) -> NextReading {
+    Box::pin(async move { (readings.recv().await, readings) })
+}
+
+/// Response body of one `/events` client. Dropped once the client is gone,
+/// which frees its slot.
+struct SseStream {
+    retry_sent: bool,
+    reading: NextReading,
+    heartbeat: Interval,
+    _slot: ClientSlot,
+}
+
+impl Stream for SseStream {
+    type Item = Result<Bytes, io::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if !self.retry_sent {
+            self.retry_sent = true;
+            return Poll::Ready(Some(Ok(Bytes::from(format!("retry: {}\n\n", RETRY_MS)))));
+        }
+        if self.heartbeat.poll_tick(cx).is_ready() {
+            return Poll::Ready(Some(Ok(Bytes::from_static(b": heartbeat\n\n"))));
+        }
+        loop {
+            let (result, readings) = ready!(self.reading.as_mut().poll(cx));
+            self.reading = next_reading(readings);
+            match result {
+                Ok(event) => return Poll::Ready(Some(Ok(Bytes::from(format!("data: {}\n\n", event))))),
+                Err(RecvError::Lagged(missed)) => debug!("/events client fell behind and missed {} readings", missed),
+                Err(RecvError::Closed) => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+/// Stream every accepted reading as a server-sent event, with a heartbeat
+/// comment every `HEARTBEAT_INTERVAL`. A client that goes away is noticed,
+/// and its slot freed, by the next write at the latest.
+pub async fn handle_sse(state: web::types::State<AppState>) -> Result<web::HttpResponse, AppError> {
+    let Some(slot) = state.events.connect() else {
+        warn!("Refusing /events client: {} already connected", state.events.max_clients);
+        return Err(AppError::TooManyConnections(state.events.max_clients));
+    };
+    info!("/events client connected");
+    let start = Instant::now() + HEARTBEAT_INTERVAL;
+    let body = SseStream {
+        retry_sent: false,
+        reading: next_reading(state.events.sender.subscribe()),
+        heartbeat: tokio::time::interval_at(start, HEARTBEAT_INTERVAL),
+        _slot: slot,
+    };
+
+    Ok(web::HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header(CACHE_CONTROL, "no-cache")
+        .streaming(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use ntex::http::body::{BodySize, MessageBody, ResponseBody};
+    use ntex::http::StatusCode;
+    use ntex::web::test::{call_service, init_service, TestRequest};
+
+    async fn next_chunk(body: &mut ResponseBody<ntex::http::body::Body>) -> String {
+        let chunk = std::future::poll_fn(|cx| body.poll_next_chunk(cx)).await;
+        String::from_utf8(chunk.unwrap().unwrap().to_vec()).unwrap()
+    }
+
+    #[ntex::test]
+    async fn pushes_reach_connected_clients() {
+        let state = test_support::state(&[("STORMCAST_MAX_SSE_CLIENTS", "1")]);
+        let app = init_service(
+            web::App::new()
+                .state(state.clone())
+                .route("/push/", web::get().to(crate::handle_weather_data))
+                .route("/events", web::get().to(handle_sse)),
+        )
+        .await;
+
+        let mut res = call_service(&app, TestRequest::with_uri("/events").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get("content-type").unwrap(), "text/event-stream");
+        let mut body = res.take_body();
+        assert_eq!(body.size(), BodySize::Stream);
+        assert_eq!(next_chunk(&mut body).await, "retry: 5000\n\n");
+
+        // Only one client is allowed
+        let second = call_service(&app, TestRequest::with_uri("/events").to_request()).await;
+        assert_eq!(second.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let req = TestRequest::with_uri("/push/?PASSKEY=sse&tempf=61.5").to_request();
+        assert_eq!(call_service(&app, req).await.status(), StatusCode::OK);
+        let event = next_chunk(&mut body).await;
+        let json = event.strip_prefix("data: ").and_then(|e| e.strip_suffix("\n\n")).unwrap();
+        let reading: serde_json::Value = serde_json::from_str(json).unwrap();
+        assert_eq!(reading["station_id"], "sse");
+        assert_eq!(reading["tempf"], 61.5);
+
+        // Dropping the stream frees the slot
+        drop(body);
+        drop(res);
+        let again = call_service(&app, TestRequest::with_uri("/events").to_request()).await;
+        assert_eq!(again.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,82 @@
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::WeatherData;
+
+/// Where to take a reading from when its own sensor stops reporting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FallbackSource {
+    Indoor,
+}
+
+/// Fallbacks for outdoor readings, read from `STORMCAST_SENSOR_FALLBACK` as a
+/// TOML table, e.g. `outdoor_temp_fallback = "indoor"`.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FallbackConfig {
+    pub outdoor_temp_fallback: Option<FallbackSource>,
+    pub outdoor_humidity_fallback: Option<FallbackSource>,
+}
+
+/// Fills in readings missing because a sensor died so their gauges don't go stale.
+pub struct FallbackApplier;
+
+impl FallbackApplier {
+    pub fn apply(data: &mut WeatherData, config: &FallbackConfig) {
+        if data.tempf.is_none() && config.outdoor_temp_fallback == Some(FallbackSource::Indoor) {
+            if let Some(tempinf) = data.tempinf {
+                debug!("Using indoor temperature {} in place of missing tempf", tempinf);
+                data.tempf = Some(tempinf);
+            }
+        }
+        if data.humidity.is_none() && config.outdoor_humidity_fallback == Some(FallbackSource::Indoor) {
+            if let Some(humidityin) = data.humidityin {
+                debug!("Using indoor humidity {} in place of missing humidity", humidityin);
+                data.humidity = Some(humidityin);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::metrics;
+    use crate::{ingest, test_support, PushProtocol};
+
+    fn indoor_fallback() -> FallbackConfig {
+        FallbackConfig {
+            outdoor_temp_fallback: Some(FallbackSource::Indoor),
+            outdoor_humidity_fallback: Some(FallbackSource::Indoor),
+        }
+    }
+
+    #[test]
+    fn missing_outdoor_readings_use_indoor_ones() {
+        let mut data = WeatherData::from_query("tempinf=68.5&humidityin=41").unwrap();
+        FallbackApplier::apply(&mut data, &indoor_fallback());
+        assert_eq!(data.tempf, Some(68.5));
+        assert_eq!(data.humidity, Some(41));
+    }
+
+    #[test]
+    fn reported_outdoor_readings_are_kept() {
+        let mut data = WeatherData::from_query("tempf=50.0&tempinf=68.5").unwrap();
+        FallbackApplier::apply(&mut data, &indoor_fallback());
+        assert_eq!(data.tempf, Some(50.0));
+
+        let mut data = WeatherData::from_query("tempinf=68.5").unwrap();
+        FallbackApplier::apply(&mut data, &FallbackConfig::default());
+        assert_eq!(data.tempf, None);
+    }
+
+    #[ntex::test]
+    async fn push_without_tempf_sets_temperature_from_indoor() {
+        let state = test_support::state(&[("STORMCAST_SENSOR_FALLBACK", r#"outdoor_temp_fallback = "indoor""#)]);
+        let data = WeatherData::from_query("PASSKEY=fallback&tempinf=70.3").unwrap();
+        ingest(&state, crate::authorize(&state, None).unwrap(), data, PushProtocol::V1).await.unwrap();
+        let temp = test_support::series_value(metrics(), "weather_temperature_fahrenheit", "fallback");
+        assert_eq!(temp, Some(70.3));
+    }
+}
@@ -0,0 +1,79 @@
+use regex::Regex;
+use serde::Serialize;
+use std::sync::OnceLock;
+
+/// Station firmware identified from a push's `User-Agent`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FirmwareInfo {
+    pub name: String,
+    pub version: String,
+}
+
+/// User-Agent formats of known station firmware; each captures the firmware
+/// name and version.
+const PATTERNS: [&str; 5] = [
+    // Fine Offset / Ecowitt consoles: EasyWeather/1.6.8, EasyWeatherV1.6.4
+    r"^(?i)(EasyWeather)[/ ]?V?(\d+(?:\.\d+)*)",
+    // Ecowitt gateways: GW1000B_V1.7.3, GW2000A_V3.1.1
+    r"^(?i)(GW\d{4}[A-Z]?)_V(\d+(?:\.\d+)*)",
+    // Ambient Weather consoles: AMBWeatherV4.3.2, AMBWeatherPro_V5.0.6
+    r"^(?i)(AMBWeather(?:Pro)?)_?V(\d+(?:\.\d+)*)",
+    // WeeWX uploaders: weewx/4.10.2
+    r"^(?i)(weewx)/(\d+(?:\.\d+)*)",
+    // Generic Wunderground-protocol firmware: weatherstation/1.0
+    r"^(?i)(weatherstation)/(\d+(?:\.\d+)*)",
+];
+
+pub struct FirmwareVersionParser;
+
+impl FirmwareVersionParser {
+    fn patterns() -> &'static [Regex] {
+        static PARSED: OnceLock<Vec<Regex>> = OnceLock::new();
+        PARSED.get_or_init(|| {
+            PATTERNS
+                .iter()
+                .map(|pattern| Regex::new(pattern).expect("valid firmware pattern"))
+                .collect()
+        })
+    }
+
+    pub fn parse(ua: &str) -> Option<FirmwareInfo> {
+        let ua = ua.trim();
+        Self::patterns().iter().find_map(|pattern| {
+            let captures = pattern.captures(ua)?;
+            Some(FirmwareInfo {
+                name: captures[1].to_string(),
+                version: captures[2].to_string(),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(ua: &str) -> Option<(String, String)> {
+        FirmwareVersionParser::parse(ua).map(|info| (info.name, info.version))
+    }
+
+    #[test]
+    fn easyweather_name_and_version() {
+        let info = FirmwareVersionParser::parse("EasyWeather/1.6.8").unwrap();
+        assert_eq!(info.name, "EasyWeather");
+        assert_eq!(info.version, "1.6.8");
+        let components: Vec<u32> = info.version.split('.').map(|part| part.parse().unwrap()).collect();
+        assert_eq!(components, [1, 6, 8]);
+    }
+
+    #[test]
+    fn known_formats_are_recognised() {
+        let expected = |name: &str, version: &str| Some((name.to_string(), version.to_string()));
+        assert_eq!(parsed("EasyWeatherV1.6.4"), expected("EasyWeather", "1.6.4"));
+        assert_eq!(parsed("GW2000A_V3.1.1"), expected("GW2000A", "3.1.1"));
+        assert_eq!(parsed("AMBWeatherPro_V5.0.6"), expected("AMBWeatherPro", "5.0.6"));
+        assert_eq!(parsed("weewx/4.10.2"), expected("weewx", "4.10.2"));
+        assert_eq!(parsed(" weatherstation/1.0 "), expected("weatherstation", "1.0"));
+        assert_eq!(parsed("curl/8.4.0"), None);
+    }
+}
@@ -0,0 +1,63 @@
+const BASE32: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// Longest geohash accepted, about 3.7 cm × 1.9 cm.
+pub const MAX_PRECISION: usize = 12;
+
+/// Geohash of a location with `precision` characters, interleaving
+/// longitude and latitude bisections five bits per character.
+pub fn encode(latitude: f64, longitude: f64, precision: usize) -> String {
+    let mut lat = (-90.0, 90.0);
+    let mut lon = (-180.0, 180.0);
+    let mut hash = String::with_capacity(precision);
+    let mut bits = 0;
+    let mut index = 0;
+    let mut even = true;
+
+    while hash.len() < precision {
+        let (range, value) = if even { (&mut lon, longitude) } else { (&mut lat, latitude) };
+        let mid = (range.0 + range.1) / 2.0;
+        index <<= 1;
+        if value >= mid {
+            index |= 1;
+            range.0 = mid;
+        } else {
+            range.1 = mid;
+        }
+        even = !even;
+
+        bits += 1;
+        if bits == 5 {
+            hash.push(BASE32[index] as char);
+            bits = 0;
+            index = 0;
+        }
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::{ExpositionFormat, Metrics};
+    use crate::test_support;
+
+    #[test]
+    fn san_francisco_encodes_to_9q8yy() {
+        assert_eq!(encode(37.7749, -122.4194, 5), "9q8yy");
+        assert!(encode(37.7749, -122.4194, 9).starts_with("9q8yy"));
+        assert_eq!(encode(-33.8688, 151.2093, 6), "r3gx2f");
+    }
+
+    #[test]
+    fn geohash_is_a_constant_label() {
+        let config = test_support::config(&[
+            ("STORMCAST_STATION_LAT", "37.7749"),
+            ("STORMCAST_STATION_LON", "-122.4194"),
+            ("STORMCAST_GEOHASH_PRECISION", "5"),
+        ]);
+        assert_eq!(config.geohash.as_deref(), Some("9q8yy"));
+        let metrics = Metrics::new(&config).unwrap();
+        let text = String::from_utf8(metrics.encode(ExpositionFormat::Prometheus)).unwrap();
+        assert!(text.contains("geohash=\"9q8yy\""));
+    }
+}
@@ -0,0 +1,204 @@
+use prometheus::Gauge;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::metrics::MetricRegistry;
+use crate::WeatherData;
+
+/// Nearby stations whose readings are averaged together.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StationGroup {
+    pub name: String,
+    pub stations: Vec<String>,
+}
+
+/// How a field's readings are combined across a group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregation {
+    /// Arithmetic mean.
+    Mean,
+    /// Mean of the unit vectors of directions in degrees, so 350° and 10° average to 0°.
+    VectorMean,
+    /// Highest value, for flags where any station reporting one matters.
+    Max,
+}
+
+impl Aggregation {
+    /// How `field` is combined, or `None` for fields no group value makes
+    /// sense for: battery flags and the time of the last lightning strike.
+    pub fn for_field(field: &str) -> Option<Aggregation> {
+        match field {
+            "battout" | "battin" | "lightning_time" => None,
+            "winddir" | "winddir_avg10m" => Some(Aggregation::VectorMean),
+            _ if field.starts_with("waterleakage") => Some(Aggregation::Max),
+            _ => Some(Aggregation::Mean),
+        }
+    }
+
+    fn apply(self, values: &[f64]) -> f64 {
+        match self {
+            Aggregation::Mean => values.iter().sum::<f64>() / values.len() as f64,
+            Aggregation::VectorMean => {
+                let (sin, cos) = values
+                    .iter()
+                    .fold((0.0, 0.0), |(sin, cos), deg| (sin + deg.to_radians().sin(), cos + deg.to_radians().cos()));
+                // Round away floating point noise so due north isn't reported as 359.99999
+                let degrees = (sin.atan2(cos).to_degrees() * 1e6).round() / 1e6;
+                degrees.rem_euclid(360.0)
+            }
+            Aggregation::Max => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+}
+
+/// Name of the gauge, without the metric prefix, holding `group`'s value of `field`.
+pub fn gauge_name(field: &str, group: &str) -> String {
+    format!("{}_group_{}", field, group)
+}
+
+/// Combines each field across the stations of every group and exposes the
+/// result as `weather_{field}_group_{group}` gauges. A gauge is registered
+/// once a station of the group first reports its field, so groups don't
+/// export values for readings they never had.
+pub struct GroupAverager {
+    groups: Vec<StationGroup>,
+    state: Mutex<GroupState>,
+}
+
+#[derive(Default)]
+struct GroupState {
+    /// Latest value of every field, by station.
+    latest: HashMap<String, HashMap<&'static str, f64>>,
+    /// Group gauges registered so far, by group name and field.
+    gauges: HashMap<(String, &'static str), Gauge>,
+}
+
+impl GroupAverager {
+    pub fn new(groups: &[StationGroup]) -> Self {
+        GroupAverager {
+            groups: groups.to_vec(),
+            state: Mutex::new(GroupState::default()),
+        }
+    }
+
+    /// Record a push and recompute the values of the groups it belongs to,
+    /// registering gauges in `registry` for fields seen for the first time.
+    pub fn update(&self, registry: &MetricRegistry, data: &WeatherData) {
+        let Some(station) = data.station_id() else {
+            return;
+        };
+        let groups: Vec<_> = self
+            .groups
+            .iter()
+            .filter(|group| group.stations.iter().any(|s| s == station))
+            .collect();
+        if groups.is_empty() {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let GroupState { latest, gauges } = &mut *state;
+        let readings = latest.entry(station.to_string()).or_default();
+        let fields = data.fields();
+        for (field, value) in fields {
+            if let Some(value) = value {
+                readings.insert(field, value);
+            }
+        }
+
+        for group in groups {
+            for (field, value) in fields {
+                let Some(aggregation) = value.and(Aggregation::for_field(field)) else {
+                    continue;
+                };
+                let values: Vec<f64> = group
+                    .stations
+                    .iter()
+                    .filter_map(|station| latest.get(station)?.get(field).copied())
+                    .collect();
+                let key = (group.name.clone(), field);
+                let gauge = match gauges.get(&key) {
+                    Some(gauge) => gauge,
+                    None => match register_gauge(registry, field, &group.name) {
+                        Ok(gauge) => gauges.entry(key).or_insert(gauge),
+                        Err(e) => {
+                            warn!("Failed to register {} gauge for group {}: {}", field, group.name, e);
+                            continue;
+                        }
+                    },
+                };
+                gauge.set(aggregation.apply(&values));
+            }
+        }
+    }
+}
+
+fn register_gauge(registry: &MetricRegistry, field: &str, group: &str) -> prometheus::Result<Gauge> {
+    let name = gauge_name(field, group);
+    let gauge = Gauge::new(name.as_str(), format!("{} across the stations of group {}", field, group))?;
+    registry.register(Box::new(gauge.clone()))?;
+    info!("Registered gauge {} for group {}", name, group);
+    Ok(gauge)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Metrics;
+    use crate::test_support;
+
+    fn farm_metrics() -> Metrics {
+        let groups = r#"[{"name": "farm", "stations": ["s1", "s2"]}]"#;
+        Metrics::new(&test_support::config(&[("STORMCAST_GROUPS", groups)])).unwrap()
+    }
+
+    fn push(metrics: &Metrics, query: &str) {
+        metrics.update(&WeatherData::from_query(query).unwrap()).unwrap();
+    }
+
+    fn group_value(metrics: &Metrics, name: &str) -> Option<f64> {
+        let text = String::from_utf8(metrics.encode(crate::metrics::ExpositionFormat::Prometheus)).unwrap();
+        text.lines()
+            .find(|line| line.split(['{', ' ']).next() == Some(name))
+            .and_then(|line| line.rsplit(' ').next()?.parse().ok())
+    }
+
+    #[test]
+    fn group_value_is_the_mean_of_its_stations() {
+        let metrics = farm_metrics();
+        push(&metrics, "PASSKEY=s1&tempf=60.0&humidity=40");
+        push(&metrics, "PASSKEY=s2&tempf=70.0&humidity=50");
+        push(&metrics, "PASSKEY=elsewhere&tempf=100.0");
+        assert_eq!(group_value(&metrics, "weather_tempf_group_farm"), Some(65.0));
+        assert_eq!(group_value(&metrics, "weather_humidity_group_farm"), Some(45.0));
+    }
+
+    #[test]
+    fn gauges_appear_once_a_field_is_reported() {
+        let metrics = farm_metrics();
+        assert_eq!(group_value(&metrics, "weather_tempf_group_farm"), None);
+        push(&metrics, "PASSKEY=s1&tempf=60.0&battout=1&lightning_time=1700000000");
+        assert_eq!(group_value(&metrics, "weather_tempf_group_farm"), Some(60.0));
+        assert_eq!(group_value(&metrics, "weather_uv_group_farm"), None);
+        assert_eq!(group_value(&metrics, "weather_battout_group_farm"), None);
+        assert_eq!(group_value(&metrics, "weather_lightning_time_group_farm"), None);
+    }
+
+    #[test]
+    fn wind_direction_uses_the_vector_mean() {
+        let metrics = farm_metrics();
+        push(&metrics, "PASSKEY=s1&winddir=350");
+        push(&metrics, "PASSKEY=s2&winddir=10");
+        assert_eq!(group_value(&metrics, "weather_winddir_group_farm"), Some(0.0));
+        assert_eq!(Aggregation::VectorMean.apply(&[80.0, 100.0]), 90.0);
+    }
+
+    #[test]
+    fn leak_flags_use_the_maximum() {
+        assert_eq!(Aggregation::for_field("waterleakage2"), Some(Aggregation::Max));
+        assert_eq!(Aggregation::Max.apply(&[0.0, 1.0, 0.0]), 1.0);
+    }
+}
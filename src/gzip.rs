@@ -0,0 +1,335 @@
+use ntex::http::header::{ACCEPT_ENCODING, CONTENT_ENCODING, VARY};
+use ntex::web::{self, HttpRequest};
+use serde::Serialize;
+
+/// DEFLATE looks back at most this far for repeated strings.
+const WINDOW_SIZE: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// Candidates tried per position; longer chains compress slightly better
+/// at a steep cost in time.
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+/// Base length and extra bits for length symbols 257 to 285.
+const LENGTH_CODES: [(u16, u8); 29] = [
+    (3, 0), (4, 0), (5, 0), (6, 0), (7, 0), (8, 0), (9, 0), (10, 0),
+    (11, 1), (13, 1), (15, 1), (17, 1), (19, 2), (23, 2), (27, 2), (31, 2),
+    (35, 3), (43, 3), (51, 3), (59, 3), (67, 4), (83, 4), (99, 4), (115, 4),
+    (131, 5), (163, 5), (195, 5), (227, 5), (258, 0),
+];
+
+/// Base distance and extra bits for distance symbols 0 to 29.
+const DISTANCE_CODES: [(u16, u8); 30] = [
+    (1, 0), (2, 0), (3, 0), (4, 0), (5, 1), (7, 1), (9, 2), (13, 2),
+    (17, 3), (25, 3), (33, 4), (49, 4), (65, 5), (97, 5), (129, 6), (193, 6),
+    (257, 7), (385, 7), (513, 8), (769, 8), (1025, 9), (1537, 9), (2049, 10), (3073, 10),
+    (4097, 11), (6145, 11), (8193, 12), (12289, 12), (16385, 13), (24577, 13),
+];
+
+/// Whether the client accepts gzip-encoded responses.
+pub fn accepts_gzip(req: &HttpRequest) -> bool {
+    req.headers()
+        .get_all(ACCEPT_ENCODING)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|coding| {
+            let mut parts = coding.split(';').map(str::trim);
+            let name = parts.next().unwrap_or_default();
+            // A quality of zero means the coding is not acceptable
+            let refused = parts.any(|param| {
+                param
+                    .strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .is_some_and(|q| q == 0.0)
+            });
+            (name.eq_ignore_ascii_case("gzip") || name == "*") && !refused
+        })
+}
+
+/// Respond with `body`, gzip-encoded when `enabled` and the client accepts it.
+pub fn body(
+    req: &HttpRequest,
+    enabled: bool,
+    mut builder: web::HttpResponseBuilder,
+    body: Vec<u8>,
+) -> web::HttpResponse {
+    if !enabled {
+        return builder.body(body);
+    }
+    builder.header(VARY, "Accept-Encoding");
+    if accepts_gzip(req) {
+        builder.header(CONTENT_ENCODING, "gzip").body(compress(&body))
+    } else {
+        builder.body(body)
+    }
+}
+
+/// Respond with `value` as JSON, gzip-encoded when `enabled` and the client
+/// accepts it.
+pub fn json<T: Serialize>(
+    req: &HttpRequest,
+    enabled: bool,
+    mut builder: web::HttpResponseBuilder,
+    value: &T,
+) -> web::HttpResponse {
+    builder.content_type("application/json");
+    body(req, enabled, builder, serde_json::to_vec(value).expect("response serializes to JSON"))
+}
+
+/// Gzip member holding `data` as a single fixed-Huffman DEFLATE block.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    // Header: magic, deflate, no flags, no mtime, no extra flags, unknown OS
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+    out.extend_from_slice(&deflate(data));
+    out.extend_from_slice(&crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+/// Writes bits least significant first, as DEFLATE packs them.
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, bits: u32) {
+        self.buffer |= u64::from(value) << self.bits;
+        self.bits += bits;
+        while self.bits >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    /// Huffman codes are defined most significant bit first.
+    fn write_code(&mut self, code: u32, bits: u32) {
+        self.write(code.reverse_bits() >> (32 - bits), bits);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+/// Write a literal/length symbol with the fixed Huffman code.
+fn write_symbol(writer: &mut BitWriter, symbol: u16) {
+    let symbol = u32::from(symbol);
+    match symbol {
+        0..=143 => writer.write_code(0x30 + symbol, 8),
+        144..=255 => writer.write_code(0x190 + symbol - 144, 9),
+        256..=279 => writer.write_code(symbol - 256, 7),
+        _ => writer.write_code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let index = LENGTH_CODES.iter().rposition(|&(base, _)| usize::from(base) <= length).unwrap();
+    let (base, extra) = LENGTH_CODES[index];
+    write_symbol(writer, 257 + index as u16);
+    writer.write((length - usize::from(base)) as u32, u32::from(extra));
+
+    let index = DISTANCE_CODES.iter().rposition(|&(base, _)| usize::from(base) <= distance).unwrap();
+    let (base, extra) = DISTANCE_CODES[index];
+    writer.write_code(index as u32, 5);
+    writer.write((distance - usize::from(base)) as u32, u32::from(extra));
+}
+
+fn hash(bytes: &[u8]) -> usize {
+    let value = u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2]);
+    (value.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+}
+
+/// Compress `data` with greedy LZ77 matching and the fixed Huffman codes,
+/// which suits the short, repetitive text of the exposition formats.
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter {
+        out: Vec::with_capacity(data.len() / 2),
+        buffer: 0,
+        bits: 0,
+    };
+    // Final block, fixed Huffman codes
+    writer.write(1, 1);
+    writer.write(1, 2);
+
+    // Most recent position of each hash, and the previous position with the same hash
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW_SIZE];
+    let insert = |head: &mut Vec<usize>, prev: &mut Vec<usize>, pos: usize| {
+        if pos + MIN_MATCH <= data.len() {
+            let h = hash(&data[pos..]);
+            prev[pos % WINDOW_SIZE] = head[h];
+            head[h] = pos;
+        }
+    };
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let mut best = (0, 0);
+        if pos + MIN_MATCH <= data.len() {
+            let max_length = MAX_MATCH.min(data.len() - pos);
+            let mut candidate = head[hash(&data[pos..])];
+            for _ in 0..MAX_CHAIN {
+                if candidate == usize::MAX || pos - candidate > WINDOW_SIZE {
+                    break;
+                }
+                let length = data[candidate..]
+                    .iter()
+                    .zip(&data[pos..pos + max_length])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if length > best.0 {
+                    best = (length, pos - candidate);
+                    if length == max_length {
+                        break;
+                    }
+                }
+                let next = prev[candidate % WINDOW_SIZE];
+                // Entries older than the window may have been overwritten
+                if next == usize::MAX || next >= candidate {
+                    break;
+                }
+                candidate = next;
+            }
+        }
+
+        let (length, distance) = best;
+        if length >= MIN_MATCH {
+            write_match(&mut writer, length, distance);
+            for offset in 0..length {
+                insert(&mut head, &mut prev, pos + offset);
+            }
+            pos += length;
+        } else {
+            write_symbol(&mut writer, u16::from(data[pos]));
+            insert(&mut head, &mut prev, pos);
+            pos += 1;
+        }
+    }
+    write_symbol(&mut writer, 256);
+    writer.finish()
+}
+
+/// CRC-32 as used by gzip (IEEE 802.3, reflected).
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ntex::web::test::TestRequest;
+
+    /// Reads bits least significant first.
+    struct BitReader<'a> {
+        data: &'a [u8],
+        pos: usize,
+    }
+
+    impl BitReader<'_> {
+        fn bits(&mut self, count: u32) -> u32 {
+            (0..count).fold(0, |value, i| {
+                let bit = (self.data[self.pos / 8] >> (self.pos % 8)) & 1;
+                self.pos += 1;
+                value | u32::from(bit) << i
+            })
+        }
+
+        /// A Huffman code of `count` bits, most significant first.
+        fn code(&mut self, count: u32) -> u32 {
+            (0..count).fold(0, |code, _| code << 1 | self.bits(1))
+        }
+
+        /// A literal/length symbol in the fixed Huffman code.
+        fn symbol(&mut self) -> u16 {
+            let code = self.code(7);
+            if code <= 0x17 {
+                return 256 + code as u16;
+            }
+            let code = code << 1 | self.bits(1);
+            match code {
+                0x30..=0xbf => (code - 0x30) as u16,
+                0xc0..=0xc7 => (280 + code - 0xc0) as u16,
+                _ => (144 + (code << 1 | self.bits(1)) - 0x190) as u16,
+            }
+        }
+    }
+
+    /// Reference decoder for a gzip member of one fixed-Huffman block,
+    /// checking the trailer.
+    fn decompress(gzip: &[u8]) -> Vec<u8> {
+        assert_eq!(gzip[..4], [0x1f, 0x8b, 8, 0]);
+        let mut reader = BitReader { data: &gzip[10..gzip.len() - 8], pos: 0 };
+        assert_eq!((reader.bits(1), reader.bits(2)), (1, 1), "one final fixed-Huffman block");
+        let mut out = Vec::new();
+        loop {
+            let symbol = reader.symbol();
+            match symbol {
+                0..=255 => out.push(symbol as u8),
+                256 => break,
+                _ => {
+                    let (base, extra) = LENGTH_CODES[usize::from(symbol - 257)];
+                    let length = usize::from(base) + reader.bits(u32::from(extra)) as usize;
+                    let (base, extra) = DISTANCE_CODES[reader.code(5) as usize];
+                    let distance = usize::from(base) + reader.bits(u32::from(extra)) as usize;
+                    for _ in 0..length {
+                        out.push(out[out.len() - distance]);
+                    }
+                }
+            }
+        }
+        let trailer = &gzip[gzip.len() - 8..];
+        assert_eq!(trailer[..4], crc32(&out).to_le_bytes());
+        assert_eq!(trailer[4..], (out.len() as u32).to_le_bytes());
+        out
+    }
+
+    #[test]
+    fn crc_matches_the_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn round_trips() {
+        let exposition = "weather_temperature_fahrenheit{station=\"gzip\"} 71.2\n".repeat(2000);
+        let bytes: Vec<u8> = (0..=255u8).cycle().take(100_000).collect();
+        let long_run = vec![b'a'; 70_000];
+        let inputs: [&[u8]; 5] = [b"", b"x", exposition.as_bytes(), &bytes, &long_run];
+        for input in inputs {
+            assert_eq!(decompress(&compress(input)), input);
+        }
+        assert!(compress(exposition.as_bytes()).len() < exposition.len() / 10);
+    }
+
+    #[test]
+    fn gzip_only_when_accepted() {
+        let accepts = |value: &str| accepts_gzip(&TestRequest::default().header(ACCEPT_ENCODING, value).to_http_request());
+        assert!(accepts("gzip"));
+        assert!(accepts("deflate, GZIP;q=0.5"));
+        assert!(accepts("*"));
+        assert!(!accepts("gzip;q=0"));
+        assert!(!accepts("br, deflate"));
+        assert!(!accepts_gzip(&TestRequest::default().to_http_request()));
+
+        let req = TestRequest::default().header(ACCEPT_ENCODING, "gzip").to_http_request();
+        let res = body(&req, true, web::HttpResponse::Ok(), b"plain".to_vec());
+        assert_eq!(res.headers().get(CONTENT_ENCODING).unwrap(), "gzip");
+        assert_eq!(res.headers().get(VARY).unwrap(), "Accept-Encoding");
+        let res = body(&req, false, web::HttpResponse::Ok(), b"plain".to_vec());
+        assert!(res.headers().get(CONTENT_ENCODING).is_none());
+    }
+}
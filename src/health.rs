@@ -0,0 +1,100 @@
+use ntex::web;
+use std::time::Duration;
+
+use crate::metrics::metrics;
+use crate::{shutdown, AppState};
+
+/// Readings older than this no longer count as fresh.
+const FRESH_AGE_SECS: f64 = 300.0;
+/// How often the score is recomputed when no pushes arrive.
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+/// How often stations are checked for having gone stale.
+const STALE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+const BATTERY_WEIGHT: f64 = 0.33;
+const FRESHNESS_WEIGHT: f64 = 0.33;
+const QUALITY_WEIGHT: f64 = 0.34;
+
+/// Combines battery state, data freshness and field completeness into a
+/// single 0.0–1.0 score, so one alert rule can cover a degraded station.
+#[derive(Debug, Default)]
+pub struct HealthScoreCalculator;
+
+impl HealthScoreCalculator {
+    /// Score a station from whether its outdoor battery is good, the field
+    /// completeness of its last reading and that reading's age.
+    pub fn compute(&self, battery_ok: bool, data_quality: f64, age_seconds: f64) -> f64 {
+        let mut score = 0.0;
+        if battery_ok {
+            score += BATTERY_WEIGHT;
+        }
+        if age_seconds < FRESH_AGE_SECS {
+            score += FRESHNESS_WEIGHT;
+        }
+        score += QUALITY_WEIGHT * data_quality;
+        score.clamp(0.0, 1.0)
+    }
+}
+
+/// Recompute health scores periodically so it decays when pushes stop.
+pub async fn refresh_loop() {
+    loop {
+        ntex::time::sleep(REFRESH_INTERVAL).await;
+        metrics().refresh_health_scores();
+    }
+}
+
+/// Set the readings of stations that stop pushing for `timeout` to NaN.
+pub async fn stale_loop(timeout: Duration) {
+    loop {
+        ntex::time::sleep(STALE_CHECK_INTERVAL).await;
+        metrics().expire_stale(timeout);
+    }
+}
+
+/// Why the server is not healthy: shutting down, or no station has
+/// updated within `stale_threshold`.
+pub fn check(stale_threshold: Duration) -> Result<(), String> {
+    if shutdown::is_shutting_down() {
+        return Err("Shutting down".to_string());
+    }
+    match metrics().last_update_age() {
+        Some(age) if age <= stale_threshold => Ok(()),
+        Some(age) => Err(format!("Last update {}s ago", age.as_secs())),
+        None => Err("No data received yet".to_string()),
+    }
+}
+
+/// Liveness check: 503 once no station has updated within the stale
+/// threshold, or while the server is shutting down.
+pub async fn handle_health(state: web::types::State<AppState>) -> web::HttpResponse {
+    match check(state.config.stale_threshold) {
+        Ok(()) => web::HttpResponse::Ok().body("OK"),
+        Err(reason) => web::HttpResponse::ServiceUnavailable().body(reason),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn healthy_station_scores_one() {
+        let score = HealthScoreCalculator.compute(true, 1.0, 10.0);
+        assert!((score - 1.0).abs() < 1e-9, "{}", score);
+    }
+
+    #[test]
+    fn low_battery_stale_station_scores_at_most_a_third() {
+        let score = HealthScoreCalculator.compute(false, 0.9, 3600.0);
+        assert!(score <= 0.33, "{}", score);
+        assert_eq!(HealthScoreCalculator.compute(false, 0.0, 3600.0), 0.0);
+    }
+
+    #[test]
+    fn freshness_ends_at_five_minutes() {
+        let fresh = HealthScoreCalculator.compute(true, 0.5, FRESH_AGE_SECS - 1.0);
+        let stale = HealthScoreCalculator.compute(true, 0.5, FRESH_AGE_SECS);
+        assert!((fresh - stale - FRESHNESS_WEIGHT).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,203 @@
+use ntex::web;
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use crate::calendar;
+use crate::gzip;
+use crate::precision::PrecisionProfile;
+use crate::{AppState, WeatherData};
+
+/// Fixed-capacity queue that drops its oldest entry to make room for a new one.
+#[derive(Debug)]
+pub struct RingBuffer<T> {
+    entries: VecDeque<T>,
+    capacity: usize,
+}
+
+impl<T> RingBuffer<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, entry: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// The newest `limit` entries, oldest first.
+    pub fn last(&self, limit: usize) -> impl Iterator<Item = &T> {
+        self.entries.iter().skip(self.entries.len().saturating_sub(limit))
+    }
+}
+
+/// Recently accepted pushes from all stations, so recent history can be
+/// fetched without a time-series database.
+#[derive(Debug)]
+pub struct ReadingHistory {
+    readings: RwLock<RingBuffer<(i64, WeatherData)>>,
+}
+
+impl ReadingHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            readings: RwLock::new(RingBuffer::new(capacity)),
+        }
+    }
+
+    /// Remember `data`, received at `unix_secs`.
+    pub fn record(&self, data: &WeatherData, unix_secs: i64) {
+        self.readings.write().unwrap().push((unix_secs, data.clone()));
+    }
+
+    pub fn clear(&self) {
+        self.readings.write().unwrap().clear();
+    }
+}
+
+/// A reading as returned by `/history` and streamed by `/events`, with
+/// fields in push order.
+pub struct HistoryEntry<'a> {
+    timestamp: String,
+    station_id: Option<&'a str>,
+    /// Name from the station's metadata, if set.
+    name: Option<String>,
+    fields: Vec<(&'static str, f64)>,
+}
+
+impl<'a> HistoryEntry<'a> {
+    pub fn new(data: &'a WeatherData, received_at: i64, name: Option<String>, precision: &PrecisionProfile) -> Self {
+        HistoryEntry {
+            timestamp: calendar::format_datetime(received_at),
+            station_id: data.station_id(),
+            name,
+            fields: data.to_response(precision),
+        }
+    }
+}
+
+impl Serialize for HistoryEntry<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.fields.len() + 3))?;
+        map.serialize_entry("timestamp", &self.timestamp)?;
+        map.serialize_entry("station_id", &self.station_id)?;
+        map.serialize_entry("name", &self.name)?;
+        for (name, value) in &self.fields {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    limit: Option<usize>,
+}
+
+/// The last `limit` readings as a JSON array, oldest first; all retained
+/// readings when `limit` is omitted or larger than the history.
+pub async fn handle_history(
+    req: web::HttpRequest,
+    state: web::types::State<AppState>,
+    query: web::types::Query<HistoryQuery>,
+) -> web::HttpResponse {
+    let readings = state.history.readings.read().unwrap();
+    let entries: Vec<_> = readings
+        .last(query.limit.unwrap_or(usize::MAX))
+        .map(|(received_at, data)| {
+            HistoryEntry::new(data, *received_at, state.station_metadata_name(data), &state.config.precision)
+        })
+        .collect();
+    gzip::json(&req, state.config.compress_metrics, web::HttpResponse::Ok(), &entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use ntex::web::test;
+
+    #[test]
+    fn oldest_entries_are_dropped_at_capacity() {
+        let mut buffer = RingBuffer::new(3);
+        for entry in 1..=5 {
+            buffer.push(entry);
+        }
+        assert_eq!(buffer.last(usize::MAX).copied().collect::<Vec<_>>(), [3, 4, 5]);
+        assert_eq!(buffer.last(2).copied().collect::<Vec<_>>(), [4, 5]);
+        buffer.clear();
+        assert_eq!(buffer.last(usize::MAX).count(), 0);
+
+        let mut disabled = RingBuffer::new(0);
+        disabled.push(1);
+        assert_eq!(disabled.last(usize::MAX).count(), 0);
+    }
+
+    #[ntex::test]
+    async fn history_returns_the_newest_readings() {
+        let app = test::init_service(
+            web::App::new()
+                .state(test_support::state(&[("STORMCAST_HISTORY_SIZE", "2")]))
+                .route("/push/", web::get().to(crate::handle_weather_data))
+                .route("/history", web::get().to(handle_history)),
+        )
+        .await;
+        for temp in ["60.0", "61.0", "62.0"] {
+            let uri = format!("/push/?PASSKEY=history&tempf={}", temp);
+            test::call_service(&app, test::TestRequest::with_uri(&uri).to_request()).await;
+        }
+
+        let history = |uri: &'static str| {
+            let app = &app;
+            async move {
+                let res = test::call_service(app, test::TestRequest::with_uri(uri).to_request()).await;
+                serde_json::from_slice::<Vec<serde_json::Value>>(&test::read_body(res).await).unwrap()
+            }
+        };
+        let entries = history("/history?limit=10").await;
+        let temps: Vec<f64> = entries.iter().map(|entry| entry["tempf"].as_f64().unwrap()).collect();
+        assert_eq!(temps, [61.0, 62.0]);
+        assert_eq!(entries[1]["station_id"], "history");
+        assert!(entries[1]["name"].is_null());
+        assert!(entries[1]["timestamp"].as_str().unwrap().ends_with('Z'));
+        assert!(entries[1].get("humidity").is_none());
+
+        let entries = history("/history?limit=1").await;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["tempf"], 62.0);
+    }
+
+    #[ntex::test]
+    async fn history_entries_carry_the_metadata_name() {
+        let state = test_support::state(&[]);
+        let metadata = serde_json::from_str(r#"{"name": "Backyard"}"#).unwrap();
+        state.metadata.set("historyname", metadata).unwrap();
+        let app = test::init_service(
+            web::App::new()
+                .state(state)
+                .route("/push/", web::get().to(crate::handle_weather_data))
+                .route("/history", web::get().to(handle_history)),
+        )
+        .await;
+        let req = test::TestRequest::with_uri("/push/?PASSKEY=historyname&tempf=60.0").to_request();
+        test::call_service(&app, req).await;
+
+        let res = test::call_service(&app, test::TestRequest::with_uri("/history").to_request()).await;
+        let entries: Vec<serde_json::Value> = serde_json::from_slice(&test::read_body(res).await).unwrap();
+        assert_eq!(entries[0]["station_id"], "historyname");
+        assert_eq!(entries[0]["name"], "Backyard");
+    }
+}
@@ -0,0 +1,143 @@
+use ntex::web;
+use std::fmt::Write;
+
+use crate::metrics::metrics;
+use crate::precision::{OutputFormat, PrecisionProfile};
+use crate::WeatherData;
+
+/// Push fields reported by the indoor console and its sensors.
+const INDOOR_FIELDS: [&str; 7] = [
+    "tempinf",
+    "humidityin",
+    "battin",
+    "co2",
+    "co2_avg_24h",
+    "pm_in_temp_f",
+    "pm_in_humidity",
+];
+
+/// Measurement suffix a push field is written under.
+fn measurement(field: &str) -> &'static str {
+    if field.ends_with("rainin") {
+        "rain"
+    } else if INDOOR_FIELDS.contains(&field) {
+        "indoor"
+    } else {
+        "outdoor"
+    }
+}
+
+/// Escape a tag value for line protocol.
+fn escape_tag(value: &str) -> String {
+    value.replace('\\', "\\\\").replace(',', "\\,").replace('=', "\\=").replace(' ', "\\ ")
+}
+
+/// Write one station's latest reading as InfluxDB line protocol: one line per
+/// measurement, `{prefix}_outdoor`, `{prefix}_indoor` and `{prefix}_rain`.
+pub fn write_reading(
+    out: &mut String,
+    prefix: &str,
+    station: &str,
+    data: &WeatherData,
+    unix_secs: i64,
+    precision: &PrecisionProfile,
+) {
+    let fields = data.fields();
+    for suffix in ["outdoor", "indoor", "rain"] {
+        let values: Vec<String> = fields
+            .iter()
+            .filter(|(field, _)| measurement(field) == suffix)
+            .filter_map(|&(field, value)| {
+                value.map(|value| {
+                    format!("{}={}", field, precision.round_for_format(value as f32, field, OutputFormat::InfluxDb))
+                })
+            })
+            .collect();
+        if values.is_empty() {
+            continue;
+        }
+        let _ = writeln!(
+            out,
+            "{}_{},station={} {} {}",
+            prefix,
+            suffix,
+            escape_tag(station),
+            values.join(","),
+            unix_secs * 1_000_000_000
+        );
+    }
+}
+
+/// Each station's latest reading as InfluxDB line protocol.
+pub async fn handle_influx() -> web::HttpResponse {
+    web::HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .body(metrics().encode_influx())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Metrics;
+    use crate::test_support;
+    use std::collections::HashMap;
+
+    /// Measurement, tags, fields and timestamp of a line.
+    struct Line {
+        measurement: String,
+        tags: HashMap<String, String>,
+        fields: HashMap<String, f64>,
+        timestamp: i64,
+    }
+
+    fn parse(line: &str) -> Line {
+        let mut parts = line.split(' ');
+        let mut series = parts.next().unwrap().split(',');
+        let measurement = series.next().unwrap().to_string();
+        let pairs = |text: &str| -> Vec<(String, String)> {
+            text.split(',')
+                .map(|pair| {
+                    let (key, value) = pair.split_once('=').unwrap();
+                    (key.to_string(), value.to_string())
+                })
+                .collect()
+        };
+        let tags = series.flat_map(pairs).collect();
+        let fields = pairs(parts.next().unwrap())
+            .into_iter()
+            .map(|(key, value)| (key, value.parse().unwrap()))
+            .collect();
+        let timestamp = parts.next().unwrap().parse().unwrap();
+        Line {
+            measurement,
+            tags,
+            fields,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn readings_are_split_into_measurements() {
+        let metrics = Metrics::new(&test_support::config(&[])).unwrap();
+        let push = "PASSKEY=influx&tempf=71.25&humidity=45&tempinf=68.5&dailyrainin=0.123456";
+        metrics.update(&WeatherData::from_query(push).unwrap()).unwrap();
+
+        let lines: Vec<Line> = metrics.encode_influx().lines().map(parse).collect();
+        let measurements: Vec<&str> = lines.iter().map(|line| line.measurement.as_str()).collect();
+        assert_eq!(measurements, ["weather_outdoor", "weather_indoor", "weather_rain"]);
+        for line in &lines {
+            assert_eq!(line.tags["station"], "influx");
+            assert!(line.timestamp > 0 && line.timestamp % 1_000_000_000 == 0);
+        }
+        assert_eq!(lines[0].fields["tempf"], 71.25);
+        assert_eq!(lines[0].fields["humidity"], 45.0);
+        assert_eq!(lines[1].fields["tempinf"], 68.5);
+        // InfluxDB keeps full precision
+        assert_eq!(lines[2].fields["dailyrainin"], 0.123456);
+    }
+
+    #[test]
+    fn tag_values_are_escaped() {
+        assert_eq!(escape_tag(r"my station,a=b\c"), r"my\ station\,a\=b\\c");
+    }
+}
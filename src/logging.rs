@@ -0,0 +1,175 @@
+use serde_json::{json, Map, Value};
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::EnvFilter;
+
+use crate::calendar;
+
+/// How log lines are written, from `STORMCAST_LOG_FORMAT`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable text.
+    #[default]
+    Text,
+    /// One JSON object per line, for log aggregation systems.
+    Json,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("unknown log format {0:?}, expected text or json")]
+pub struct LogFormatError(String);
+
+impl FromStr for LogFormat {
+    type Err = LogFormatError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(LogFormatError(value.to_string())),
+        }
+    }
+}
+
+impl LogFormat {
+    /// Read `STORMCAST_LOG_FORMAT`, defaulting to text. Logging starts
+    /// before the rest of the config is loaded, so this is read on its own.
+    pub fn from_env() -> Result<LogFormat, LogFormatError> {
+        match env::var("STORMCAST_LOG_FORMAT") {
+            Ok(value) if !value.is_empty() => value.parse(),
+            _ => Ok(LogFormat::default()),
+        }
+    }
+}
+
+/// Collects an event's fields into a JSON object.
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name().to_string(), json!(format!("{:?}", value)));
+    }
+}
+
+/// Writes each event as a JSON object with `timestamp`, `level`, `message`,
+/// `target`, `file` and `line`, the event's own fields, and the enclosing
+/// spans with their fields as formatted text.
+struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        let metadata = event.metadata();
+        let mut fields = Map::new();
+        event.record(&mut JsonVisitor(&mut fields));
+
+        let mut line = Map::new();
+        line.insert("timestamp".to_string(), json!(calendar::format_datetime(calendar::now())));
+        line.insert("level".to_string(), json!(metadata.level().as_str()));
+        line.insert("message".to_string(), fields.remove("message").unwrap_or(Value::Null));
+        line.insert("target".to_string(), json!(metadata.target()));
+        line.insert("file".to_string(), json!(metadata.file()));
+        line.insert("line".to_string(), json!(metadata.line()));
+        line.insert("fields".to_string(), Value::Object(fields));
+        if let Some(scope) = ctx.event_scope() {
+            let spans: Vec<Value> = scope
+                .from_root()
+                .map(|span| {
+                    let extensions = span.extensions();
+                    let fields = extensions.get::<FormattedFields<N>>().map(|fields| fields.as_str());
+                    json!({ "name": span.name(), "fields": fields.unwrap_or_default() })
+                })
+                .collect();
+            line.insert("spans".to_string(), Value::Array(spans));
+        }
+        writeln!(writer, "{}", Value::Object(line))
+    }
+}
+
+/// Install the global subscriber, honouring `RUST_LOG` filters.
+pub fn init(format: LogFormat) {
+    let builder = tracing_subscriber::fmt().with_env_filter(EnvFilter::from_default_env());
+    match format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.with_ansi(false).event_format(JsonFormat).init(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn json_lines_carry_metadata_and_fields() {
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .event_format(JsonFormat)
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("request", method = "GET");
+            let _entered = span.enter();
+            tracing::info!(passkey = "ABC123", fields = 3, "Received data");
+        });
+
+        let output = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(output.lines().count(), 1, "{}", output);
+        let line: Value = serde_json::from_str(output.trim_end()).unwrap();
+        assert!(line["timestamp"].as_str().unwrap().ends_with('Z'));
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["message"], "Received data");
+        assert_eq!(line["target"], module_path!());
+        assert_eq!(line["file"], file!());
+        assert!(line["line"].as_u64().unwrap() > 0);
+        assert_eq!(line["fields"]["passkey"], "ABC123");
+        assert_eq!(line["fields"]["fields"], 3);
+        assert_eq!(line["spans"][0]["name"], "request");
+    }
+}
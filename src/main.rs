@@ -1,234 +1,1459 @@
+mod alert_rules;
+mod alerts;
+mod auth;
+mod calendar;
+mod config;
+mod convert;
+mod cwop;
+mod data;
+mod davis;
+mod derived;
+mod dead_letter;
+mod error;
+mod events;
+mod fallback;
+mod firmware;
+mod geohash;
+mod group;
+mod gzip;
+mod influx;
+mod logging;
+mod health;
+mod history;
+mod metrics;
+mod middleware;
+mod mqtt;
+mod openhab;
+mod openmetrics;
+mod precision;
+mod pressure_trend;
+mod push_rate;
+mod push_v2;
+mod rain;
+mod rate_limit;
+mod relay;
+mod pws;
+mod remote_write;
+mod rename;
+mod reset;
+mod rolling;
+mod schema;
+mod scrape;
+mod shutdown;
+mod simulate;
+mod slo;
+mod snappy;
+mod station;
+mod status;
+#[cfg(test)]
+mod test_support;
+mod timezone;
+mod validate;
+mod virtual_sensor;
+mod webhook;
+mod wind;
+
+use ntex::http::header::{ACCEPT, CACHE_CONTROL, CONTENT_LENGTH, USER_AGENT};
+use ntex::util::BytesMut;
 use ntex::web;
-use prometheus::{Encoder, TextEncoder, register_gauge, Gauge};
 use serde::Deserialize;
-use lazy_static::lazy_static;
 use std::collections::HashMap;
-use log::info; // For logging
-
-#[derive(Debug, Deserialize)]
-struct WeatherData {
-    tempf: f32,
-    humidity: u8,
-    windspeedmph: f32,
-    windgustmph: f32,
-    maxdailygust: f32,
-    winddir: u16,
-    winddir_avg10m: u16,
-    uv: u8,
-    solarradiation: f32,
-    hourlyrainin: f32,
-    eventrainin: f32,
-    dailyrainin: f32,
-    weeklyrainin: f32,
-    monthlyrainin: f32,
-    yearlyrainin: f32,
-    battout: u8,
-    tempinf: f32,
-    humidityin: u8,
-    baromrelin: f32,
-    baromabsin: f32,
-    battin: u8,
-}
-
-// Define Prometheus metrics
-lazy_static! {
-    static ref TEMP_GAUGE: Gauge = register_gauge!(
-        "weather_temperature_fahrenheit",
-        "Outdoor temperature in Fahrenheit"
-    )
-    .unwrap();
-    static ref HUMIDITY_GAUGE: Gauge = register_gauge!(
-        "weather_humidity_percentage",
-        "Outdoor humidity percentage"
-    )
-    .unwrap();
-    static ref WIND_SPEED_GAUGE: Gauge = register_gauge!(
-        "weather_windspeed_mph",
-        "Windspeed in miles per hour"
-    )
-    .unwrap();
-    static ref WIND_GUST_GAUGE: Gauge = register_gauge!(
-        "weather_windgust_mph",
-        "Wind gust in miles per hour"
-    )
-    .unwrap();
-    static ref MAX_DAILY_GUST_GAUGE: Gauge = register_gauge!(
-        "weather_max_daily_gust_mph",
-        "Maximum daily wind gust in miles per hour"
-    )
-    .unwrap();
-    static ref WIND_DIR_GAUGE: Gauge = register_gauge!(
-        "weather_wind_direction_degrees",
-        "Wind direction in degrees"
-    )
-    .unwrap();
-    static ref WIND_DIR_AVG10M_GAUGE: Gauge = register_gauge!(
-        "weather_wind_direction_avg10m_degrees",
-        "Wind direction averaged over 10 minutes in degrees"
-    )
-    .unwrap();
-    static ref UV_INDEX_GAUGE: Gauge = register_gauge!(
-        "weather_uv_index",
-        "UV index level"
-    )
-    .unwrap();
-    static ref SOLAR_RADIATION_GAUGE: Gauge = register_gauge!(
-        "weather_solar_radiation",
-        "Solar radiation level"
-    )
-    .unwrap();
-    static ref HOURLY_RAIN_GAUGE: Gauge = register_gauge!(
-        "weather_hourly_rain_in",
-        "Rainfall in the last hour in inches"
-    )
-    .unwrap();
-    static ref EVENT_RAIN_GAUGE: Gauge = register_gauge!(
-        "weather_event_rain_in",
-        "Rainfall for a specific event in inches"
-    )
-    .unwrap();
-    static ref DAILY_RAIN_GAUGE: Gauge = register_gauge!(
-        "weather_daily_rain_in",
-        "Daily rainfall in inches"
-    )
-    .unwrap();
-    static ref WEEKLY_RAIN_GAUGE: Gauge = register_gauge!(
-        "weather_weekly_rain_in",
-        "Weekly rainfall in inches"
-    )
-    .unwrap();
-    static ref MONTHLY_RAIN_GAUGE: Gauge = register_gauge!(
-        "weather_monthly_rain_in",
-        "Monthly rainfall in inches"
-    )
-    .unwrap();
-    static ref YEARLY_RAIN_GAUGE: Gauge = register_gauge!(
-        "weather_yearly_rain_in",
-        "Yearly rainfall in inches"
-    )
-    .unwrap();
-    static ref BATT_OUT_GAUGE: Gauge = register_gauge!(
-        "weather_battout_level",
-        "Outdoor battery level"
-    )
-    .unwrap();
-    static ref TEMP_INDOOR_GAUGE: Gauge = register_gauge!(
-        "weather_indoor_temperature_fahrenheit",
-        "Indoor temperature in Fahrenheit"
-    )
-    .unwrap();
-    static ref HUMIDITY_INDOOR_GAUGE: Gauge = register_gauge!(
-        "weather_indoor_humidity_percentage",
-        "Indoor humidity percentage"
-    )
-    .unwrap();
-    static ref BAROM_REL_GAUGE: Gauge = register_gauge!(
-        "weather_barom_relative_in",
-        "Relative barometric pressure in inches"
-    )
-    .unwrap();
-    static ref BAROM_ABS_GAUGE: Gauge = register_gauge!(
-        "weather_barom_absolute_in",
-        "Absolute barometric pressure in inches"
-    )
-    .unwrap();
-    static ref BATT_IN_GAUGE: Gauge = register_gauge!(
-        "weather_battin_level",
-        "Indoor battery level"
-    )
-    .unwrap();
-}
-
-fn round_to_places(value: f32, places: i32) -> f64 {
-    let factor = 10f32.powi(places);
-    (value * factor).round() as f64 / factor as f64
-}
-
-fn set_round_gauge(gauge: &Gauge, value: f32, places: i32) {
-    gauge.set(round_to_places(value, places));
+use std::env;
+use std::io;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{debug, info, warn}; // For logging
+
+use alert_rules::AlertEvaluator;
+use auth::{has_bearer_token, Authorized};
+use config::Config;
+use cwop::Cwop;
+use data::LatestReading;
+use dead_letter::{DeadLetterQueue, RawPayload};
+use error::AppError;
+use events::EventStream;
+use fallback::FallbackApplier;
+use firmware::FirmwareVersionParser;
+use history::ReadingHistory;
+use logging::LogFormat;
+use metrics::{metrics, ExpositionFormat, Metrics};
+use precision::{OutputFormat, PrecisionProfile};
+use push_rate::{PushRateMonitor, PushVerdict};
+use rate_limit::RateLimiter;
+use relay::Relay;
+use rename::FieldRenamer;
+use reset::ResetDetector;
+use schema::SchemaDiscoverer;
+use scrape::ScrapeLimiter;
+use status::ServerStats;
+use station::{MetadataStore, StationHardware};
+use webhook::Webhook;
+
+/// State shared by all server workers.
+#[derive(Clone)]
+struct AppState {
+    config: Arc<Config>,
+    push_rate: Arc<PushRateMonitor>,
+    resets: Arc<ResetDetector>,
+    metadata: Arc<MetadataStore>,
+    scrapes: Arc<ScrapeLimiter>,
+    dead_letters: Option<Arc<DeadLetterQueue>>,
+    webhook: Option<Arc<Webhook>>,
+    schema: Arc<SchemaDiscoverer>,
+    latest: Arc<LatestReading>,
+    history: Arc<ReadingHistory>,
+    events: Arc<EventStream>,
+    stats: Arc<ServerStats>,
+    relays: Arc<Vec<Box<dyn Relay>>>,
+    alerts: Option<Arc<AlertEvaluator>>,
+    cwop: Option<Arc<Cwop>>,
+}
+
+impl AppState {
+    fn new(config: Config) -> AppState {
+        AppState {
+            push_rate: Arc::new(PushRateMonitor::new()),
+            resets: Arc::new(ResetDetector::new(config.station_timezone.clone())),
+            metadata: Arc::new(MetadataStore::new(config.state_file.clone())),
+            scrapes: Arc::new(ScrapeLimiter::new(config.min_scrape_interval)),
+            dead_letters: config
+                .dead_letter_dir
+                .clone()
+                .map(|dir| Arc::new(DeadLetterQueue::new(dir, config.max_dead_letters))),
+            webhook: config
+                .webhook_url
+                .clone()
+                .map(|url| Arc::new(Webhook::new(url, config.webhook_threshold_pct))),
+            schema: Arc::new(SchemaDiscoverer::default()),
+            latest: Arc::new(LatestReading::default()),
+            history: Arc::new(ReadingHistory::new(config.history_size)),
+            events: Arc::new(EventStream::new(config.max_sse_clients)),
+            stats: Arc::new(ServerStats::new()),
+            relays: Arc::new(config.forward.relays()),
+            cwop: config.forward.cwop().map(Arc::new),
+            alerts: (!config.alert_rules.is_empty()).then(|| Arc::new(AlertEvaluator::new(config.alert_rules.clone()))),
+            config: Arc::new(config),
+        }
+    }
+
+    /// The name set in the metadata of the station that sent `data`.
+    fn station_metadata_name(&self, data: &WeatherData) -> Option<String> {
+        self.metadata.name(data.station_id()?)
+    }
+}
+
+/// Define `WeatherData` and its `fields` table from one list of sensor
+/// fields, each named after its push parameter. The `core` ones are those
+/// every station reports, which `completeness` counts.
+macro_rules! weather_data {
+    (
+        $(#[$meta:meta])*
+        pub struct WeatherData {
+            $($(#[$id_meta:meta])* pub $id:ident: $id_ty:ty,)*
+            core {
+                $($(#[$core_meta:meta])* $core:ident: $core_ty:ty,)*
+            }
+            optional {
+                $($(#[$opt_meta:meta])* $opt:ident: $opt_ty:ty,)*
+            }
+            $($(#[$rest_meta:meta])* pub $rest:ident: $rest_ty:ty,)*
+        }
+    ) => {
+        $(#[$meta])*
+        pub struct WeatherData {
+            $($(#[$id_meta])* pub $id: $id_ty,)*
+            $($(#[$core_meta])* pub $core: Option<$core_ty>,)*
+            $($(#[$opt_meta])* pub $opt: Option<$opt_ty>,)*
+            $($(#[$rest_meta])* pub $rest: $rest_ty,)*
+        }
+
+        /// Number of leading entries of `WeatherData::fields` every station reports.
+        const CORE_FIELDS: usize = [$(stringify!($core)),*].len();
+        /// Number of entries of `WeatherData::fields`.
+        const SENSOR_FIELDS: usize = CORE_FIELDS + [$(stringify!($opt)),*].len();
+
+        impl WeatherData {
+            /// Known sensor fields by push parameter name, with their values if present.
+            pub fn fields(&self) -> [(&'static str, Option<f64>); SENSOR_FIELDS] {
+                [
+                    $((stringify!($core), self.$core.map(SensorValue::to_f64)),)*
+                    $((stringify!($opt), self.$opt.map(SensorValue::to_f64)),)*
+                ]
+            }
+        }
+    };
+}
+
+/// A sensor reading's value as the `f64` `WeatherData::fields` reports.
+trait SensorValue {
+    fn to_f64(self) -> f64;
+}
+
+impl SensorValue for f32 {
+    fn to_f64(self) -> f64 {
+        f64::from(self)
+    }
+}
+
+impl SensorValue for u8 {
+    fn to_f64(self) -> f64 {
+        f64::from(self)
+    }
+}
+
+impl SensorValue for u16 {
+    fn to_f64(self) -> f64 {
+        f64::from(self)
+    }
+}
+
+impl SensorValue for u32 {
+    fn to_f64(self) -> f64 {
+        f64::from(self)
+    }
+}
+
+impl SensorValue for i64 {
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+weather_data! {
+    #[derive(Debug, Default, Clone, Deserialize)]
+    pub struct WeatherData {
+        #[serde(rename = "PASSKEY")]
+        pub passkey: Option<String>,
+        pub stationid: Option<String>,
+        /// Station model and firmware as the station reports them, e.g.
+        /// `GW2000B_V2.1.4` or `AMBWeatherV4.2.9`.
+        pub stationtype: Option<String>,
+        /// Sensor radio band Ecowitt gateways report, e.g. `868M`.
+        pub freq: Option<String>,
+        core {
+            tempf: f32,
+            humidity: u8,
+            windspeedmph: f32,
+            windgustmph: f32,
+            maxdailygust: f32,
+            winddir: u16,
+            winddir_avg10m: u16,
+            uv: u8,
+            solarradiation: f32,
+            hourlyrainin: f32,
+            eventrainin: f32,
+            dailyrainin: f32,
+            weeklyrainin: f32,
+            monthlyrainin: f32,
+            yearlyrainin: f32,
+            battout: u8,
+            tempinf: f32,
+            humidityin: u8,
+            baromrelin: f32,
+            baromabsin: f32,
+            battin: u8,
+        }
+        optional {
+            visibility_km: f32,
+            visibility_miles: f32,
+            soiltempc1: f32,
+            soiltempc2: f32,
+            soiltempc3: f32,
+            soiltempc4: f32,
+            soilmoisture1: u8,
+            soilmoisture2: u8,
+            soilmoisture3: u8,
+            soilmoisture4: u8,
+            temp1f: f32,
+            temp2f: f32,
+            temp3f: f32,
+            temp4f: f32,
+            temp5f: f32,
+            temp6f: f32,
+            temp7f: f32,
+            temp8f: f32,
+            humidity1: u8,
+            humidity2: u8,
+            humidity3: u8,
+            humidity4: u8,
+            humidity5: u8,
+            humidity6: u8,
+            humidity7: u8,
+            humidity8: u8,
+            /// Probe temperatures from WH65-style sensors, which Ecowitt names
+            /// apart from the `temp1f` channels.
+            tf_ch1: f32,
+            tf_ch2: f32,
+            tf_ch3: f32,
+            tf_ch4: f32,
+            humi_ch1: u8,
+            humi_ch2: u8,
+            humi_ch3: u8,
+            humi_ch4: u8,
+            leafwetness1: u8,
+            leafwetness2: u8,
+            leafwetness3: u8,
+            leafwetness4: u8,
+            leafwetness5: u8,
+            leafwetness6: u8,
+            leafwetness7: u8,
+            leafwetness8: u8,
+            /// Water leak sensors, 1 when wet and 0 when dry.
+            waterleakage1: u8,
+            waterleakage2: u8,
+            waterleakage3: u8,
+            waterleakage4: u8,
+            pm25: f32,
+            pm25_avg_24h: f32,
+            pm10: f32,
+            co2: u16,
+            co2_avg_24h: u16,
+            pm_in_temp_f: f32,
+            pm_in_humidity: u8,
+            /// Distance to the last lightning strike in km, from an Ecowitt WH57.
+            lightning: f32,
+            /// Strikes detected since the station's midnight.
+            lightning_num: u32,
+            /// Unix time of the last strike.
+            lightning_time: i64,
+        }
+        /// Observation time in UTC as `YYYY-MM-DD HH:MM:SS`, or `now`.
+        pub dateutc: Option<String>,
+        /// Fields not recognised above, by name, as received.
+        #[serde(skip)]
+        pub extra: HashMap<String, String>,
+    }
+}
+
+/// Query parameters that identify the pushing station, in order of preference.
+const STATION_ID_PARAMS: [&str; 2] = ["PASSKEY", "stationid"];
+
+/// Station identifier taken straight from raw push parameters, for use when
+/// the push could not be parsed into `WeatherData`.
+fn station_id_from_params(params: &HashMap<String, String>) -> Option<&str> {
+    STATION_ID_PARAMS
+        .iter()
+        .find_map(|name| params.get(*name))
+        .map(String::as_str)
+}
+
+impl WeatherData {
+    /// Parse a URL-encoded push, collecting unrecognised fields into `extra`.
+    pub fn from_query(query: &str) -> Result<WeatherData, serde_urlencoded::de::Error> {
+        let mut unknown = Vec::new();
+        let deserializer = serde_urlencoded::Deserializer::new(form_urlencoded::parse(query.as_bytes()));
+        let mut data: WeatherData =
+            serde_ignored::deserialize(deserializer, |path| unknown.push(path.to_string()))?;
+        data.extra = form_urlencoded::parse(query.as_bytes())
+            .filter(|(key, _)| unknown.iter().any(|name| name == key))
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect();
+        Ok(data)
+    }
+
+    /// Identifier of the pushing station: the `PASSKEY` sent by Ecowitt and
+    /// Ambient Weather firmware, or the `stationid` some other firmware uses.
+    pub fn station_id(&self) -> Option<&str> {
+        self.passkey.as_deref().or(self.stationid.as_deref())
+    }
+
+    /// Observation time from `dateutc` as Unix seconds, when the station sent one.
+    pub fn timestamp(&self) -> Option<i64> {
+        self.dateutc.as_deref().and_then(calendar::parse_datetime)
+    }
+
+    /// Present fields, rounded for JSON output.
+    pub fn to_response(&self, precision: &PrecisionProfile) -> Vec<(&'static str, f64)> {
+        self.fields()
+            .into_iter()
+            .filter_map(|(name, value)| {
+                value.map(|value| (name, precision.round_for_format(value as f32, name, OutputFormat::Json)))
+            })
+            .collect()
+    }
+
+    /// Fraction of the core sensor fields present in this reading. Optional
+    /// sensors such as visibility, soil probes, extra channels and air quality
+    /// don't count against a station.
+    pub fn completeness(&self) -> f64 {
+        let fields = &self.fields()[..CORE_FIELDS];
+        let present = fields.iter().filter(|(_, value)| value.is_some()).count();
+        present as f64 / fields.len() as f64
+    }
+}
+
+/// Push parameters from a flat JSON object with the same field names as a
+/// URL-encoded push. Values may be strings or numbers; any others are dropped.
+fn params_from_json(bytes: &[u8]) -> Result<HashMap<String, String>, AppError> {
+    let fields: HashMap<String, serde_json::Value> =
+        serde_json::from_slice(bytes).map_err(|e| AppError::Parse(format!("invalid JSON: {}", e)))?;
+    Ok(fields
+        .into_iter()
+        .filter_map(|(key, value)| match value {
+            serde_json::Value::String(value) => Some((key, value)),
+            serde_json::Value::Number(value) => Some((key, value.to_string())),
+            _ => None,
+        })
+        .collect())
+}
+
+// Drop fields that known station firmware bugs send with no usable value,
+// e.g. `winddir_avg=` from the WS-2000 or `humidity=--` from Fine Offset
+// stations with a disconnected sensor, so they don't fail deserialization.
+fn sanitize_params(params: HashMap<String, String>) -> HashMap<String, String> {
+    params
+        .into_iter()
+        .filter(|(key, value)| {
+            let value = value.trim();
+            if value.is_empty() || value == "--" {
+                debug!("Dropping field {} with unusable value {:?}", key, value);
+                metrics().sanitized_fields.inc();
+                false
+            } else {
+                true
+            }
+        })
+        .collect()
+}
+
+/// Push protocol a reading arrived over, reported on `weather_push_total`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushProtocol {
+    V1,
+    V2,
+    Ecowitt,
+    OpenHab,
+    Pws,
+    Mqtt,
+    Json,
+    Davis,
+}
+
+impl PushProtocol {
+    pub fn label(self) -> &'static str {
+        match self {
+            PushProtocol::V1 => "v1",
+            PushProtocol::V2 => "v2",
+            PushProtocol::Ecowitt => "ecowitt",
+            PushProtocol::OpenHab => "openhab",
+            PushProtocol::Pws => "pws",
+            PushProtocol::Mqtt => "mqtt",
+            PushProtocol::Json => "json",
+            PushProtocol::Davis => "davis",
+        }
+    }
 }
 
 async fn handle_weather_data(
+    req: web::HttpRequest,
+    state: web::types::State<AppState>,
     query: web::types::Query<HashMap<String, String>>,
-) -> String {
-    // Extract the underlying HashMap from the Query
-    let query_params = query.into_inner();
+) -> Result<web::HttpResponse, AppError> {
+    let raw = RawPayload::Form(req.query_string().as_bytes());
+    handle_query_push(&state, &req, query.into_inner(), raw, PushProtocol::V1).await
+}
+
+/// Receive a push sent as an `application/x-www-form-urlencoded` POST body,
+/// as some Ecowitt HP2550 firmware does.
+async fn handle_weather_data_post(
+    req: web::HttpRequest,
+    state: web::types::State<AppState>,
+    payload: web::types::Payload,
+) -> Result<web::HttpResponse, AppError> {
+    let body = read_body(&req, payload, state.config.max_body_bytes).await?;
+    let params = serde_urlencoded::from_bytes(&body).map_err(|e| {
+        warn!("Failed to parse push body: {}", e);
+        AppError::Parse(format!("invalid form body: {}", e))
+    })?;
+    handle_query_push(&state, &req, params, RawPayload::Form(&body), PushProtocol::V1).await
+}
+
+/// Hold back the response to an accepted push for `STORMCAST_PUSH_DELAY_MS`,
+/// for station firmware that misbehaves when acknowledged immediately. Only
+/// this request waits; the runtime keeps serving others.
+async fn delay_ack(state: &AppState) {
+    if !state.config.push_delay.is_zero() {
+        ntex::time::sleep(state.config.push_delay).await;
+    }
+}
+
+/// Receive a push sent as a JSON object, for DIY stations that find JSON
+/// easier to build than a query string. Fields are named as in `/push/`.
+async fn handle_weather_data_json(
+    req: web::HttpRequest,
+    state: web::types::State<AppState>,
+    payload: web::types::Payload,
+) -> Result<web::HttpResponse, AppError> {
+    let body = read_body(&req, payload, state.config.max_body_bytes).await?;
+    let params = params_from_json(&body).inspect_err(|e| warn!("Failed to parse JSON push body: {}", e))?;
+    handle_query_push(&state, &req, params, RawPayload::Json(&body), PushProtocol::Json).await
+}
+
+/// Read a request body of at most `limit` bytes. A `Content-Length` over the
+/// limit is refused before reading anything; chunked bodies are read until
+/// they pass it.
+async fn read_body(
+    req: &web::HttpRequest,
+    mut payload: web::types::Payload,
+    limit: usize,
+) -> Result<BytesMut, AppError> {
+    let length = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if let Some(size) = length.filter(|&size| size > limit) {
+        warn!("Refusing push body of {} bytes", size);
+        return Err(AppError::RequestTooLarge { size, limit });
+    }
+
+    let mut body = BytesMut::with_capacity(length.unwrap_or(0));
+    while let Some(chunk) = payload.recv().await {
+        let chunk = chunk.map_err(|e| AppError::BadRequest(format!("failed to read body: {}", e)))?;
+        if body.len() + chunk.len() > limit {
+            let size = body.len() + chunk.len();
+            warn!("Refusing push body of more than {} bytes", limit);
+            return Err(AppError::RequestTooLarge { size, limit });
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// Parse a push sent as URL query parameters and ingest it. `raw` is the
+/// payload as sent, written to the dead-letter queue if parsing fails.
+async fn handle_query_push(
+    state: &AppState,
+    req: &web::HttpRequest,
+    mut query_params: HashMap<String, String>,
+    raw: RawPayload<'_>,
+    protocol: PushProtocol,
+) -> Result<web::HttpResponse, AppError> {
+    // Reject pushes without a valid API key, keeping the key out of the logs
+    let api_key = auth::take_api_key(&mut query_params).or_else(|| auth::query_api_key(req));
+    let authorized = authorize(state, api_key.as_deref())?;
 
     // Log that we received data
-    info!("Received data: {:?}", query_params);
+    let passkey = query_params.get("PASSKEY").map_or("unknown", String::as_str);
+    info!(passkey, fields = ?query_params, "Received data");
+    state.schema.record(query_params.keys());
+
+    // Map non-standard field names onto the ones WeatherData expects
+    FieldRenamer::apply(&mut query_params, &state.config.field_renames);
+
+    // Remove fields known station firmware bugs send without a value
+    let query_params = sanitize_params(query_params);
 
     // Serialize the query parameters into a URL-encoded string
-    let query_string = serde_urlencoded::to_string(query_params).unwrap();
+    let query_string = serde_urlencoded::to_string(&query_params).unwrap();
 
     // Deserialize the query parameters into WeatherData
-    let weather_data: WeatherData = match serde_urlencoded::from_str(&query_string) {
+    let weather_data = match WeatherData::from_query(&query_string) {
         Ok(data) => data,
         Err(e) => {
             info!("Error parsing query params: {}", e);
-            return format!("Error parsing query params: {}", e);
+            let station = station_id_from_params(&query_params).unwrap_or("unknown");
+            metrics().record_push_error(station);
+            if let Some(dead_letters) = &state.dead_letters {
+                match dead_letters.write(station, &raw.redacted()) {
+                    Ok(path) => {
+                        debug!("Wrote rejected push to {}", path.display());
+                        metrics().dead_letters.inc();
+                    }
+                    Err(e) => warn!("Failed to write rejected push to the dead-letter dir: {}", e),
+                }
+            }
+            return Err(AppError::Parse(e.to_string()));
         }
     };
 
+    // Note which firmware the station runs, when its User-Agent says
+    let firmware = req
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .and_then(FirmwareVersionParser::parse);
+    if let (Some(station), Some(firmware)) = (weather_data.station_id(), firmware) {
+        state.metadata.set_firmware(state.config.station_name(station), firmware);
+    }
+
+    ingest(state, authorized, weather_data, protocol).await
+}
+
+/// Check the API key a push carried against `STORMCAST_API_KEY(S)`.
+fn authorize(state: &AppState, api_key: Option<&str>) -> Result<Authorized, AppError> {
+    auth::check_api_key(api_key, state.config.api_keys.as_ref()).ok_or_else(|| {
+        warn!("Rejecting push without a valid API key");
+        AppError::InvalidApiKey
+    })
+}
+
+/// Run a parsed push through rate checks and into the metrics, answering the
+/// station. Shared by all push endpoints once they have checked the push's
+/// API key and turned their payload into `WeatherData`.
+async fn ingest(
+    state: &AppState,
+    _authorized: Authorized,
+    weather_data: WeatherData,
+    protocol: PushProtocol,
+) -> Result<web::HttpResponse, AppError> {
+    let station = weather_data.station_id().map_or("unknown", |id| state.config.station_name(id)).to_string();
+    process_reading(state, weather_data, protocol)?;
+    if state.config.benchmark_mode {
+        return Ok(web::HttpResponse::Ok().body("Data received in benchmark mode"));
+    }
+
+    // Respond with success
+    let body = state.config.push_response_body.replace("{station_id}", &station);
+    delay_ack(state).await;
+    Ok(web::HttpResponse::Ok().body(body))
+}
+
+/// Check a reading and feed it to the metrics and everything else that
+/// consumes readings. Shared by the push endpoints and by readings that
+/// arrive some other way, such as MQTT or a polled gateway.
+fn process_reading(
+    state: &AppState,
+    mut weather_data: WeatherData,
+    protocol: PushProtocol,
+) -> Result<(), AppError> {
+    // Refuse stations the operator hasn't allowed or has blocked
+    if let Err(reason) = auth::check_station(
+        weather_data.passkey.as_deref(),
+        &state.config.station_allowlist,
+        &state.config.station_blocklist,
+    ) {
+        warn!("Rejecting push from station {}: {}", weather_data.station_id().unwrap_or("unknown"), reason);
+        metrics().rejected_pushes.with_label_values(&[reason.label()]).inc();
+        return Err(reason.into());
+    }
+
+    // Export the station under its assigned name, keeping PASSKEYs out of labels
+    if let Some(id) = weather_data.passkey.as_mut().or(weather_data.stationid.as_mut()) {
+        *id = state.config.station_name(id).to_string();
+    }
+
+    // In benchmark mode only count the push, so load tests measure parsing alone
+    if state.config.benchmark_mode {
+        metrics().benchmark_pushes.inc();
+        return Ok(());
+    }
+
+    // Reject readings no working sensor could produce
+    if let Err(e) = weather_data.validate() {
+        warn!("Rejecting implausible push: {}", e);
+        metrics().record_push_error(weather_data.station_id().unwrap_or("unknown"));
+        return Err(e.into());
+    }
+
+    // Stand in for readings from sensors that have stopped reporting
+    FallbackApplier::apply(&mut weather_data, &state.config.sensor_fallback);
+
     // Log the weather data
     info!("Parsed weather data: {:?}", weather_data);
 
+    // Compare the push cadence against the station's usual interval
+    let station = weather_data.station_id().unwrap_or("unknown");
+    match state.push_rate.record(station, Instant::now()) {
+        PushVerdict::Accepted { interval } => {
+            if let Some(interval) = interval {
+                metrics().push_interval.with_label_values(&[station]).set(interval.as_secs_f64());
+            }
+        }
+        PushVerdict::Anomalous { expected, elapsed } => {
+            warn!(
+                "Station {} pushed after {:?}, expected roughly every {:?}",
+                station, elapsed, expected
+            );
+            metrics().anomalous_push_rate.with_label_values(&[station]).inc();
+        }
+        PushVerdict::Throttled(retry_after) => {
+            warn!("Station {} is backed off for another {:?}", station, retry_after);
+            metrics().record_push_error(station);
+            return Err(AppError::RateLimited(retry_after));
+        }
+    }
+
+    // Note when the station's daily totals should have reset at local midnight
+    let now = weather_data.timestamp().unwrap_or_else(calendar::now);
+    if state.resets.record(station, now) {
+        info!("Station {} crossed local midnight ({})", station, state.config.station_timezone);
+        metrics().daily_resets.with_label_values(&[station]).inc();
+    }
+
     // Update Prometheus metrics with appropriate decimal places
-    set_round_gauge(&TEMP_GAUGE, weather_data.tempf, 1);               // Temperature (outdoor) with 1 decimal place
-    HUMIDITY_GAUGE.set(weather_data.humidity as f64);                  // Humidity (outdoor) no decimal places
-    set_round_gauge(&WIND_SPEED_GAUGE, weather_data.windspeedmph, 2);  // Wind speed with 2 decimal places
-    set_round_gauge(&WIND_GUST_GAUGE, weather_data.windgustmph, 2);    // Wind gust with 2 decimal places
-    set_round_gauge(&MAX_DAILY_GUST_GAUGE, weather_data.maxdailygust, 2); // Max daily gust with 2 decimal places
-    WIND_DIR_GAUGE.set(weather_data.winddir as f64);                   // Wind direction with no decimal places
-    WIND_DIR_AVG10M_GAUGE.set(weather_data.winddir_avg10m as f64);     // Wind direction (10m average) no decimal places
-    UV_INDEX_GAUGE.set(weather_data.uv as f64);                        // UV index no decimal places
-    set_round_gauge(&SOLAR_RADIATION_GAUGE, weather_data.solarradiation, 2); // Solar radiation with 2 decimal places
-
-    // Set rain-related metrics (3 decimal places)
-    set_round_gauge(&HOURLY_RAIN_GAUGE, weather_data.hourlyrainin, 3); // Hourly rain with 3 decimal places
-    set_round_gauge(&EVENT_RAIN_GAUGE, weather_data.eventrainin, 3);   // Event rain with 3 decimal places
-    set_round_gauge(&DAILY_RAIN_GAUGE, weather_data.dailyrainin, 3);   // Daily rain with 3 decimal places
-    set_round_gauge(&WEEKLY_RAIN_GAUGE, weather_data.weeklyrainin, 3); // Weekly rain with 3 decimal places
-    set_round_gauge(&MONTHLY_RAIN_GAUGE, weather_data.monthlyrainin, 3); // Monthly rain with 3 decimal places
-    set_round_gauge(&YEARLY_RAIN_GAUGE, weather_data.yearlyrainin, 3); // Yearly rain with 3 decimal places
-
-    BATT_OUT_GAUGE.set(weather_data.battout as f64);                   // Battery (outdoor) no decimal places
-    set_round_gauge(&TEMP_INDOOR_GAUGE, weather_data.tempinf, 1);      // Temperature (indoor) with 1 decimal place
-    HUMIDITY_INDOOR_GAUGE.set(weather_data.humidityin as f64);         // Humidity (indoor) no decimal places
-    set_round_gauge(&BAROM_REL_GAUGE, weather_data.baromrelin, 3);     // Relative barometric pressure with 3 decimal places
-    set_round_gauge(&BAROM_ABS_GAUGE, weather_data.baromabsin, 3);     // Absolute barometric pressure with 3 decimal places
-    BATT_IN_GAUGE.set(weather_data.battin as f64);                     // Battery (indoor) no decimal places
+    if let Err(e) = metrics().update(&weather_data) {
+        metrics().record_push_error(station);
+        return Err(e.into());
+    }
+    metrics().record_push(station, protocol);
+    if weather_data.stationtype.is_some() || weather_data.freq.is_some() {
+        state.metadata.set_hardware(
+            station,
+            StationHardware {
+                stationtype: weather_data.stationtype.clone(),
+                freq: weather_data.freq.clone(),
+            },
+        );
+    }
+    let received_at = calendar::now();
+    state.latest.set(&weather_data, received_at);
+    state.history.record(&weather_data, received_at);
+    state.stats.record_push(received_at);
+    state.events.publish(&weather_data, received_at, state.station_metadata_name(&weather_data), &state.config.precision);
 
-    // Respond with success
-    "Data received and metrics updated".to_string()
+    // Relay the reading to other weather services without holding up the station
+    for relay in state.relays.iter() {
+        relay::forward(relay.as_ref(), &weather_data);
+    }
+    if let Some(cwop) = &state.cwop {
+        cwop.submit(&weather_data);
+    }
+
+    // Notify alert webhooks of thresholds this reading crossed
+    if let Some(alerts) = &state.alerts {
+        alerts.notify(station, &weather_data);
+    }
+
+    // Pass the reading on to the webhook, if one is configured
+    if let Some(webhook) = &state.webhook {
+        webhook.notify(station, &weather_data);
+    }
+    Ok(())
 }
 
+/// Ecowitt gateways validate a custom server by sending `test_key=<value>`
+/// alone and expecting the value echoed back; anything else is a normal push.
+async fn handle_ecowitt_callback(
+    req: web::HttpRequest,
+    state: web::types::State<AppState>,
+    query: web::types::Query<HashMap<String, String>>,
+) -> Result<web::HttpResponse, AppError> {
+    if query.len() == 1 {
+        if let Some(test_key) = query.get("test_key") {
+            info!("Answering Ecowitt server validation");
+            return Ok(web::HttpResponse::Ok().body(test_key.clone()));
+        }
+    }
+    let raw = RawPayload::Form(req.query_string().as_bytes());
+    handle_query_push(&state, &req, query.into_inner(), raw, PushProtocol::Ecowitt).await
+}
 
-async fn handle_metrics() -> web::HttpResponse {
-    info!("Called metrics endpoint: {}", 1);
-    let encoder = TextEncoder::new();
-    let metric_families = prometheus::gather();
-    let mut buffer = Vec::new();
+/// Zero the benchmark push counter between load test runs.
+async fn handle_benchmark_reset(state: web::types::State<AppState>) -> Result<web::HttpResponse, AppError> {
+    if !state.config.benchmark_mode {
+        return Err(AppError::NotFound("benchmark mode is not enabled".to_string()));
+    }
+    metrics().benchmark_pushes.reset();
+    Ok(web::HttpResponse::Ok().body("Benchmark counter reset"))
+}
 
-    // Encode metrics into text format that Prometheus understands
-    encoder.encode(&metric_families, &mut buffer).unwrap();
+/// Zero all readings and clear the history without restarting, e.g. after
+/// replacing a station. Requires the admin token.
+async fn handle_admin_reset(
+    req: web::HttpRequest,
+    state: web::types::State<AppState>,
+) -> Result<web::HttpResponse, AppError> {
+    if !has_bearer_token(&req, state.config.admin_token.as_deref()) {
+        warn!("Rejected metrics reset without a valid admin token");
+        return Err(AppError::Unauthorized);
+    }
+    let caller = req.peer_addr().map(|peer| peer.ip().to_string());
+    warn!("Resetting all readings, requested by {}", caller.as_deref().unwrap_or("unknown"));
+    metrics().reset();
+    state.history.clear();
+    Ok(web::HttpResponse::Ok().body("Metrics reset"))
+}
 
+/// List every registered metric with its help, type and labels, so
+/// dashboards can be provisioned without parsing the exposition format.
+/// The list only changes when extra field gauges are registered.
+async fn handle_metric_names() -> web::HttpResponse {
     web::HttpResponse::Ok()
-        .content_type("text/plain; charset=utf-8")
-        .body(buffer)
+        .header(CACHE_CONTROL, "max-age=300")
+        .json(&metrics().describe())
+}
+
+async fn handle_metrics(
+    req: web::HttpRequest,
+    state: web::types::State<AppState>,
+) -> Result<web::HttpResponse, AppError> {
+    info!("Called metrics endpoint: {}", 1);
+    if let Some(peer) = req.peer_addr() {
+        if let Err(retry_after) = state.scrapes.check(peer.ip(), Instant::now()) {
+            warn!("Rejecting scrape from {} arriving {:?} too soon", peer.ip(), retry_after);
+            metrics().scrape_rate_limited.inc();
+            return Err(AppError::ScrapeTooSoon(retry_after));
+        }
+    }
+    let accept = req.headers().get(ACCEPT).and_then(|value| value.to_str().ok());
+    let format = ExpositionFormat::negotiate(accept);
+    let buffer = metrics().encode(format);
+
+    let mut res = web::HttpResponse::Ok();
+    res.content_type(format.content_type());
+    Ok(gzip::body(&req, state.config.compress_metrics, res, buffer))
+}
+
+/// Every endpoint but the metrics ones, served on `STORMCAST_BIND`.
+fn app_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/push/", web::get().to(handle_weather_data)) // Receive weather data
+        .route("/push/", web::post().to(handle_weather_data_post)) // Receive weather data as a form body
+        .route("/push/v2", web::post().to(push_v2::handle_push_v2)) // Receive v2 JSON pushes
+        .route("/push/json", web::post().to(handle_weather_data_json)) // Receive v1 fields as a JSON body
+        .route("/push/openhab", web::post().to(openhab::handle_push_openhab)) // Receive openHAB weather binding pushes
+        .route("/push/pws", web::get().to(pws::handle_pws_get)) // Receive PWSweather.com uploads
+        .route("/push/pws", web::post().to(pws::handle_pws_post)) // Receive PWSweather.com form uploads
+        .route("/push/ecowitt-callback", web::get().to(handle_ecowitt_callback)) // Ecowitt server validation
+        .route("/push/simulate-storm", web::post().to(simulate::handle_simulate_storm)) // Generate synthetic storm data
+        .route("/station/{id}/metadata", web::get().to(station::handle_get_metadata)) // Read station metadata
+        .route("/station/{id}/metadata", web::put().to(station::handle_put_metadata)) // Set station metadata
+        .route("/fetch/davis", web::get().to(davis::handle_fetch_davis)) // Poll the Davis gateway now
+        .route("/data", web::get().to(data::handle_data)) // Latest reading as JSON
+        .route("/history", web::get().to(history::handle_history)) // Recent readings as JSON
+        .route("/events", web::get().to(events::handle_sse)) // Stream readings as server-sent events
+        .route("/influx", web::get().to(influx::handle_influx)) // Latest readings as InfluxDB line protocol
+        .route("/schema/discovered", web::get().to(schema::handle_discovered)) // List push fields seen so far
+        .route("/alerts/battery-rules", web::get().to(alerts::handle_battery_rules)) // Generate battery alert rules
+        .route("/benchmark/reset", web::post().to(handle_benchmark_reset)) // Zero the benchmark counter
+        .route("/admin/reset", web::post().to(handle_admin_reset)) // Zero all readings
+        .route("/health", web::get().to(health::handle_health)) // Report whether readings are fresh
+        .route("/status", web::get().to(status::handle_status)); // Server statistics as JSON
+}
+
+/// The metrics endpoints, served on `STORMCAST_METRICS_BIND` when it is set
+/// and alongside the others otherwise.
+fn metrics_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/metrics/names", web::get().to(handle_metric_names)) // List registered metrics
+        .route("/metrics", web::get().to(handle_metrics));   // Expose metrics for Prometheus
+}
+
+/// Pending connections each listener queues, as `web::server` allows.
+const LISTEN_BACKLOG: i32 = 2048;
+
+/// Serve the app `factory` builds on `listeners`, as `web::server` would
+/// but with connections counted against `limit` across all listeners and
+/// workers.
+fn listen<F, I, S, B>(
+    mut builder: ntex::server::ServerBuilder,
+    listeners: Vec<std::net::TcpListener>,
+    limit: middleware::ConnectionLimit,
+    factory: F,
+) -> io::Result<ntex::server::ServerBuilder>
+where
+    F: Fn() -> I + Send + Clone + 'static,
+    I: ntex::service::IntoServiceFactory<S, ntex::http::Request, web::dev::AppConfig>,
+    S: ntex::service::ServiceFactory<ntex::http::Request, web::dev::AppConfig> + 'static,
+    S::Error: ntex::http::ResponseError,
+    S::InitError: std::fmt::Debug,
+    S::Response: Into<ntex::http::Response<B>>,
+    B: ntex::http::body::MessageBody + 'static,
+{
+    for listener in listeners {
+        let addr = listener.local_addr()?;
+        let (factory, limit) = (factory.clone(), limit.clone());
+        builder = builder.listen(format!("stormcastrs-{}", addr), listener, move |_| {
+            let config = web::dev::AppConfig::new(false, addr, addr.to_string());
+            let http = ntex::http::HttpService::build()
+                .headers_read_rate(ntex::time::Seconds(1), ntex::time::Seconds(13), 256)
+                .finish(ntex::service::map_config(factory(), move |_| config.clone()));
+            ntex::service::apply(limit.clone(), http)
+        })?;
+    }
+    Ok(builder)
 }
 
 #[ntex::main]
-async fn main() -> std::io::Result<()> {
-    // Initialize logging
-    env_logger::init();
-
-    // Start the web server
-    web::server(|| {
-        web::App::new()
-            .route("/push/", web::get().to(handle_weather_data)) // Receive weather data
-            .route("/metrics", web::get().to(handle_metrics))    // Expose metrics for Prometheus
-    })
-    .bind("0.0.0.0:8080")?
-    .run()
-    .await
+async fn main() -> io::Result<()> {
+    if env::args().nth(1).as_deref() == Some("--sample-config") {
+        print!("{}", config::SAMPLE_CONFIG);
+        return Ok(());
+    }
+
+    // Initialize logging, honouring RUST_LOG filters
+    logging::init(LogFormat::from_env().map_err(io::Error::other)?);
+
+    // Load configuration and register metrics; invalid config aborts startup
+    let config = Config::load().map_err(io::Error::other)?;
+    metrics::init(Metrics::new(&config).map_err(io::Error::other)?);
+
+    // Push metrics to a remote endpoint when Prometheus can't scrape us
+    if let Some(url) = config.remote_write_url.clone() {
+        info!("Pushing metrics to {} every {:?}", url, config.push_interval);
+        ntex::rt::spawn(remote_write::push_loop(
+            url,
+            config.remote_write_token.clone(),
+            config.push_interval,
+        ));
+    }
+
+    // Keep the health score current even when pushes stop arriving
+    ntex::rt::spawn(health::refresh_loop());
+    if let Some(timeout) = config.stale_timeout {
+        ntex::rt::spawn(health::stale_loop(timeout));
+    }
+
+    let state = AppState::new(config);
+    state.metadata.load()?;
+
+    // Poll a Davis WeatherLink Live gateway in the background when configured
+    if let Some(url) = &state.config.davis_url {
+        info!("Polling Davis WeatherLink at {} every {:?}", url, state.config.davis_poll_interval);
+        ntex::rt::spawn(davis::poll_loop(state.clone()));
+    }
+
+    // Ingest readings from gateways that publish to an MQTT broker
+    if let Some(url) = &state.config.mqtt_url {
+        info!("Subscribing to {} on MQTT broker {}", state.config.mqtt_topic, url);
+        ntex::rt::spawn(mqtt::subscribe_loop(state.clone()));
+    }
+
+    let rate_limiter = Arc::new(RateLimiter::new(state.config.rate_limit_rps));
+    let connection_limit = middleware::ConnectionLimit::new(state.config.max_connections.unwrap_or(usize::MAX));
+    let cors_origins = state.config.cors_origins.clone();
+
+    let shutdown_grace = state.config.shutdown_grace;
+    let shutdown_timeout = state.config.shutdown_timeout;
+
+    // With STORMCAST_METRICS_BIND set, /metrics is only served on that
+    // address, so it can be kept off the interface stations push to
+    let metrics_bind = state.config.metrics_bind.clone();
+    let metrics_server = match &metrics_bind {
+        Some(address) => {
+            info!("Serving metrics on {}", address);
+            let state = state.clone();
+            let listeners = ntex::server::bind_addr(address, LISTEN_BACKLOG)?;
+            let server = listen(ntex::server::build(), listeners, connection_limit.clone(), move || {
+                web::App::new()
+                    .state(state.clone())
+                    .wrap(middleware::RequestLogger)
+                    .configure(metrics_routes)
+            })?;
+            Some(server.disable_signals().shutdown_timeout(shutdown_timeout).run())
+        }
+        None => None,
+    };
+
+    // Start the web server, draining requests on SIGTERM/SIGINT ourselves so
+    // /health can report the shutdown first
+    let listeners = ntex::server::bind_addr(&state.config.bind, LISTEN_BACKLOG)?;
+    let server = listen(ntex::server::build(), listeners, connection_limit, move || {
+        let app = web::App::new()
+            .state(state.clone())
+            .wrap(middleware::RateLimit::new(rate_limiter.clone())) // Limit pushes per client IP
+            .wrap(middleware::RequestLogger)                     // Log requests within a trace span
+            .wrap(middleware::Cors::new(cors_origins.clone()))   // Allow dashboards on other origins
+            .configure(app_routes);
+        if metrics_bind.is_some() {
+            return app;
+        }
+        app.configure(metrics_routes)
+    })?;
+    let server = server.disable_signals().shutdown_timeout(shutdown_timeout).run();
+    // Stop the metrics listener first so it has drained once the main server exits
+    let servers = metrics_server.into_iter().chain([server.clone()]).collect();
+    ntex::rt::spawn(shutdown::wait_for_signal(servers, shutdown_grace));
+    server.await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ntex::http::StatusCode;
+    use ntex::web::test::{call_service, init_service, read_body, TestRequest};
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|&(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn sanitize_drops_empty_and_placeholder_values() {
+        test_support::init_metrics();
+        let sanitized = sanitize_params(params(&[
+            ("winddir_avg", ""),
+            ("humidity", "--"),
+            ("windspeedmph", "  "),
+            ("tempf", "71.2"),
+        ]));
+        assert_eq!(sanitized, params(&[("tempf", "71.2")]));
+    }
+
+    #[test]
+    fn sanitized_push_with_empty_winddir_avg_parses() {
+        test_support::init_metrics();
+        let query = "PASSKEY=sanitize&tempf=70.0&winddir_avg10m=&winddir=180";
+        assert!(WeatherData::from_query(query).is_err());
+
+        let raw = form_urlencoded::parse(query.as_bytes()).into_owned().collect();
+        let sanitized = sanitize_params(raw);
+        assert!(!sanitized.contains_key("winddir_avg10m"));
+        let data = WeatherData::from_query(&serde_urlencoded::to_string(&sanitized).unwrap()).unwrap();
+        assert_eq!(data.winddir, Some(180));
+        assert_eq!(data.winddir_avg10m, None);
+    }
+
+    fn reading(query: &str) -> WeatherData {
+        WeatherData::from_query(query).unwrap()
+    }
+
+    #[ntex::test]
+    async fn rapid_pushes_count_as_anomalous() {
+        let state = test_support::state(&[]);
+        let counter = metrics().anomalous_push_rate.with_label_values(&["rapid"]);
+        let push = || ingest(&state, authorize(&state, None).unwrap(), reading("PASSKEY=rapid&tempf=60.0"), PushProtocol::V1);
+
+        push().await.unwrap();
+        ntex::time::sleep(std::time::Duration::from_millis(200)).await;
+        push().await.unwrap();
+        assert_eq!(counter.get(), 0);
+
+        for _ in 0..3 {
+            push().await.unwrap();
+        }
+        assert_eq!(counter.get(), 3);
+    }
+
+    #[ntex::test]
+    async fn ecowitt_callback_echoes_test_key() {
+        let app = init_service(
+            web::App::new()
+                .state(test_support::state(&[]))
+                .route("/push/ecowitt-callback", web::get().to(handle_ecowitt_callback)),
+        )
+        .await;
+
+        let req = TestRequest::with_uri("/push/ecowitt-callback?test_key=abc123").to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(read_body(res).await, "abc123");
+
+        // With weather fields the request is a normal push
+        let req = TestRequest::with_uri("/push/ecowitt-callback?test_key=abc123&PASSKEY=ecowitt&tempf=50.0").to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_ne!(read_body(res).await, "abc123");
+        assert_eq!(test_support::series_value(metrics(), "weather_temperature_fahrenheit", "ecowitt"), Some(50.0));
+    }
+
+    #[ntex::test]
+    async fn pushes_are_counted_by_protocol_version() {
+        let app = init_service(
+            web::App::new()
+                .state(test_support::state(&[]))
+                .route("/push/", web::get().to(handle_weather_data))
+                .route("/push/v2", web::post().to(push_v2::handle_push_v2)),
+        )
+        .await;
+
+        let req = TestRequest::with_uri("/push/?PASSKEY=protocols&tempf=50.0").to_request();
+        assert_eq!(call_service(&app, req).await.status(), StatusCode::OK);
+        let req = TestRequest::post()
+            .uri("/push/v2")
+            .header("Content-Type", "application/json")
+            .set_payload(r#"{"station_id": "protocols", "temp_f": 51.0}"#)
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), StatusCode::OK);
+
+        for protocol in ["v1", "v2"] {
+            let labels = [("station", "protocols"), ("protocol_version", protocol)];
+            assert_eq!(test_support::sample(metrics(), "weather_push_total", &labels), Some(1.0), "{}", protocol);
+        }
+    }
+
+    #[ntex::test]
+    async fn push_response_body_defaults_to_ok() {
+        let app = init_service(
+            web::App::new()
+                .state(test_support::state(&[]))
+                .route("/push/", web::get().to(handle_weather_data)),
+        )
+        .await;
+        let res = call_service(&app, TestRequest::with_uri("/push/?PASSKEY=okbody&tempf=50.0").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(read_body(res).await, "ok");
+    }
+
+    #[ntex::test]
+    async fn push_response_body_template_names_the_station() {
+        let app = init_service(
+            web::App::new()
+                .state(test_support::state(&[("STORMCAST_PUSH_RESPONSE_BODY", "OK-{station_id}")]))
+                .route("/push/", web::get().to(handle_weather_data)),
+        )
+        .await;
+        let res = call_service(&app, TestRequest::with_uri("/push/?stationid=mystation&tempf=50.0").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(read_body(res).await, "OK-mystation");
+    }
+
+    #[ntex::test]
+    async fn benchmark_mode_only_counts_pushes() {
+        let app = init_service(
+            web::App::new()
+                .state(test_support::state(&[("STORMCAST_BENCHMARK_MODE", "true")]))
+                .route("/push/", web::get().to(handle_weather_data))
+                .route("/benchmark/reset", web::post().to(handle_benchmark_reset)),
+        )
+        .await;
+        let reset = || TestRequest::post().uri("/benchmark/reset").to_request();
+        assert_eq!(call_service(&app, reset()).await.status(), StatusCode::OK);
+
+        for _ in 0..100 {
+            let req = TestRequest::with_uri("/push/?PASSKEY=benchmark&tempf=50.0").to_request();
+            assert_eq!(call_service(&app, req).await.status(), StatusCode::OK);
+        }
+        assert_eq!(test_support::sample(metrics(), "weather_benchmark_pushes_total", &[]), Some(100.0));
+        assert_eq!(test_support::series_value(metrics(), "weather_temperature_fahrenheit", "benchmark"), None);
+
+        assert_eq!(call_service(&app, reset()).await.status(), StatusCode::OK);
+        assert_eq!(test_support::sample(metrics(), "weather_benchmark_pushes_total", &[]), Some(0.0));
+    }
+
+    #[ntex::test]
+    async fn form_encoded_post_sets_gauges() {
+        let app = init_service(
+            web::App::new()
+                .state(test_support::state(&[]))
+                .route("/push/", web::post().to(handle_weather_data_post)),
+        )
+        .await;
+        let req = TestRequest::post()
+            .uri("/push/")
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .set_payload("PASSKEY=formpost&tempf=61.5&humidity=55")
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), StatusCode::OK);
+        assert_eq!(test_support::series_value(metrics(), "weather_temperature_fahrenheit", "formpost"), Some(61.5));
+        assert_eq!(test_support::series_value(metrics(), "weather_humidity_percentage", "formpost"), Some(55.0));
+
+        let req = TestRequest::post()
+            .uri("/push/")
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .set_payload("PASSKEY=formpost&tempf=warm")
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[ntex::test]
+    async fn every_push_endpoint_requires_the_api_key() {
+        let app = init_service(
+            web::App::new()
+                .state(test_support::state(&[("STORMCAST_API_KEY", "secret")]))
+                .route("/push/", web::get().to(handle_weather_data))
+                .route("/push/", web::post().to(handle_weather_data_post))
+                .route("/push/v2", web::post().to(push_v2::handle_push_v2))
+                .route("/push/json", web::post().to(handle_weather_data_json))
+                .route("/push/openhab", web::post().to(openhab::handle_push_openhab))
+                .route("/push/pws", web::get().to(pws::handle_pws_get))
+                .route("/push/pws", web::post().to(pws::handle_pws_post))
+                .route("/push/ecowitt-callback", web::get().to(handle_ecowitt_callback)),
+        )
+        .await;
+        let form = "application/x-www-form-urlencoded";
+        let json = "application/json";
+        // Method, path, body content type and body of a push from each protocol
+        let pushes = [
+            ("GET", "/push/?PASSKEY=keyed&tempf=50.0", form, ""),
+            ("POST", "/push/", form, "PASSKEY=keyed&tempf=50.0"),
+            ("POST", "/push/v2", json, r#"{"station_id": "keyed", "temp_f": 50.0}"#),
+            ("POST", "/push/json", json, r#"{"PASSKEY": "keyed", "tempf": 50.0}"#),
+            ("POST", "/push/openhab", json, r#"{"items": [{"name": "outdoor_temperature", "state": "50.0 °F"}]}"#),
+            ("GET", "/push/pws?ID=keyed&tempf=50.0", form, ""),
+            ("POST", "/push/pws", form, "ID=keyed&tempf=50.0"),
+            ("GET", "/push/ecowitt-callback?PASSKEY=keyed&tempf=50.0", form, ""),
+        ];
+        for (method, path, content_type, body) in pushes {
+            let request = |path: String| {
+                let req = if method == "GET" { TestRequest::get() } else { TestRequest::post() };
+                req.uri(&path).header("Content-Type", content_type).set_payload(body).to_request()
+            };
+            let separator = if path.contains('?') { '&' } else { '?' };
+
+            let res = call_service(&app, request(path.to_string())).await;
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED, "{} {} without a key", method, path);
+            let res = call_service(&app, request(format!("{}{}key=wrong", path, separator))).await;
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED, "{} {} with the wrong key", method, path);
+            let res = call_service(&app, request(format!("{}{}APIKEY=secret", path, separator))).await;
+            assert_eq!(res.status(), StatusCode::OK, "{} {} with the key", method, path);
+        }
+    }
+
+    #[ntex::test]
+    async fn allowlisted_stations_are_exported_under_their_names() {
+        let passkey = "A1B2C3D4E5F60718";
+        let state = test_support::state(&[
+            ("STORMCAST_STATION_ALLOWLIST", passkey),
+            ("STORMCAST_STATION_NAMES", &format!("{}=backyard", passkey)),
+            ("STORMCAST_PUSH_RESPONSE_BODY", "ok {station_id}"),
+        ]);
+        let app = init_service(
+            web::App::new()
+                .state(state)
+                .route("/push/", web::get().to(handle_weather_data))
+                .route("/data", web::get().to(data::handle_data)),
+        )
+        .await;
+
+        let req = TestRequest::get().uri(&format!("/push/?PASSKEY={}&tempf=61.5", passkey)).to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(read_body(res).await, "ok backyard");
+        let req = TestRequest::get().uri("/push/?PASSKEY=stranger&tempf=61.5").to_request();
+        assert_eq!(call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+
+        let metrics = metrics();
+        assert_eq!(test_support::series_value(metrics, "weather_temperature_fahrenheit", "backyard"), Some(61.5));
+        let text = String::from_utf8(metrics.encode(ExpositionFormat::Prometheus)).unwrap();
+        assert!(!text.contains(passkey));
+        let body = read_body(call_service(&app, TestRequest::get().uri("/data").to_request()).await).await;
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("backyard") && !body.contains(passkey), "{}", body);
+    }
+
+    #[ntex::test]
+    async fn admin_reset_is_a_post_requiring_the_admin_token() {
+        let app = init_service(
+            web::App::new()
+                .state(test_support::state(&[("STORMCAST_ADMIN_TOKEN", "admin")]))
+                .route("/admin/reset", web::post().to(handle_admin_reset)),
+        )
+        .await;
+        // ntex answers a method with no route like an unknown path
+        let req = TestRequest::get().uri("/admin/reset").header("Authorization", "Bearer admin").to_request();
+        assert_eq!(call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+        let req = TestRequest::post().uri("/admin/reset").header("Authorization", "Bearer wrong").to_request();
+        assert_eq!(call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+        // A successful reset would zero the metrics the other tests share; see
+        // metrics::tests for what it does
+    }
+
+    #[test]
+    fn field_table_names_match_push_parameters() {
+        for (name, _) in WeatherData::default().fields() {
+            let data = WeatherData::from_query(&format!("{}=1", name)).unwrap();
+            assert!(data.extra.is_empty(), "{} was not parsed", name);
+            let set: Vec<_> = data.fields().into_iter().filter(|(_, value)| value.is_some()).collect();
+            assert_eq!(set, [(name, Some(1.0))]);
+        }
+    }
+
+    #[test]
+    fn completeness_counts_only_core_fields() {
+        assert_eq!(CORE_FIELDS, 21);
+        assert_eq!(WeatherData::default().completeness(), 0.0);
+        let data = reading("tempf=70.0&humidity=40&soiltempc1=12.0&pm25=8.0&lightning=10.0");
+        assert_eq!(data.completeness(), 2.0 / 21.0);
+    }
+
+    #[ntex::test]
+    async fn metric_names_are_served_as_cacheable_json() {
+        test_support::init_metrics();
+        let app = init_service(web::App::new().route("/metrics/names", web::get().to(handle_metric_names))).await;
+        let res = call_service(&app, TestRequest::get().uri("/metrics/names").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get(CACHE_CONTROL).unwrap(), "max-age=300");
+        let body: Vec<serde_json::Value> = serde_json::from_slice(&read_body(res).await).unwrap();
+        let names: Vec<&str> = body.iter().map(|metric| metric["name"].as_str().unwrap()).collect();
+        for name in ["weather_temperature_fahrenheit", "weather_push_total", "weather_lightning_daily_count"] {
+            assert!(names.contains(&name), "{} missing", name);
+        }
+        assert!(body.iter().all(|metric| metric["help"].is_string() && metric["labels"].is_array()));
+    }
+
+    #[test]
+    fn connections_past_the_limit_are_refused() {
+        use std::io::{Read, Write};
+        use std::net::{TcpListener, TcpStream};
+        use std::time::Duration;
+
+        test_support::init_metrics();
+        let rejected = metrics().rejected_connections.get();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            ntex::rt::System::new("connection-limit").run(move || {
+                // Two workers, so the limit must hold across them
+                let builder = ntex::server::build().workers(2).disable_signals();
+                let limit = middleware::ConnectionLimit::new(1);
+                listen(builder, vec![listener], limit, || web::App::new().route("/", web::get().to(|| async { "ok" })))?
+                    .run();
+                Ok(())
+            })
+        });
+        let request = |stream: &mut TcpStream| stream.write_all(b"GET / HTTP/1.1\r\nHost: test\r\n\r\n").unwrap();
+        let read = |stream: &mut TcpStream| {
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            let mut buf = [0; 1024];
+            let n = stream.read(&mut buf).unwrap();
+            String::from_utf8_lossy(&buf[..n]).into_owned()
+        };
+
+        // The first connection is served and kept alive
+        let mut first = TcpStream::connect(address).unwrap();
+        request(&mut first);
+        assert!(read(&mut first).starts_with("HTTP/1.1 200"));
+
+        // The second is answered with 503 and closed
+        let mut second = TcpStream::connect(address).unwrap();
+        request(&mut second);
+        let mut refused = String::new();
+        second.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        second.read_to_string(&mut refused).unwrap();
+        assert!(refused.starts_with("HTTP/1.1 503 Service Unavailable\r\n"), "{}", refused);
+        assert!(refused.contains("too-many-connections"), "{}", refused);
+        assert_eq!(metrics().rejected_connections.get(), rejected + 1);
+        assert_eq!(metrics().active_connections.get(), 1.0);
+
+        // Closing the first frees its slot
+        drop(first);
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        loop {
+            let mut third = TcpStream::connect(address).unwrap();
+            request(&mut third);
+            if read(&mut third).starts_with("HTTP/1.1 200") {
+                break;
+            }
+            assert!(std::time::Instant::now() < deadline, "the slot was never freed");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    #[ntex::test]
+    async fn push_bodies_are_limited_to_max_body_bytes() {
+        let app = init_service(
+            web::App::new()
+                .state(test_support::state(&[("STORMCAST_MAX_BODY_BYTES", "64")]))
+                .route("/push/", web::post().to(handle_weather_data_post)),
+        )
+        .await;
+        let body = |len: usize| {
+            let push = "PASSKEY=bodylimit&tempf=50.0&note=";
+            format!("{}{}", push, "x".repeat(len - push.len()))
+        };
+        let post = |body: String, content_length: bool| {
+            let req = TestRequest::post().uri("/push/").header("Content-Type", "application/x-www-form-urlencoded");
+            let req = if content_length { req.header("Content-Length", body.len().to_string()) } else { req };
+            req.set_payload(body).to_request()
+        };
+
+        for content_length in [true, false] {
+            let res = call_service(&app, post(body(64), content_length)).await;
+            assert_eq!(res.status(), StatusCode::OK, "at the limit");
+            let res = call_service(&app, post(body(65), content_length)).await;
+            assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE, "one byte over");
+            let res = call_service(&app, post(body(1 << 20), content_length)).await;
+            assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE, "much larger");
+        }
+    }
+
+    #[ntex::test]
+    async fn json_bodies_push_like_queries() {
+        let app = init_service(
+            web::App::new()
+                .state(test_support::state(&[]))
+                .route("/push/json", web::post().to(handle_weather_data_json)),
+        )
+        .await;
+        let push = |body: &str| {
+            TestRequest::post()
+                .uri("/push/json")
+                .header("content-type", "application/json")
+                .set_payload(body.to_string())
+                .to_request()
+        };
+        let temperature = |station| test_support::series_value(metrics(), "weather_temperature_fahrenheit", station);
+
+        let full = r#"{"PASSKEY": "json-full", "dateutc": "2024-06-01 12:00:00", "tempf": 71.5, "humidity": 40,
+            "windspeedmph": "3.4", "winddir": 180, "baromrelin": 29.92, "dailyrainin": 0.01, "uv": 3}"#;
+        let res = call_service(&app, push(full)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(read_body(res).await, "ok");
+        assert_eq!(temperature("json-full"), Some(71.5));
+        assert_eq!(test_support::series_value(metrics(), "weather_humidity_percentage", "json-full"), Some(40.0));
+
+        let res = call_service(&app, push(r#"{"PASSKEY": "json-partial", "tempf": 50}"#)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(temperature("json-partial"), Some(50.0));
+
+        let res = call_service(&app, push(r#"{"PASSKEY": "json-extra", "tempf": 52, "mystery": 7, "nested": {"a": 1}}"#)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(temperature("json-extra"), Some(52.0));
+
+        for malformed in [r#"{"PASSKEY": "json-bad", "tempf": "#, "[1, 2]", r#"{"PASSKEY": "json-bad", "tempf": "warm"}"#] {
+            let res = call_service(&app, push(malformed)).await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{}", malformed);
+        }
+        assert_eq!(temperature("json-bad"), None);
+    }
+
+    #[ntex::test]
+    async fn separate_listeners_serve_only_their_endpoints() {
+        let state = test_support::state(&[]);
+        let push_state = state.clone();
+        let push_server = ntex::web::test::server(move || web::App::new().state(push_state.clone()).configure(app_routes));
+        let metrics_server = ntex::web::test::server(move || web::App::new().state(state.clone()).configure(metrics_routes));
+        assert_ne!(push_server.addr(), metrics_server.addr());
+
+        let push = "/push/?PASSKEY=listeners&tempf=58.0";
+        assert_eq!(push_server.get(push).send().await.unwrap().status(), StatusCode::OK);
+        assert_eq!(metrics_server.get(push).send().await.unwrap().status(), StatusCode::NOT_FOUND);
+        assert_ne!(push_server.get("/health").send().await.unwrap().status(), StatusCode::NOT_FOUND);
+        assert_eq!(metrics_server.get("/health").send().await.unwrap().status(), StatusCode::NOT_FOUND);
+        assert_eq!(push_server.get("/metrics").send().await.unwrap().status(), StatusCode::NOT_FOUND);
+
+        // Both listeners share the metrics
+        let mut res = metrics_server.get("/metrics").send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = String::from_utf8(res.body().await.unwrap().to_vec()).unwrap();
+        assert!(body.contains(r#"weather_temperature_fahrenheit{station="listeners"} 58"#), "{}", body);
+        let res = metrics_server.get("/metrics/names").send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[ntex::test]
+    async fn every_push_endpoint_is_delayed_and_answered_with_the_configured_body() {
+        let state = test_support::state(&[("STORMCAST_PUSH_DELAY_MS", "150"), ("STORMCAST_PUSH_RESPONSE_BODY", "success")]);
+        let app = init_service(web::App::new().state(state).configure(app_routes)).await;
+        let json = |uri: &str, body: &str| {
+            TestRequest::post()
+                .uri(uri)
+                .header("content-type", "application/json")
+                .set_payload(body.to_string())
+                .to_request()
+        };
+        let pushes = [
+            TestRequest::with_uri("/push/?PASSKEY=delay-v1&tempf=50.0").to_request(),
+            json("/push/json", r#"{"PASSKEY": "delay-json", "tempf": 50.0}"#),
+            json("/push/v2", r#"{"station_id": "delay-v2", "temp_f": 50.0}"#),
+            TestRequest::with_uri("/push/pws?ID=delay-pws&tempf=50.0").to_request(),
+            TestRequest::with_uri("/push/ecowitt-callback?PASSKEY=delay-ecowitt&tempf=50.0").to_request(),
+        ];
+        for req in pushes {
+            let path = req.path().to_string();
+            let start = std::time::Instant::now();
+            let res = call_service(&app, req).await;
+            assert_eq!(res.status(), StatusCode::OK, "{}", path);
+            assert!(start.elapsed() >= std::time::Duration::from_millis(150), "{} answered without the delay", path);
+            assert_eq!(read_body(res).await, "success", "{}", path);
+        }
+
+        // Ecowitt server validation isn't a push, so isn't held back
+        let start = std::time::Instant::now();
+        let res = call_service(&app, TestRequest::with_uri("/push/ecowitt-callback?test_key=abc").to_request()).await;
+        assert_eq!(read_body(res).await, "abc");
+        assert!(start.elapsed() < std::time::Duration::from_millis(150));
+    }
+
+    #[ntex::test]
+    async fn push_response_body_may_be_empty() {
+        let app = init_service(
+            web::App::new()
+                .state(test_support::state(&[("STORMCAST_PUSH_RESPONSE_BODY", "")]))
+                .route("/push/", web::get().to(handle_weather_data)),
+        )
+        .await;
+        let res = call_service(&app, TestRequest::with_uri("/push/?PASSKEY=emptybody&tempf=50.0").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(read_body(res).await.is_empty());
+    }
 }
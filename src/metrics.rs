@@ -0,0 +1,2023 @@
+use prometheus::core::Collector;
+use prometheus::proto::{MetricFamily, MetricType};
+use prometheus::{
+    CounterVec, Encoder, Gauge, GaugeVec, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts,
+    Registry, TextEncoder,
+};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+
+use crate::calendar::{self, SECS_PER_DAY};
+use crate::config::{is_valid_metric_name, Config};
+use crate::convert::{self, MetricSystem, KM_PER_MILE, MM_PER_INCH};
+use crate::derived;
+use crate::firmware::{FirmwareInfo, FirmwareVersionParser};
+use crate::group::GroupAverager;
+use crate::influx;
+use crate::openmetrics;
+use crate::health::HealthScoreCalculator;
+use crate::precision::{round_to_places, OutputFormat, PrecisionProfile};
+use crate::pressure_trend::PressureTrendCalculator;
+use crate::rain::RainAccumulator;
+use crate::remote_write;
+use crate::timezone::TimeZone;
+use crate::rolling::RollingWindow;
+use crate::slo::SloWindow;
+use crate::station::StationHardware;
+use crate::virtual_sensor::VirtualSensor;
+use crate::wind::WindDirectionEntropyCalculator;
+use crate::{PushProtocol, WeatherData};
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+/// Longest gap between pushes credited with evapotranspiration, so a station
+/// coming back online doesn't book its whole outage at the current rate.
+const MAX_EVAPOTRANSPIRATION_GAP: Duration = Duration::from_secs(3600);
+
+/// Text format `/metrics` is served in, negotiated from the `Accept` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpositionFormat {
+    Prometheus,
+    OpenMetrics,
+}
+
+impl ExpositionFormat {
+    /// OpenMetrics when `accept` lists it, the Prometheus text format otherwise.
+    pub fn negotiate(accept: Option<&str>) -> Self {
+        match accept {
+            Some(accept) if accept.contains("application/openmetrics-text") => ExpositionFormat::OpenMetrics,
+            _ => ExpositionFormat::Prometheus,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExpositionFormat::Prometheus => "text/plain; charset=utf-8",
+            ExpositionFormat::OpenMetrics => openmetrics::CONTENT_TYPE,
+        }
+    }
+}
+
+/// Install the process-wide metrics instance. Must be called once at startup.
+pub fn init(metrics: Metrics) {
+    if METRICS.set(metrics).is_err() {
+        panic!("metrics already initialized");
+    }
+}
+
+/// The process-wide metrics instance installed by [`init`].
+pub fn metrics() -> &'static Metrics {
+    METRICS.get().expect("metrics not initialized")
+}
+
+pub struct Metrics {
+    registry: MetricRegistry,
+    temperature: GaugeVec,
+    temperature_daily_max: GaugeVec,
+    temperature_daily_min: GaugeVec,
+    humidity: GaugeVec,
+    temperature_avg: GaugeVec,
+    humidity_avg: GaugeVec,
+    solar_radiation_avg: GaugeVec,
+    wind_speed: GaugeVec,
+    wind_gust: GaugeVec,
+    max_daily_gust: GaugeVec,
+    wind_dir: GaugeVec,
+    wind_dir_avg10m: GaugeVec,
+    wind_dir_entropy: GaugeVec,
+    wind_beaufort: GaugeVec,
+    wind_gust_beaufort: GaugeVec,
+    uv_index: GaugeVec,
+    uv_risk_level: GaugeVec,
+    solar_radiation: GaugeVec,
+    hourly_rain: GaugeVec,
+    event_rain: GaugeVec,
+    daily_rain: GaugeVec,
+    weekly_rain: GaugeVec,
+    monthly_rain: GaugeVec,
+    yearly_rain: GaugeVec,
+    monthly_rain_rate: GaugeVec,
+    yearly_rain_rate: GaugeVec,
+    batt_out: GaugeVec,
+    temperature_indoor: GaugeVec,
+    humidity_indoor: GaugeVec,
+    barom_rel: GaugeVec,
+    barom_abs: GaugeVec,
+    barom_trend: GaugeVec,
+    batt_in: GaugeVec,
+    visibility_km: GaugeVec,
+    visibility_miles: GaugeVec,
+    dew_point: GaugeVec,
+    heat_index: GaugeVec,
+    wind_chill: GaugeVec,
+    evapotranspiration: GaugeVec,
+    soil_temperature: GaugeVec,
+    soil_temperature_celsius: GaugeVec,
+    soil_moisture: GaugeVec,
+    channel_temperature: GaugeVec,
+    channel_temperature_celsius: GaugeVec,
+    channel_humidity: GaugeVec,
+    leaf_wetness: GaugeVec,
+    leaf_wetness_risk: GaugeVec,
+    water_leak: GaugeVec,
+    water_leak_any: GaugeVec,
+    water_leak_events: IntCounterVec,
+    pm25: GaugeVec,
+    pm25_avg24h: GaugeVec,
+    pm10: GaugeVec,
+    aqi_pm25: GaugeVec,
+    co2: GaugeVec,
+    co2_avg24h: GaugeVec,
+    co2_level: GaugeVec,
+    indoor_comfort: GaugeVec,
+    pm_indoor_temperature: GaugeVec,
+    pm_indoor_humidity: GaugeVec,
+    lightning_distance: GaugeVec,
+    lightning_last_strike: GaugeVec,
+    /// A counter despite its name: the station's daily strike count summed
+    /// across its midnight resets.
+    lightning_daily_count: IntCounterVec,
+    rain_total: CounterVec,
+    rain_1h: GaugeVec,
+    rain_6h: GaugeVec,
+    rain_24h: GaugeVec,
+    /// Parallel gauges in metric units.
+    metric: MetricGauges,
+    units: MetricSystem,
+    temperature_histogram: Histogram,
+    wind_speed_histogram: Histogram,
+    pub sanitized_fields: IntCounter,
+    pub push_interval: GaugeVec,
+    pub anomalous_push_rate: IntCounterVec,
+    pub daily_resets: IntCounterVec,
+    pub scrape_rate_limited: IntCounter,
+    pub active_connections: Gauge,
+    pub rejected_connections: IntCounter,
+    pub rate_limited: IntCounterVec,
+    pub dead_letters: IntCounter,
+    pub rejected_pushes: IntCounterVec,
+    remote_write_errors: IntCounter,
+    pub relay_errors: IntCounterVec,
+    pub benchmark_pushes: IntCounter,
+    pub push_duration: HistogramVec,
+    pushes: IntCounterVec,
+    push_errors: IntCounterVec,
+    last_push_timestamp: GaugeVec,
+    last_update_timestamp: GaugeVec,
+    station_timestamp: GaugeVec,
+    slo: SloMetrics,
+    last_reset_timestamp: Gauge,
+    stale_metrics: IntCounter,
+    station_info: GaugeVec,
+    station_firmware: GaugeVec,
+    station_type: GaugeVec,
+    data_quality: GaugeVec,
+    health_score: GaugeVec,
+    stations: Mutex<HashMap<String, StationMetrics>>,
+    max_stations: usize,
+    pressure_trend_readings: usize,
+    pressure_trend_window: Duration,
+    avg_window: Duration,
+    altitude_m: f32,
+    timezone: TimeZone,
+    precision: PrecisionProfile,
+    groups: GroupAverager,
+    virtual_sensors: Vec<(VirtualSensor, GaugeVec)>,
+    /// HELP text overrides by metric name without the prefix.
+    help_overrides: HashMap<String, String>,
+    /// Prepended with `_` to every metric name, `weather` by default.
+    prefix: String,
+    extra: ExtraMetrics,
+}
+
+/// State kept for each station that has pushed, alongside its gauge series.
+struct StationMetrics {
+    wind_dir_history: WindDirectionEntropyCalculator,
+    pressure_history: PressureTrendCalculator,
+    /// Recent readings for the rolling averages.
+    temperature_window: RollingWindow<f32>,
+    humidity_window: RollingWindow<u8>,
+    solar_radiation_window: RollingWindow<f32>,
+    /// Evapotranspiration accumulated so far on `evapotranspiration_day`,
+    /// counted in days since the Unix epoch.
+    evapotranspiration_mm: f64,
+    evapotranspiration_day: i64,
+    /// Outdoor temperature extremes on `temperature_day`, in days since the
+    /// Unix epoch.
+    temperature_range: Option<(f32, f32)>,
+    temperature_day: i64,
+    /// Last daily lightning strike count reported.
+    lightning_num: Option<u32>,
+    /// Last daily rainfall reported, in inches.
+    daily_rain: Option<f32>,
+    /// Rain that fell over the last day, from increases in the daily total.
+    rain_events: RainAccumulator,
+    /// Last state each water leak sensor reported.
+    water_leak: [Option<u8>; 4],
+    /// Last outdoor battery level reported; kept when a push omits it.
+    batt_out: Option<u8>,
+    data_quality: f64,
+    last_update: Instant,
+    /// Whether the station's readings have been set to NaN for going stale.
+    stale: bool,
+    /// The station's latest reading and when it arrived, in Unix seconds.
+    latest: WeatherData,
+    latest_at: i64,
+}
+
+/// Readings in °C, m/s, hPa and mm, for `STORMCAST_UNITS=metric` or `both`.
+struct MetricGauges {
+    temperature: GaugeVec,
+    temperature_indoor: GaugeVec,
+    wind_speed: GaugeVec,
+    wind_gust: GaugeVec,
+    max_daily_gust: GaugeVec,
+    hourly_rain: GaugeVec,
+    event_rain: GaugeVec,
+    daily_rain: GaugeVec,
+    weekly_rain: GaugeVec,
+    monthly_rain: GaugeVec,
+    yearly_rain: GaugeVec,
+    rain_1h: GaugeVec,
+    rain_6h: GaugeVec,
+    rain_24h: GaugeVec,
+    barom_rel: GaugeVec,
+    barom_abs: GaugeVec,
+    dew_point: GaugeVec,
+    heat_index: GaugeVec,
+    wind_chill: GaugeVec,
+}
+
+impl MetricGauges {
+    fn new(r: &MetricRegistry) -> prometheus::Result<Self> {
+        Ok(MetricGauges {
+            temperature: register_station_gauge(r, "temperature_celsius", "Outdoor temperature in Celsius")?,
+            temperature_indoor: register_station_gauge(r, "indoor_temperature_celsius", "Indoor temperature in Celsius")?,
+            wind_speed: register_station_gauge(r, "wind_speed_ms", "Windspeed in metres per second")?,
+            wind_gust: register_station_gauge(r, "wind_gust_ms", "Wind gust in metres per second")?,
+            max_daily_gust: register_station_gauge(r, "max_daily_gust_ms", "Maximum daily wind gust in metres per second")?,
+            hourly_rain: register_station_gauge(r, "rain_hourly_mm", "Rainfall in the last hour in millimetres")?,
+            event_rain: register_station_gauge(r, "rain_event_mm", "Rainfall for a specific event in millimetres")?,
+            daily_rain: register_station_gauge(r, "rain_daily_mm", "Daily rainfall in millimetres")?,
+            weekly_rain: register_station_gauge(r, "rain_weekly_mm", "Weekly rainfall in millimetres")?,
+            monthly_rain: register_station_gauge(r, "rain_monthly_mm", "Monthly rainfall in millimetres")?,
+            yearly_rain: register_station_gauge(r, "rain_yearly_mm", "Yearly rainfall in millimetres")?,
+            rain_1h: register_station_gauge(
+                r,
+                "rain_1h_server_mm",
+                "Rainfall over the last hour in millimetres, summed by the server from the daily total",
+            )?,
+            rain_6h: register_station_gauge(
+                r,
+                "rain_6h_server_mm",
+                "Rainfall over the last 6 hours in millimetres, summed by the server from the daily total",
+            )?,
+            rain_24h: register_station_gauge(
+                r,
+                "rain_24h_server_mm",
+                "Rainfall over the last 24 hours in millimetres, summed by the server from the daily total",
+            )?,
+            barom_rel: register_station_gauge(r, "barometer_relative_hpa", "Relative barometric pressure in hectopascals")?,
+            barom_abs: register_station_gauge(r, "barometer_absolute_hpa", "Absolute barometric pressure in hectopascals")?,
+            dew_point: register_station_gauge(r, "dew_point_celsius", "Dew point computed from outdoor temperature and humidity in Celsius")?,
+            heat_index: register_station_gauge(r, "heat_index_celsius", "Heat index in Celsius, present from 80°F and 40% humidity")?,
+            wind_chill: register_station_gauge(r, "wind_chill_celsius", "Wind chill in Celsius, present up to 50°F and from 3 mph")?,
+        })
+    }
+
+    fn gauges(&self) -> [&GaugeVec; 19] {
+        [
+            &self.temperature,
+            &self.temperature_indoor,
+            &self.wind_speed,
+            &self.wind_gust,
+            &self.max_daily_gust,
+            &self.hourly_rain,
+            &self.event_rain,
+            &self.daily_rain,
+            &self.weekly_rain,
+            &self.monthly_rain,
+            &self.yearly_rain,
+            &self.rain_1h,
+            &self.rain_6h,
+            &self.rain_24h,
+            &self.barom_rel,
+            &self.barom_abs,
+            &self.dew_point,
+            &self.heat_index,
+            &self.wind_chill,
+        ]
+    }
+
+    fn update(&self, station: &str, data: &WeatherData) {
+        let celsius = |f: Option<f32>| f.map(convert::fahrenheit_to_celsius);
+        let ms = |mph: Option<f32>| mph.map(convert::mph_to_ms);
+        let mm = |inches: Option<f32>| inches.map(convert::inches_to_mm);
+        let hpa = |inhg: Option<f32>| inhg.map(convert::inhg_to_hpa);
+
+        set_round_gauge(&self.temperature, station, celsius(data.tempf), 1);
+        set_round_gauge(&self.temperature_indoor, station, celsius(data.tempinf), 1);
+        set_round_gauge(&self.wind_speed, station, ms(data.windspeedmph), 2);
+        set_round_gauge(&self.wind_gust, station, ms(data.windgustmph), 2);
+        set_round_gauge(&self.max_daily_gust, station, ms(data.maxdailygust), 2);
+        set_round_gauge(&self.hourly_rain, station, mm(data.hourlyrainin), 1);
+        set_round_gauge(&self.event_rain, station, mm(data.eventrainin), 1);
+        set_round_gauge(&self.daily_rain, station, mm(data.dailyrainin), 1);
+        set_round_gauge(&self.weekly_rain, station, mm(data.weeklyrainin), 1);
+        set_round_gauge(&self.monthly_rain, station, mm(data.monthlyrainin), 1);
+        set_round_gauge(&self.yearly_rain, station, mm(data.yearlyrainin), 1);
+        set_round_gauge(&self.barom_rel, station, hpa(data.baromrelin), 1);
+        set_round_gauge(&self.barom_abs, station, hpa(data.baromabsin), 1);
+    }
+}
+
+/// A push from a new station arrived with `STORMCAST_MAX_STATIONS` already tracked.
+#[derive(Debug, thiserror::Error)]
+#[error("already tracking the maximum of {0} stations")]
+pub struct TooManyStations(pub usize);
+
+/// Push availability against the configured SLO target.
+struct SloMetrics {
+    target: f64,
+    total: IntCounter,
+    errors: IntCounter,
+    budget_remaining: Gauge,
+    budget_exhausted: Gauge,
+    window: Mutex<SloWindow>,
+}
+
+/// Gauges registered on demand for fields `WeatherData` doesn't know about.
+struct ExtraMetrics {
+    enabled: bool,
+    allowlist: Option<HashSet<String>>,
+    gauges: RwLock<HashMap<String, GaugeVec>>,
+}
+
+/// A metric as listed by `/metrics/names`.
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricDescriptor {
+    pub name: String,
+    pub help: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub labels: Vec<String>,
+}
+
+/// Registry that also keeps a descriptor of every metric registered, since
+/// `Registry::gather` leaves out vectors without any series yet.
+pub struct MetricRegistry {
+    registry: Registry,
+    prefix: String,
+    /// Labels the registry adds to every metric.
+    const_labels: Vec<String>,
+    descriptors: Mutex<Vec<MetricDescriptor>>,
+}
+
+impl MetricRegistry {
+    fn new(prefix: &str, const_labels: HashMap<String, String>) -> prometheus::Result<Self> {
+        let mut label_names: Vec<String> = const_labels.keys().cloned().collect();
+        label_names.sort();
+        Ok(MetricRegistry {
+            registry: Registry::new_custom(Some(prefix.to_string()), Some(const_labels))?,
+            prefix: prefix.to_string(),
+            const_labels: label_names,
+            descriptors: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub fn register(&self, collector: Box<dyn Collector>) -> prometheus::Result<()> {
+        let kinds: HashMap<String, MetricType> = collector
+            .collect()
+            .into_iter()
+            .map(|family| (family.get_name().to_string(), family.get_field_type()))
+            .collect();
+        let descriptors: Vec<MetricDescriptor> = collector
+            .desc()
+            .into_iter()
+            .map(|desc| MetricDescriptor {
+                name: format!("{}_{}", self.prefix, desc.fq_name),
+                help: desc.help.clone(),
+                kind: match kinds.get(&desc.fq_name) {
+                    Some(MetricType::COUNTER) => "counter",
+                    Some(MetricType::GAUGE) => "gauge",
+                    Some(MetricType::HISTOGRAM) => "histogram",
+                    Some(MetricType::SUMMARY) => "summary",
+                    _ => "untyped",
+                },
+                labels: desc.variable_labels.iter().chain(&self.const_labels).cloned().collect(),
+            })
+            .collect();
+        self.registry.register(collector)?;
+        self.descriptors.lock().unwrap().extend(descriptors);
+        Ok(())
+    }
+
+    fn gather(&self) -> Vec<MetricFamily> {
+        self.registry.gather()
+    }
+}
+
+fn register_gauge(registry: &MetricRegistry, name: &str, help: &str) -> prometheus::Result<Gauge> {
+    let gauge = Gauge::new(name, help)?;
+    registry.register(Box::new(gauge.clone()))?;
+    Ok(gauge)
+}
+
+fn register_gauge_vec(registry: &MetricRegistry, name: &str, help: &str, labels: &[&str]) -> prometheus::Result<GaugeVec> {
+    let gauge = GaugeVec::new(Opts::new(name, help), labels)?;
+    registry.register(Box::new(gauge.clone()))?;
+    Ok(gauge)
+}
+
+fn register_int_counter(registry: &MetricRegistry, name: &str, help: &str) -> prometheus::Result<IntCounter> {
+    let counter = IntCounter::new(name, help)?;
+    registry.register(Box::new(counter.clone()))?;
+    Ok(counter)
+}
+
+fn register_int_counter_vec(registry: &MetricRegistry, name: &str, help: &str, labels: &[&str]) -> prometheus::Result<IntCounterVec> {
+    let counter = IntCounterVec::new(Opts::new(name, help), labels)?;
+    registry.register(Box::new(counter.clone()))?;
+    Ok(counter)
+}
+
+fn register_counter_vec(registry: &MetricRegistry, name: &str, help: &str, labels: &[&str]) -> prometheus::Result<CounterVec> {
+    let counter = CounterVec::new(Opts::new(name, help), labels)?;
+    registry.register(Box::new(counter.clone()))?;
+    Ok(counter)
+}
+
+fn register_histogram(registry: &MetricRegistry, name: &str, help: &str, buckets: &[f64]) -> prometheus::Result<Histogram> {
+    let histogram = Histogram::with_opts(HistogramOpts::new(name, help).buckets(buckets.to_vec()))?;
+    registry.register(Box::new(histogram.clone()))?;
+    Ok(histogram)
+}
+
+fn register_histogram_vec(
+    registry: &MetricRegistry,
+    name: &str,
+    help: &str,
+    buckets: &[f64],
+    labels: &[&str],
+) -> prometheus::Result<HistogramVec> {
+    let histogram = HistogramVec::new(HistogramOpts::new(name, help).buckets(buckets.to_vec()), labels)?;
+    registry.register(Box::new(histogram.clone()))?;
+    Ok(histogram)
+}
+
+/// Register a gauge labelled by the station the reading came from.
+fn register_station_gauge(registry: &MetricRegistry, name: &str, help: &str) -> prometheus::Result<GaugeVec> {
+    register_gauge_vec(registry, name, help, &["station"])
+}
+
+fn set_round_gauge(gauge: &GaugeVec, station: &str, value: Option<f32>, places: i32) {
+    if let Some(value) = value {
+        gauge.with_label_values(&[station]).set(round_to_places(value, places));
+    }
+}
+
+/// Set `station`'s series, or remove it when there is no longer a value.
+fn set_or_clear_gauge(gauge: &GaugeVec, station: &str, value: Option<f32>, places: i32) {
+    match value {
+        Some(value) => gauge.with_label_values(&[station]).set(round_to_places(value, places)),
+        None => {
+            let _ = gauge.remove_label_values(&[station]);
+        }
+    }
+}
+
+/// Strikes to add to the lightning counter for a daily count of `count`
+/// following `last`. A count lower than the last one means the station
+/// reset it at midnight, so every strike since counts. The first count seen
+/// only sets the baseline, so a restart doesn't count the day's strikes twice.
+fn new_strikes(last: Option<u32>, count: u32) -> u32 {
+    match last {
+        Some(last) if count >= last => count - last,
+        Some(_) => count,
+        None => 0,
+    }
+}
+
+/// Rain to add to the rain counter for a daily total of `daily` inches
+/// following `last`, on the same terms as `new_strikes`.
+fn new_rain(last: Option<f32>, daily: f32) -> f32 {
+    match last {
+        Some(last) if daily >= last => daily - last,
+        Some(_) => daily,
+        None => 0.0,
+    }
+}
+
+fn station_model(stationtype: &str) -> String {
+    FirmwareVersionParser::parse(stationtype).map_or_else(|| stationtype.to_string(), |firmware| firmware.name)
+}
+
+fn station_type_labels(station: &str, hardware: &StationHardware) -> [String; 4] {
+    let stationtype = hardware.stationtype.as_deref().unwrap_or_default();
+    [
+        station.to_string(),
+        stationtype.to_string(),
+        station_model(stationtype),
+        hardware.freq.clone().unwrap_or_default(),
+    ]
+}
+
+/// Set every series of `gauge` labelled with `station`, or every series
+/// when `station` is `None`, to `value`. Returns how many were set.
+fn set_series(gauge: &GaugeVec, station: Option<&str>, value: f64) -> u64 {
+    let mut count = 0;
+    for family in gauge.collect() {
+        for metric in family.get_metric() {
+            let labels: HashMap<&str, &str> = metric
+                .get_label()
+                .iter()
+                .map(|pair| (pair.get_name(), pair.get_value()))
+                .collect();
+            if station.is_some_and(|station| labels.get("station") != Some(&station)) {
+                continue;
+            }
+            gauge.with(&labels).set(value);
+            count += 1;
+        }
+    }
+    count
+}
+
+fn set_gauge<T: Into<f64>>(gauge: &GaugeVec, station: &str, value: Option<T>) {
+    if let Some(value) = value {
+        gauge.with_label_values(&[station]).set(value.into());
+    }
+}
+
+impl Metrics {
+    pub fn new(config: &Config) -> prometheus::Result<Self> {
+        // Label every metric with the station location when it is configured
+        let const_labels = config
+            .geohash
+            .iter()
+            .map(|hash| ("geohash".to_string(), hash.clone()))
+            .collect();
+        let registry = MetricRegistry::new(&config.metric_prefix, const_labels)?;
+        let r = &registry;
+
+        Ok(Metrics {
+            temperature: register_station_gauge(r, "temperature_fahrenheit", "Outdoor temperature in Fahrenheit")?,
+            temperature_daily_max: register_station_gauge(
+                r,
+                "temperature_daily_max_fahrenheit",
+                "Highest outdoor temperature so far this UTC day in Fahrenheit",
+            )?,
+            temperature_daily_min: register_station_gauge(
+                r,
+                "temperature_daily_min_fahrenheit",
+                "Lowest outdoor temperature so far this UTC day in Fahrenheit",
+            )?,
+            humidity: register_station_gauge(r, "humidity_percentage", "Outdoor humidity percentage")?,
+            temperature_avg: register_station_gauge(
+                r,
+                "temperature_avg10m_fahrenheit",
+                "Mean outdoor temperature over the averaging window, 10 minutes by default, in Fahrenheit",
+            )?,
+            humidity_avg: register_station_gauge(
+                r,
+                "humidity_avg10m_percent",
+                "Mean outdoor humidity over the averaging window, 10 minutes by default",
+            )?,
+            solar_radiation_avg: register_station_gauge(
+                r,
+                "solar_radiation_avg10m_wm2",
+                "Mean solar radiation over the averaging window, 10 minutes by default, in watts per square metre",
+            )?,
+            wind_speed: register_station_gauge(r, "windspeed_mph", "Windspeed in miles per hour")?,
+            wind_gust: register_station_gauge(r, "windgust_mph", "Wind gust in miles per hour")?,
+            max_daily_gust: register_station_gauge(r, "max_daily_gust_mph", "Maximum daily wind gust in miles per hour")?,
+            wind_dir: register_station_gauge(r, "wind_direction_degrees", "Wind direction in degrees")?,
+            wind_dir_avg10m: register_station_gauge(r, "wind_direction_avg10m_degrees", "Wind direction averaged over 10 minutes in degrees")?,
+            wind_dir_entropy: register_station_gauge(
+                r,
+                "wind_direction_entropy",
+                "Shannon entropy of the wind direction across 16 sectors over the past hour in bits",
+            )?,
+            wind_beaufort: register_station_gauge(
+                r,
+                "wind_beaufort_force",
+                "Beaufort force of the wind speed: 0 calm, 1 light air, 2 light breeze, 3 gentle breeze, \
+                 4 moderate breeze, 5 fresh breeze, 6 strong breeze, 7 near gale, 8 gale, 9 strong gale, \
+                 10 storm, 11 violent storm, 12 hurricane",
+            )?,
+            wind_gust_beaufort: register_station_gauge(
+                r,
+                "wind_gust_beaufort_force",
+                "Beaufort force of the wind gust, on the same 0-12 scale as the wind speed",
+            )?,
+            uv_index: register_station_gauge(r, "uv_index", "UV index level")?,
+            uv_risk_level: register_station_gauge(
+                r,
+                "uv_risk_level",
+                "WHO UV exposure category: 0 low (UV 0-2), 1 moderate (3-5), 2 high (6-7), 3 very high (8-10), 4 extreme (11+)",
+            )?,
+            solar_radiation: register_station_gauge(r, "solar_radiation", "Solar radiation level")?,
+            hourly_rain: register_station_gauge(r, "hourly_rain_in", "Rainfall in the last hour in inches")?,
+            event_rain: register_station_gauge(r, "event_rain_in", "Rainfall for a specific event in inches")?,
+            daily_rain: register_station_gauge(r, "daily_rain_in", "Daily rainfall in inches")?,
+            weekly_rain: register_station_gauge(r, "weekly_rain_in", "Weekly rainfall in inches")?,
+            monthly_rain: register_station_gauge(r, "monthly_rain_in", "Monthly rainfall in inches")?,
+            yearly_rain: register_station_gauge(r, "yearly_rain_in", "Yearly rainfall in inches")?,
+            monthly_rain_rate: register_station_gauge(
+                r,
+                "monthly_rain_rate_mm_per_day",
+                "Month-to-date rainfall divided by the day of the month in millimetres per day",
+            )?,
+            yearly_rain_rate: register_station_gauge(
+                r,
+                "yearly_rain_rate_mm_per_day",
+                "Year-to-date rainfall divided by the day of the year in millimetres per day",
+            )?,
+            batt_out: register_station_gauge(r, "battout_level", "Outdoor battery level")?,
+            temperature_indoor: register_station_gauge(r, "indoor_temperature_fahrenheit", "Indoor temperature in Fahrenheit")?,
+            humidity_indoor: register_station_gauge(r, "indoor_humidity_percentage", "Indoor humidity percentage")?,
+            barom_rel: register_station_gauge(r, "barom_relative_in", "Relative barometric pressure in inches")?,
+            barom_abs: register_station_gauge(r, "barom_absolute_in", "Absolute barometric pressure in inches")?,
+            barom_trend: register_station_gauge(
+                r,
+                "barometer_trend",
+                "Relative barometric pressure trend: 1 rising, 0 steady, -1 falling",
+            )?,
+            batt_in: register_station_gauge(r, "battin_level", "Indoor battery level")?,
+            visibility_km: register_station_gauge(
+                r,
+                "visibility_km",
+                "Visibility distance in kilometres; 0 may mean the sensor is not connected",
+            )?,
+            visibility_miles: register_station_gauge(
+                r,
+                "visibility_miles",
+                "Visibility distance in miles; 0 may mean the sensor is not connected",
+            )?,
+            dew_point: register_station_gauge(
+                r,
+                "dew_point_fahrenheit",
+                "Dew point computed from outdoor temperature and humidity in Fahrenheit",
+            )?,
+            heat_index: register_station_gauge(
+                r,
+                "heat_index_fahrenheit",
+                "Heat index in Fahrenheit, present from 80°F and 40% humidity",
+            )?,
+            wind_chill: register_station_gauge(
+                r,
+                "wind_chill_fahrenheit",
+                "Wind chill in Fahrenheit, present up to 50°F and from 3 mph",
+            )?,
+            evapotranspiration: register_station_gauge(
+                r,
+                "evapotranspiration_mm_daily",
+                "FAO-56 Penman-Monteith reference evapotranspiration so far this UTC day in millimetres",
+            )?,
+            soil_temperature: register_gauge_vec(
+                r,
+                "soil_temperature_fahrenheit",
+                "Soil temperature per probe channel in Fahrenheit",
+                &["station", "channel"],
+            )?,
+            soil_temperature_celsius: register_gauge_vec(
+                r,
+                "soil_temperature_celsius",
+                "Soil temperature per probe channel in Celsius",
+                &["station", "channel"],
+            )?,
+            soil_moisture: register_gauge_vec(
+                r,
+                "soil_moisture_percent",
+                "Soil moisture per probe channel in percent",
+                &["station", "channel"],
+            )?,
+            channel_temperature: register_gauge_vec(
+                r,
+                "channel_temperature_fahrenheit",
+                "Temperature from each extra sensor channel in Fahrenheit, by sensor type (standard or probe)",
+                &["station", "channel", "sensor_type"],
+            )?,
+            channel_temperature_celsius: register_gauge_vec(
+                r,
+                "channel_temperature_celsius",
+                "Temperature from each extra sensor channel in Celsius, by sensor type (standard or probe)",
+                &["station", "channel", "sensor_type"],
+            )?,
+            channel_humidity: register_gauge_vec(
+                r,
+                "channel_humidity_percentage",
+                "Humidity from each extra sensor channel in percent, by sensor type (standard or probe)",
+                &["station", "channel", "sensor_type"],
+            )?,
+            leaf_wetness: register_gauge_vec(
+                r,
+                "leaf_wetness_percent",
+                "Leaf wetness per sensor channel in percent",
+                &["station", "channel"],
+            )?,
+            leaf_wetness_risk: register_gauge_vec(
+                r,
+                "leaf_wetness_risk",
+                "Leaf wetness per sensor channel as 0 (dry, below 30%), 1 (moist, 30-70%) or 2 (wet, above 70%)",
+                &["station", "channel"],
+            )?,
+            water_leak: register_gauge_vec(
+                r,
+                "water_leak_detected",
+                "1 when a water leak sensor is wet, 0 when dry",
+                &["station", "sensor"],
+            )?,
+            water_leak_any: register_station_gauge(
+                r,
+                "water_leak_any",
+                "1 when any of the station's water leak sensors is wet",
+            )?,
+            water_leak_events: register_int_counter_vec(
+                r,
+                "water_leak_events_total",
+                "Number of times a water leak sensor went from dry to wet",
+                &["station", "sensor"],
+            )?,
+            pm25: register_station_gauge(r, "pm25_ugm3", "PM2.5 concentration in micrograms per cubic metre")?,
+            pm25_avg24h: register_station_gauge(
+                r,
+                "pm25_avg24h_ugm3",
+                "PM2.5 concentration averaged over 24 hours in micrograms per cubic metre",
+            )?,
+            pm10: register_station_gauge(r, "pm10_ugm3", "PM10 concentration in micrograms per cubic metre")?,
+            aqi_pm25: register_station_gauge(r, "aqi_pm25", "US EPA air quality index from PM2.5 (0-500)")?,
+            co2: register_station_gauge(r, "co2_ppm", "CO2 concentration in parts per million")?,
+            co2_avg24h: register_station_gauge(
+                r,
+                "co2_avg24h_ppm",
+                "CO2 concentration averaged over 24 hours in parts per million",
+            )?,
+            co2_level: register_station_gauge(
+                r,
+                "co2_level",
+                "CO2 comfort level: 1 good (<800 ppm), 2 moderate (800-1500 ppm), 3 poor (>1500 ppm)",
+            )?,
+            indoor_comfort: register_station_gauge(
+                r,
+                "indoor_comfort_score",
+                "Indoor comfort from 0 to 100, penalising distance from 70°F, 50% humidity and 800 ppm CO2",
+            )?,
+            pm_indoor_temperature: register_station_gauge(
+                r,
+                "pm_indoor_temperature_fahrenheit",
+                "Temperature from the indoor air quality sensor in Fahrenheit",
+            )?,
+            pm_indoor_humidity: register_station_gauge(
+                r,
+                "pm_indoor_humidity_percent",
+                "Humidity from the indoor air quality sensor in percent",
+            )?,
+            lightning_distance: register_station_gauge(
+                r,
+                "lightning_distance_km",
+                "Distance to the last lightning strike in kilometres",
+            )?,
+            lightning_last_strike: register_station_gauge(
+                r,
+                "lightning_last_strike_timestamp_seconds",
+                "Unix time of the last lightning strike",
+            )?,
+            lightning_daily_count: register_int_counter_vec(
+                r,
+                "lightning_daily_count",
+                "Number of lightning strikes detected, carried across the station's daily count resets",
+                &["station"],
+            )?,
+            rain_total: register_counter_vec(
+                r,
+                "rain_total_inches",
+                "Rainfall in inches, carried across the station's daily total resets",
+                &["station"],
+            )?,
+            rain_1h: register_station_gauge(
+                r,
+                "rain_1h_server_inches",
+                "Rainfall over the last hour in inches, summed by the server from the daily total",
+            )?,
+            rain_6h: register_station_gauge(
+                r,
+                "rain_6h_server_inches",
+                "Rainfall over the last 6 hours in inches, summed by the server from the daily total",
+            )?,
+            rain_24h: register_station_gauge(
+                r,
+                "rain_24h_server_inches",
+                "Rainfall over the last 24 hours in inches, summed by the server from the daily total",
+            )?,
+            metric: MetricGauges::new(r)?,
+            units: config.units,
+            temperature_histogram: register_histogram(
+                r,
+                "temperature_fahrenheit_distribution",
+                "Distribution of outdoor temperature readings in Fahrenheit",
+                &config.histograms.temperature_f,
+            )?,
+            wind_speed_histogram: register_histogram(
+                r,
+                "windspeed_mph_distribution",
+                "Distribution of windspeed readings in miles per hour",
+                &config.histograms.wind_speed_mph,
+            )?,
+            sanitized_fields: register_int_counter(
+                r,
+                "sanitized_fields_total",
+                "Number of malformed fields removed from incoming pushes",
+            )?,
+            push_interval: register_gauge_vec(
+                r,
+                "push_interval_seconds",
+                "Detected interval between pushes from each station in seconds",
+                &["station"],
+            )?,
+            anomalous_push_rate: register_int_counter_vec(
+                r,
+                "anomalous_push_rate_total",
+                "Number of pushes that arrived far sooner than the station's usual interval",
+                &["station"],
+            )?,
+            daily_resets: register_int_counter_vec(
+                r,
+                "daily_reset_total",
+                "Number of local midnights crossed between pushes from each station",
+                &["station"],
+            )?,
+            scrape_rate_limited: register_int_counter(
+                r,
+                "metrics_scrape_rate_limited_total",
+                "Number of scrapes rejected for arriving before the minimum scrape interval",
+            )?,
+            active_connections: register_gauge(r, "active_connections", "Number of client connections open")?,
+            rejected_connections: register_int_counter(
+                r,
+                "rejected_connections_total",
+                "Number of connections refused because STORMCAST_MAX_CONNECTIONS were already open",
+            )?,
+            rate_limited: register_int_counter_vec(
+                r,
+                "rate_limited_total",
+                "Number of pushes rejected for exceeding the per-IP rate limit, by client network",
+                &["prefix"],
+            )?,
+            remote_write_errors: register_int_counter(
+                r,
+                "remote_write_errors_total",
+                "Number of failed pushes to the remote write endpoint",
+            )?,
+            relay_errors: register_int_counter_vec(
+                r,
+                "relay_errors_total",
+                "Number of pushes that failed to forward to another weather service, by target",
+                &["target"],
+            )?,
+            rejected_pushes: register_int_counter_vec(
+                r,
+                "rejected_push_total",
+                "Number of pushes refused by the station allowlist or blocklist, by reason",
+                &["reason"],
+            )?,
+            dead_letters: register_int_counter(
+                r,
+                "dead_letter_total",
+                "Number of rejected push payloads written to the dead-letter directory",
+            )?,
+            benchmark_pushes: register_int_counter(
+                r,
+                "benchmark_pushes_total",
+                "Number of pushes parsed in benchmark mode without updating metrics",
+            )?,
+            push_duration: register_histogram_vec(
+                r,
+                "push_duration_seconds",
+                "Time taken to handle each push in seconds, by outcome",
+                &[0.001, 0.005, 0.01, 0.05, 0.1, 0.5],
+                &["status"],
+            )?,
+            pushes: register_int_counter_vec(
+                r,
+                "push_total",
+                "Number of pushes accepted from each station, by push protocol",
+                &["station", "protocol_version"],
+            )?,
+            push_errors: register_int_counter_vec(
+                r,
+                "push_errors_total",
+                "Number of pushes from each station that were rejected",
+                &["station"],
+            )?,
+            last_push_timestamp: register_gauge_vec(
+                r,
+                "last_push_timestamp_seconds",
+                "Unix time of the last accepted push from each station",
+                &["station"],
+            )?,
+            last_update_timestamp: register_station_gauge(
+                r,
+                "last_update_timestamp_seconds",
+                "Unix time each station's readings were last updated",
+            )?,
+            station_timestamp: register_station_gauge(
+                r,
+                "station_timestamp_seconds",
+                "Observation time each station reported in dateutc, as Unix time",
+            )?,
+            slo: SloMetrics {
+                target: config.slo_target,
+                total: register_int_counter(r, "slo_total", "Number of pushes counted towards the SLO")?,
+                errors: register_int_counter(r, "slo_errors_total", "Number of failed pushes counted towards the SLO")?,
+                budget_remaining: register_gauge(
+                    r,
+                    "slo_error_budget_remaining",
+                    "Fraction of pushes that succeeded over the past hour",
+                )?,
+                budget_exhausted: register_gauge(
+                    r,
+                    "slo_budget_exhausted",
+                    "1 when push success over the past hour is below the SLO target",
+                )?,
+                window: Mutex::new(SloWindow::default()),
+            },
+            last_reset_timestamp: register_gauge(
+                r,
+                "last_reset_timestamp_seconds",
+                "Unix time readings were last zeroed through /admin/reset",
+            )?,
+            stale_metrics: register_int_counter(
+                r,
+                "stale_metrics_total",
+                "Number of station series set to NaN after the station stopped pushing",
+            )?,
+            station_info: register_gauge_vec(
+                r,
+                "station_info",
+                "Metadata about each station, always 1",
+                &["station", "name"],
+            )?,
+            station_firmware: register_gauge_vec(
+                r,
+                "station_firmware",
+                "Firmware each station reports in its User-Agent, always 1",
+                &["station", "name", "version"],
+            )?,
+            station_type: register_gauge_vec(
+                r,
+                "station_type_info",
+                "Station type and radio band each station reports in its pushes, with the model parsed from the type, always 1",
+                &["station", "type", "model", "freq"],
+            )?,
+            data_quality: register_station_gauge(
+                r,
+                "data_quality_ratio",
+                "Fraction of known sensor fields present in the last reading",
+            )?,
+            health_score: register_station_gauge(
+                r,
+                "station_health_score",
+                "Composite station health from battery, data freshness and data quality (0-1)",
+            )?,
+            stations: Mutex::new(HashMap::new()),
+            max_stations: config.max_stations,
+            pressure_trend_readings: config.pressure_trend_readings,
+            pressure_trend_window: config.pressure_trend_window,
+            avg_window: config.avg_window,
+            altitude_m: config.altitude_m,
+            timezone: config.station_timezone.clone(),
+            precision: config.precision.clone(),
+            groups: GroupAverager::new(&config.groups),
+            virtual_sensors: config
+                .virtual_sensors
+                .iter()
+                .map(|sensor| Ok((sensor.clone(), sensor.register(r)?)))
+                .collect::<prometheus::Result<_>>()?,
+            help_overrides: config.metric_help.clone(),
+            prefix: config.metric_prefix.clone(),
+            extra: ExtraMetrics {
+                enabled: config.register_extra_metrics,
+                allowlist: config.extra_metrics_allowlist.clone(),
+                gauges: RwLock::new(HashMap::new()),
+            },
+            registry,
+        })
+    }
+
+    /// Call `f` with every gauge holding a station's readings, including
+    /// those derived from them.
+    fn for_each_reading_gauge(&self, mut f: impl FnMut(&GaugeVec)) {
+        let readings = [
+            &self.temperature,
+            &self.temperature_daily_max,
+            &self.temperature_daily_min,
+            &self.humidity,
+            &self.temperature_avg,
+            &self.humidity_avg,
+            &self.solar_radiation_avg,
+            &self.wind_speed,
+            &self.wind_gust,
+            &self.max_daily_gust,
+            &self.wind_dir,
+            &self.wind_dir_avg10m,
+            &self.wind_dir_entropy,
+            &self.wind_beaufort,
+            &self.wind_gust_beaufort,
+            &self.uv_index,
+            &self.uv_risk_level,
+            &self.solar_radiation,
+            &self.hourly_rain,
+            &self.event_rain,
+            &self.daily_rain,
+            &self.weekly_rain,
+            &self.monthly_rain,
+            &self.yearly_rain,
+            &self.rain_1h,
+            &self.rain_6h,
+            &self.rain_24h,
+            &self.monthly_rain_rate,
+            &self.yearly_rain_rate,
+            &self.batt_out,
+            &self.temperature_indoor,
+            &self.humidity_indoor,
+            &self.barom_rel,
+            &self.barom_abs,
+            &self.barom_trend,
+            &self.batt_in,
+            &self.visibility_km,
+            &self.visibility_miles,
+            &self.dew_point,
+            &self.heat_index,
+            &self.wind_chill,
+            &self.evapotranspiration,
+            &self.soil_temperature,
+            &self.soil_temperature_celsius,
+            &self.soil_moisture,
+            &self.channel_temperature,
+            &self.channel_temperature_celsius,
+            &self.channel_humidity,
+            &self.leaf_wetness,
+            &self.leaf_wetness_risk,
+            &self.water_leak,
+            &self.water_leak_any,
+            &self.pm25,
+            &self.pm25_avg24h,
+            &self.pm10,
+            &self.aqi_pm25,
+            &self.co2,
+            &self.co2_avg24h,
+            &self.co2_level,
+            &self.indoor_comfort,
+            &self.pm_indoor_temperature,
+            &self.pm_indoor_humidity,
+            &self.lightning_distance,
+            &self.lightning_last_strike,
+        ];
+        readings.into_iter().chain(self.metric.gauges()).for_each(&mut f);
+        self.virtual_sensors.iter().for_each(|(_, gauge)| f(gauge));
+        self.extra.gauges.read().unwrap().values().for_each(f);
+    }
+
+    /// Zero every reading gauge and forget each station's daily extremes,
+    /// accumulated evapotranspiration and trend history, for when a station
+    /// is replaced or after testing. Station series keep their labels.
+    pub fn reset(&self) {
+        self.for_each_reading_gauge(|gauge| {
+            set_series(gauge, None, 0.0);
+        });
+
+        for station in self.stations.lock().unwrap().values_mut() {
+            station.wind_dir_history = WindDirectionEntropyCalculator::default();
+            station.pressure_history = PressureTrendCalculator::new(self.pressure_trend_readings, self.pressure_trend_window);
+            station.temperature_window.clear();
+            station.humidity_window.clear();
+            station.solar_radiation_window.clear();
+            station.evapotranspiration_mm = 0.0;
+            station.temperature_range = None;
+            station.rain_events.clear();
+        }
+        self.last_reset_timestamp.set(calendar::now() as f64);
+    }
+
+    /// Set the readings of every station that hasn't pushed within `timeout`
+    /// to NaN, so dashboards show a gap instead of the last known values.
+    /// The next push from the station sets them again.
+    pub fn expire_stale(&self, timeout: Duration) {
+        for (station, state) in self.stations.lock().unwrap().iter_mut() {
+            if state.stale || state.last_update.elapsed() < timeout {
+                continue;
+            }
+            let mut expired = 0;
+            self.for_each_reading_gauge(|gauge| expired += set_series(gauge, Some(station), f64::NAN));
+            info!("Station {} has not pushed for {}s; marked {} series stale", station, timeout.as_secs(), expired);
+            self.stale_metrics.inc_by(expired);
+            state.stale = true;
+        }
+    }
+
+    /// Update the pushing station's gauges from a parsed push with appropriate
+    /// decimal places, tracking the station if it is new.
+    pub fn update(&self, data: &WeatherData) -> Result<(), TooManyStations> {
+        // Derive sea-level pressure for stations that only report absolute
+        // pressure, assuming the standard atmosphere when temperature is missing
+        let with_sea_level;
+        let data = match (data.baromrelin, data.baromabsin) {
+            (None, Some(baromabsin)) => {
+                let temp_f = data.tempf.unwrap_or_else(|| derived::standard_temp_f(self.altitude_m));
+                with_sea_level = WeatherData {
+                    baromrelin: Some(derived::absolute_to_sea_level_inhg(baromabsin, self.altitude_m, temp_f)),
+                    ..data.clone()
+                };
+                &with_sea_level
+            }
+            _ => data,
+        };
+        let station = data.station_id().unwrap_or("unknown");
+        let mut stations = self.stations.lock().unwrap();
+        if !stations.contains_key(station) {
+            if stations.len() >= self.max_stations {
+                warn!("Ignoring push from new station {}: {} stations already tracked", station, stations.len());
+                return Err(TooManyStations(self.max_stations));
+            }
+            info!("Tracking new station {}", station);
+        }
+        let state = stations.entry(station.to_string()).or_insert_with(|| StationMetrics {
+            wind_dir_history: WindDirectionEntropyCalculator::default(),
+            pressure_history: PressureTrendCalculator::new(self.pressure_trend_readings, self.pressure_trend_window),
+            temperature_window: RollingWindow::new(self.avg_window),
+            humidity_window: RollingWindow::new(self.avg_window),
+            solar_radiation_window: RollingWindow::new(self.avg_window),
+            evapotranspiration_mm: 0.0,
+            evapotranspiration_day: 0,
+            temperature_range: None,
+            temperature_day: 0,
+            lightning_num: None,
+            daily_rain: None,
+            rain_events: RainAccumulator::default(),
+            water_leak: [None; 4],
+            batt_out: None,
+            data_quality: 0.0,
+            last_update: Instant::now(),
+            stale: false,
+            latest: WeatherData::default(),
+            latest_at: 0,
+        });
+
+        self.set_imperial(&self.temperature, station, "tempf", data.tempf);                     // Temperature (outdoor) with 1 decimal place by default
+        set_gauge(&self.humidity, station, data.humidity);                                      // Humidity (outdoor) no decimal places
+
+        // Track the day's high and low, starting over at midnight UTC
+        if let Some(tempf) = data.tempf {
+            let today = calendar::now().div_euclid(SECS_PER_DAY);
+            let (min, max) = match state.temperature_range {
+                Some((min, max)) if state.temperature_day == today => (min.min(tempf), max.max(tempf)),
+                _ => (tempf, tempf),
+            };
+            state.temperature_range = Some((min, max));
+            state.temperature_day = today;
+            self.set_imperial(&self.temperature_daily_min, station, "tempf", Some(min));
+            self.set_imperial(&self.temperature_daily_max, station, "tempf", Some(max));
+        }
+        self.set_imperial(&self.wind_speed, station, "windspeedmph", data.windspeedmph);        // Wind speed with 2 decimal places by default
+        self.set_imperial(&self.wind_gust, station, "windgustmph", data.windgustmph);           // Wind gust with 2 decimal places by default
+        self.set_imperial(&self.max_daily_gust, station, "maxdailygust", data.maxdailygust);    // Max daily gust with 2 decimal places by default
+        set_gauge(&self.wind_dir, station, data.winddir);                                       // Wind direction with no decimal places
+        set_gauge(&self.wind_beaufort, station, data.windspeedmph.map(derived::beaufort_from_mph)); // Beaufort force of the wind speed
+        set_gauge(&self.wind_gust_beaufort, station, data.windgustmph.map(derived::beaufort_from_mph)); // Beaufort force of the wind gust
+        set_gauge(&self.wind_dir_avg10m, station, data.winddir_avg10m);                         // Wind direction (10m average) no decimal places
+        set_gauge(&self.uv_index, station, data.uv);                                            // UV index no decimal places
+        set_gauge(&self.uv_risk_level, station, data.uv.map(derived::uv_risk_level));          // UV exposure category
+        self.set_field(&self.solar_radiation, station, "solarradiation", data.solarradiation);  // Solar radiation with 2 decimal places by default
+
+        // Track how variable the wind direction has been over the past hour
+        if let Some(winddir) = data.winddir {
+            state.wind_dir_history.record(winddir, Instant::now());
+            self.wind_dir_entropy.with_label_values(&[station]).set(state.wind_dir_history.entropy());
+        }
+
+        // Set rain-related metrics (3 decimal places by default)
+        self.set_imperial(&self.hourly_rain, station, "hourlyrainin", data.hourlyrainin);       // Hourly rain with 3 decimal places by default
+        self.set_imperial(&self.event_rain, station, "eventrainin", data.eventrainin);          // Event rain with 3 decimal places by default
+        self.set_imperial(&self.daily_rain, station, "dailyrainin", data.dailyrainin);          // Daily rain with 3 decimal places by default
+        self.set_imperial(&self.weekly_rain, station, "weeklyrainin", data.weeklyrainin);       // Weekly rain with 3 decimal places by default
+        self.set_imperial(&self.monthly_rain, station, "monthlyrainin", data.monthlyrainin);    // Monthly rain with 3 decimal places by default
+        self.set_imperial(&self.yearly_rain, station, "yearlyrainin", data.yearlyrainin);       // Yearly rain with 3 decimal places by default
+
+        // Accumulate the daily total so rain can be summed over any window;
+        // a push without it keeps the last baseline
+        if let Some(daily) = data.dailyrainin {
+            let rain = new_rain(state.daily_rain, daily);
+            let rain = self.precision.round_for_format(rain, "dailyrainin", OutputFormat::Prometheus);
+            self.rain_total.with_label_values(&[station]).inc_by(rain);
+            state.rain_events.record(rain, Instant::now());
+            state.daily_rain = Some(daily);
+        }
+        if state.daily_rain.is_some() {
+            let now = Instant::now();
+            let windows = [
+                (&self.rain_1h, &self.metric.rain_1h, 3600),
+                (&self.rain_6h, &self.metric.rain_6h, 6 * 3600),
+                (&self.rain_24h, &self.metric.rain_24h, 24 * 3600),
+            ];
+            for (inches, mm, secs) in windows {
+                let total = state.rain_events.total_inches_in_window(secs, now) as f32;
+                if self.units.imperial() {
+                    inches.with_label_values(&[station]).set(round_to_places(total, 3));
+                }
+                if self.units.metric() {
+                    mm.with_label_values(&[station]).set(round_to_places(convert::inches_to_mm(total), 1));
+                }
+            }
+        }
+
+        // Average daily rain so far this month and year, on the station's calendar
+        let date = self.timezone.local_date(data.timestamp().unwrap_or_else(calendar::now));
+        set_round_gauge(&self.monthly_rain_rate, station, data.monthlyrainin.map(|r| r * MM_PER_INCH / date.day as f32), 3);
+        set_round_gauge(&self.yearly_rain_rate, station, data.yearlyrainin.map(|r| r * MM_PER_INCH / date.ordinal() as f32), 3);
+
+        set_gauge(&self.batt_out, station, data.battout);                                       // Battery (outdoor) no decimal places
+        self.set_imperial(&self.temperature_indoor, station, "tempinf", data.tempinf);          // Temperature (indoor) with 1 decimal place by default
+        set_gauge(&self.humidity_indoor, station, data.humidityin);                             // Humidity (indoor) no decimal places
+        self.set_imperial(&self.barom_rel, station, "baromrelin", data.baromrelin);             // Relative barometric pressure with 3 decimal places by default
+        self.set_imperial(&self.barom_abs, station, "baromabsin", data.baromabsin);             // Absolute barometric pressure with 3 decimal places by default
+        set_gauge(&self.batt_in, station, data.battin);                                         // Battery (indoor) no decimal places
+
+        // Classify whether the relative pressure is rising, steady or falling
+        if let Some(pressure) = data.baromrelin {
+            state.pressure_history.record(f64::from(pressure), Instant::now());
+            self.barom_trend.with_label_values(&[station]).set(state.pressure_history.trend().value());
+        }
+
+        // Average readings the station doesn't average itself over the last few minutes
+        let now = Instant::now();
+        if let Some(tempf) = data.tempf {
+            state.temperature_window.record(tempf, now);
+            let mean = state.temperature_window.mean().map(|mean| mean as f32);
+            self.set_imperial(&self.temperature_avg, station, "tempf", mean);
+        }
+        if let Some(humidity) = data.humidity {
+            state.humidity_window.record(humidity, now);
+            set_round_gauge(&self.humidity_avg, station, state.humidity_window.mean().map(|mean| mean as f32), 1);
+        }
+        if let Some(solar) = data.solarradiation {
+            state.solar_radiation_window.record(solar, now);
+            let mean = state.solar_radiation_window.mean().map(|mean| mean as f32);
+            self.set_field(&self.solar_radiation_avg, station, "solarradiation", mean);
+        }
+
+        // Visibility may come in either unit; prefer kilometres when both are sent
+        let (visibility_km, visibility_miles) = match (data.visibility_km, data.visibility_miles) {
+            (Some(km), _) => (Some(km), Some(km / KM_PER_MILE)),
+            (None, Some(miles)) => (Some(miles * KM_PER_MILE), Some(miles)),
+            (None, None) => (None, None),
+        };
+        self.set_field(&self.visibility_km, station, "visibility_km", visibility_km);
+        self.set_field(&self.visibility_miles, station, "visibility_miles", visibility_miles);
+
+        if self.units.metric() {
+            self.metric.update(station, data);
+        }
+
+        // Readings derived from several fields
+        if let (Some(tempf), Some(humidity)) = (data.tempf, data.humidity) {
+            let dew_point = derived::dew_point_f(tempf, humidity);
+            if self.units.imperial() {
+                set_round_gauge(&self.dew_point, station, Some(dew_point), 1);
+            }
+            if self.units.metric() {
+                set_round_gauge(&self.metric.dew_point, station, Some(convert::fahrenheit_to_celsius(dew_point)), 1);
+            }
+
+            // Drop the heat index outside the conditions it is defined for
+            let heat_index = derived::heat_index_f(tempf, humidity);
+            if self.units.imperial() {
+                set_or_clear_gauge(&self.heat_index, station, heat_index, 1);
+            }
+            if self.units.metric() {
+                set_or_clear_gauge(&self.metric.heat_index, station, heat_index.map(convert::fahrenheit_to_celsius), 1);
+            }
+        }
+        if let (Some(tempf), Some(windspeedmph)) = (data.tempf, data.windspeedmph) {
+            let wind_chill = derived::wind_chill_f(tempf, windspeedmph);
+            if self.units.imperial() {
+                set_or_clear_gauge(&self.wind_chill, station, wind_chill, 1);
+            }
+            if self.units.metric() {
+                set_or_clear_gauge(&self.metric.wind_chill, station, wind_chill.map(convert::fahrenheit_to_celsius), 1);
+            }
+        }
+
+        // Accumulate reference evapotranspiration over the UTC day, at the
+        // current rate for the time since the previous push
+        if let (Some(tempf), Some(humidity), Some(windspeedmph), Some(solar)) =
+            (data.tempf, data.humidity, data.windspeedmph, data.solarradiation)
+        {
+            let today = calendar::now().div_euclid(SECS_PER_DAY);
+            if state.evapotranspiration_day != today {
+                state.evapotranspiration_day = today;
+                state.evapotranspiration_mm = 0.0;
+            }
+            let hours = state.last_update.elapsed().min(MAX_EVAPOTRANSPIRATION_GAP).as_secs_f64() / 3600.0;
+            let rate = derived::evapotranspiration_mm(
+                convert::fahrenheit_to_celsius(tempf),
+                humidity,
+                convert::mph_to_ms(windspeedmph),
+                solar,
+                self.altitude_m,
+            );
+            state.evapotranspiration_mm += f64::from(rate) * hours;
+            self.evapotranspiration
+                .with_label_values(&[station])
+                .set(round_to_places(state.evapotranspiration_mm as f32, 2));
+        }
+
+        // Soil probes report Celsius, one channel per probe
+        let soil = [
+            ("1", "soiltempc1", data.soiltempc1, data.soilmoisture1),
+            ("2", "soiltempc2", data.soiltempc2, data.soilmoisture2),
+            ("3", "soiltempc3", data.soiltempc3, data.soilmoisture3),
+            ("4", "soiltempc4", data.soiltempc4, data.soilmoisture4),
+        ];
+        for (channel, field, temp_c, moisture) in soil {
+            let labels = [station, channel];
+            if let Some(temp_c) = temp_c {
+                if self.units.imperial() {
+                    let temp_f = round_to_places(convert::celsius_to_fahrenheit(temp_c), 1);
+                    self.soil_temperature.with_label_values(&labels).set(temp_f);
+                }
+                if self.units.metric() {
+                    let temp_c = self.precision.round_for_format(temp_c, field, OutputFormat::Prometheus);
+                    self.soil_temperature_celsius.with_label_values(&labels).set(temp_c);
+                }
+            }
+            if let Some(moisture) = moisture {
+                self.soil_moisture.with_label_values(&labels).set(moisture.into());
+            }
+        }
+
+        // Extra temperature/humidity sensors, one channel per sensor; WH65-style
+        // probes number their channels separately
+        let channels = [
+            ("1", "standard", "temp1f", data.temp1f, data.humidity1),
+            ("2", "standard", "temp2f", data.temp2f, data.humidity2),
+            ("3", "standard", "temp3f", data.temp3f, data.humidity3),
+            ("4", "standard", "temp4f", data.temp4f, data.humidity4),
+            ("5", "standard", "temp5f", data.temp5f, data.humidity5),
+            ("6", "standard", "temp6f", data.temp6f, data.humidity6),
+            ("7", "standard", "temp7f", data.temp7f, data.humidity7),
+            ("8", "standard", "temp8f", data.temp8f, data.humidity8),
+            ("1", "probe", "tf_ch1", data.tf_ch1, data.humi_ch1),
+            ("2", "probe", "tf_ch2", data.tf_ch2, data.humi_ch2),
+            ("3", "probe", "tf_ch3", data.tf_ch3, data.humi_ch3),
+            ("4", "probe", "tf_ch4", data.tf_ch4, data.humi_ch4),
+        ];
+        for (channel, sensor_type, field, tempf, humidity) in channels {
+            let labels = [station, channel, sensor_type];
+            if let Some(tempf) = tempf {
+                if self.units.imperial() {
+                    let tempf = self.precision.round_for_format(tempf, field, OutputFormat::Prometheus);
+                    self.channel_temperature.with_label_values(&labels).set(tempf);
+                }
+                if self.units.metric() {
+                    let temp_c = round_to_places(convert::fahrenheit_to_celsius(tempf), 1);
+                    self.channel_temperature_celsius.with_label_values(&labels).set(temp_c);
+                }
+            }
+            if let Some(humidity) = humidity {
+                self.channel_humidity.with_label_values(&labels).set(humidity.into());
+            }
+        }
+
+        // Leaf wetness sensors, one channel per sensor
+        let leaf_wetness = [
+            ("1", data.leafwetness1),
+            ("2", data.leafwetness2),
+            ("3", data.leafwetness3),
+            ("4", data.leafwetness4),
+            ("5", data.leafwetness5),
+            ("6", data.leafwetness6),
+            ("7", data.leafwetness7),
+            ("8", data.leafwetness8),
+        ];
+        for (channel, wetness) in leaf_wetness {
+            if let Some(wetness) = wetness {
+                let labels = [station, channel];
+                self.leaf_wetness.with_label_values(&labels).set(wetness.into());
+                self.leaf_wetness_risk
+                    .with_label_values(&labels)
+                    .set(derived::leaf_wetness_risk(wetness).into());
+            }
+        }
+
+        // Water leak sensors, counting each dry to wet transition
+        let water_leak = [data.waterleakage1, data.waterleakage2, data.waterleakage3, data.waterleakage4];
+        for (index, (leak, sensor)) in water_leak.into_iter().zip(["1", "2", "3", "4"]).enumerate() {
+            let Some(leak) = leak else {
+                continue;
+            };
+            let labels = [station, sensor];
+            self.water_leak.with_label_values(&labels).set(leak.into());
+            if leak == 1 && state.water_leak[index] == Some(0) {
+                warn!("Water leak sensor {} of station {} detected a leak", sensor, station);
+                self.water_leak_events.with_label_values(&labels).inc();
+            }
+            state.water_leak[index] = Some(leak);
+        }
+        if state.water_leak.iter().any(Option::is_some) {
+            let any = state.water_leak.contains(&Some(1));
+            self.water_leak_any.with_label_values(&[station]).set(if any { 1.0 } else { 0.0 });
+        }
+
+        // Air quality
+        self.set_field(&self.pm25, station, "pm25", data.pm25);
+        self.set_field(&self.pm25_avg24h, station, "pm25_avg_24h", data.pm25_avg_24h);
+        self.set_field(&self.pm10, station, "pm10", data.pm10);
+        set_gauge(&self.aqi_pm25, station, data.pm25.map(derived::pm25_to_aqi));
+        set_gauge(&self.co2, station, data.co2);
+        set_gauge(&self.co2_avg24h, station, data.co2_avg_24h);
+        set_gauge(&self.co2_level, station, data.co2.map(derived::co2_level));
+        if let (Some(tempinf), Some(humidityin)) = (data.tempinf, data.humidityin) {
+            let score = derived::indoor_comfort_score(tempinf, humidityin, data.co2);
+            set_round_gauge(&self.indoor_comfort, station, Some(score), 1);
+        }
+        self.set_field(&self.pm_indoor_temperature, station, "pm_in_temp_f", data.pm_in_temp_f);
+        set_gauge(&self.pm_indoor_humidity, station, data.pm_in_humidity);
+
+        // Lightning; the strike time only means anything alongside a distance
+        if let Some(distance) = data.lightning {
+            set_gauge(&self.lightning_distance, station, Some(distance));
+            set_gauge(&self.lightning_last_strike, station, data.lightning_time.map(|secs| secs as f64));
+        }
+        if let Some(count) = data.lightning_num {
+            let strikes = new_strikes(state.lightning_num, count);
+            self.lightning_daily_count.with_label_values(&[station]).inc_by(u64::from(strikes));
+            state.lightning_num = Some(count);
+        }
+
+        if let Some(tempf) = data.tempf {
+            self.temperature_histogram.observe(tempf as f64);
+        }
+        if let Some(windspeedmph) = data.windspeedmph {
+            self.wind_speed_histogram.observe(windspeedmph as f64);
+        }
+
+        if self.extra.enabled {
+            for (name, value) in &data.extra {
+                self.update_extra(station, name, value);
+            }
+        }
+
+        self.groups.update(&self.registry, data);
+
+        // Recompute virtual sensors from this reading's fields
+        let fields = data.fields();
+        let field = |name: &str| fields.iter().find(|(field, _)| *field == name).and_then(|(_, value)| *value);
+        for (sensor, gauge) in &self.virtual_sensors {
+            if let Some(value) = sensor.expr.eval(&field) {
+                gauge.with_label_values(&[station]).set(value);
+            }
+        }
+
+        state.batt_out = data.battout.or(state.batt_out);
+        state.data_quality = data.completeness();
+        state.last_update = Instant::now();
+        state.stale = false;
+        state.latest = data.clone();
+        state.latest_at = calendar::now();
+        self.last_update_timestamp.with_label_values(&[station]).set(state.latest_at as f64);
+
+        // The station's own clock, to spot drift against time()
+        match data.dateutc.as_deref() {
+            None | Some("now") => {}
+            Some(dateutc) => match calendar::parse_datetime(dateutc) {
+                Some(timestamp) => self.station_timestamp.with_label_values(&[station]).set(timestamp as f64),
+                None => warn!("Station {} sent unparseable dateutc {:?}", station, dateutc),
+            },
+        }
+        self.data_quality.with_label_values(&[station]).set(state.data_quality);
+        self.refresh_station_health(station, state);
+        Ok(())
+    }
+
+    /// Set `station`'s series of `gauge` from push field `field`, rounded for Prometheus.
+    fn set_field(&self, gauge: &GaugeVec, station: &str, field: &str, value: Option<f32>) {
+        if let Some(value) = value {
+            gauge
+                .with_label_values(&[station])
+                .set(self.precision.round_for_format(value, field, OutputFormat::Prometheus));
+        }
+    }
+
+    /// Like [`Self::set_field`], for gauges in US customary units that are
+    /// left out when only metric units are exported.
+    fn set_imperial(&self, gauge: &GaugeVec, station: &str, field: &str, value: Option<f32>) {
+        if self.units.imperial() {
+            self.set_field(gauge, station, field, value);
+        }
+    }
+
+    /// Number of stations that have pushed.
+    pub fn station_count(&self) -> usize {
+        self.stations.lock().unwrap().len()
+    }
+
+    /// Time since any station's readings were last updated, if they ever were.
+    pub fn last_update_age(&self) -> Option<Duration> {
+        self.stations
+            .lock()
+            .unwrap()
+            .values()
+            .map(|state| state.last_update.elapsed())
+            .min()
+    }
+
+    /// Recompute every station's health score from its last reading and age.
+    pub fn refresh_health_scores(&self) {
+        for (station, state) in self.stations.lock().unwrap().iter() {
+            self.refresh_station_health(station, state);
+        }
+    }
+
+    fn refresh_station_health(&self, station: &str, state: &StationMetrics) {
+        let age = state.last_update.elapsed().as_secs_f64();
+        let score = HealthScoreCalculator.compute(state.batt_out == Some(1), state.data_quality, age);
+        self.health_score.with_label_values(&[station]).set(score);
+    }
+
+    /// Count an accepted push from `station` and record when it arrived.
+    pub fn record_push(&self, station: &str, protocol: PushProtocol) {
+        self.pushes.with_label_values(&[station, protocol.label()]).inc();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        self.last_push_timestamp.with_label_values(&[station]).set(now);
+        self.record_slo(false);
+    }
+
+    /// Count a rejected push from `station`.
+    pub fn record_push_error(&self, station: &str) {
+        self.push_errors.with_label_values(&[station]).inc();
+        self.record_slo(true);
+    }
+
+    /// Count a push outcome towards the SLO and update the error budget.
+    fn record_slo(&self, error: bool) {
+        let slo = &self.slo;
+        slo.total.inc();
+        if error {
+            slo.errors.inc();
+        }
+        let now = Instant::now();
+        let mut window = slo.window.lock().unwrap();
+        window.record(now, error);
+        let success = window.success_ratio(now);
+        slo.budget_remaining.set(success);
+        slo.budget_exhausted.set(if success < slo.target { 1.0 } else { 0.0 });
+    }
+
+    /// Label `station` with its configured name.
+    pub fn set_station_info(&self, station: &str, name: &str) {
+        self.station_info.with_label_values(&[station, name]).set(1.0);
+    }
+
+    /// Drop a name `station` no longer uses.
+    pub fn clear_station_info(&self, station: &str, name: &str) {
+        let _ = self.station_info.remove_label_values(&[station, name]);
+    }
+
+    /// Label `station` with the firmware it runs.
+    pub fn set_station_firmware(&self, station: &str, firmware: &FirmwareInfo) {
+        self.station_firmware
+            .with_label_values(&[station, &firmware.name, &firmware.version])
+            .set(1.0);
+    }
+
+    /// Drop firmware `station` no longer runs.
+    pub fn clear_station_firmware(&self, station: &str, firmware: &FirmwareInfo) {
+        let _ = self
+            .station_firmware
+            .remove_label_values(&[station, &firmware.name, &firmware.version]);
+    }
+
+    /// Label `station` with the `stationtype` and radio band it reports. The
+    /// model is the firmware name when the type is in a known format, else
+    /// the type itself. Values not reported are left empty.
+    pub fn set_station_type(&self, station: &str, hardware: &StationHardware) {
+        let labels = station_type_labels(station, hardware);
+        self.station_type.with_label_values(&labels.each_ref().map(String::as_str)).set(1.0);
+    }
+
+    /// Drop a type `station` no longer reports.
+    pub fn clear_station_type(&self, station: &str, hardware: &StationHardware) {
+        let labels = station_type_labels(station, hardware);
+        let _ = self.station_type.remove_label_values(&labels.each_ref().map(String::as_str));
+    }
+
+    /// Set `station`'s gauge for an extra field, registering it on first sight.
+    fn update_extra(&self, station: &str, name: &str, value: &str) {
+        if let Some(allowlist) = &self.extra.allowlist {
+            if !allowlist.contains(name) {
+                debug!("Ignoring extra field {} not in the allowlist", name);
+                return;
+            }
+        }
+        let Ok(value) = value.parse::<f64>() else {
+            return;
+        };
+
+        if let Some(gauge) = self.extra.gauges.read().unwrap().get(name) {
+            gauge.with_label_values(&[station]).set(value);
+            return;
+        }
+
+        let metric_name = format!("extra_{}", name.to_lowercase());
+        if !is_valid_metric_name(&metric_name) {
+            debug!("Ignoring extra field {} with an invalid metric name", name);
+            return;
+        }
+        let mut gauges = self.extra.gauges.write().unwrap();
+        let gauge = match gauges.get(name) {
+            Some(gauge) => gauge.clone(),
+            None => {
+                let help = format!("Extra station field {}", name);
+                match register_station_gauge(&self.registry, &metric_name, &help) {
+                    Ok(gauge) => {
+                        info!("Registered gauge {} for extra field {}", metric_name, name);
+                        gauges.insert(name.to_string(), gauge.clone());
+                        gauge
+                    }
+                    Err(e) => {
+                        warn!("Failed to register gauge for extra field {}: {}", name, e);
+                        return;
+                    }
+                }
+            }
+        };
+        gauge.with_label_values(&[station]).set(value);
+    }
+
+    /// Prefix of every metric name, without the trailing underscore.
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Each station's latest reading as InfluxDB line protocol.
+    pub fn encode_influx(&self) -> String {
+        let stations = self.stations.lock().unwrap();
+        let mut out = String::new();
+        for (station, state) in stations.iter() {
+            influx::write_reading(&mut out, &self.prefix, station, &state.latest, state.latest_at, &self.precision);
+        }
+        out
+    }
+
+    /// Every metric registered so far, sorted by name, with HELP overrides
+    /// applied. Only extra field and group gauges are added after startup.
+    pub fn describe(&self) -> Vec<MetricDescriptor> {
+        let mut descriptors = self.registry.descriptors.lock().unwrap().clone();
+        for descriptor in &mut descriptors {
+            let name = descriptor.name.strip_prefix(&self.prefix).and_then(|name| name.strip_prefix('_'));
+            if let Some(help) = name.and_then(|name| self.help_overrides.get(name)) {
+                descriptor.help = help.clone();
+            }
+        }
+        descriptors.sort_by(|a, b| a.name.cmp(&b.name));
+        descriptors
+    }
+
+    /// Gather all registered metrics with HELP overrides applied.
+    fn gather(&self) -> Vec<MetricFamily> {
+        let mut metric_families = self.registry.gather();
+        for family in &mut metric_families {
+            let name = family.get_name();
+            let name = name.strip_prefix(&self.prefix).and_then(|name| name.strip_prefix('_')).unwrap_or(name);
+            if let Some(help) = self.help_overrides.get(name) {
+                family.set_help(help.clone());
+            }
+        }
+        metric_families
+    }
+
+    /// Send all registered metrics to the remote write endpoint as a
+    /// snappy-compressed `prometheus.WriteRequest`, counting and logging any
+    /// failure.
+    pub async fn gather_and_push(&self, url: &str, token: Option<&str>) {
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as i64;
+        let write_request = remote_write::write_request(&self.gather(), now_ms);
+        match remote_write::send(url, token, &write_request).await {
+            Ok(()) => debug!("Pushed metrics to {}", url),
+            Err(e) => {
+                self.remote_write_errors.inc();
+                warn!("Failed to push metrics to {}: {}", url, e);
+            }
+        }
+    }
+
+    /// Encode all registered metrics in the given text format.
+    pub fn encode(&self, format: ExpositionFormat) -> Vec<u8> {
+        let metric_families = self.gather();
+        if format == ExpositionFormat::OpenMetrics {
+            return openmetrics::encode(&metric_families).into_bytes();
+        }
+        let encoder = TextEncoder::new();
+        let mut buffer = Vec::new();
+
+        // Encode metrics into text format that Prometheus understands
+        encoder.encode(&metric_families, &mut buffer).unwrap();
+        buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ConfigEnv, ConfigFile};
+
+    fn config_from_toml(toml: &str) -> Config {
+        Config::merge(toml::from_str(toml).unwrap(), ConfigEnv::default()).unwrap()
+    }
+
+    fn reading(query: &str) -> WeatherData {
+        WeatherData::from_query(query).unwrap()
+    }
+
+    fn encoded(metrics: &Metrics) -> String {
+        String::from_utf8(metrics.encode(ExpositionFormat::Prometheus)).unwrap()
+    }
+
+    #[test]
+    fn custom_histogram_buckets_count_readings() {
+        let config = config_from_toml(
+            "[histograms]\ntemperature_f = [-20.0, 0.0, 32.0, 50.0, 70.0, 80.0, 90.0, 100.0]\n",
+        );
+        let metrics = Metrics::new(&config).unwrap();
+        metrics.update(&reading("PASSKEY=hist&tempf=75.0")).unwrap();
+
+        let text = encoded(&metrics);
+        let buckets: Vec<&str> = text
+            .lines()
+            .filter(|line| line.starts_with("weather_temperature_fahrenheit_distribution_bucket"))
+            .collect();
+        // Eight configured boundaries plus +Inf
+        assert_eq!(buckets.len(), 9);
+        assert!(text.contains("weather_temperature_fahrenheit_distribution_bucket{le=\"70\"} 0"));
+        assert!(text.contains("weather_temperature_fahrenheit_distribution_bucket{le=\"80\"} 1"));
+        assert!(text.contains("weather_temperature_fahrenheit_distribution_bucket{le=\"+Inf\"} 1"));
+    }
+
+    #[test]
+    fn invalid_histogram_buckets_are_rejected() {
+        for toml in ["[histograms]\ntemperature_f = []\n", "[histograms]\nwind_speed_mph = [5.0, 2.0]\n"] {
+            let file: ConfigFile = toml::from_str(toml).unwrap();
+            assert!(Config::merge(file, ConfigEnv::default()).is_err(), "{}", toml);
+        }
+    }
+
+    #[test]
+    fn visibility_in_miles_sets_the_km_gauge() {
+        // Visibility is exported to two places unless configured otherwise
+        let config = crate::test_support::config(&[("STORMCAST_ROUND_VISIBILITY", "3")]);
+        let metrics = Metrics::new(&config).unwrap();
+        metrics.update(&reading("PASSKEY=vis&visibility_miles=10.0")).unwrap();
+
+        let km = crate::test_support::series_value(&metrics, "weather_visibility_km", "vis").unwrap();
+        assert!((km - 16.093).abs() < 0.001, "{}", km);
+        let miles = crate::test_support::series_value(&metrics, "weather_visibility_miles", "vis");
+        assert_eq!(miles, Some(10.0));
+    }
+
+    #[test]
+    fn visibility_prefers_km_when_both_are_sent() {
+        let config = crate::test_support::config(&[("STORMCAST_ROUND_VISIBILITY", "3")]);
+        let metrics = Metrics::new(&config).unwrap();
+        metrics.update(&reading("PASSKEY=both&visibility_km=5.0&visibility_miles=10.0")).unwrap();
+
+        let km = crate::test_support::series_value(&metrics, "weather_visibility_km", "both");
+        assert_eq!(km, Some(5.0));
+        let miles = crate::test_support::series_value(&metrics, "weather_visibility_miles", "both").unwrap();
+        assert!((miles - 3.107).abs() < 0.001, "{}", miles);
+    }
+
+    #[test]
+    fn daily_temperature_range_tracks_the_extremes() {
+        let metrics = Metrics::new(&crate::test_support::config(&[])).unwrap();
+        let range = |metrics: &Metrics| {
+            let value = |name| crate::test_support::series_value(metrics, name, "range");
+            (value("weather_temperature_daily_min_fahrenheit"), value("weather_temperature_daily_max_fahrenheit"))
+        };
+        for tempf in ["60.0", "50.0", "70.0", "65.0"] {
+            metrics.update(&reading(&format!("PASSKEY=range&tempf={}", tempf))).unwrap();
+        }
+        assert_eq!(range(&metrics), (Some(50.0), Some(70.0)));
+        metrics.update(&reading("PASSKEY=range&humidity=40")).unwrap();
+        assert_eq!(range(&metrics), (Some(50.0), Some(70.0)));
+
+        // The first reading of a new UTC day starts the range over
+        metrics.stations.lock().unwrap().get_mut("range").unwrap().temperature_day -= 1;
+        metrics.update(&reading("PASSKEY=range&tempf=62.0")).unwrap();
+        assert_eq!(range(&metrics), (Some(62.0), Some(62.0)));
+    }
+
+    #[test]
+    fn reset_zeroes_readings_and_records_when() {
+        let metrics = Metrics::new(&crate::test_support::config(&[])).unwrap();
+        metrics.update(&reading("PASSKEY=reset&tempf=60.0&humidity=40")).unwrap();
+        metrics.update(&reading("PASSKEY=reset&tempf=70.0")).unwrap();
+        metrics.reset();
+
+        let value = |name| crate::test_support::series_value(&metrics, name, "reset");
+        assert_eq!(value("weather_temperature_fahrenheit"), Some(0.0));
+        assert_eq!(value("weather_humidity_percentage"), Some(0.0));
+        assert!(crate::test_support::sample(&metrics, "weather_last_reset_timestamp_seconds", &[]).unwrap() > 0.0);
+
+        // The daily range starts over rather than remembering the old extremes
+        metrics.update(&reading("PASSKEY=reset&tempf=65.0")).unwrap();
+        assert_eq!(value("weather_temperature_daily_min_fahrenheit"), Some(65.0));
+        assert_eq!(value("weather_temperature_daily_max_fahrenheit"), Some(65.0));
+    }
+
+    #[test]
+    fn strike_counts_carry_across_daily_resets() {
+        assert_eq!(new_strikes(None, 12), 0);
+        assert_eq!(new_strikes(Some(12), 12), 0);
+        assert_eq!(new_strikes(Some(12), 15), 3);
+        assert_eq!(new_strikes(Some(15), 2), 2);
+        assert_eq!(new_strikes(Some(15), 0), 0);
+    }
+
+    #[test]
+    fn lightning_daily_count_only_increases() {
+        let metrics = Metrics::new(&crate::test_support::config(&[])).unwrap();
+        let count = |metrics: &Metrics| crate::test_support::series_value(metrics, "weather_lightning_daily_count", "storm");
+        for num in ["5", "9", "9", "1", "4"] {
+            metrics.update(&reading(&format!("PASSKEY=storm&lightning=12.0&lightning_num={}", num))).unwrap();
+        }
+        // 9 - 5, then 1 after the midnight reset and 4 - 1
+        assert_eq!(count(&metrics), Some(8.0));
+        let distance = crate::test_support::series_value(&metrics, "weather_lightning_distance_km", "storm");
+        assert_eq!(distance, Some(12.0));
+    }
+
+    #[test]
+    fn strike_time_needs_a_distance() {
+        let metrics = Metrics::new(&crate::test_support::config(&[])).unwrap();
+        metrics.update(&reading("PASSKEY=far&lightning_time=1700000000")).unwrap();
+        let last = |station| crate::test_support::series_value(&metrics, "weather_lightning_last_strike_timestamp_seconds", station);
+        assert_eq!(last("far"), None);
+        metrics.update(&reading("PASSKEY=near&lightning=3.0&lightning_time=1700000000")).unwrap();
+        assert_eq!(last("near"), Some(1_700_000_000.0));
+    }
+
+    #[test]
+    fn water_leaks_count_dry_to_wet_transitions() {
+        let metrics = Metrics::new(&crate::test_support::config(&[])).unwrap();
+        let sensor = |name, sensor| crate::test_support::sample(&metrics, name, &[("station", "leak"), ("sensor", sensor)]);
+        let any = || crate::test_support::series_value(&metrics, "weather_water_leak_any", "leak");
+        for query in ["waterleakage1=1&waterleakage2=0", "waterleakage1=0", "waterleakage1=1", "waterleakage2=1"] {
+            metrics.update(&reading(&format!("PASSKEY=leak&{}", query))).unwrap();
+        }
+        // Sensor 1 was wet when first seen, so only its second wetting counts
+        assert_eq!(sensor("weather_water_leak_events_total", "1"), Some(1.0));
+        assert_eq!(sensor("weather_water_leak_events_total", "2"), Some(1.0));
+        assert_eq!(sensor("weather_water_leak_detected", "1"), Some(1.0));
+        assert_eq!(any(), Some(1.0));
+
+        metrics.update(&reading("PASSKEY=leak&waterleakage1=0&waterleakage2=0")).unwrap();
+        assert_eq!(any(), Some(0.0));
+        assert_eq!(sensor("weather_water_leak_detected", "3"), None);
+    }
+
+    #[test]
+    fn describe_lists_every_gathered_metric() {
+        let metrics = Metrics::new(&crate::test_support::config(&[("STORMCAST_UNITS", "both")])).unwrap();
+        metrics
+            .update(&reading("PASSKEY=names&tempf=70.0&humidity=40&windspeedmph=5.0&baromrelin=30.0&lightning_num=1"))
+            .unwrap();
+        let described = metrics.describe();
+        let names: Vec<&str> = described.iter().map(|descriptor| descriptor.name.as_str()).collect();
+        assert!(names.windows(2).all(|pair| pair[0] < pair[1]), "not sorted and unique");
+        for family in metrics.gather() {
+            assert!(names.contains(&family.get_name()), "{} is not described", family.get_name());
+        }
+
+        let temperature = described.iter().find(|d| d.name == "weather_temperature_fahrenheit").unwrap();
+        assert_eq!((temperature.kind, temperature.labels.as_slice()), ("gauge", &["station".to_string()][..]));
+        let histogram = described.iter().find(|d| d.name == "weather_temperature_fahrenheit_distribution").unwrap();
+        assert_eq!(histogram.kind, "histogram");
+        assert!(described.iter().any(|d| d.kind == "counter"));
+    }
+
+    #[test]
+    fn rain_total_adds_daily_increases_across_resets() {
+        let metrics = Metrics::new(&crate::test_support::config(&[])).unwrap();
+        let total = || crate::test_support::series_value(&metrics, "weather_rain_total_inches", "rainy").unwrap();
+        // The first total after startup only sets the baseline
+        metrics.update(&reading("PASSKEY=rainy&dailyrainin=0.10")).unwrap();
+        assert_eq!(total(), 0.0);
+        metrics.update(&reading("PASSKEY=rainy&dailyrainin=0.25")).unwrap();
+        assert!((total() - 0.15).abs() < 1e-9);
+
+        // A push without rain data keeps the baseline
+        metrics.update(&reading("PASSKEY=rainy&tempf=60.0")).unwrap();
+        metrics.update(&reading("PASSKEY=rainy&dailyrainin=0.30")).unwrap();
+        assert!((total() - 0.20).abs() < 1e-9);
+
+        // A lower total is the midnight reset, and all of it fell since
+        metrics.update(&reading("PASSKEY=rainy&dailyrainin=0.02")).unwrap();
+        metrics.update(&reading("PASSKEY=rainy&dailyrainin=0.05")).unwrap();
+        assert!((total() - 0.25).abs() < 1e-9);
+        assert_eq!(new_rain(Some(0.3), 0.3), 0.0);
+    }
+
+    #[test]
+    fn stale_stations_read_nan_until_they_push() {
+        let metrics = Metrics::new(&crate::test_support::config(&[])).unwrap();
+        let temperature = |station| crate::test_support::series_value(&metrics, "weather_temperature_fahrenheit", station).unwrap();
+        metrics.update(&reading("PASSKEY=quiet&tempf=60.0&humidity=40")).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        metrics.update(&reading("PASSKEY=chatty&tempf=70.0")).unwrap();
+
+        metrics.expire_stale(Duration::from_millis(40));
+        assert!(temperature("quiet").is_nan());
+        assert_eq!(temperature("chatty"), 70.0);
+        let expired = metrics.stale_metrics.get();
+        assert!(expired >= 2, "{} series expired", expired);
+        // Already stale stations aren't counted again
+        metrics.expire_stale(Duration::from_millis(40));
+        assert_eq!(metrics.stale_metrics.get(), expired);
+
+        metrics.update(&reading("PASSKEY=quiet&tempf=61.0")).unwrap();
+        assert_eq!(temperature("quiet"), 61.0);
+        assert!(!metrics.stations.lock().unwrap()["quiet"].stale);
+    }
+
+    #[test]
+    fn probe_channels_are_kept_apart_from_standard_ones() {
+        let query = "PASSKEY=wh65probe&stationtype=GW1100A_V2.3.1&dateutc=2024-06-01+12:00:00&tempinf=72.1\
+            &humidityin=45&tempf=68.4&humidity=60&temp1f=70.2&humidity1=50&tf_ch1=66.9&humi_ch1=88&tf_ch2=64.2\
+            &humi_ch2=91&freq=868M";
+        let data = reading(query);
+        assert_eq!((data.tf_ch1, data.humi_ch1, data.tf_ch2, data.humi_ch2), (Some(66.9), Some(88), Some(64.2), Some(91)));
+        assert_eq!((data.tf_ch3, data.humi_ch3), (None, None));
+        assert!(data.extra.is_empty(), "{:?}", data.extra);
+
+        let metrics = Metrics::new(&crate::test_support::config(&[])).unwrap();
+        metrics.update(&data).unwrap();
+        let channel = |name, channel, sensor_type| {
+            let labels = [("station", "wh65probe"), ("channel", channel), ("sensor_type", sensor_type)];
+            crate::test_support::sample(&metrics, name, &labels)
+        };
+        assert_eq!(channel("weather_channel_temperature_fahrenheit", "1", "standard"), Some(70.2));
+        assert_eq!(channel("weather_channel_temperature_fahrenheit", "1", "probe"), Some(66.9));
+        assert_eq!(channel("weather_channel_humidity_percentage", "1", "probe"), Some(88.0));
+        assert_eq!(channel("weather_channel_humidity_percentage", "2", "probe"), Some(91.0));
+        assert_eq!(channel("weather_channel_humidity_percentage", "2", "standard"), None);
+    }
+
+    #[test]
+    fn rain_rates_average_the_totals_over_the_days_elapsed() {
+        let metrics = Metrics::new(&crate::test_support::config(&[("STORMCAST_UNITS", "metric")])).unwrap();
+        let push = "PASSKEY=rainrate&dailyrainin=1.5&monthlyrainin=1.5&yearlyrainin=1.5&dateutc=2024-03-15+12:00:00";
+        metrics.update(&reading(push)).unwrap();
+
+        let rate = |name| crate::test_support::series_value(&metrics, name, "rainrate").unwrap();
+        let monthly = rate("weather_monthly_rain_rate_mm_per_day");
+        assert!((monthly - 1.5 / 15.0 * 25.4).abs() < 1e-3, "{}", monthly);
+        // March 15th is day 75 of a leap year
+        let yearly = rate("weather_yearly_rain_rate_mm_per_day");
+        assert!((yearly - 1.5 / 75.0 * 25.4).abs() < 1e-3, "{}", yearly);
+    }
+
+    #[test]
+    fn only_allowlisted_extra_fields_get_gauges() {
+        let config = crate::test_support::config(&[
+            ("STORMCAST_REGISTER_EXTRA_METRICS", "true"),
+            ("STORMCAST_EXTRA_METRICS_ALLOWLIST", "soilgood"),
+        ]);
+        let metrics = Metrics::new(&config).unwrap();
+        metrics.update(&reading("PASSKEY=extras&soilgood=1.5&soilbad=2.5")).unwrap();
+
+        let text = encoded(&metrics);
+        assert!(text.contains(r#"weather_extra_soilgood{station="extras"} 1.5"#), "{}", text);
+        assert!(!text.contains("soilbad"), "{}", text);
+    }
+
+    #[test]
+    fn help_overrides_replace_the_help_text() {
+        let config = config_from_toml("[metric_help]\ntemperature_fahrenheit = \"Outdoor temperature at the fence\"\n");
+        let metrics = Metrics::new(&config).unwrap();
+        metrics.update(&reading("PASSKEY=help&tempf=60.0&humidity=40")).unwrap();
+
+        let text = encoded(&metrics);
+        assert!(text.contains("# HELP weather_temperature_fahrenheit Outdoor temperature at the fence\n"), "{}", text);
+        assert!(text.contains("# HELP weather_humidity_percentage Outdoor humidity percentage\n"), "{}", text);
+    }
+}
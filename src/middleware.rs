@@ -0,0 +1,494 @@
+use ntex::http::header::{
+    HeaderMap, HeaderName, HeaderValue, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
+    ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_HEADERS, ORIGIN, VARY,
+};
+use ntex::codec::BytesCodec;
+use ntex::http::Method;
+use ntex::io::Io;
+use ntex::service::{Middleware, Service, ServiceCtx};
+use ntex::util::Bytes;
+use ntex::web::{self, DefaultError, WebRequest, WebResponse};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, warn, Instrument};
+
+use crate::error::{AppError, ProblemDetail};
+use crate::metrics::metrics;
+use crate::rate_limit::{ip_prefix, RateLimiter};
+
+/// Logs every request inside a span carrying the caller's trace ID, so all
+/// log lines emitted while handling it can be correlated across services.
+/// Push handling time is also recorded on `weather_push_duration_seconds`.
+///
+/// The trace ID is taken from `X-Trace-ID`, then from the trace-id part of a
+/// W3C `traceparent` header, and a fresh UUID is generated when neither is set.
+pub struct RequestLogger;
+
+impl<S> Middleware<S> for RequestLogger {
+    type Service = RequestLoggerMiddleware<S>;
+
+    fn create(&self, service: S) -> Self::Service {
+        RequestLoggerMiddleware { service }
+    }
+}
+
+pub struct RequestLoggerMiddleware<S> {
+    service: S,
+}
+
+fn trace_id<E>(req: &WebRequest<E>) -> String {
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    };
+
+    if let Some(id) = header("x-trace-id") {
+        return id.to_string();
+    }
+    // traceparent: {version}-{trace-id}-{parent-id}-{flags}
+    if let Some(id) = header("traceparent").and_then(|value| value.split('-').nth(1)) {
+        return id.to_string();
+    }
+    uuid::Uuid::new_v4().to_string()
+}
+
+impl<S, E> Service<WebRequest<E>> for RequestLoggerMiddleware<S>
+where
+    S: Service<WebRequest<E>, Response = WebResponse>,
+{
+    type Response = WebResponse;
+    type Error = S::Error;
+
+    ntex::forward_ready!(service);
+    ntex::forward_shutdown!(service);
+
+    async fn call(
+        &self,
+        req: WebRequest<E>,
+        ctx: ServiceCtx<'_, Self>,
+    ) -> Result<Self::Response, Self::Error> {
+        let span = tracing::info_span!(
+            "request",
+            trace_id = %trace_id(&req),
+            method = %req.method(),
+            path = %req.path(),
+        );
+
+        let is_push = req.path().starts_with("/push/");
+
+        async move {
+            let start = Instant::now();
+            let res = ctx.call(&self.service, req).await;
+            let elapsed = start.elapsed();
+            if is_push {
+                let status = match &res {
+                    Ok(res) if res.status().is_success() => "ok",
+                    _ => "error",
+                };
+                metrics()
+                    .push_duration
+                    .with_label_values(&[status])
+                    .observe(elapsed.as_secs_f64());
+            }
+            let res = res?;
+            info!(status = res.status().as_u16(), elapsed = ?elapsed, "Request handled");
+            Ok(res)
+        }
+        .instrument(span)
+        .await
+    }
+}
+
+/// Rejects pushes from clients exceeding the per-IP rate limit with
+/// `429 Too Many Requests`, and reports the limit and the pushes left in
+/// `X-RateLimit-*` headers. Requests outside `/push/` are not limited.
+pub struct RateLimit {
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimit {
+    pub fn new(limiter: Arc<RateLimiter>) -> Self {
+        RateLimit { limiter }
+    }
+}
+
+impl<S> Middleware<S> for RateLimit {
+    type Service = RateLimitMiddleware<S>;
+
+    fn create(&self, service: S) -> Self::Service {
+        RateLimitMiddleware {
+            service,
+            limiter: self.limiter.clone(),
+        }
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: S,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<S> Service<WebRequest<DefaultError>> for RateLimitMiddleware<S>
+where
+    S: Service<WebRequest<DefaultError>, Response = WebResponse>,
+{
+    type Response = WebResponse;
+    type Error = S::Error;
+
+    ntex::forward_ready!(service);
+    ntex::forward_shutdown!(service);
+
+    async fn call(
+        &self,
+        req: WebRequest<DefaultError>,
+        ctx: ServiceCtx<'_, Self>,
+    ) -> Result<Self::Response, Self::Error> {
+        let limit = self.limiter.limit();
+        let peer = req.peer_addr().filter(|_| limit > 0 && req.path().starts_with("/push/"));
+        let Some(peer) = peer else {
+            return ctx.call(&self.service, req).await;
+        };
+        let remaining = match self.limiter.check(peer.ip(), Instant::now()) {
+            Ok(remaining) => remaining,
+            Err(retry_after) => {
+                let prefix = ip_prefix(peer.ip());
+                warn!("Rate limiting pushes from {}", prefix);
+                metrics().rate_limited.with_label_values(&[&prefix]).inc();
+                let mut res = req.render_error(AppError::RateLimited(retry_after));
+                set_rate_limit_headers(res.headers_mut(), limit, 0);
+                return Ok(res);
+            }
+        };
+        let mut res = ctx.call(&self.service, req).await?;
+        set_rate_limit_headers(res.headers_mut(), limit, remaining);
+        Ok(res)
+    }
+}
+
+/// Tell a client its per-second push limit and how many pushes it has left
+/// in the current one-second window, so it can slow down before a 429.
+fn set_rate_limit_headers(headers: &mut HeaderMap, limit: u32, remaining: u32) {
+    headers.insert(HeaderName::from_static("x-ratelimit-limit"), HeaderValue::from(limit));
+    headers.insert(HeaderName::from_static("x-ratelimit-remaining"), HeaderValue::from(remaining));
+}
+
+/// Read-only endpoints browser dashboards may call from another origin.
+/// Pushes come from station firmware, not browsers, so they are left out.
+const CORS_PATHS: [&str; 4] = ["/data", "/history", "/events", "/metrics/names"];
+/// How long browsers may cache a preflight response, in seconds.
+const CORS_MAX_AGE_SECS: &str = "86400";
+
+/// Origins allowed to call the `CORS_PATHS` endpoints, from
+/// `STORMCAST_CORS_ORIGINS`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CorsOrigins {
+    Any,
+    List(HashSet<String>),
+}
+
+impl CorsOrigins {
+    /// Parse `*` or a comma-separated list of origins.
+    pub fn parse(value: &str) -> CorsOrigins {
+        if value.trim() == "*" {
+            return CorsOrigins::Any;
+        }
+        let origins = value
+            .split(',')
+            .map(|origin| origin.trim().trim_end_matches('/'))
+            .filter(|origin| !origin.is_empty())
+            .map(str::to_string)
+            .collect();
+        CorsOrigins::List(origins)
+    }
+
+    /// `Access-Control-Allow-Origin` value for a request from `origin`, if allowed.
+    fn allow(&self, origin: &str) -> Option<HeaderValue> {
+        match self {
+            CorsOrigins::Any => Some(HeaderValue::from_static("*")),
+            CorsOrigins::List(origins) if origins.contains(origin) => HeaderValue::from_str(origin).ok(),
+            CorsOrigins::List(_) => None,
+        }
+    }
+}
+
+/// Adds CORS headers to responses from the `CORS_PATHS` endpoints for
+/// allowed origins, and answers their `OPTIONS` preflight requests.
+pub struct Cors {
+    origins: Option<Arc<CorsOrigins>>,
+}
+
+impl Cors {
+    /// With no origins configured, no CORS headers are ever sent.
+    pub fn new(origins: Option<CorsOrigins>) -> Self {
+        Cors {
+            origins: origins.map(Arc::new),
+        }
+    }
+}
+
+impl<S> Middleware<S> for Cors {
+    type Service = CorsMiddleware<S>;
+
+    fn create(&self, service: S) -> Self::Service {
+        CorsMiddleware {
+            service,
+            origins: self.origins.clone(),
+        }
+    }
+}
+
+pub struct CorsMiddleware<S> {
+    service: S,
+    origins: Option<Arc<CorsOrigins>>,
+}
+
+impl<S> Service<WebRequest<DefaultError>> for CorsMiddleware<S>
+where
+    S: Service<WebRequest<DefaultError>, Response = WebResponse>,
+{
+    type Response = WebResponse;
+    type Error = S::Error;
+
+    ntex::forward_ready!(service);
+    ntex::forward_shutdown!(service);
+
+    async fn call(
+        &self,
+        req: WebRequest<DefaultError>,
+        ctx: ServiceCtx<'_, Self>,
+    ) -> Result<Self::Response, Self::Error> {
+        let allow_origin = match &self.origins {
+            Some(origins) if CORS_PATHS.contains(&req.path()) => req
+                .headers()
+                .get(ORIGIN)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|origin| origins.allow(origin)),
+            _ => None,
+        };
+        let Some(allow_origin) = allow_origin else {
+            return ctx.call(&self.service, req).await;
+        };
+
+        if req.method() == Method::OPTIONS {
+            let allow_headers = req.headers().get(ACCESS_CONTROL_REQUEST_HEADERS).cloned();
+            let mut res = web::HttpResponse::NoContent();
+            res.header(ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin)
+                .header(ACCESS_CONTROL_ALLOW_METHODS, "GET, OPTIONS")
+                .header(ACCESS_CONTROL_MAX_AGE, CORS_MAX_AGE_SECS)
+                .header(VARY, "Origin");
+            if let Some(allow_headers) = allow_headers {
+                res.header(ACCESS_CONTROL_ALLOW_HEADERS, allow_headers);
+            }
+            return Ok(req.into_response(res.finish()));
+        }
+
+        let mut res = ctx.call(&self.service, req).await?;
+        res.headers_mut().insert(ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+        res.headers_mut().append(VARY, HeaderValue::from_static("Origin"));
+        Ok(res)
+    }
+}
+
+/// Longest a refused connection is given to send its request head, so the
+/// 503 is read rather than lost to a reset when the socket closes.
+const REFUSED_HEAD_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Refuses connections with `503 Service Unavailable` while `max` are
+/// already open, so a flood of clients can't exhaust file descriptors. It
+/// wraps the HTTP service of every listener and worker, so the count is a
+/// total, exported as `weather_active_connections`.
+#[derive(Clone)]
+pub struct ConnectionLimit {
+    active: Arc<AtomicUsize>,
+    max: usize,
+}
+
+impl ConnectionLimit {
+    pub fn new(max: usize) -> Self {
+        ConnectionLimit {
+            active: Arc::new(AtomicUsize::new(0)),
+            max,
+        }
+    }
+}
+
+impl<S> Middleware<S> for ConnectionLimit {
+    type Service = ConnectionLimitMiddleware<S>;
+
+    fn create(&self, service: S) -> Self::Service {
+        ConnectionLimitMiddleware {
+            service,
+            limit: self.clone(),
+        }
+    }
+}
+
+pub struct ConnectionLimitMiddleware<S> {
+    service: S,
+    limit: ConnectionLimit,
+}
+
+/// One open connection, released when dropped so connections that fail
+/// still free their slot.
+struct ConnectionGuard<'a>(&'a AtomicUsize);
+
+impl Drop for ConnectionGuard<'_> {
+    fn drop(&mut self) {
+        let active = self.0.fetch_sub(1, Ordering::AcqRel) - 1;
+        metrics().active_connections.set(active as f64);
+    }
+}
+
+impl<S> Service<Io> for ConnectionLimitMiddleware<S>
+where
+    S: Service<Io, Response = ()>,
+{
+    type Response = ();
+    type Error = S::Error;
+
+    ntex::forward_ready!(service);
+    ntex::forward_shutdown!(service);
+
+    async fn call(&self, io: Io, ctx: ServiceCtx<'_, Self>) -> Result<(), Self::Error> {
+        let active = self.limit.active.fetch_add(1, Ordering::AcqRel) + 1;
+        let _guard = ConnectionGuard(&self.limit.active);
+        if active > self.limit.max {
+            warn!("Refusing connection: {} already open", self.limit.max);
+            metrics().rejected_connections.inc();
+            refuse(&io, self.limit.max).await;
+            return Ok(());
+        }
+        metrics().active_connections.set(active as f64);
+        ctx.call(&self.service, io).await
+    }
+}
+
+/// Answer a connection over the limit with a 503 problem detail and close it.
+async fn refuse(io: &Io, max: usize) {
+    // Wait for the request head: closing with it unread would reset the
+    // connection and could discard the answer before the client reads it
+    let _ = ntex::time::timeout(REFUSED_HEAD_TIMEOUT, async {
+        let mut head = Vec::new();
+        while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+            match io.recv(&BytesCodec).await {
+                Ok(Some(chunk)) => head.extend_from_slice(&chunk),
+                _ => break,
+            }
+        }
+    })
+    .await;
+
+    let body = serde_json::to_string(&ProblemDetail::from(&AppError::TooManyConnections(max))).unwrap_or_default();
+    let response = format!(
+        "HTTP/1.1 503 Service Unavailable\r\ncontent-type: application/problem+json\r\n\
+         content-length: {}\r\nretry-after: 1\r\nconnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    let _ = io.send(Bytes::from(response), &BytesCodec).await;
+    let _ = io.shutdown().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ntex::web::test::{call_service, init_service, server, TestRequest};
+    use ntex::web::{App, HttpResponse};
+    use std::io;
+    use std::sync::Mutex;
+
+    /// Log sink shared between the subscriber and the test.
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Send `req` through `RequestLogger` to a handler that logs, returning
+    /// the captured log lines.
+    async fn logged_lines(req: TestRequest) -> Vec<String> {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let app = init_service(App::new().wrap(RequestLogger).route(
+            "/traced",
+            web::get().to(|| async {
+                info!("Inside handler");
+                HttpResponse::Ok().finish()
+            }),
+        ))
+        .await;
+        let res = call_service(&app, req.uri("/traced").to_request()).await;
+        assert!(res.status().is_success());
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        output.lines().map(str::to_string).collect()
+    }
+
+    #[ntex::test]
+    async fn spans_carry_x_trace_id() {
+        let lines = logged_lines(TestRequest::get().header("X-Trace-ID", "abc123")).await;
+        let handler = lines.iter().find(|line| line.contains("Inside handler")).unwrap();
+        assert!(handler.contains("trace_id=abc123"), "{}", handler);
+        let handled = lines.iter().find(|line| line.contains("Request handled")).unwrap();
+        assert!(handled.contains("trace_id=abc123"), "{}", handled);
+    }
+
+    #[ntex::test]
+    async fn spans_carry_traceparent_trace_id() {
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let lines = logged_lines(TestRequest::get().header("traceparent", traceparent)).await;
+        let handler = lines.iter().find(|line| line.contains("Inside handler")).unwrap();
+        assert!(handler.contains("trace_id=4bf92f3577b34da6a3ce929d0e0e4736"), "{}", handler);
+    }
+
+    #[ntex::test]
+    async fn spans_generate_trace_id_without_header() {
+        let lines = logged_lines(TestRequest::get()).await;
+        let handler = lines.iter().find(|line| line.contains("Inside handler")).unwrap();
+        let id = handler.split("trace_id=").nth(1).unwrap().split([' ', '}', ':']).next().unwrap();
+        assert!(uuid::Uuid::parse_str(id).is_ok(), "{}", handler);
+    }
+
+    #[ntex::test]
+    async fn pushes_carry_rate_limit_headers_and_limited_ones_retry_after() {
+        crate::test_support::init_metrics();
+        let limiter = Arc::new(RateLimiter::new(2));
+        // Peer addresses need a real connection
+        let srv = server(move || {
+            App::new()
+                .wrap(RateLimit::new(limiter.clone()))
+                .route("/push/", web::get().to(|| async { HttpResponse::Ok().finish() }))
+        });
+
+        for remaining in ["1", "0"] {
+            let res = srv.get("/push/").send().await.unwrap();
+            assert!(res.status().is_success());
+            assert_eq!(res.headers().get("X-RateLimit-Limit").unwrap(), "2");
+            assert_eq!(res.headers().get("X-RateLimit-Remaining").unwrap(), remaining);
+        }
+        let res = srv.get("/push/").send().await.unwrap();
+        assert_eq!(res.status(), ntex::http::StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers().get("X-RateLimit-Remaining").unwrap(), "0");
+        let retry_after: u64 = res.headers().get("Retry-After").unwrap().to_str().unwrap().parse().unwrap();
+        assert!(retry_after >= 1);
+    }
+}
@@ -0,0 +1,324 @@
+use ntex::util::{select, Either};
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tracing::{debug, info, warn};
+
+use crate::error::AppError;
+use crate::metrics::metrics;
+use crate::shutdown;
+use crate::{params_from_json, process_reading, sanitize_params, AppState, PushProtocol, WeatherData};
+
+/// Port brokers listen on when the URL doesn't name one.
+const DEFAULT_PORT: u16 = 1883;
+/// Keep-alive interval announced to the broker; a ping is sent at half of it.
+const KEEP_ALIVE: Duration = Duration::from_secs(60);
+/// Delay before reconnecting after the broker connection is lost.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// Packet identifier of our single SUBSCRIBE.
+const SUBSCRIBE_ID: u16 = 1;
+
+// MQTT 3.1.1 control packet types, in the high nibble of the first byte
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PUBACK: u8 = 0x40;
+const SUBSCRIBE: u8 = 0x82;
+const SUBACK: u8 = 0x90;
+const PINGREQ: u8 = 0xc0;
+const PINGRESP: u8 = 0xd0;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MqttError {
+    #[error("invalid broker URL {0:?}, expected mqtt://host[:port]")]
+    InvalidUrl(String),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("connection refused by broker with code {0}")]
+    Refused(u8),
+    #[error("subscription to {0} rejected by broker")]
+    SubscribeRejected(String),
+    #[error("malformed packet: {0}")]
+    Malformed(&'static str),
+    #[error("connection closed by broker")]
+    Closed,
+}
+
+impl WeatherData {
+    /// Parse a reading an Ecowitt gateway published over MQTT. The JSON
+    /// object carries the same fields as an HTTP push, with values sent as
+    /// either strings or numbers.
+    pub fn from_mqtt_json(bytes: &[u8]) -> Result<WeatherData, AppError> {
+        let params = params_from_json(bytes)?;
+        let query = serde_urlencoded::to_string(sanitize_params(params)).map_err(|e| AppError::Parse(e.to_string()))?;
+        WeatherData::from_query(&query).map_err(|e| AppError::Parse(e.to_string()))
+    }
+}
+
+/// `host:port` of the broker named by an `mqtt://` URL.
+fn broker_address(url: &str) -> Result<String, MqttError> {
+    let authority = url
+        .strip_prefix("mqtt://")
+        .map(|rest| rest.trim_end_matches('/'))
+        .filter(|authority| !authority.is_empty() && !authority.contains('/'))
+        .ok_or_else(|| MqttError::InvalidUrl(url.to_string()))?;
+    if authority.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok()) {
+        Ok(authority.to_string())
+    } else {
+        Ok(format!("{}:{}", authority, DEFAULT_PORT))
+    }
+}
+
+fn put_remaining_length(out: &mut Vec<u8>, mut len: usize) {
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        out.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut out = vec![header];
+    put_remaining_length(&mut out, body.len());
+    out.extend_from_slice(body);
+    out
+}
+
+fn connect_packet(client_id: &str) -> Vec<u8> {
+    let mut body = Vec::new();
+    put_str(&mut body, "MQTT");
+    body.push(4); // Protocol level 3.1.1
+    body.push(0x02); // Clean session
+    body.extend_from_slice(&(KEEP_ALIVE.as_secs() as u16).to_be_bytes());
+    put_str(&mut body, client_id);
+    packet(CONNECT, &body)
+}
+
+fn subscribe_packet(topic: &str) -> Vec<u8> {
+    let mut body = SUBSCRIBE_ID.to_be_bytes().to_vec();
+    put_str(&mut body, topic);
+    body.push(0); // QoS 0
+    packet(SUBSCRIBE, &body)
+}
+
+/// Split the first complete packet off `buf`, if one has fully arrived.
+fn take_packet(buf: &mut Vec<u8>) -> Result<Option<(u8, Vec<u8>)>, MqttError> {
+    let mut len = 0;
+    let mut offset = 1;
+    loop {
+        let Some(&byte) = buf.get(offset) else {
+            return Ok(None);
+        };
+        len += usize::from(byte & 0x7f) << (7 * (offset - 1));
+        offset += 1;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        if offset > 4 {
+            return Err(MqttError::Malformed("remaining length longer than 4 bytes"));
+        }
+    }
+    if buf.len() < offset + len {
+        return Ok(None);
+    }
+    let header = buf[0];
+    let body = buf[offset..offset + len].to_vec();
+    buf.drain(..offset + len);
+    Ok(Some((header, body)))
+}
+
+/// Split a PUBLISH body into its topic, packet identifier (for QoS 1 and 2)
+/// and payload.
+fn parse_publish(header: u8, body: &[u8]) -> Result<(String, Option<u16>, &[u8]), MqttError> {
+    let topic_len = body
+        .get(..2)
+        .map(|len| usize::from(u16::from_be_bytes([len[0], len[1]])))
+        .ok_or(MqttError::Malformed("PUBLISH too short"))?;
+    let topic = body
+        .get(2..2 + topic_len)
+        .ok_or(MqttError::Malformed("PUBLISH topic truncated"))?;
+    let topic = String::from_utf8_lossy(topic).into_owned();
+    let rest = &body[2 + topic_len..];
+    if (header >> 1) & 0x03 == 0 {
+        return Ok((topic, None, rest));
+    }
+    let id = rest
+        .get(..2)
+        .map(|id| u16::from_be_bytes([id[0], id[1]]))
+        .ok_or(MqttError::Malformed("PUBLISH packet identifier missing"))?;
+    Ok((topic, Some(id), &rest[2..]))
+}
+
+/// Feed one published reading through the same checks and consumers as
+/// an HTTP push.
+fn handle_message(state: &AppState, topic: &str, payload: &[u8]) {
+    let data = match WeatherData::from_mqtt_json(payload) {
+        Ok(data) => data,
+        Err(e) => {
+            warn!("Ignoring MQTT message on {} that isn't a reading: {}", topic, e);
+            metrics().record_push_error("unknown");
+            return;
+        }
+    };
+    info!("Received MQTT weather data on {}: {:?}", topic, data);
+    if let Err(e) = process_reading(state, data, PushProtocol::Mqtt) {
+        warn!("Rejected MQTT message on {}: {}", topic, e);
+    }
+}
+
+/// Connect to the broker and process readings until the connection drops.
+async fn subscribe(state: &AppState, url: &str, topic: &str) -> Result<(), MqttError> {
+    let mut stream = TcpStream::connect(broker_address(url)?).await?;
+    let client_id = format!("stormcastrs-{}", uuid::Uuid::new_v4().simple());
+    stream.write_all(&connect_packet(&client_id)).await?;
+
+    let mut buf = Vec::new();
+    let mut ping = tokio::time::interval(KEEP_ALIVE / 2);
+    ping.tick().await;
+    loop {
+        while let Some((header, body)) = take_packet(&mut buf)? {
+            match header & 0xf0 {
+                CONNACK => match body.get(1) {
+                    Some(0) => {
+                        info!("Connected to MQTT broker at {}", url);
+                        stream.write_all(&subscribe_packet(topic)).await?;
+                    }
+                    Some(&code) => return Err(MqttError::Refused(code)),
+                    None => return Err(MqttError::Malformed("CONNACK too short")),
+                },
+                SUBACK => {
+                    if body.get(2).is_none_or(|&code| code == 0x80) {
+                        return Err(MqttError::SubscribeRejected(topic.to_string()));
+                    }
+                    info!("Subscribed to MQTT topic {}", topic);
+                }
+                PUBLISH => {
+                    let (message_topic, id, payload) = parse_publish(header, &body)?;
+                    handle_message(state, &message_topic, payload);
+                    if let Some(id) = id {
+                        stream.write_all(&packet(PUBACK, &id.to_be_bytes())).await?;
+                    }
+                }
+                PINGRESP => debug!("MQTT broker answered ping"),
+                other => debug!("Ignoring MQTT packet type {:#04x}", other),
+            }
+        }
+
+        match select(stream.read_buf(&mut buf), ping.tick()).await {
+            Either::Left(read) => {
+                if read? == 0 {
+                    return Err(MqttError::Closed);
+                }
+            }
+            Either::Right(_) => {
+                if shutdown::is_shutting_down() {
+                    return Ok(());
+                }
+                stream.write_all(&[PINGREQ, 0]).await?;
+            }
+        }
+    }
+}
+
+/// Subscribe to readings published by gateways that push over MQTT instead
+/// of HTTP, reconnecting whenever the broker connection is lost.
+pub async fn subscribe_loop(state: AppState) {
+    let Some(url) = state.config.mqtt_url.clone() else {
+        return;
+    };
+    let topic = state.config.mqtt_topic.clone();
+    loop {
+        if let Err(e) = subscribe(&state, &url, &topic).await {
+            warn!("MQTT connection to {} failed: {}", url, e);
+        }
+        if shutdown::is_shutting_down() {
+            break;
+        }
+        ntex::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn broker_addresses_default_the_port() {
+        assert_eq!(broker_address("mqtt://broker").unwrap(), "broker:1883");
+        assert_eq!(broker_address("mqtt://broker:8883/").unwrap(), "broker:8883");
+        assert!(broker_address("http://broker").is_err());
+        assert!(broker_address("mqtt://broker/topic").is_err());
+    }
+
+    #[test]
+    fn packets_are_encoded() {
+        let connect = connect_packet("id");
+        assert_eq!(connect[..2], [CONNECT, 14]);
+        assert_eq!(connect[2..], *b"\x00\x04MQTT\x04\x02\x00\x3c\x00\x02id");
+        assert_eq!(subscribe_packet("w/#"), b"\x82\x08\x00\x01\x00\x03w/#\x00");
+
+        let mut long = Vec::new();
+        put_remaining_length(&mut long, 321);
+        assert_eq!(long, [0xc1, 0x02]);
+    }
+
+    #[test]
+    fn packets_are_split_once_complete() {
+        let mut buf = packet(PUBLISH, &[0; 200]);
+        let tail = buf.split_off(100);
+        assert_eq!(take_packet(&mut buf).unwrap(), None);
+        buf.extend_from_slice(&tail);
+        buf.extend_from_slice(&[PINGRESP, 0]);
+
+        let (header, body) = take_packet(&mut buf).unwrap().unwrap();
+        assert_eq!((header, body.len()), (PUBLISH, 200));
+        assert_eq!(take_packet(&mut buf).unwrap(), Some((PINGRESP, Vec::new())));
+        assert!(buf.is_empty());
+
+        let mut bad = vec![PUBLISH, 0xff, 0xff, 0xff, 0xff, 0x01];
+        assert!(take_packet(&mut bad).is_err());
+    }
+
+    #[test]
+    fn publish_carries_an_id_above_qos_0() {
+        let body = b"\x00\x03w/xpayload";
+        let (topic, id, payload) = parse_publish(PUBLISH, body).unwrap();
+        assert_eq!((topic.as_str(), id, payload), ("w/x", None, &b"payload"[..]));
+
+        let body = b"\x00\x03w/x\x00\x07payload";
+        let (_, id, payload) = parse_publish(PUBLISH | 0x02, body).unwrap();
+        assert_eq!((id, payload), (Some(7), &b"payload"[..]));
+
+        assert!(parse_publish(PUBLISH, b"\x00\x09w").is_err());
+    }
+
+    #[test]
+    fn messages_go_through_the_push_pipeline() {
+        let state = test_support::state(&[("STORMCAST_STATION_BLOCKLIST", "mqttblocked")]);
+        handle_message(&state, "weather/a", br#"{"PASSKEY": "mqttstation", "tempf": "55.5", "humidity": 60}"#);
+        handle_message(&state, "weather/b", br#"{"PASSKEY": "mqttblocked", "tempf": 50.0}"#);
+        handle_message(&state, "weather/c", b"not json");
+
+        let temp = |station| test_support::series_value(metrics(), "weather_temperature_fahrenheit", station);
+        assert_eq!(temp("mqttstation"), Some(55.5));
+        assert_eq!(temp("mqttblocked"), None);
+        let pushes = [("station", "mqttstation"), ("protocol_version", "mqtt")];
+        assert_eq!(test_support::sample(metrics(), "weather_push_total", &pushes), Some(1.0));
+        // The latest reading is shared with /data like any other push
+        let (latest, _) = state.latest.get().unwrap();
+        assert_eq!(latest.station_id(), Some("mqttstation"));
+    }
+}
@@ -0,0 +1,126 @@
+use ntex::web;
+use serde::Deserialize;
+use std::collections::HashMap;
+use tracing::{debug, info};
+
+use crate::auth::query_api_key;
+use crate::error::AppError;
+use crate::metrics::metrics;
+use crate::{authorize, ingest, AppState, PushProtocol, WeatherData};
+
+/// Station ID used for openHAB pushes, which don't identify a station.
+const OPENHAB_STATION_ID: &str = "openhab";
+
+/// Which `WeatherData` field each openHAB item name feeds.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OpenHabConfig {
+    pub items: HashMap<String, String>,
+}
+
+impl Default for OpenHabConfig {
+    fn default() -> Self {
+        let items = [
+            ("outdoor_temperature", "tempf"),
+            ("outdoor_humidity", "humidity"),
+            ("wind_speed", "windspeedmph"),
+            ("wind_gust", "windgustmph"),
+            ("wind_direction", "winddir"),
+            ("uv_index", "uv"),
+            ("solar_radiation", "solarradiation"),
+            ("rain_daily", "dailyrainin"),
+            ("pressure", "baromrelin"),
+            ("indoor_temperature", "tempinf"),
+            ("indoor_humidity", "humidityin"),
+        ];
+        OpenHabConfig {
+            items: items
+                .into_iter()
+                .map(|(item, field)| (item.to_string(), field.to_string()))
+                .collect(),
+        }
+    }
+}
+
+/// Payload POSTed by the openHAB weather binding.
+#[derive(Debug, Deserialize)]
+pub struct OpenHabData {
+    pub channel: Option<String>,
+    pub items: Vec<OpenHabItem>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenHabItem {
+    pub name: String,
+    /// Value with its unit, e.g. `72.5 °F`.
+    pub state: String,
+}
+
+/// The numeric part of an item state, without its unit.
+fn strip_unit(state: &str) -> &str {
+    state.split_whitespace().next().unwrap_or("")
+}
+
+impl OpenHabData {
+    /// Map items onto `WeatherData` fields through the configured table.
+    /// Items with no mapping are ignored.
+    pub fn to_weather_data(&self, config: &OpenHabConfig) -> Result<WeatherData, AppError> {
+        let mut params = form_urlencoded::Serializer::new(String::new());
+        params.append_pair("stationid", OPENHAB_STATION_ID);
+        for item in &self.items {
+            match config.items.get(&item.name) {
+                Some(field) => {
+                    params.append_pair(field, strip_unit(&item.state));
+                }
+                None => debug!("Ignoring unmapped openHAB item {}", item.name),
+            }
+        }
+        WeatherData::from_query(&params.finish()).map_err(|e| AppError::Parse(e.to_string()))
+    }
+}
+
+/// Receive observations from the openHAB weather binding.
+pub async fn handle_push_openhab(
+    req: web::HttpRequest,
+    state: web::types::State<AppState>,
+    body: web::types::Json<OpenHabData>,
+) -> Result<web::HttpResponse, AppError> {
+    let authorized = authorize(&state, query_api_key(&req).as_deref())?;
+    let data = body.into_inner();
+    info!(
+        "Received openHAB {} data: {:?}",
+        data.channel.as_deref().unwrap_or("unknown"),
+        data.items
+    );
+
+    let weather_data = data.to_weather_data(&state.config.openhab).inspect_err(|_| {
+        metrics().record_push_error(OPENHAB_STATION_ID);
+    })?;
+    ingest(&state, authorized, weather_data, PushProtocol::OpenHab).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Metrics;
+    use crate::test_support;
+
+    #[test]
+    fn items_are_mapped_with_their_units_stripped() {
+        let payload = r#"{"channel": "weather", "items": [
+            {"name": "outdoor_temperature", "state": "72.5 °F"},
+            {"name": "outdoor_humidity", "state": "45 %"},
+            {"name": "wind_speed", "state": "8.25 mph"},
+            {"name": "unmapped_item", "state": "1 W"}
+        ]}"#;
+        let data: OpenHabData = serde_json::from_str(payload).unwrap();
+        let weather_data = data.to_weather_data(&OpenHabConfig::default()).unwrap();
+
+        let metrics = Metrics::new(&test_support::config(&[])).unwrap();
+        metrics.update(&weather_data).unwrap();
+        let gauge = |name| test_support::series_value(&metrics, name, OPENHAB_STATION_ID);
+        assert_eq!(gauge("weather_temperature_fahrenheit"), Some(72.5));
+        assert_eq!(gauge("weather_humidity_percentage"), Some(45.0));
+        assert_eq!(gauge("weather_windspeed_mph"), Some(8.25));
+    }
+}
@@ -0,0 +1,158 @@
+use prometheus::proto::{LabelPair, MetricFamily, MetricType};
+use std::fmt::Write;
+
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Encode metric families in the OpenMetrics 1.0 text format, which the
+/// prometheus crate doesn't provide. Differs from the Prometheus text format
+/// mainly in dropping `_total` from counter family names and ending with `# EOF`.
+pub fn encode(families: &[MetricFamily]) -> String {
+    let mut out = String::new();
+    for family in families {
+        let (kind, name) = match family.get_field_type() {
+            MetricType::COUNTER => ("counter", family.get_name().trim_end_matches("_total")),
+            MetricType::GAUGE => ("gauge", family.get_name()),
+            MetricType::HISTOGRAM => ("histogram", family.get_name()),
+            MetricType::SUMMARY => ("summary", family.get_name()),
+            MetricType::UNTYPED => ("unknown", family.get_name()),
+        };
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        let _ = writeln!(out, "# HELP {} {}", name, escape(family.get_help(), false));
+
+        for metric in family.get_metric() {
+            let labels = metric.get_label();
+            match family.get_field_type() {
+                MetricType::COUNTER => {
+                    sample(&mut out, name, "_total", labels, None, metric.get_counter().get_value());
+                }
+                MetricType::GAUGE => sample(&mut out, name, "", labels, None, metric.get_gauge().get_value()),
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    for bucket in histogram.get_bucket() {
+                        let le = format_value(bucket.get_upper_bound());
+                        sample(&mut out, name, "_bucket", labels, Some(&le), bucket.get_cumulative_count() as f64);
+                    }
+                    let count = histogram.get_sample_count() as f64;
+                    sample(&mut out, name, "_bucket", labels, Some("+Inf"), count);
+                    sample(&mut out, name, "_sum", labels, None, histogram.get_sample_sum());
+                    sample(&mut out, name, "_count", labels, None, count);
+                }
+                MetricType::SUMMARY => {
+                    let summary = metric.get_summary();
+                    for quantile in summary.get_quantile() {
+                        let q = format_value(quantile.get_quantile());
+                        sample_with(&mut out, name, "", labels, Some(("quantile", &q)), quantile.get_value());
+                    }
+                    sample(&mut out, name, "_sum", labels, None, summary.get_sample_sum());
+                    sample(&mut out, name, "_count", labels, None, summary.get_sample_count() as f64);
+                }
+                MetricType::UNTYPED => sample(&mut out, name, "", labels, None, metric.get_untyped().get_value()),
+            }
+        }
+    }
+    out.push_str("# EOF\n");
+    out
+}
+
+fn sample(out: &mut String, name: &str, suffix: &str, labels: &[LabelPair], le: Option<&str>, value: f64) {
+    sample_with(out, name, suffix, labels, le.map(|le| ("le", le)), value);
+}
+
+fn sample_with(
+    out: &mut String,
+    name: &str,
+    suffix: &str,
+    labels: &[LabelPair],
+    extra: Option<(&str, &str)>,
+    value: f64,
+) {
+    let _ = write!(out, "{}{}", name, suffix);
+    let pairs = labels
+        .iter()
+        .map(|label| (label.get_name(), label.get_value()))
+        .chain(extra);
+    let mut first = true;
+    for (label, label_value) in pairs {
+        out.push(if first { '{' } else { ',' });
+        first = false;
+        let _ = write!(out, "{}=\"{}\"", label, escape(label_value, true));
+    }
+    if !first {
+        out.push('}');
+    }
+    let _ = writeln!(out, " {}", format_value(value));
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value == f64::INFINITY {
+        "+Inf".to_string()
+    } else if value == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn escape(value: &str, quote: bool) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '"' if quote => escaped.push_str("\\\""),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use ntex::http::header::CONTENT_TYPE as CONTENT_TYPE_HEADER;
+    use ntex::web::{self, test};
+    use prometheus::{Encoder, IntCounter, Registry, TextEncoder};
+
+    #[ntex::test]
+    async fn accept_header_selects_openmetrics() {
+        let app = test::init_service(
+            web::App::new()
+                .state(test_support::state(&[]))
+                .route("/metrics", web::get().to(crate::handle_metrics)),
+        )
+        .await;
+
+        let req = test::TestRequest::with_uri("/metrics")
+            .header("Accept", "application/openmetrics-text; version=1.0.0")
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.headers().get(CONTENT_TYPE_HEADER).unwrap(), CONTENT_TYPE);
+        let body = test::read_body(res).await;
+        assert!(body.ends_with(b"# EOF\n"));
+
+        let res = test::call_service(&app, test::TestRequest::with_uri("/metrics").to_request()).await;
+        assert_eq!(res.headers().get(CONTENT_TYPE_HEADER).unwrap(), "text/plain; charset=utf-8");
+        assert!(!test::read_body(res).await.ends_with(b"# EOF\n"));
+    }
+
+    #[test]
+    fn counters_drop_total_from_the_family_name() {
+        let registry = Registry::new();
+        let counter = IntCounter::new("pushes_total", "Pushes with \"quotes\"\nand lines").unwrap();
+        registry.register(Box::new(counter.clone())).unwrap();
+        counter.inc_by(3);
+
+        let text = encode(&registry.gather());
+        assert_eq!(
+            text,
+            "# TYPE pushes counter\n# HELP pushes Pushes with \"quotes\"\\nand lines\npushes_total 3\n# EOF\n"
+        );
+        // The Prometheus text format keeps the suffix on the family
+        let mut prometheus = Vec::new();
+        TextEncoder::new().encode(&registry.gather(), &mut prometheus).unwrap();
+        assert!(String::from_utf8(prometheus).unwrap().contains("# TYPE pushes_total counter"));
+    }
+}
@@ -0,0 +1,253 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::config::ConfigError;
+use crate::WeatherData;
+
+/// Places each float field is rounded to unless configured otherwise.
+const DEFAULT_PLACES: [(&str, u8); 36] = [
+    ("tempf", 1),
+    ("windspeedmph", 2),
+    ("windgustmph", 2),
+    ("maxdailygust", 2),
+    ("solarradiation", 2),
+    ("hourlyrainin", 3),
+    ("eventrainin", 3),
+    ("dailyrainin", 3),
+    ("weeklyrainin", 3),
+    ("monthlyrainin", 3),
+    ("yearlyrainin", 3),
+    ("tempinf", 1),
+    ("baromrelin", 3),
+    ("baromabsin", 3),
+    ("visibility_km", 2),
+    ("visibility_miles", 2),
+    ("soiltempc1", 1),
+    ("soiltempc2", 1),
+    ("soiltempc3", 1),
+    ("soiltempc4", 1),
+    ("temp1f", 1),
+    ("temp2f", 1),
+    ("temp3f", 1),
+    ("temp4f", 1),
+    ("temp5f", 1),
+    ("temp6f", 1),
+    ("temp7f", 1),
+    ("temp8f", 1),
+    ("tf_ch1", 1),
+    ("tf_ch2", 1),
+    ("tf_ch3", 1),
+    ("tf_ch4", 1),
+    ("pm25", 1),
+    ("pm25_avg_24h", 1),
+    ("pm10", 1),
+    ("pm_in_temp_f", 1),
+];
+
+/// Most decimal places a field may be rounded to; f32 readings carry no
+/// more than about seven significant digits.
+const MAX_PLACES: u8 = 6;
+
+/// Decimal places per measurement type, from `STORMCAST_ROUND_<TYPE>`,
+/// replacing the built-in places of every field of that type.
+#[derive(Debug, Clone, Default)]
+pub struct RoundingConfig {
+    pub temperature: Option<u8>,
+    pub wind: Option<u8>,
+    pub rain: Option<u8>,
+    pub pressure: Option<u8>,
+    pub solar: Option<u8>,
+    pub visibility: Option<u8>,
+    pub air_quality: Option<u8>,
+}
+
+impl RoundingConfig {
+    /// Each measurement type's setting name, places and fields.
+    fn types(&self) -> [(&'static str, Option<u8>, &'static [&'static str]); 7] {
+        [
+            (
+                "STORMCAST_ROUND_TEMPERATURE",
+                self.temperature,
+                &[
+                    "tempf", "tempinf", "temp1f", "temp2f", "temp3f", "temp4f", "temp5f", "temp6f", "temp7f",
+                    "temp8f", "tf_ch1", "tf_ch2", "tf_ch3", "tf_ch4", "soiltempc1", "soiltempc2", "soiltempc3", "soiltempc4", "pm_in_temp_f",
+                ],
+            ),
+            ("STORMCAST_ROUND_WIND", self.wind, &["windspeedmph", "windgustmph", "maxdailygust"]),
+            (
+                "STORMCAST_ROUND_RAIN",
+                self.rain,
+                &["hourlyrainin", "eventrainin", "dailyrainin", "weeklyrainin", "monthlyrainin", "yearlyrainin"],
+            ),
+            ("STORMCAST_ROUND_PRESSURE", self.pressure, &["baromrelin", "baromabsin"]),
+            ("STORMCAST_ROUND_SOLAR", self.solar, &["solarradiation"]),
+            ("STORMCAST_ROUND_VISIBILITY", self.visibility, &["visibility_km", "visibility_miles"]),
+            ("STORMCAST_ROUND_AIR_QUALITY", self.air_quality, &["pm25", "pm25_avg_24h", "pm10"]),
+        ]
+    }
+}
+
+/// Where a value is being written to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Prometheus,
+    Json,
+    InfluxDb,
+}
+
+/// Decimal places per push field for each output format. Fields without an
+/// entry are written at full precision.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrecisionProfile {
+    pub prometheus: HashMap<String, u8>,
+    pub json: HashMap<String, u8>,
+    pub influxdb: HashMap<String, u8>,
+}
+
+pub fn round_to_places(value: f32, places: i32) -> f64 {
+    let factor = 10f32.powi(places);
+    (value * factor).round() as f64 / factor as f64
+}
+
+/// Widen to f64 via the shortest decimal form, so 0.1 stays 0.1 rather than
+/// exposing f32 representation error as 0.10000000149011612.
+fn widen(value: f32) -> f64 {
+    value.to_string().parse().unwrap_or(value.into())
+}
+
+impl PrecisionProfile {
+    /// The built-in Prometheus and JSON precisions with `rounding`, then the
+    /// per-field `overrides`, applied. InfluxDB keeps full precision by
+    /// default and is only affected by its own overrides.
+    pub fn with_overrides(
+        rounding: &RoundingConfig,
+        overrides: PrecisionProfile,
+    ) -> Result<PrecisionProfile, ConfigError> {
+        let known = WeatherData::default().fields().map(|(field, _)| field);
+        for (field, &places) in overrides
+            .prometheus
+            .iter()
+            .chain(&overrides.json)
+            .chain(&overrides.influxdb)
+        {
+            if !known.contains(&field.as_str()) {
+                return Err(ConfigError::Invalid(format!("precision set for unknown field {:?}", field)));
+            }
+            if places > MAX_PLACES {
+                return Err(ConfigError::Invalid(format!(
+                    "precision for {} must be between 0 and {}",
+                    field, MAX_PLACES
+                )));
+            }
+        }
+
+        let types = rounding.types();
+        for (name, places, _) in &types {
+            if places.is_some_and(|places| places > MAX_PLACES) {
+                return Err(ConfigError::Invalid(format!("{} must be between 0 and {}", name, MAX_PLACES)));
+            }
+        }
+
+        let defaults = || -> HashMap<String, u8> {
+            let mut places: HashMap<String, u8> = DEFAULT_PLACES
+                .iter()
+                .map(|&(field, places)| (field.to_string(), places))
+                .collect();
+            for (_, type_places, fields) in &types {
+                if let Some(type_places) = *type_places {
+                    places.extend(fields.iter().map(|field| (field.to_string(), type_places)));
+                }
+            }
+            places
+        };
+        let mut profile = PrecisionProfile {
+            prometheus: defaults(),
+            json: defaults(),
+            influxdb: HashMap::new(),
+        };
+        profile.prometheus.extend(overrides.prometheus);
+        profile.json.extend(overrides.json);
+        profile.influxdb.extend(overrides.influxdb);
+        Ok(profile)
+    }
+
+    /// Round `value` of push field `field` for writing to `format`.
+    pub fn round_for_format(&self, value: f32, field: &str, format: OutputFormat) -> f64 {
+        let places = match format {
+            OutputFormat::Prometheus => &self.prometheus,
+            OutputFormat::Json => &self.json,
+            OutputFormat::InfluxDb => &self.influxdb,
+        };
+        match places.get(field) {
+            Some(&places) => round_to_places(value, places.into()),
+            None => widen(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, ConfigEnv, ConfigFile};
+    use crate::metrics::Metrics;
+    use crate::test_support;
+
+    fn profile(overrides: &str) -> Result<PrecisionProfile, ConfigError> {
+        PrecisionProfile::with_overrides(&RoundingConfig::default(), serde_json::from_str(overrides).unwrap())
+    }
+
+    #[test]
+    fn daily_rain_precision_differs_per_format() {
+        let profile = profile(r#"{"influxdb": {"dailyrainin": 6}}"#).unwrap();
+        let rain = 1.2345678;
+        assert_eq!(profile.round_for_format(rain, "dailyrainin", OutputFormat::Prometheus), 1.235);
+        assert_eq!(profile.round_for_format(rain, "dailyrainin", OutputFormat::Json), 1.235);
+        assert!((profile.round_for_format(rain, "dailyrainin", OutputFormat::InfluxDb) - 1.234568).abs() < 1e-6);
+    }
+
+    #[test]
+    fn fields_without_places_keep_full_precision() {
+        let profile = profile("{}").unwrap();
+        assert_eq!(profile.round_for_format(0.1, "dailyrainin", OutputFormat::InfluxDb), 0.1);
+        assert_eq!(profile.round_for_format(12.34, "humidity", OutputFormat::Prometheus), 12.34);
+    }
+
+    #[test]
+    fn type_rounding_replaces_the_defaults() {
+        let rounding = RoundingConfig {
+            rain: Some(1),
+            ..Default::default()
+        };
+        let profile = PrecisionProfile::with_overrides(&rounding, PrecisionProfile::default()).unwrap();
+        assert_eq!(profile.round_for_format(1.26, "hourlyrainin", OutputFormat::Prometheus), 1.3);
+        assert_eq!(profile.round_for_format(71.26, "tempf", OutputFormat::Prometheus), 71.3);
+    }
+
+    #[test]
+    fn invalid_overrides_are_rejected() {
+        assert!(profile(r#"{"prometheus": {"nonsense": 2}}"#).is_err());
+        assert!(profile(r#"{"json": {"tempf": 7}}"#).is_err());
+        assert!(serde_json::from_str::<PrecisionProfile>(r#"{"graphite": {"tempf": 2}}"#).is_err());
+    }
+
+    #[test]
+    fn configured_temperature_places_reach_the_gauges() {
+        let config = test_support::config(&[("STORMCAST_ROUND_TEMPERATURE", "2")]);
+        let metrics = Metrics::new(&config).unwrap();
+        let push = "PASSKEY=round2&tempf=71.2345&temp1f=65.678&dailyrainin=0.12345";
+        metrics.update(&WeatherData::from_query(push).unwrap()).unwrap();
+        assert_eq!(test_support::series_value(&metrics, "weather_temperature_fahrenheit", "round2"), Some(71.23));
+        assert_eq!(test_support::sample(&metrics, "weather_channel_temperature_fahrenheit", &[("station", "round2"), ("channel", "1")]), Some(65.68));
+        // Other types keep their built-in places
+        assert_eq!(test_support::series_value(&metrics, "weather_daily_rain_in", "round2"), Some(0.123));
+    }
+
+    #[test]
+    fn rounding_places_above_six_are_rejected() {
+        let env = |value: &str| ConfigEnv::from_vars([("STORMCAST_ROUND_RAIN".to_string(), value.to_string())]);
+        assert!(Config::merge(ConfigFile::default(), env("6")).is_ok());
+        assert!(Config::merge(ConfigFile::default(), env("7")).is_err());
+        assert!(Config::merge(ConfigFile::default(), env("-1")).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Rate of change in inches of mercury per hour beyond which pressure is
+/// no longer considered steady.
+const STEADY_THRESHOLD_IN_PER_HOUR: f64 = 0.02;
+
+/// Direction the barometer is heading, exported as +1, 0 or -1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PressureTrend {
+    Rising,
+    Steady,
+    Falling,
+}
+
+impl PressureTrend {
+    pub fn value(self) -> f64 {
+        match self {
+            PressureTrend::Rising => 1.0,
+            PressureTrend::Steady => 0.0,
+            PressureTrend::Falling => -1.0,
+        }
+    }
+}
+
+/// Classifies the barometric pressure trend from the last few readings
+/// taken within a time window.
+#[derive(Debug)]
+pub struct PressureTrendCalculator {
+    readings: VecDeque<(Instant, f64)>,
+    max_readings: usize,
+    window: Duration,
+}
+
+impl PressureTrendCalculator {
+    pub fn new(max_readings: usize, window: Duration) -> Self {
+        Self {
+            readings: VecDeque::with_capacity(max_readings),
+            max_readings: max_readings.max(2),
+            window,
+        }
+    }
+
+    /// Add a reading in inHg taken at `now`, dropping readings that left
+    /// the window or exceed the reading limit.
+    pub fn record(&mut self, pressure: f64, now: Instant) {
+        while let Some(&(at, _)) = self.readings.front() {
+            if now.saturating_duration_since(at) <= self.window && self.readings.len() < self.max_readings {
+                break;
+            }
+            self.readings.pop_front();
+        }
+        self.readings.push_back((now, pressure));
+    }
+
+    /// Trend between the oldest and newest reading, steady until two
+    /// readings some time apart are available.
+    pub fn trend(&self) -> PressureTrend {
+        let (Some(&(oldest_at, oldest)), Some(&(newest_at, newest))) = (self.readings.front(), self.readings.back())
+        else {
+            return PressureTrend::Steady;
+        };
+        let hours = newest_at.saturating_duration_since(oldest_at).as_secs_f64() / 3600.0;
+        if hours == 0.0 {
+            return PressureTrend::Steady;
+        }
+        let rate = (newest - oldest) / hours;
+        if rate > STEADY_THRESHOLD_IN_PER_HOUR {
+            PressureTrend::Rising
+        } else if rate < -STEADY_THRESHOLD_IN_PER_HOUR {
+            PressureTrend::Falling
+        } else {
+            PressureTrend::Steady
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Trend after readings in inHg taken `minutes` apart.
+    fn trend(readings: &[f64], minutes: u64) -> PressureTrend {
+        let start = Instant::now();
+        let mut calculator = PressureTrendCalculator::new(10, Duration::from_secs(3 * 3600));
+        for (i, &pressure) in readings.iter().enumerate() {
+            calculator.record(pressure, start + Duration::from_secs(60 * minutes * i as u64));
+        }
+        calculator.trend()
+    }
+
+    #[test]
+    fn rising_steady_and_falling() {
+        assert_eq!(trend(&[29.90, 29.95, 30.00], 30), PressureTrend::Rising);
+        assert_eq!(trend(&[30.00, 30.01, 30.00], 30), PressureTrend::Steady);
+        assert_eq!(trend(&[30.00, 29.95, 29.90], 30), PressureTrend::Falling);
+        assert_eq!(PressureTrend::Falling.value(), -1.0);
+    }
+
+    #[test]
+    fn steady_until_two_readings_apart() {
+        assert_eq!(trend(&[], 30), PressureTrend::Steady);
+        assert_eq!(trend(&[29.5], 30), PressureTrend::Steady);
+        assert_eq!(trend(&[29.5, 30.5], 0), PressureTrend::Steady);
+    }
+
+    #[test]
+    fn old_readings_leave_the_window() {
+        let start = Instant::now();
+        let mut calculator = PressureTrendCalculator::new(10, Duration::from_secs(3600));
+        calculator.record(29.0, start);
+        calculator.record(30.0, start + Duration::from_secs(1800));
+        assert_eq!(calculator.trend(), PressureTrend::Rising);
+        // Two hours on, only the flat readings since remain
+        calculator.record(30.0, start + Duration::from_secs(7200));
+        calculator.record(30.0, start + Duration::from_secs(9000));
+        assert_eq!(calculator.trend(), PressureTrend::Steady);
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A push arriving sooner than this fraction of the expected interval is anomalous.
+const ANOMALY_RATIO: f64 = 0.2;
+/// Number of consecutive anomalous pushes before a station is backed off.
+const BACKOFF_AFTER: u32 = 5;
+/// Upper bound on a single backoff period.
+const MAX_BACKOFF: Duration = Duration::from_secs(3600);
+/// Weight of the newest sample in the moving average of the push interval.
+const INTERVAL_SMOOTHING: f64 = 0.2;
+
+#[derive(Debug, PartialEq)]
+pub enum PushVerdict {
+    /// Push arrived at the station's usual cadence.
+    Accepted { interval: Option<Duration> },
+    /// Push arrived far sooner than the station's usual cadence.
+    Anomalous { expected: Duration, elapsed: Duration },
+    /// Station is backed off after repeated anomalies; retry after the duration.
+    Throttled(Duration),
+}
+
+#[derive(Debug)]
+struct StationRate {
+    last_push: Instant,
+    interval: Option<Duration>,
+    consecutive_anomalies: u32,
+    consecutive_normal: u32,
+    strikes: u32,
+    backoff_until: Option<Instant>,
+}
+
+/// Learns each station's push interval and flags stations that suddenly start
+/// pushing much faster than usual, which tends to indicate a firmware bug or a
+/// replayed payload. Stations that keep doing so are backed off exponentially.
+#[derive(Debug, Default)]
+pub struct PushRateMonitor {
+    stations: Mutex<HashMap<String, StationRate>>,
+}
+
+impl PushRateMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, station: &str, now: Instant) -> PushVerdict {
+        let mut stations = self.stations.lock().unwrap();
+        let Some(state) = stations.get_mut(station) else {
+            stations.insert(
+                station.to_string(),
+                StationRate {
+                    last_push: now,
+                    interval: None,
+                    consecutive_anomalies: 0,
+                    consecutive_normal: 0,
+                    strikes: 0,
+                    backoff_until: None,
+                },
+            );
+            return PushVerdict::Accepted { interval: None };
+        };
+
+        if let Some(until) = state.backoff_until {
+            if now < until {
+                return PushVerdict::Throttled(until - now);
+            }
+            state.backoff_until = None;
+        }
+
+        let elapsed = now.saturating_duration_since(state.last_push);
+        state.last_push = now;
+
+        match state.interval {
+            Some(expected) if elapsed < expected.mul_f64(ANOMALY_RATIO) => {
+                state.consecutive_normal = 0;
+                state.consecutive_anomalies += 1;
+                if state.consecutive_anomalies >= BACKOFF_AFTER {
+                    let backoff = expected
+                        .saturating_mul(2u32.saturating_pow(state.strikes))
+                        .min(MAX_BACKOFF);
+                    state.backoff_until = Some(now + backoff);
+                    state.strikes += 1;
+                    state.consecutive_anomalies = 0;
+                }
+                PushVerdict::Anomalous { expected, elapsed }
+            }
+            expected => {
+                state.consecutive_anomalies = 0;
+                state.consecutive_normal += 1;
+                if state.consecutive_normal >= BACKOFF_AFTER {
+                    state.strikes = 0;
+                }
+                let interval = match expected {
+                    Some(expected) => expected.mul_f64(1.0 - INTERVAL_SMOOTHING) + elapsed.mul_f64(INTERVAL_SMOOTHING),
+                    None => elapsed,
+                };
+                state.interval = Some(interval);
+                PushVerdict::Accepted { interval: Some(interval) }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    /// Monitor that has learnt a one-minute interval for `station`.
+    fn learnt(station: &str, start: Instant) -> PushRateMonitor {
+        let monitor = PushRateMonitor::new();
+        assert_eq!(monitor.record(station, start), PushVerdict::Accepted { interval: None });
+        assert_eq!(
+            monitor.record(station, start + MINUTE),
+            PushVerdict::Accepted { interval: Some(MINUTE) }
+        );
+        monitor
+    }
+
+    #[test]
+    fn regular_pushes_are_accepted() {
+        let start = Instant::now();
+        let monitor = learnt("steady", start);
+        for n in 2..10 {
+            assert_eq!(
+                monitor.record("steady", start + MINUTE * n),
+                PushVerdict::Accepted { interval: Some(MINUTE) }
+            );
+        }
+    }
+
+    #[test]
+    fn burst_is_anomalous_then_throttled() {
+        let start = Instant::now();
+        let monitor = learnt("burst", start);
+        let mut now = start + MINUTE;
+        for _ in 0..BACKOFF_AFTER {
+            now += Duration::from_secs(1);
+            let verdict = monitor.record("burst", now);
+            assert!(matches!(verdict, PushVerdict::Anomalous { expected: MINUTE, .. }), "{:?}", verdict);
+        }
+
+        // The fifth anomaly backs the station off for one expected interval
+        assert_eq!(
+            monitor.record("burst", now + Duration::from_secs(1)),
+            PushVerdict::Throttled(MINUTE - Duration::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn repeated_backoffs_grow_exponentially() {
+        let start = Instant::now();
+        let monitor = learnt("replay", start);
+        let mut now = start + MINUTE;
+        for expected_backoff in [MINUTE, MINUTE * 2] {
+            for _ in 0..BACKOFF_AFTER {
+                now += Duration::from_secs(1);
+                monitor.record("replay", now);
+            }
+            assert_eq!(monitor.record("replay", now), PushVerdict::Throttled(expected_backoff));
+
+            // Pushing again once the backoff ends keeps the learnt interval
+            now += expected_backoff;
+            assert!(matches!(monitor.record("replay", now), PushVerdict::Accepted { .. }));
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use ntex::web;
+use serde::Deserialize;
+use tracing::info;
+
+use crate::auth::query_api_key;
+use crate::error::AppError;
+use crate::metrics::metrics;
+use crate::{authorize, ingest, AppState, PushProtocol, WeatherData};
+
+/// JSON push schema served on `/push/v2`. Unlike v1 it names fields in
+/// snake_case and requires the station to identify itself.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WeatherDataV2 {
+    pub station_id: Option<String>,
+    pub temp_f: Option<f32>,
+    pub humidity: Option<u8>,
+    pub wind_speed_mph: Option<f32>,
+    pub wind_gust_mph: Option<f32>,
+    pub max_daily_gust_mph: Option<f32>,
+    pub wind_dir: Option<u16>,
+    pub wind_dir_avg10m: Option<u16>,
+    pub uv: Option<u8>,
+    pub solar_radiation: Option<f32>,
+    pub hourly_rain_in: Option<f32>,
+    pub event_rain_in: Option<f32>,
+    pub daily_rain_in: Option<f32>,
+    pub weekly_rain_in: Option<f32>,
+    pub monthly_rain_in: Option<f32>,
+    pub yearly_rain_in: Option<f32>,
+    pub batt_out: Option<u8>,
+    pub temp_in_f: Option<f32>,
+    pub humidity_in: Option<u8>,
+    pub barom_rel_in: Option<f32>,
+    pub barom_abs_in: Option<f32>,
+    pub batt_in: Option<u8>,
+}
+
+impl From<WeatherDataV2> for WeatherData {
+    fn from(v2: WeatherDataV2) -> Self {
+        WeatherData {
+            stationid: v2.station_id,
+            tempf: v2.temp_f,
+            humidity: v2.humidity,
+            windspeedmph: v2.wind_speed_mph,
+            windgustmph: v2.wind_gust_mph,
+            maxdailygust: v2.max_daily_gust_mph,
+            winddir: v2.wind_dir,
+            winddir_avg10m: v2.wind_dir_avg10m,
+            uv: v2.uv,
+            solarradiation: v2.solar_radiation,
+            hourlyrainin: v2.hourly_rain_in,
+            eventrainin: v2.event_rain_in,
+            dailyrainin: v2.daily_rain_in,
+            weeklyrainin: v2.weekly_rain_in,
+            monthlyrainin: v2.monthly_rain_in,
+            yearlyrainin: v2.yearly_rain_in,
+            battout: v2.batt_out,
+            tempinf: v2.temp_in_f,
+            humidityin: v2.humidity_in,
+            baromrelin: v2.barom_rel_in,
+            baromabsin: v2.barom_abs_in,
+            battin: v2.batt_in,
+            ..Default::default()
+        }
+    }
+}
+
+/// Receive a v2 JSON push. The v1 `/push/` endpoint is unaffected.
+pub async fn handle_push_v2(
+    req: web::HttpRequest,
+    state: web::types::State<AppState>,
+    body: web::types::Json<WeatherDataV2>,
+) -> Result<web::HttpResponse, AppError> {
+    let authorized = authorize(&state, query_api_key(&req).as_deref())?;
+    let data = body.into_inner();
+    info!("Received v2 data: {:?}", data);
+
+    if data.station_id.as_deref().is_none_or(str::is_empty) {
+        metrics().record_push_error("unknown");
+        return Err(AppError::BadRequest("station_id is required".to_string()));
+    }
+
+    ingest(&state, authorized, WeatherData::from(data), PushProtocol::V2).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use ntex::http::StatusCode;
+    use ntex::web::test::{call_service, init_service, TestRequest};
+    use ntex::web::App;
+
+    async fn post_v2(body: &str) -> StatusCode {
+        let app = init_service(
+            App::new()
+                .state(test_support::state(&[]))
+                .route("/push/v2", web::post().to(handle_push_v2)),
+        )
+        .await;
+        let req = TestRequest::post()
+            .uri("/push/v2")
+            .header("Content-Type", "application/json")
+            .set_payload(body.to_string())
+            .to_request();
+        call_service(&app, req).await.status()
+    }
+
+    #[ntex::test]
+    async fn v2_push_updates_gauges() {
+        let body = r#"{"station_id": "v2station", "temp_f": 64.4, "humidity": 55, "wind_speed_mph": 7.5}"#;
+        assert_eq!(post_v2(body).await, StatusCode::OK);
+        let gauge = |name| test_support::series_value(metrics(), name, "v2station");
+        assert_eq!(gauge("weather_temperature_fahrenheit"), Some(64.4));
+        assert_eq!(gauge("weather_humidity_percentage"), Some(55.0));
+        assert_eq!(gauge("weather_windspeed_mph"), Some(7.5));
+    }
+
+    #[ntex::test]
+    async fn v2_push_requires_station_id() {
+        assert_eq!(post_v2(r#"{"temp_f": 64.4}"#).await, StatusCode::BAD_REQUEST);
+        assert_eq!(post_v2(r#"{"station_id": "", "temp_f": 64.4}"#).await, StatusCode::BAD_REQUEST);
+    }
+
+    #[ntex::test]
+    async fn v2_push_rejects_v1_field_names() {
+        assert_eq!(post_v2(r#"{"station_id": "v2names", "tempf": 64.4}"#).await, StatusCode::BAD_REQUEST);
+    }
+}
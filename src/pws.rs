@@ -0,0 +1,116 @@
+use ntex::web;
+use serde::Deserialize;
+use std::collections::HashMap;
+use tracing::info;
+
+use crate::auth::{query_api_key, take_api_key};
+use crate::error::AppError;
+use crate::metrics::metrics;
+use crate::rename::FieldRenamer;
+use crate::{authorize, ingest, sanitize_params, AppState, PushProtocol, WeatherData};
+
+/// Upload in the PWSweather.com protocol, which follows Weather Underground's
+/// field names.
+#[derive(Debug, Default, Deserialize)]
+pub struct PwsWeatherData {
+    #[serde(rename = "ID")]
+    pub id: Option<String>,
+    pub dateutc: Option<String>,
+    pub tempf: Option<f32>,
+    pub humidity: Option<u8>,
+    pub windspeedmph: Option<f32>,
+    pub windgustmph: Option<f32>,
+    pub winddir: Option<u16>,
+    pub rainin: Option<f32>,
+    pub dailyrainin: Option<f32>,
+    pub monthrainin: Option<f32>,
+    pub yearrainin: Option<f32>,
+    pub baromin: Option<f32>,
+    pub solarradiation: Option<f32>,
+    #[serde(rename = "UV")]
+    pub uv: Option<u8>,
+}
+
+impl From<PwsWeatherData> for WeatherData {
+    fn from(pws: PwsWeatherData) -> Self {
+        WeatherData {
+            stationid: pws.id,
+            dateutc: pws.dateutc,
+            tempf: pws.tempf,
+            humidity: pws.humidity,
+            windspeedmph: pws.windspeedmph,
+            windgustmph: pws.windgustmph,
+            winddir: pws.winddir,
+            hourlyrainin: pws.rainin,
+            dailyrainin: pws.dailyrainin,
+            monthlyrainin: pws.monthrainin,
+            yearlyrainin: pws.yearrainin,
+            baromrelin: pws.baromin,
+            solarradiation: pws.solarradiation,
+            uv: pws.uv,
+            ..Default::default()
+        }
+    }
+}
+
+async fn handle_pws_params(
+    state: &AppState,
+    req: &web::HttpRequest,
+    mut params: HashMap<String, String>,
+) -> Result<web::HttpResponse, AppError> {
+    let api_key = take_api_key(&mut params).or_else(|| query_api_key(req));
+    let authorized = authorize(state, api_key.as_deref())?;
+    info!("Received PWSweather data for station {:?}", params.get("ID"));
+    FieldRenamer::apply(&mut params, &state.config.field_renames);
+    let params = sanitize_params(params);
+
+    let query_string = serde_urlencoded::to_string(&params).unwrap();
+    let data: PwsWeatherData = serde_urlencoded::from_str(&query_string).map_err(|e| {
+        metrics().record_push_error(params.get("ID").map_or("unknown", String::as_str));
+        AppError::Parse(e.to_string())
+    })?;
+
+    ingest(state, authorized, WeatherData::from(data), PushProtocol::Pws).await
+}
+
+/// Receive a PWSweather.com upload sent as query parameters.
+pub async fn handle_pws_get(
+    req: web::HttpRequest,
+    state: web::types::State<AppState>,
+    query: web::types::Query<HashMap<String, String>>,
+) -> Result<web::HttpResponse, AppError> {
+    handle_pws_params(&state, &req, query.into_inner()).await
+}
+
+/// Receive a PWSweather.com upload sent as a form body.
+pub async fn handle_pws_post(
+    req: web::HttpRequest,
+    state: web::types::State<AppState>,
+    form: web::types::Form<HashMap<String, String>>,
+) -> Result<web::HttpResponse, AppError> {
+    handle_pws_params(&state, &req, form.into_inner()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Metrics;
+    use crate::test_support;
+
+    #[test]
+    fn pws_fields_are_mapped_onto_push_fields() {
+        let upload = "ID=PWSTEST&PASSWORD=secret&dateutc=now&tempf=68.2&humidity=55&rainin=0.12&dailyrainin=0.5&baromin=30.05";
+        let pws: PwsWeatherData = serde_urlencoded::from_str(upload).unwrap();
+        let weather_data = WeatherData::from(pws);
+        assert_eq!(weather_data.station_id(), Some("PWSTEST"));
+
+        let metrics = Metrics::new(&test_support::config(&[])).unwrap();
+        metrics.update(&weather_data).unwrap();
+        let gauge = |name| test_support::series_value(&metrics, name, "PWSTEST");
+        assert_eq!(gauge("weather_temperature_fahrenheit"), Some(68.2));
+        assert_eq!(gauge("weather_humidity_percentage"), Some(55.0));
+        assert_eq!(gauge("weather_hourly_rain_in"), Some(0.12));
+        assert_eq!(gauge("weather_daily_rain_in"), Some(0.5));
+        assert_eq!(gauge("weather_barom_relative_in"), Some(30.05));
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Longest window rain is summed over; older rain is dropped.
+const MAX_WINDOW: Duration = Duration::from_secs(24 * 3600);
+
+/// Rain that fell at each push over the last 24 hours, for totals over
+/// windows that don't depend on when the station resets its own counters.
+#[derive(Debug, Default)]
+pub struct RainAccumulator {
+    events: VecDeque<(Instant, f64)>,
+}
+
+impl RainAccumulator {
+    /// Add `inches` of rain that fell by `now`, dropping rain older than a day.
+    pub fn record(&mut self, inches: f64, now: Instant) {
+        while let Some(&(at, _)) = self.events.front() {
+            if now.saturating_duration_since(at) <= MAX_WINDOW {
+                break;
+            }
+            self.events.pop_front();
+        }
+        if inches > 0.0 {
+            self.events.push_back((now, inches));
+        }
+    }
+
+    /// Rain that fell in the `secs` seconds up to `now`.
+    pub fn total_inches_in_window(&self, secs: u64, now: Instant) -> f64 {
+        let window = Duration::from_secs(secs);
+        self.events
+            .iter()
+            .rev()
+            .take_while(|&&(at, _)| now.saturating_duration_since(at) <= window)
+            .map(|&(_, inches)| inches)
+            .sum()
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Metrics;
+    use crate::{test_support, WeatherData};
+
+    #[test]
+    fn windows_sum_recent_rain() {
+        let start = Instant::now();
+        let hours = |hours: f64| start + Duration::from_secs_f64(hours * 3600.0);
+        let mut rain = RainAccumulator::default();
+        for (at, inches) in [(0.0, 0.5), (10.0, 0.25), (20.0, 0.0), (22.0, 0.125), (23.5, 0.0625)] {
+            rain.record(inches, hours(at));
+        }
+        let now = hours(23.5);
+        assert_eq!(rain.total_inches_in_window(3600, now), 0.0625);
+        assert_eq!(rain.total_inches_in_window(6 * 3600, now), 0.1875);
+        assert_eq!(rain.total_inches_in_window(24 * 3600, now), 0.9375);
+        // Dry pushes add no entries
+        assert_eq!(rain.events.len(), 4);
+
+        rain.record(0.0, hours(25.0));
+        assert_eq!(rain.total_inches_in_window(24 * 3600, hours(25.0)), 0.4375);
+        assert_eq!(rain.events.len(), 3);
+    }
+
+    #[test]
+    fn server_totals_span_the_station_midnight_reset() {
+        let metrics = Metrics::new(&test_support::config(&[])).unwrap();
+        for daily in ["0.10", "0.30", "0.05", "0.15"] {
+            let push = format!("PASSKEY=midnight&dailyrainin={}", daily);
+            metrics.update(&WeatherData::from_query(&push).unwrap()).unwrap();
+        }
+        // 0.20 before the reset, then 0.05 and 0.10 after it
+        for name in ["weather_rain_1h_server_inches", "weather_rain_6h_server_inches", "weather_rain_24h_server_inches"] {
+            let total = test_support::series_value(&metrics, name, "midnight").unwrap();
+            assert!((total - 0.35).abs() < 1e-6, "{} is {}", name, total);
+        }
+    }
+
+    #[test]
+    fn server_totals_are_exported_in_millimetres_for_metric_units() {
+        let metrics = Metrics::new(&test_support::config(&[("STORMCAST_UNITS", "metric")])).unwrap();
+        for daily in ["0.10", "0.30"] {
+            let push = format!("PASSKEY=metricrain&dailyrainin={}", daily);
+            metrics.update(&WeatherData::from_query(&push).unwrap()).unwrap();
+        }
+        // 0.20 inches since the first push
+        for name in ["weather_rain_1h_server_mm", "weather_rain_6h_server_mm", "weather_rain_24h_server_mm"] {
+            assert_eq!(test_support::series_value(&metrics, name, "metricrain"), Some(5.1), "{}", name);
+        }
+        let inches = test_support::series_value(&metrics, "weather_rain_1h_server_inches", "metricrain");
+        assert_eq!(inches, None);
+    }
+}
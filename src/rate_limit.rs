@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// Limits how many pushes each client IP may send per second, so a
+/// misconfigured station can't flood the server with updates.
+#[derive(Debug)]
+pub struct RateLimiter {
+    max_per_second: u32,
+    windows: Mutex<HashMap<IpAddr, (u32, Instant)>>,
+}
+
+impl RateLimiter {
+    /// A zero `max_per_second` disables the limit.
+    pub fn new(max_per_second: u32) -> Self {
+        RateLimiter {
+            max_per_second,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Requests each client may send per second; zero when unlimited.
+    pub fn limit(&self) -> u32 {
+        self.max_per_second
+    }
+
+    /// Count a request from `client`, returning how many more it may send in
+    /// the current window, or how long until its window resets.
+    pub fn check(&self, client: IpAddr, now: Instant) -> Result<u32, Duration> {
+        if self.max_per_second == 0 {
+            return Ok(u32::MAX);
+        }
+        let mut windows = self.windows.lock().unwrap();
+        if let Some((count, started)) = windows.get_mut(&client) {
+            let elapsed = now.saturating_duration_since(*started);
+            if elapsed < WINDOW {
+                if *count >= self.max_per_second {
+                    return Err(WINDOW - elapsed);
+                }
+                *count += 1;
+                return Ok(self.max_per_second - *count);
+            }
+        }
+        // Forget clients whose window has ended
+        windows.retain(|_, (_, started)| now.saturating_duration_since(*started) < WINDOW);
+        windows.insert(client, (1, now));
+        Ok(self.max_per_second - 1)
+    }
+}
+
+/// The client's network, `/24` for IPv4 and `/48` for IPv6, so abusive
+/// clients can be identified without recording full addresses.
+pub fn ip_prefix(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            format!("{}.{}.{}.0/24", a, b, c)
+        }
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            format!("{:x}:{:x}:{:x}::/48", segments[0], segments[1], segments[2])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_beyond_the_limit_wait_for_the_window() {
+        let limiter = RateLimiter::new(2);
+        let client: IpAddr = "192.0.2.7".parse().unwrap();
+        let start = Instant::now();
+        assert_eq!(limiter.check(client, start), Ok(1));
+        assert_eq!(limiter.check(client, start + Duration::from_millis(100)), Ok(0));
+        assert_eq!(
+            limiter.check(client, start + Duration::from_millis(400)),
+            Err(Duration::from_millis(600))
+        );
+        assert_eq!(limiter.check(client, start + WINDOW), Ok(1));
+    }
+
+    #[test]
+    fn clients_are_limited_separately() {
+        let limiter = RateLimiter::new(1);
+        let now = Instant::now();
+        assert!(limiter.check("192.0.2.7".parse().unwrap(), now).is_ok());
+        assert!(limiter.check("192.0.2.8".parse().unwrap(), now).is_ok());
+        assert!(limiter.check("192.0.2.7".parse().unwrap(), now).is_err());
+    }
+
+    #[test]
+    fn zero_disables_the_limit() {
+        let limiter = RateLimiter::new(0);
+        let now = Instant::now();
+        for _ in 0..100 {
+            assert!(limiter.check("192.0.2.7".parse().unwrap(), now).is_ok());
+        }
+    }
+
+    #[test]
+    fn prefixes_hide_host_bits() {
+        assert_eq!(ip_prefix("192.0.2.7".parse().unwrap()), "192.0.2.0/24");
+        assert_eq!(ip_prefix("2001:db8:1:2::7".parse().unwrap()), "2001:db8:1::/48");
+    }
+}
@@ -0,0 +1,296 @@
+use ntex::http::client::Client;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::cwop::Cwop;
+use crate::metrics::metrics;
+use crate::WeatherData;
+
+/// Weather Underground's rapid-fire upload endpoint.
+const WU_UPLOAD_URL: &str = "https://rtupdate.wunderground.com/weatherstation/updateweatherstation.php";
+/// PWSweather.com's upload endpoint.
+const PWSWEATHER_UPLOAD_URL: &str = "https://pwsupdate.pwsweather.com/api/v1/submitwx";
+
+/// Weather Underground parameter each `WeatherData` field is uploaded as.
+/// Fields Weather Underground has no equivalent for are not forwarded.
+const WU_FIELDS: [(&str, &str); 14] = [
+    ("tempf", "tempf"),
+    ("humidity", "humidity"),
+    ("windspeedmph", "windspeedmph"),
+    ("windgustmph", "windgustmph"),
+    ("winddir", "winddir"),
+    ("hourlyrainin", "rainin"),
+    ("dailyrainin", "dailyrainin"),
+    ("baromrelin", "baromin"),
+    ("solarradiation", "solarradiation"),
+    ("uv", "UV"),
+    ("tempinf", "indoortempf"),
+    ("humidityin", "indoorhumidity"),
+    ("pm25", "AqPM2.5"),
+    ("pm10", "AqPM10"),
+];
+
+/// PWSweather parameter each `WeatherData` field is uploaded as. PWSweather
+/// follows Weather Underground's names but takes monthly and yearly rain
+/// and no indoor or air quality readings.
+const PWSWEATHER_FIELDS: [(&str, &str); 12] = [
+    ("tempf", "tempf"),
+    ("humidity", "humidity"),
+    ("windspeedmph", "windspeedmph"),
+    ("windgustmph", "windgustmph"),
+    ("winddir", "winddir"),
+    ("hourlyrainin", "rainin"),
+    ("dailyrainin", "dailyrainin"),
+    ("monthlyrainin", "monthrainin"),
+    ("yearlyrainin", "yearrainin"),
+    ("baromrelin", "baromin"),
+    ("solarradiation", "solarradiation"),
+    ("uv", "UV"),
+];
+
+/// Credentials for relaying accepted pushes to other weather services.
+#[derive(Debug, Default, Clone)]
+pub struct ForwardConfig {
+    pub wunderground_id: Option<String>,
+    pub wunderground_key: Option<String>,
+    pub pwsweather_id: Option<String>,
+    pub pwsweather_key: Option<String>,
+    /// CWOP callsign or station ID, e.g. `CW0001`.
+    pub cwop_id: Option<String>,
+    /// Latitude and longitude reported to CWOP.
+    pub cwop_location: Option<(f64, f64)>,
+    /// APRS-IS server as `host:port`.
+    pub cwop_server: String,
+}
+
+impl ForwardConfig {
+    /// A relay for every service with both a station ID and key configured.
+    pub fn relays(&self) -> Vec<Box<dyn Relay>> {
+        let mut relays: Vec<Box<dyn Relay>> = Vec::new();
+        if let (Some(id), Some(key)) = (&self.wunderground_id, &self.wunderground_key) {
+            relays.push(Box::new(WeatherUnderground { id: id.clone(), key: key.clone() }));
+        }
+        if let (Some(id), Some(key)) = (&self.pwsweather_id, &self.pwsweather_key) {
+            relays.push(Box::new(PwsWeather { id: id.clone(), key: key.clone() }));
+        }
+        relays
+    }
+
+    /// CWOP submitter, when a station ID and location are configured.
+    pub fn cwop(&self) -> Option<Cwop> {
+        let (lat, lon) = self.cwop_location?;
+        Some(Cwop::new(self.cwop_id.clone()?, lat, lon, self.cwop_server.clone()))
+    }
+}
+
+/// A weather service accepted pushes are uploaded to.
+pub trait Relay: Send + Sync {
+    /// Label on `weather_relay_errors_total`.
+    fn target(&self) -> &'static str;
+    /// Upload URL carrying `data`.
+    fn upload_url(&self, data: &WeatherData) -> String;
+}
+
+pub struct WeatherUnderground {
+    id: String,
+    key: String,
+}
+
+impl Relay for WeatherUnderground {
+    fn target(&self) -> &'static str {
+        "wunderground"
+    }
+
+    fn upload_url(&self, data: &WeatherData) -> String {
+        build_wunderground_url(&self.id, &self.key, data)
+    }
+}
+
+pub struct PwsWeather {
+    id: String,
+    key: String,
+}
+
+impl Relay for PwsWeather {
+    fn target(&self) -> &'static str {
+        "pwsweather"
+    }
+
+    fn upload_url(&self, data: &WeatherData) -> String {
+        build_pwsweather_url(&self.id, &self.key, data)
+    }
+}
+
+/// `base` with the station credentials, timestamp and every field of `data`
+/// that `names` maps as query parameters.
+fn build_url(base: &str, id: &str, key: &str, names: &[(&str, &str)], data: &WeatherData) -> String {
+    let mut params = vec![
+        ("ID", id.to_string()),
+        ("PASSWORD", key.to_string()),
+        ("dateutc", data.dateutc.clone().unwrap_or_else(|| "now".to_string())),
+    ];
+    let fields = data.fields();
+    for &(field, name) in names {
+        if let Some(value) = fields.iter().find(|(f, _)| *f == field).and_then(|(_, value)| *value) {
+            // Fields are f32; format them without the f64 widening noise
+            params.push((name, (value as f32).to_string()));
+        }
+    }
+    format!("{}?{}", base, serde_urlencoded::to_string(&params).unwrap())
+}
+
+/// Weather Underground upload URL for `data`.
+pub fn build_wunderground_url(id: &str, key: &str, data: &WeatherData) -> String {
+    let url = build_url(WU_UPLOAD_URL, id, key, &WU_FIELDS, data);
+    format!("{}&action=updateraw&realtime=1", url)
+}
+
+/// PWSweather.com upload URL for `data`.
+pub fn build_pwsweather_url(id: &str, key: &str, data: &WeatherData) -> String {
+    build_url(PWSWEATHER_UPLOAD_URL, id, key, &PWSWEATHER_FIELDS, data)
+}
+
+/// Upload `data` through `relay` in the background. Failures are logged and
+/// counted but never reach the station that pushed.
+pub fn forward(relay: &dyn Relay, data: &WeatherData) {
+    let target = relay.target();
+    let url = relay.upload_url(data);
+    ntex::rt::spawn(async move {
+        let client = Client::build().timeout(Duration::from_secs(10)).finish();
+        match client.get(&url).send().await {
+            Ok(res) if res.status().is_success() => debug!("Forwarded push to {}", target),
+            Ok(res) => {
+                warn!("{} rejected forwarded push with status {}", target, res.status());
+                metrics().relay_errors.with_label_values(&[target]).inc();
+            }
+            Err(e) => {
+                warn!("Failed to forward push to {}: {}", target, e);
+                metrics().relay_errors.with_label_values(&[target]).inc();
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn reading(query: &str) -> WeatherData {
+        WeatherData::from_query(query).unwrap()
+    }
+
+    /// Base URL and query parameters of an upload URL.
+    fn split(url: &str) -> (&str, HashMap<String, String>) {
+        let (base, query) = url.split_once('?').unwrap();
+        (base, form_urlencoded::parse(query.as_bytes()).into_owned().collect())
+    }
+
+    #[test]
+    fn wunderground_url_translates_field_names() {
+        let data = reading("PASSKEY=relay&dateutc=2024-01-01+12:00:00&tempf=71.2&hourlyrainin=0.1&baromrelin=30.01&uv=3&tempinf=68.0&lightning=12");
+        let url = build_wunderground_url("KCASANFR1", "s3cret&", &data);
+        let (base, params) = split(&url);
+        assert_eq!(base, WU_UPLOAD_URL);
+        let expected = [
+            ("ID", "KCASANFR1"),
+            ("PASSWORD", "s3cret&"),
+            ("dateutc", "2024-01-01 12:00:00"),
+            ("tempf", "71.2"),
+            ("rainin", "0.1"),
+            ("baromin", "30.01"),
+            ("UV", "3"),
+            ("indoortempf", "68"),
+            ("action", "updateraw"),
+            ("realtime", "1"),
+        ];
+        assert_eq!(params.len(), expected.len(), "{:?}", params);
+        for (name, value) in expected {
+            assert_eq!(params.get(name).map(String::as_str), Some(value), "{}", name);
+        }
+    }
+
+    #[test]
+    fn missing_timestamp_uploads_as_now() {
+        let (_, params) = split(&build_wunderground_url("id", "key", &reading("tempf=50.0")));
+        assert_eq!(params["dateutc"], "now");
+    }
+
+    #[test]
+    fn pwsweather_url_translates_field_names() {
+        let data = reading("PASSKEY=relay&tempf=60.5&hourlyrainin=0.2&monthlyrainin=1.5&yearlyrainin=20&baromrelin=29.9&uv=4&tempinf=68.0");
+        let url = build_pwsweather_url("STATION", "key", &data);
+        let (base, params) = split(&url);
+        assert_eq!(base, PWSWEATHER_UPLOAD_URL);
+        let expected = [
+            ("ID", "STATION"),
+            ("PASSWORD", "key"),
+            ("dateutc", "now"),
+            ("tempf", "60.5"),
+            ("rainin", "0.2"),
+            ("monthrainin", "1.5"),
+            ("yearrainin", "20"),
+            ("baromin", "29.9"),
+            ("UV", "4"),
+        ];
+        assert_eq!(params.len(), expected.len(), "{:?}", params);
+        for (name, value) in expected {
+            assert_eq!(params.get(name).map(String::as_str), Some(value), "{}", name);
+        }
+    }
+
+    #[test]
+    fn relays_need_both_id_and_key() {
+        let targets = |config: ForwardConfig| config.relays().iter().map(|relay| relay.target()).collect::<Vec<_>>();
+        assert!(targets(ForwardConfig::default()).is_empty());
+        let partial = ForwardConfig {
+            wunderground_id: Some("KCASANFR1".to_string()),
+            pwsweather_key: Some("key".to_string()),
+            ..Default::default()
+        };
+        assert!(targets(partial).is_empty());
+        let both = ForwardConfig {
+            wunderground_id: Some("KCASANFR1".to_string()),
+            wunderground_key: Some("key".to_string()),
+            pwsweather_id: Some("STATION".to_string()),
+            pwsweather_key: Some("key".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(targets(both), ["wunderground", "pwsweather"]);
+    }
+
+    /// Relay uploading to a local mock.
+    struct MockRelay(String);
+
+    impl Relay for MockRelay {
+        fn target(&self) -> &'static str {
+            "mock"
+        }
+
+        fn upload_url(&self, _data: &WeatherData) -> String {
+            self.0.clone()
+        }
+    }
+
+    #[ntex::test]
+    async fn rejected_uploads_count_as_relay_errors() {
+        use ntex::web::{self, test::server, App};
+
+        let metrics = crate::test_support::init_metrics();
+        let mock = server(|| {
+            App::new()
+                .route("/ok", web::get().to(|| async { web::HttpResponse::Ok().finish() }))
+                .route("/fail", web::get().to(|| async { web::HttpResponse::InternalServerError().finish() }))
+        });
+        let errors = || metrics.relay_errors.with_label_values(&["mock"]).get();
+        let data = reading("PASSKEY=relay&tempf=50.0");
+
+        forward(&MockRelay(mock.url("/ok")), &data);
+        ntex::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(errors(), 0);
+
+        forward(&MockRelay(mock.url("/fail")), &data);
+        ntex::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(errors(), 1);
+    }
+}
@@ -0,0 +1,331 @@
+use ntex::http::client::Client;
+use ntex::http::header;
+use prometheus::proto::{LabelPair, MetricFamily, MetricType};
+use std::time::Duration;
+
+use crate::metrics::metrics;
+use crate::shutdown;
+use crate::snappy;
+
+/// Version of the remote write protocol the request bodies follow.
+const PROTOCOL_VERSION: &str = "0.1.0";
+
+#[derive(Debug, thiserror::Error)]
+pub enum RemoteWriteError {
+    #[error("request failed: {0}")]
+    Request(String),
+    #[error("unexpected response status {0}")]
+    Status(u16),
+}
+
+/// Protobuf wire types used by `prometheus.WriteRequest`.
+const VARINT: u8 = 0;
+const FIXED64: u8 = 1;
+const LENGTH_DELIMITED: u8 = 2;
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_key(out: &mut Vec<u8>, field: u8, wire_type: u8) {
+    out.push(field << 3 | wire_type);
+}
+
+fn write_bytes(out: &mut Vec<u8>, field: u8, bytes: &[u8]) {
+    write_key(out, field, LENGTH_DELIMITED);
+    write_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+/// One `prometheus.TimeSeries`: labels, sorted by name and including
+/// `__name__`, and a single sample.
+struct Series {
+    labels: Vec<(String, String)>,
+    value: f64,
+    timestamp_ms: i64,
+}
+
+impl Series {
+    fn encode(&self, out: &mut Vec<u8>) {
+        let mut series = Vec::new();
+        for (name, value) in &self.labels {
+            // Label { string name = 1; string value = 2; }
+            let mut label = Vec::new();
+            write_bytes(&mut label, 1, name.as_bytes());
+            write_bytes(&mut label, 2, value.as_bytes());
+            write_bytes(&mut series, 1, &label);
+        }
+        // Sample { double value = 1; int64 timestamp = 2; }
+        let mut sample = Vec::new();
+        write_key(&mut sample, 1, FIXED64);
+        sample.extend_from_slice(&self.value.to_le_bytes());
+        write_key(&mut sample, 2, VARINT);
+        write_varint(&mut sample, self.timestamp_ms as u64);
+        write_bytes(&mut series, 2, &sample);
+        // WriteRequest { repeated TimeSeries timeseries = 1; }
+        write_bytes(out, 1, &series);
+    }
+}
+
+/// Add a series for `name` with the metric's labels plus `extra`.
+fn push_series(
+    series: &mut Vec<Series>,
+    name: String,
+    labels: &[LabelPair],
+    extra: Option<(&str, String)>,
+    value: f64,
+    timestamp_ms: i64,
+) {
+    let mut labels: Vec<(String, String)> = labels
+        .iter()
+        .map(|label| (label.get_name().to_string(), label.get_value().to_string()))
+        .chain(extra.map(|(name, value)| (name.to_string(), value)))
+        .chain([("__name__".to_string(), name)])
+        .collect();
+    labels.sort();
+    series.push(Series {
+        labels,
+        value,
+        timestamp_ms,
+    });
+}
+
+/// Encode metric families as a `prometheus.WriteRequest`, one series per
+/// sample as the text format would expose it. Samples without their own
+/// timestamp are stamped `now_ms`.
+pub fn write_request(families: &[MetricFamily], now_ms: i64) -> Vec<u8> {
+    let mut series = Vec::new();
+    for family in families {
+        let name = family.get_name();
+        for metric in family.get_metric() {
+            let labels = metric.get_label();
+            let ts = match metric.get_timestamp_ms() {
+                0 => now_ms,
+                ts => ts,
+            };
+            match family.get_field_type() {
+                MetricType::COUNTER => {
+                    push_series(&mut series, name.to_string(), labels, None, metric.get_counter().get_value(), ts)
+                }
+                MetricType::GAUGE => {
+                    push_series(&mut series, name.to_string(), labels, None, metric.get_gauge().get_value(), ts)
+                }
+                MetricType::UNTYPED => {
+                    push_series(&mut series, name.to_string(), labels, None, metric.get_untyped().get_value(), ts)
+                }
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    let bucket_name = format!("{}_bucket", name);
+                    for bucket in histogram.get_bucket() {
+                        let le = Some(("le", bucket.get_upper_bound().to_string()));
+                        let count = bucket.get_cumulative_count() as f64;
+                        push_series(&mut series, bucket_name.clone(), labels, le, count, ts);
+                    }
+                    let count = histogram.get_sample_count() as f64;
+                    push_series(&mut series, bucket_name, labels, Some(("le", "+Inf".to_string())), count, ts);
+                    push_series(&mut series, format!("{}_sum", name), labels, None, histogram.get_sample_sum(), ts);
+                    push_series(&mut series, format!("{}_count", name), labels, None, count, ts);
+                }
+                MetricType::SUMMARY => {
+                    let summary = metric.get_summary();
+                    for quantile in summary.get_quantile() {
+                        let q = Some(("quantile", quantile.get_quantile().to_string()));
+                        push_series(&mut series, name.to_string(), labels, q, quantile.get_value(), ts);
+                    }
+                    let count = summary.get_sample_count() as f64;
+                    push_series(&mut series, format!("{}_sum", name), labels, None, summary.get_sample_sum(), ts);
+                    push_series(&mut series, format!("{}_count", name), labels, None, count, ts);
+                }
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    for series in &series {
+        series.encode(&mut out);
+    }
+    out
+}
+
+/// Compress an encoded `WriteRequest` and POST it to the remote write endpoint.
+pub async fn send(url: &str, token: Option<&str>, write_request: &[u8]) -> Result<(), RemoteWriteError> {
+    let client = Client::build().timeout(Duration::from_secs(10)).finish();
+    let mut request = client
+        .post(url)
+        .header(header::CONTENT_TYPE, "application/x-protobuf")
+        .header(header::CONTENT_ENCODING, "snappy")
+        .header("X-Prometheus-Remote-Write-Version", PROTOCOL_VERSION);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let res = request
+        .send_body(snappy::compress(write_request))
+        .await
+        .map_err(|e| RemoteWriteError::Request(e.to_string()))?;
+    if !res.status().is_success() {
+        return Err(RemoteWriteError::Status(res.status().as_u16()));
+    }
+    Ok(())
+}
+
+/// Push metrics at the configured interval until a shutdown signal arrives,
+/// for deployments where Prometheus can't reach the server to scrape it.
+pub async fn push_loop(url: String, token: Option<String>, interval: Duration) {
+    loop {
+        ntex::time::sleep(interval).await;
+        if shutdown::is_shutting_down() {
+            break;
+        }
+        metrics().gather_and_push(&url, token.as_deref()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ntex::util::Bytes;
+    use ntex::web::{self, test::server, App, HttpRequest};
+    use prometheus::{GaugeVec, HistogramOpts, HistogramVec, Opts, Registry};
+    use std::sync::{Arc, Mutex};
+
+    /// Fields of a protobuf message: number, wire type and raw value (the
+    /// varint, the eight fixed bytes or the delimited bytes).
+    fn fields(mut bytes: &[u8]) -> Vec<(u8, u8, Vec<u8>)> {
+        let varint = |bytes: &mut &[u8]| {
+            let mut value = 0u64;
+            let mut shift = 0;
+            loop {
+                let byte = bytes[0];
+                *bytes = &bytes[1..];
+                value |= u64::from(byte & 0x7f) << shift;
+                shift += 7;
+                if byte < 0x80 {
+                    return value;
+                }
+            }
+        };
+        let mut fields = Vec::new();
+        while !bytes.is_empty() {
+            let key = varint(&mut bytes) as u8;
+            let raw = match key & 0b111 {
+                VARINT => varint(&mut bytes).to_le_bytes().to_vec(),
+                FIXED64 => {
+                    let (value, rest) = bytes.split_at(8);
+                    bytes = rest;
+                    value.to_vec()
+                }
+                LENGTH_DELIMITED => {
+                    let len = varint(&mut bytes) as usize;
+                    let (value, rest) = bytes.split_at(len);
+                    bytes = rest;
+                    value.to_vec()
+                }
+                wire_type => panic!("unexpected wire type {}", wire_type),
+            };
+            fields.push((key >> 3, key & 0b111, raw));
+        }
+        fields
+    }
+
+    type Pairs = Vec<(String, String)>;
+
+    /// Labels, value and timestamp of each series in a `WriteRequest`.
+    fn decode(request: &[u8]) -> Vec<(Pairs, f64, i64)> {
+        fields(request)
+            .into_iter()
+            .map(|(number, _, series)| {
+                assert_eq!(number, 1);
+                let mut labels = Vec::new();
+                let mut sample = (f64::NAN, 0);
+                for (number, _, raw) in fields(&series) {
+                    let pair = fields(&raw);
+                    match number {
+                        1 => labels.push((
+                            String::from_utf8(pair[0].2.clone()).unwrap(),
+                            String::from_utf8(pair[1].2.clone()).unwrap(),
+                        )),
+                        _ => {
+                            sample.0 = f64::from_le_bytes(pair[0].2.clone().try_into().unwrap());
+                            sample.1 = i64::from_le_bytes(pair[1].2.clone().try_into().unwrap());
+                        }
+                    }
+                }
+                (labels, sample.0, sample.1)
+            })
+            .collect()
+    }
+
+    fn labels(pairs: &[(&str, &str)]) -> Pairs {
+        pairs.iter().map(|&(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn gauges_and_histograms_become_series() {
+        let registry = Registry::new();
+        let gauge = GaugeVec::new(Opts::new("weather_temperature", "Temperature"), &["station"]).unwrap();
+        registry.register(Box::new(gauge.clone())).unwrap();
+        gauge.with_label_values(&["abc"]).set(71.5);
+        let histogram = HistogramVec::new(HistogramOpts::new("wind", "Wind").buckets(vec![5.0]), &["station"]).unwrap();
+        registry.register(Box::new(histogram.clone())).unwrap();
+        histogram.with_label_values(&["abc"]).observe(3.0);
+
+        let series = decode(&write_request(&registry.gather(), 1_700_000_000_000));
+        let expected = [
+            (labels(&[("__name__", "weather_temperature"), ("station", "abc")]), 71.5),
+            (labels(&[("__name__", "wind_bucket"), ("le", "5"), ("station", "abc")]), 1.0),
+            (labels(&[("__name__", "wind_bucket"), ("le", "+Inf"), ("station", "abc")]), 1.0),
+            (labels(&[("__name__", "wind_sum"), ("station", "abc")]), 3.0),
+            (labels(&[("__name__", "wind_count"), ("station", "abc")]), 1.0),
+        ];
+        assert_eq!(series.len(), expected.len());
+        for ((labels, value, timestamp), (expected_labels, expected_value)) in series.into_iter().zip(expected) {
+            assert_eq!(labels, expected_labels);
+            assert_eq!(value, expected_value);
+            assert_eq!(timestamp, 1_700_000_000_000);
+        }
+    }
+
+    #[ntex::test]
+    async fn pushes_are_snappy_compressed_protobuf() {
+        // Headers and body of the last request received
+        let received: Arc<Mutex<Option<(Pairs, Bytes)>>> = Arc::default();
+        let store = received.clone();
+        let receiver = server(move || {
+            let store = store.clone();
+            App::new().route(
+                "/api/v1/write",
+                web::post().to(move |req: HttpRequest, body: Bytes| {
+                    let headers = ["content-type", "content-encoding", "x-prometheus-remote-write-version", "authorization"]
+                        .iter()
+                        .map(|name| (name.to_string(), req.headers().get(*name).unwrap().to_str().unwrap().to_string()))
+                        .collect();
+                    *store.lock().unwrap() = Some((headers, body));
+                    async { web::HttpResponse::NoContent().finish() }
+                }),
+            )
+        });
+
+        let request = b"\x0a\x04test-write-request".repeat(20);
+        send(&receiver.url("/api/v1/write"), Some("token"), &request).await.unwrap();
+
+        let (headers, body) = received.lock().unwrap().take().unwrap();
+        assert_eq!(
+            headers,
+            labels(&[
+                ("content-type", "application/x-protobuf"),
+                ("content-encoding", "snappy"),
+                ("x-prometheus-remote-write-version", "0.1.0"),
+                ("authorization", "Bearer token"),
+            ])
+        );
+        assert_eq!(body, snappy::compress(&request));
+        assert!(body.len() < request.len());
+
+        let err = send(&receiver.url("/elsewhere"), None, &request).await.unwrap_err();
+        assert!(matches!(err, RemoteWriteError::Status(404)));
+    }
+}
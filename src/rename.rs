@@ -0,0 +1,77 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use tracing::debug;
+
+/// `[field_renames]`: non-standard push parameter names mapped to the
+/// canonical names `WeatherData` expects, e.g. `outdoor_temp = "tempf"`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct FieldRenameConfig {
+    pub renames: HashMap<String, String>,
+}
+
+pub struct FieldRenamer;
+
+impl FieldRenamer {
+    /// Rename parameters in place. A parameter that already uses the
+    /// canonical name is kept over a renamed one.
+    pub fn apply(map: &mut HashMap<String, String>, config: &FieldRenameConfig) {
+        for (from, to) in &config.renames {
+            let Some(value) = map.remove(from) else {
+                continue;
+            };
+            if map.contains_key(to) {
+                debug!("Dropping {} as {} was also sent", from, to);
+                continue;
+            }
+            map.insert(to.clone(), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, ConfigEnv};
+    use crate::metrics::metrics;
+    use crate::{test_support, AppState};
+    use ntex::http::StatusCode;
+    use ntex::web::{self, test};
+
+    fn renames(pairs: &[(&str, &str)]) -> FieldRenameConfig {
+        FieldRenameConfig {
+            renames: pairs.iter().map(|&(from, to)| (from.to_string(), to.to_string())).collect(),
+        }
+    }
+
+    #[test]
+    fn canonical_name_wins_over_a_renamed_one() {
+        let config = renames(&[("outdoor_temp", "tempf"), ("rh", "humidity")]);
+        let mut map: HashMap<String, String> = [("outdoor_temp", "72.5"), ("tempf", "70.0"), ("rh", "40")]
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        FieldRenamer::apply(&mut map, &config);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["tempf"], "70.0");
+        assert_eq!(map["humidity"], "40");
+    }
+
+    #[ntex::test]
+    async fn renamed_field_sets_its_gauge() {
+        test_support::init_metrics();
+        let file = toml::from_str("[field_renames]\noutdoor_temp = \"tempf\"\n").unwrap();
+        let state = AppState::new(Config::merge(file, ConfigEnv::default()).unwrap());
+        let app = test::init_service(
+            web::App::new()
+                .state(state)
+                .route("/push/", web::get().to(crate::handle_weather_data)),
+        )
+        .await;
+
+        let req = test::TestRequest::with_uri("/push/?PASSKEY=renamed&outdoor_temp=72.5").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        let temp = test_support::series_value(metrics(), "weather_temperature_fahrenheit", "renamed");
+        assert_eq!(temp, Some(72.5));
+    }
+}
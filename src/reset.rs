@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::timezone::TimeZone;
+
+/// Detects when a station's pushes cross local midnight, which is when
+/// station firmware resets `dailyrainin` and the other daily totals.
+#[derive(Debug)]
+pub struct ResetDetector {
+    timezone: TimeZone,
+    last_push: Mutex<HashMap<String, i64>>,
+}
+
+impl ResetDetector {
+    pub fn new(timezone: TimeZone) -> Self {
+        ResetDetector {
+            timezone,
+            last_push: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record a push at `unix_secs` and report whether a local day boundary
+    /// was crossed since the station's previous push.
+    pub fn record(&self, station: &str, unix_secs: i64) -> bool {
+        let mut last_push = self.last_push.lock().unwrap();
+        match last_push.insert(station.to_string(), unix_secs) {
+            Some(previous) => self.timezone.local_day(unix_secs) > self.timezone.local_day(previous),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::parse_datetime;
+
+    fn utc(datetime: &str) -> i64 {
+        parse_datetime(datetime).unwrap()
+    }
+
+    #[test]
+    fn crossing_local_midnight_is_a_reset() {
+        let detector = ResetDetector::new("America/Los_Angeles".parse().unwrap());
+        // 23:59 and 00:01 PDT on July 1st and 2nd
+        assert!(!detector.record("la", utc("2024-07-02 06:59:00")));
+        assert!(detector.record("la", utc("2024-07-02 07:01:00")));
+        assert!(!detector.record("la", utc("2024-07-02 07:03:00")));
+    }
+
+    #[test]
+    fn utc_midnight_is_not_a_local_reset() {
+        let detector = ResetDetector::new("America/Los_Angeles".parse().unwrap());
+        // 16:59 and 17:01 PST, either side of midnight UTC
+        assert!(!detector.record("la", utc("2024-01-15 23:59:00")));
+        assert!(!detector.record("la", utc("2024-01-16 00:01:00")));
+        // 23:59 and 00:01 PST
+        assert!(!detector.record("la", utc("2024-01-16 07:59:00")));
+        assert!(detector.record("la", utc("2024-01-16 08:01:00")));
+    }
+
+    #[test]
+    fn stations_are_tracked_separately() {
+        let detector = ResetDetector::new(TimeZone::default());
+        assert!(!detector.record("a", utc("2024-01-15 23:59:00")));
+        assert!(!detector.record("b", utc("2024-01-16 00:01:00")));
+        assert!(detector.record("a", utc("2024-01-16 00:01:00")));
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Values recorded over the last `window`, for averaging readings the
+/// station doesn't average itself.
+#[derive(Debug)]
+pub struct RollingWindow<T> {
+    values: VecDeque<(Instant, T)>,
+    window: Duration,
+}
+
+impl<T> RollingWindow<T> {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            values: VecDeque::new(),
+            window,
+        }
+    }
+
+    /// Add a value taken at `now`, dropping values that left the window.
+    pub fn record(&mut self, value: T, now: Instant) {
+        while let Some(&(at, _)) = self.values.front() {
+            if now.saturating_duration_since(at) <= self.window {
+                break;
+            }
+            self.values.pop_front();
+        }
+        self.values.push_back((now, value));
+    }
+
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+}
+
+impl<T: Copy + Into<f64>> RollingWindow<T> {
+    /// Mean of the values in the window as of the latest one, if any.
+    pub fn mean(&self) -> Option<f64> {
+        if self.values.is_empty() {
+            return None;
+        }
+        let sum: f64 = self.values.iter().map(|&(_, value)| value.into()).sum();
+        Some(sum / self.values.len() as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mean_covers_only_values_in_the_window() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut window = RollingWindow::new(Duration::from_secs(600));
+        assert_eq!(window.mean(), None);
+
+        for (secs, value) in [(0, 60.0f32), (120, 62.0), (300, 64.0), (600, 66.0)] {
+            window.record(value, at(secs));
+        }
+        // The first value is exactly one window old, so still counts
+        assert_eq!(window.mean(), Some(63.0));
+
+        window.record(70.0, at(780));
+        assert_eq!(window.mean(), Some(200.0 / 3.0));
+
+        // A gap longer than the window leaves only the new value
+        window.record(50.0, at(3600));
+        assert_eq!(window.mean(), Some(50.0));
+
+        window.clear();
+        assert_eq!(window.mean(), None);
+    }
+}
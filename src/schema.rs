@@ -0,0 +1,96 @@
+use ntex::web;
+use serde::{Serialize, Serializer};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::AppState;
+
+/// Counts every push parameter name seen, so operators can spot fields a new
+/// station sends that `WeatherData` doesn't capture yet.
+#[derive(Debug, Default)]
+pub struct SchemaDiscoverer {
+    counts: Mutex<HashMap<String, u64>>,
+}
+
+/// Field counts that serialize as a JSON object in the order given.
+struct OrderedCounts(Vec<(String, u64)>);
+
+impl Serialize for OrderedCounts {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(name, count)| (name, count)))
+    }
+}
+
+impl SchemaDiscoverer {
+    pub fn record<'a>(&self, fields: impl IntoIterator<Item = &'a String>) {
+        let mut counts = self.counts.lock().unwrap();
+        for field in fields {
+            match counts.get_mut(field) {
+                Some(count) => *count += 1,
+                None => {
+                    counts.insert(field.clone(), 1);
+                }
+            }
+        }
+    }
+
+    /// Field names and how often they were seen, most frequent first.
+    pub fn discovered(&self) -> Vec<(String, u64)> {
+        let mut fields: Vec<_> = self
+            .counts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, &count)| (name.clone(), count))
+            .collect();
+        fields.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        fields
+    }
+}
+
+/// Every push field name seen so far with its count, most frequent first.
+pub async fn handle_discovered(state: web::types::State<AppState>) -> web::HttpResponse {
+    web::HttpResponse::Ok().json(&OrderedCounts(state.schema.discovered()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use ntex::http::StatusCode;
+    use ntex::web::test;
+
+    #[test]
+    fn most_frequent_fields_come_first() {
+        let schema = SchemaDiscoverer::default();
+        let fields = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        schema.record(&fields(&["tempf", "humidity"]));
+        schema.record(&fields(&["tempf"]));
+        assert_eq!(
+            schema.discovered(),
+            [("tempf".to_string(), 2), ("humidity".to_string(), 1)]
+        );
+    }
+
+    #[ntex::test]
+    async fn known_and_unknown_fields_are_discovered() {
+        let app = test::init_service(
+            web::App::new()
+                .state(test_support::state(&[]))
+                .route("/push/", web::get().to(crate::handle_weather_data))
+                .route("/schema/discovered", web::get().to(handle_discovered)),
+        )
+        .await;
+
+        let push = "/push/?PASSKEY=schema&tempf=50.0&humidity=40&wh99batt=1&leafwetness9=3";
+        let req = test::TestRequest::with_uri(push).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+        let req = test::TestRequest::with_uri("/schema/discovered").to_request();
+        let body = test::read_body(test::call_service(&app, req).await).await;
+        let discovered: HashMap<String, u64> = serde_json::from_slice(&body).unwrap();
+        for field in ["PASSKEY", "tempf", "humidity", "wh99batt", "leafwetness9"] {
+            assert_eq!(discovered.get(field), Some(&1), "{}", field);
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Rejects scrapes that arrive from the same client sooner than the
+/// configured minimum interval, protecting against misconfigured scrapers.
+#[derive(Debug)]
+pub struct ScrapeLimiter {
+    min_interval: Duration,
+    last_scrape: Mutex<HashMap<IpAddr, Instant>>,
+}
+
+impl ScrapeLimiter {
+    /// A zero `min_interval` disables the limit.
+    pub fn new(min_interval: Duration) -> Self {
+        ScrapeLimiter {
+            min_interval,
+            last_scrape: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record a scrape from `client`, or return how long it must wait.
+    pub fn check(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        if self.min_interval.is_zero() {
+            return Ok(());
+        }
+        let mut last_scrape = self.last_scrape.lock().unwrap();
+        if let Some(&last) = last_scrape.get(&client) {
+            let elapsed = now.saturating_duration_since(last);
+            if elapsed < self.min_interval {
+                return Err(self.min_interval - elapsed);
+            }
+        }
+        // Forget clients that could scrape again anyway
+        last_scrape.retain(|_, last| now.saturating_duration_since(*last) < self.min_interval);
+        last_scrape.insert(client, now);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use ntex::http::StatusCode;
+    use ntex::web::{self, test::server, App};
+
+    #[test]
+    fn scrapes_within_the_interval_are_refused() {
+        let limiter = ScrapeLimiter::new(Duration::from_secs(1));
+        let client: IpAddr = "192.0.2.9".parse().unwrap();
+        let start = Instant::now();
+        assert!(limiter.check(client, start).is_ok());
+        assert_eq!(
+            limiter.check(client, start + Duration::from_millis(400)),
+            Err(Duration::from_millis(600))
+        );
+        assert!(limiter.check("192.0.2.10".parse().unwrap(), start).is_ok());
+        assert!(limiter.check(client, start + Duration::from_secs(1)).is_ok());
+    }
+
+    #[test]
+    fn zero_interval_allows_every_scrape() {
+        let limiter = ScrapeLimiter::new(Duration::ZERO);
+        let now = Instant::now();
+        assert!(limiter.check("192.0.2.9".parse().unwrap(), now).is_ok());
+        assert!(limiter.check("192.0.2.9".parse().unwrap(), now).is_ok());
+    }
+
+    #[ntex::test]
+    async fn second_scrape_within_half_a_second_gets_429() {
+        let state = test_support::state(&[("STORMCAST_MIN_SCRAPE_INTERVAL_SECS", "1")]);
+        // Peer addresses need a real connection
+        let srv = server(move || {
+            App::new()
+                .state(state.clone())
+                .route("/metrics", web::get().to(crate::handle_metrics))
+        });
+
+        let res = srv.get("/metrics").send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        ntex::time::sleep(Duration::from_millis(100)).await;
+        let res = srv.get("/metrics").send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = res.headers().get("Retry-After").unwrap().to_str().unwrap().parse().unwrap();
+        assert_eq!(retry_after, 1);
+        assert!(crate::metrics::metrics().scrape_rate_limited.get() >= 1);
+    }
+}
@@ -0,0 +1,76 @@
+use ntex::server::Server;
+use ntex::util::{select, Either};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{info, warn};
+
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Whether a shutdown signal has been received and requests are draining.
+pub fn is_shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::Relaxed)
+}
+
+/// Wait for SIGTERM or SIGINT, then drain the servers.
+pub async fn wait_for_signal(servers: Vec<Server>, grace: Duration) {
+    let (mut terminate, mut interrupt) = match (signal(SignalKind::terminate()), signal(SignalKind::interrupt())) {
+        (Ok(terminate), Ok(interrupt)) => (terminate, interrupt),
+        (Err(e), _) | (_, Err(e)) => {
+            warn!("Failed to install shutdown signal handlers: {}", e);
+            return;
+        }
+    };
+    let name = match select(terminate.recv(), interrupt.recv()).await {
+        Either::Left(_) => "SIGTERM",
+        Either::Right(_) => "SIGINT",
+    };
+    info!("{} received, shutting down", name);
+    drain(servers, grace, &SHUTTING_DOWN).await;
+}
+
+/// Set `shutting_down` and keep serving for `grace`, so load balancers see
+/// `/health` fail and stop routing here, then stop accepting connections
+/// and let in-flight requests finish within each server's shutdown timeout.
+async fn drain(servers: Vec<Server>, grace: Duration, shutting_down: &AtomicBool) {
+    shutting_down.store(true, Ordering::Relaxed);
+    if !grace.is_zero() {
+        info!("Still serving for {:?} before draining", grace);
+        ntex::time::sleep(grace).await;
+    }
+    info!("Draining in-flight requests");
+    for server in servers {
+        server.stop(true).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ntex::http::client::Client;
+    use ntex::web::{self, App};
+    use std::net::TcpListener;
+
+    #[ntex::test]
+    async fn requests_are_served_during_the_grace_period() {
+        static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = web::server(|| App::new().route("/", web::get().to(|| async { "ok" })))
+            .workers(1)
+            .disable_signals()
+            .listen(listener)
+            .unwrap()
+            .run();
+        let client = Client::new();
+        assert!(client.get(&url).send().await.unwrap().status().is_success());
+
+        let drained = ntex::rt::spawn(drain(vec![server], Duration::from_millis(500), &SHUTTING_DOWN));
+        ntex::time::sleep(Duration::from_millis(100)).await;
+        assert!(SHUTTING_DOWN.load(Ordering::Relaxed));
+        assert!(client.get(&url).send().await.unwrap().status().is_success());
+
+        drained.await.unwrap();
+        assert!(Client::new().get(&url).send().await.is_err());
+    }
+}
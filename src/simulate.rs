@@ -0,0 +1,149 @@
+use ntex::web;
+use serde::Deserialize;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::auth::has_bearer_token;
+use crate::error::AppError;
+use crate::metrics::metrics;
+use crate::{AppState, WeatherData};
+
+/// Upper bounds that keep a simulation from tying up a worker indefinitely.
+const MAX_PUSHES: u32 = 1000;
+const MAX_INTERVAL_MS: u64 = 60_000;
+
+/// Peak values and pacing for a synthetic storm.
+#[derive(Debug, Deserialize)]
+pub struct StormParams {
+    pub wind_mph: f32,
+    pub rain_inches: f32,
+    pub duration_pushes: u32,
+    #[serde(default)]
+    pub interval_ms: u64,
+}
+
+impl StormParams {
+    /// Synthetic reading for push `index`: values ramp up linearly over the
+    /// first half of the storm and hold at the peak for the second half.
+    fn reading(&self, index: u32) -> WeatherData {
+        let ramp_pushes = (self.duration_pushes / 2).max(1);
+        let factor = ((index + 1) as f32 / ramp_pushes as f32).min(1.0);
+        let wind = self.wind_mph * factor;
+        let rain = self.rain_inches * factor;
+
+        WeatherData {
+            stationid: Some("simulated-storm".to_string()),
+            windspeedmph: Some(wind),
+            windgustmph: Some(wind),
+            hourlyrainin: Some(rain),
+            eventrainin: Some(rain),
+            dailyrainin: Some(rain),
+            ..Default::default()
+        }
+    }
+}
+
+/// Feed a synthetic storm through the metrics pipeline so alerting rules can
+/// be exercised against controlled extreme values. Requires the admin token.
+pub async fn handle_simulate_storm(
+    req: web::HttpRequest,
+    state: web::types::State<AppState>,
+    params: web::types::Json<StormParams>,
+) -> Result<web::HttpResponse, AppError> {
+    if !has_bearer_token(&req, state.config.admin_token.as_deref()) {
+        warn!("Rejected storm simulation without a valid admin token");
+        return Err(AppError::Unauthorized);
+    }
+
+    let params = params.into_inner();
+    if params.duration_pushes == 0
+        || params.duration_pushes > MAX_PUSHES
+        || params.interval_ms > MAX_INTERVAL_MS
+    {
+        return Err(AppError::BadRequest(format!(
+            "duration_pushes must be 1..={} and interval_ms at most {}",
+            MAX_PUSHES, MAX_INTERVAL_MS
+        )));
+    }
+
+    info!("Simulating storm: {:?}", params);
+    for index in 0..params.duration_pushes {
+        if index > 0 && params.interval_ms > 0 {
+            ntex::time::sleep(Duration::from_millis(params.interval_ms)).await;
+        }
+        metrics().update(&params.reading(index))?;
+    }
+
+    Ok(web::HttpResponse::Ok().body(format!(
+        "Simulated {} storm pushes",
+        params.duration_pushes
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use ntex::http::StatusCode;
+    use ntex::web::test::{call_service, init_service, TestRequest};
+    use ntex::web::App;
+
+    fn storm(pushes: u32) -> StormParams {
+        StormParams {
+            wind_mph: 65.0,
+            rain_inches: 2.5,
+            duration_pushes: pushes,
+            interval_ms: 0,
+        }
+    }
+
+    #[test]
+    fn readings_ramp_up_then_hold() {
+        let params = storm(10);
+        let winds: Vec<f32> = (0..10).map(|i| params.reading(i).windspeedmph.unwrap()).collect();
+        assert_eq!(winds, [13.0, 26.0, 39.0, 52.0, 65.0, 65.0, 65.0, 65.0, 65.0, 65.0]);
+        assert_eq!(params.reading(9).dailyrainin, Some(2.5));
+    }
+
+    #[ntex::test]
+    async fn simulation_reaches_peak_wind() {
+        let state = test_support::state(&[("STORMCAST_ADMIN_TOKEN", "secret")]);
+        let app = init_service(
+            App::new()
+                .state(state)
+                .route("/push/simulate-storm", web::post().to(handle_simulate_storm)),
+        )
+        .await;
+
+        let body = r#"{"wind_mph": 65, "rain_inches": 2.5, "duration_pushes": 3, "interval_ms": 10}"#;
+        let req = TestRequest::post()
+            .uri("/push/simulate-storm")
+            .header("Content-Type", "application/json")
+            .header("Authorization", "Bearer secret")
+            .set_payload(body)
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let wind = test_support::series_value(metrics(), "weather_windspeed_mph", "simulated-storm");
+        assert_eq!(wind, Some(65.0));
+    }
+
+    #[ntex::test]
+    async fn simulation_requires_admin_token() {
+        let state = test_support::state(&[("STORMCAST_ADMIN_TOKEN", "secret")]);
+        let app = init_service(
+            App::new()
+                .state(state)
+                .route("/push/simulate-storm", web::post().to(handle_simulate_storm)),
+        )
+        .await;
+
+        let req = TestRequest::post()
+            .uri("/push/simulate-storm")
+            .header("Content-Type", "application/json")
+            .set_payload(r#"{"wind_mph": 65, "rain_inches": 2.5, "duration_pushes": 3}"#)
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+    }
+}
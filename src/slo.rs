@@ -0,0 +1,85 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Period push outcomes are counted over.
+const WINDOW: Duration = Duration::from_secs(3600);
+/// Outcomes are counted in buckets of this width to bound memory.
+const BUCKET: Duration = Duration::from_secs(60);
+
+/// Successful and failed pushes over a sliding one-hour window.
+#[derive(Debug, Default)]
+pub struct SloWindow {
+    /// Bucket start, total pushes, failed pushes; oldest first.
+    buckets: VecDeque<(Instant, u64, u64)>,
+}
+
+impl SloWindow {
+    fn expire(&mut self, now: Instant) {
+        while let Some(&(start, _, _)) = self.buckets.front() {
+            if now.saturating_duration_since(start) < WINDOW {
+                break;
+            }
+            self.buckets.pop_front();
+        }
+    }
+
+    pub fn record(&mut self, now: Instant, error: bool) {
+        self.expire(now);
+        match self.buckets.back_mut() {
+            Some((start, total, errors)) if now.saturating_duration_since(*start) < BUCKET => {
+                *total += 1;
+                *errors += u64::from(error);
+            }
+            _ => self.buckets.push_back((now, 1, u64::from(error))),
+        }
+    }
+
+    /// Fraction of pushes in the window that succeeded, 1.0 when there were none.
+    pub fn success_ratio(&mut self, now: Instant) -> f64 {
+        self.expire(now);
+        let (total, errors) = self
+            .buckets
+            .iter()
+            .fold((0, 0), |(total, errors), &(_, t, e)| (total + t, errors + e));
+        if total == 0 {
+            1.0
+        } else {
+            1.0 - errors as f64 / total as f64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Metrics;
+    use crate::{test_support, PushProtocol};
+
+    #[test]
+    fn outcomes_expire_after_an_hour() {
+        let start = Instant::now();
+        let mut window = SloWindow::default();
+        assert_eq!(window.success_ratio(start), 1.0);
+        window.record(start, true);
+        window.record(start + Duration::from_secs(90), false);
+        assert_eq!(window.success_ratio(start + Duration::from_secs(90)), 0.5);
+        assert_eq!(window.success_ratio(start + WINDOW + Duration::from_secs(1)), 1.0);
+    }
+
+    #[test]
+    fn two_errors_in_1001_pushes_leave_0_998() {
+        let metrics = Metrics::new(&test_support::config(&[])).unwrap();
+        for _ in 0..999 {
+            metrics.record_push("slo", PushProtocol::V1);
+        }
+        metrics.record_push_error("slo");
+        metrics.record_push_error("slo");
+
+        assert_eq!(test_support::sample(&metrics, "weather_slo_total", &[]), Some(1001.0));
+        assert_eq!(test_support::sample(&metrics, "weather_slo_errors_total", &[]), Some(2.0));
+        let remaining = test_support::sample(&metrics, "weather_slo_error_budget_remaining", &[]).unwrap();
+        assert!((remaining - 0.998).abs() < 0.0001, "{}", remaining);
+        // Below the default 99.9% target
+        assert_eq!(test_support::sample(&metrics, "weather_slo_budget_exhausted", &[]), Some(1.0));
+    }
+}
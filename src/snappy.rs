@@ -0,0 +1,186 @@
+/// Input is compressed in independent blocks of this size, so copy offsets
+/// always fit in two bytes.
+const BLOCK_SIZE: usize = 1 << 16;
+/// Shortest repeat worth encoding as a copy.
+const MIN_MATCH: usize = 4;
+/// Longest copy a single element can encode.
+const MAX_COPY: usize = 64;
+const HASH_BITS: u32 = 14;
+
+fn hash(bytes: &[u8]) -> usize {
+    let word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    (word.wrapping_mul(0x1e35_a7bd) >> (32 - HASH_BITS)) as usize
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_literal(out: &mut Vec<u8>, literal: &[u8]) {
+    if literal.is_empty() {
+        return;
+    }
+    let n = literal.len() - 1;
+    if n < 60 {
+        out.push((n as u8) << 2);
+    } else {
+        // Tags 60 to 63 say how many little-endian bytes of length follow
+        let bytes = (usize::BITS - n.leading_zeros()).div_ceil(8) as usize;
+        out.push(((59 + bytes) as u8) << 2);
+        out.extend_from_slice(&n.to_le_bytes()[..bytes]);
+    }
+    out.extend_from_slice(literal);
+}
+
+/// Write one copy element of 4 to 64 bytes.
+fn write_copy_element(out: &mut Vec<u8>, offset: usize, len: usize) {
+    if (4..12).contains(&len) && offset < 2048 {
+        out.push((((offset >> 8) as u8) << 5) | (((len - 4) as u8) << 2) | 0b01);
+        out.push(offset as u8);
+    } else {
+        out.push((((len - 1) as u8) << 2) | 0b10);
+        out.extend_from_slice(&(offset as u16).to_le_bytes());
+    }
+}
+
+/// Write a copy of any length, split so no element is shorter than four bytes.
+fn write_copy(out: &mut Vec<u8>, offset: usize, mut len: usize) {
+    while len >= MAX_COPY + MIN_MATCH {
+        write_copy_element(out, offset, MAX_COPY);
+        len -= MAX_COPY;
+    }
+    if len > MAX_COPY {
+        write_copy_element(out, offset, MAX_COPY - MIN_MATCH);
+        len -= MAX_COPY - MIN_MATCH;
+    }
+    write_copy_element(out, offset, len);
+}
+
+fn compress_block(out: &mut Vec<u8>, block: &[u8]) {
+    let mut table = vec![0usize; 1 << HASH_BITS];
+    let mut literal_start = 0;
+    let mut pos = 0;
+    while pos + MIN_MATCH <= block.len() {
+        let slot = &mut table[hash(&block[pos..])];
+        let candidate = *slot;
+        *slot = pos;
+        if candidate >= pos || block[candidate..candidate + MIN_MATCH] != block[pos..pos + MIN_MATCH] {
+            pos += 1;
+            continue;
+        }
+        let len = MIN_MATCH
+            + block[pos + MIN_MATCH..]
+                .iter()
+                .zip(&block[candidate + MIN_MATCH..])
+                .take_while(|(a, b)| a == b)
+                .count();
+        write_literal(out, &block[literal_start..pos]);
+        write_copy(out, pos - candidate, len);
+        pos += len;
+        literal_start = pos;
+    }
+    write_literal(out, &block[literal_start..]);
+}
+
+/// Compress `input` in the snappy block format Prometheus remote write
+/// bodies use: its length as a varint followed by literal and copy
+/// elements, with repeats found through a hash table of four-byte
+/// sequences as in the reference implementation.
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() / 2 + 16);
+    write_varint(&mut out, input.len() as u64);
+    for block in input.chunks(BLOCK_SIZE) {
+        compress_block(&mut out, block);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reference decoder for checking round trips.
+    fn decompress(input: &[u8]) -> Vec<u8> {
+        let mut pos = 0;
+        let mut len = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = input[pos];
+            pos += 1;
+            len |= u64::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte < 0x80 {
+                break;
+            }
+        }
+        let mut out = Vec::with_capacity(len as usize);
+        while pos < input.len() {
+            let tag = input[pos];
+            pos += 1;
+            let (offset, len) = match tag & 0b11 {
+                0b00 => {
+                    let mut n = usize::from(tag >> 2);
+                    if n >= 60 {
+                        let bytes = n - 59;
+                        let mut le = [0u8; 8];
+                        le[..bytes].copy_from_slice(&input[pos..pos + bytes]);
+                        n = usize::from_le_bytes(le);
+                        pos += bytes;
+                    }
+                    out.extend_from_slice(&input[pos..pos + n + 1]);
+                    pos += n + 1;
+                    continue;
+                }
+                0b01 => {
+                    let offset = usize::from(tag >> 5) << 8 | usize::from(input[pos]);
+                    pos += 1;
+                    (offset, usize::from((tag >> 2) & 0b111) + 4)
+                }
+                0b10 => {
+                    let offset = usize::from(u16::from_le_bytes([input[pos], input[pos + 1]]));
+                    pos += 2;
+                    (offset, usize::from(tag >> 2) + 1)
+                }
+                _ => panic!("four-byte offsets are never written"),
+            };
+            assert!(offset > 0 && offset <= out.len(), "offset {} out of range", offset);
+            for _ in 0..len {
+                out.push(out[out.len() - offset]);
+            }
+        }
+        assert_eq!(out.len() as u64, len);
+        out
+    }
+
+    #[test]
+    fn known_encodings() {
+        assert_eq!(compress(b""), [0]);
+        assert_eq!(compress(b"abc"), [3, 2 << 2, b'a', b'b', b'c']);
+        // "abcd" then a copy of 8 bytes at offset 4
+        assert_eq!(compress(b"abcdabcdabcd"), [12, 3 << 2, b'a', b'b', b'c', b'd', (4 << 2) | 0b01, 4]);
+    }
+
+    #[test]
+    fn round_trips() {
+        let metrics = "weather_temperature_fahrenheit{station=\"a\"} 71.2\n".repeat(500);
+        let mut noise = Vec::new();
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        for _ in 0..200_000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            noise.push(state as u8);
+        }
+        let periodic = (0..=255u8).cycle().take(70_000).collect::<Vec<_>>();
+        let inputs: [&[u8]; 5] = [b"", b"x", metrics.as_bytes(), &noise, &periodic];
+        for input in inputs {
+            let compressed = compress(input);
+            assert_eq!(decompress(&compressed), input);
+        }
+        assert!(compress(metrics.as_bytes()).len() < metrics.len() / 10);
+    }
+}
@@ -0,0 +1,382 @@
+use ntex::web;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::auth::has_bearer_token;
+use crate::error::AppError;
+use crate::firmware::FirmwareInfo;
+use crate::metrics::metrics;
+use crate::AppState;
+
+/// Out-of-band information about a station that its pushes don't carry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StationMetadata {
+    pub name: String,
+    pub description: Option<String>,
+    pub elevation_m: Option<f64>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// Installation date as given, e.g. `2023-06-01`.
+    pub installed_at: Option<String>,
+    /// Firmware detected from the station's pushes; not settable.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub firmware: Option<FirmwareInfo>,
+}
+
+/// What a station reports about its own hardware in its pushes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StationHardware {
+    pub stationtype: Option<String>,
+    /// Sensor radio band, e.g. `868M`.
+    pub freq: Option<String>,
+}
+
+/// Metadata for every station it has been set for, keyed by station ID.
+/// Set metadata is saved to `path`, when given, so it survives restarts.
+#[derive(Debug, Default)]
+pub struct MetadataStore {
+    path: Option<PathBuf>,
+    stations: RwLock<HashMap<String, StationMetadata>>,
+    firmware: RwLock<HashMap<String, FirmwareInfo>>,
+    hardware: RwLock<HashMap<String, StationHardware>>,
+}
+
+impl MetadataStore {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        MetadataStore {
+            path,
+            ..Default::default()
+        }
+    }
+
+    /// Load the metadata saved to the state file, exposing each station's
+    /// name on `weather_station_info`. A missing file holds no metadata.
+    pub fn load(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = match fs::read(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let saved: HashMap<String, StationMetadata> = serde_json::from_slice(&json)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))?;
+        info!("Loaded metadata for {} stations from {}", saved.len(), path.display());
+        for (station, metadata) in &saved {
+            metrics().set_station_info(station, &metadata.name);
+        }
+        *self.stations.write().unwrap() = saved;
+        Ok(())
+    }
+
+    /// Write every station's metadata to the state file, via a temporary
+    /// file so a crash never leaves it half written.
+    fn save(&self, stations: &HashMap<String, StationMetadata>) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(stations)?)?;
+        fs::rename(&tmp, path)
+    }
+
+    pub fn get(&self, station: &str) -> Option<StationMetadata> {
+        let mut metadata = self.stations.read().unwrap().get(station).cloned()?;
+        metadata.firmware = self.firmware.read().unwrap().get(station).cloned();
+        Some(metadata)
+    }
+
+    /// The name set for `station`, if any.
+    pub fn name(&self, station: &str) -> Option<String> {
+        Some(self.stations.read().unwrap().get(station)?.name.clone())
+    }
+
+    /// Record the firmware a station was seen running, updating
+    /// `weather_station_firmware` when it changes.
+    pub fn set_firmware(&self, station: &str, firmware: FirmwareInfo) {
+        let mut stations = self.firmware.write().unwrap();
+        if stations.get(station) == Some(&firmware) {
+            return;
+        }
+        info!("Station {} runs {} {}", station, firmware.name, firmware.version);
+        metrics().set_station_firmware(station, &firmware);
+        if let Some(previous) = stations.insert(station.to_string(), firmware) {
+            metrics().clear_station_firmware(station, &previous);
+        }
+    }
+
+    /// Record the `stationtype` and `freq` a station reported, updating
+    /// `weather_station_type_info` when they change. The radio band only
+    /// changes when hardware is swapped, so a change is warned about.
+    pub fn set_hardware(&self, station: &str, hardware: StationHardware) {
+        if self.hardware.read().unwrap().get(station) == Some(&hardware) {
+            return;
+        }
+        let mut stations = self.hardware.write().unwrap();
+        let previous = stations.insert(station.to_string(), hardware.clone());
+        if previous.as_ref() == Some(&hardware) {
+            return;
+        }
+        let unknown = || "unknown".to_string();
+        match &previous {
+            Some(previous) => {
+                if previous.stationtype != hardware.stationtype {
+                    info!(
+                        "Station {} changed type from {} to {}",
+                        station,
+                        previous.stationtype.clone().unwrap_or_else(unknown),
+                        hardware.stationtype.clone().unwrap_or_else(unknown)
+                    );
+                }
+                if previous.freq != hardware.freq {
+                    warn!(
+                        "Station {} changed radio frequency from {} to {}",
+                        station,
+                        previous.freq.clone().unwrap_or_else(unknown),
+                        hardware.freq.clone().unwrap_or_else(unknown)
+                    );
+                }
+            }
+            None => info!(
+                "Station {} is a {} on {}",
+                station,
+                hardware.stationtype.clone().unwrap_or_else(unknown),
+                hardware.freq.clone().unwrap_or_else(unknown)
+            ),
+        }
+        metrics().set_station_type(station, &hardware);
+        if let Some(previous) = previous {
+            metrics().clear_station_type(station, &previous);
+        }
+    }
+
+    /// Save metadata for `station` to the state file, then store it,
+    /// returning what it replaced. Nothing changes if saving fails.
+    pub fn set(&self, station: &str, mut metadata: StationMetadata) -> io::Result<Option<StationMetadata>> {
+        metadata.firmware = None;
+        let mut stations = self.stations.write().unwrap();
+        let mut updated = stations.clone();
+        updated.insert(station.to_string(), metadata);
+        self.save(&updated)?;
+        Ok(std::mem::replace(&mut *stations, updated).remove(station))
+    }
+}
+
+/// Return the stored metadata for a station.
+pub async fn handle_get_metadata(
+    state: web::types::State<AppState>,
+    station: web::types::Path<String>,
+) -> Result<web::HttpResponse, AppError> {
+    match state.metadata.get(&station) {
+        Some(metadata) => Ok(web::HttpResponse::Ok().json(&metadata)),
+        None => Err(AppError::NotFound(format!("no metadata for station {}", station))),
+    }
+}
+
+/// Replace a station's metadata and expose its name on `weather_station_info`.
+/// Requires the admin token.
+pub async fn handle_put_metadata(
+    req: web::HttpRequest,
+    state: web::types::State<AppState>,
+    station: web::types::Path<String>,
+    metadata: web::types::Json<StationMetadata>,
+) -> Result<web::HttpResponse, AppError> {
+    if !has_bearer_token(&req, state.config.admin_token.as_deref()) {
+        warn!("Rejected station metadata update without a valid admin token");
+        return Err(AppError::Unauthorized);
+    }
+
+    let mut metadata = metadata.into_inner();
+    metadata.firmware = state.metadata.firmware.read().unwrap().get(station.as_str()).cloned();
+    info!("Setting metadata for station {}: {:?}", station, metadata);
+    let previous = state.metadata.set(&station, metadata.clone()).map_err(|e| {
+        warn!("Failed to save station metadata: {}", e);
+        AppError::Storage(format!("failed to save metadata: {}", e))
+    })?;
+    metrics().set_station_info(&station, &metadata.name);
+    if let Some(previous) = previous {
+        if previous.name != metadata.name {
+            metrics().clear_station_info(&station, &previous.name);
+        }
+    }
+    Ok(web::HttpResponse::Ok().json(&metadata))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::ExpositionFormat;
+    use crate::test_support;
+    use ntex::http::StatusCode;
+    use ntex::web::test::{call_service, init_service, read_body, TestRequest};
+    use ntex::web::App;
+    use std::sync::{Arc, Mutex};
+
+    #[ntex::test]
+    async fn metadata_round_trips_and_labels_metrics() {
+        let state = test_support::state(&[("STORMCAST_ADMIN_TOKEN", "secret")]);
+        let app = init_service(
+            App::new()
+                .state(state)
+                .route("/station/{id}/metadata", web::get().to(handle_get_metadata))
+                .route("/station/{id}/metadata", web::put().to(handle_put_metadata)),
+        )
+        .await;
+
+        let body = r#"{"name": "Backyard", "description": "By the fence", "elevation_m": 150.0,
+            "latitude": 37.7749, "longitude": -122.4194, "installed_at": "2023-06-01"}"#;
+        let req = TestRequest::put()
+            .uri("/station/meta1/metadata")
+            .header("Authorization", "Bearer secret")
+            .header("Content-Type", "application/json")
+            .set_payload(body)
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), StatusCode::OK);
+
+        let res = call_service(&app, TestRequest::with_uri("/station/meta1/metadata").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let metadata: serde_json::Value = serde_json::from_slice(&read_body(res).await).unwrap();
+        assert_eq!(metadata["name"], "Backyard");
+        assert_eq!(metadata["elevation_m"], 150.0);
+        assert_eq!(metadata["installed_at"], "2023-06-01");
+
+        let text = String::from_utf8(metrics().encode(ExpositionFormat::Prometheus)).unwrap();
+        assert!(text.contains(r#"weather_station_info{name="Backyard",station="meta1"} 1"#), "{}", text);
+    }
+
+    #[ntex::test]
+    async fn metadata_is_saved_to_the_state_file_and_loaded_at_startup() {
+        let path = std::env::temp_dir().join(format!("stormcast-state-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let state = test_support::state(&[
+            ("STORMCAST_ADMIN_TOKEN", "secret"),
+            ("STORMCAST_STATE_FILE", path.to_str().unwrap()),
+        ]);
+        state.metadata.load().unwrap();
+        let app = init_service(
+            App::new()
+                .state(state)
+                .route("/station/{id}/metadata", web::put().to(handle_put_metadata)),
+        )
+        .await;
+        let req = TestRequest::put()
+            .uri("/station/saved1/metadata")
+            .header("Authorization", "Bearer secret")
+            .header("Content-Type", "application/json")
+            .set_payload(r#"{"name": "Rooftop", "elevation_m": 12.5}"#)
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), StatusCode::OK);
+
+        let restarted = MetadataStore::new(Some(path.clone()));
+        restarted.load().unwrap();
+        let metadata = restarted.get("saved1").unwrap();
+        assert_eq!(metadata.name, "Rooftop");
+        assert_eq!(metadata.elevation_m, Some(12.5));
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(MetadataStore::new(Some(path.clone())).load().is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn missing_state_file_holds_no_metadata() {
+        let store = MetadataStore::new(Some(std::env::temp_dir().join("stormcast-state-missing.json")));
+        store.load().unwrap();
+        assert!(store.get("anything").is_none());
+    }
+
+    #[ntex::test]
+    async fn metadata_requires_admin_token() {
+        let state = test_support::state(&[("STORMCAST_ADMIN_TOKEN", "secret")]);
+        let app = init_service(
+            App::new()
+                .state(state)
+                .route("/station/{id}/metadata", web::get().to(handle_get_metadata))
+                .route("/station/{id}/metadata", web::put().to(handle_put_metadata)),
+        )
+        .await;
+        let req = TestRequest::put()
+            .uri("/station/meta2/metadata")
+            .header("Content-Type", "application/json")
+            .set_payload(r#"{"name": "Roof"}"#)
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+        let res = call_service(&app, TestRequest::with_uri("/station/meta2/metadata").to_request()).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    /// Log output written while a test runs.
+    #[derive(Clone, Default)]
+    struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn radio_band_changes_are_warned_about() {
+        test_support::init_metrics();
+        let data = crate::WeatherData::from_query("PASSKEY=radio&stationtype=GW2000A_V2.1.4&freq=915M").unwrap();
+        assert_eq!(data.freq.as_deref(), Some("915M"));
+        assert!(data.extra.is_empty());
+
+        let store = MetadataStore::default();
+        let hardware = |freq: &str| StationHardware {
+            stationtype: data.stationtype.clone(),
+            freq: Some(freq.to_string()),
+        };
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt().with_ansi(false).with_writer(move || writer.clone()).finish();
+        tracing::subscriber::with_default(subscriber, || {
+            store.set_hardware("radio", hardware("915M"));
+            store.set_hardware("radio", hardware("915M"));
+            store.set_hardware("radio", hardware("868M"));
+        });
+
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        let warnings: Vec<&str> = logs.lines().filter(|line| line.contains("WARN")).collect();
+        assert_eq!(warnings.len(), 1, "{}", logs);
+        assert!(warnings[0].contains("Station radio changed radio frequency from 915M to 868M"), "{}", logs);
+
+        let text = String::from_utf8(metrics().encode(ExpositionFormat::Prometheus)).unwrap();
+        assert!(text.contains(r#"freq="868M",model="GW2000A",station="radio",type="GW2000A_V2.1.4"} 1"#), "{}", text);
+        assert!(!text.contains(r#"freq="915M""#));
+    }
+
+    #[ntex::test]
+    async fn station_type_is_taken_from_a_real_push() {
+        let app = init_service(
+            App::new()
+                .state(test_support::state(&[]))
+                .route("/push/ecowitt-callback", web::get().to(crate::handle_ecowitt_callback)),
+        )
+        .await;
+        // As sent by a GW2000 gateway in Ecowitt protocol mode
+        let push = "PASSKEY=ECOWITTTYPE&stationtype=GW2000A_V3.1.1&runtime=86&heap=101840&dateutc=2024-05-01+12:00:00\
+            &tempinf=72.1&humidityin=41&baromrelin=29.921&baromabsin=29.024&tempf=64.2&humidity=55&winddir=180\
+            &windspeedmph=2.46&windgustmph=4.47&maxdailygust=8.05&solarradiation=320.55&uv=3&rainratein=0.000\
+            &eventrainin=0.000&hourlyrainin=0.000&dailyrainin=0.000&weeklyrainin=0.000&monthlyrainin=0.531\
+            &yearlyrainin=10.204&wh65batt=0&freq=915M&model=GW2000A&interval=60";
+        let req = TestRequest::with_uri(&format!("/push/ecowitt-callback?{}", push)).to_request();
+        assert_eq!(call_service(&app, req).await.status(), StatusCode::OK);
+
+        let labels = [("station", "ECOWITTTYPE"), ("type", "GW2000A_V3.1.1"), ("model", "GW2000A"), ("freq", "915M")];
+        assert_eq!(test_support::sample(metrics(), "weather_station_type_info", &labels), Some(1.0));
+    }
+}
@@ -0,0 +1,73 @@
+use ntex::web;
+use serde::Serialize;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::time::Instant;
+
+use crate::calendar;
+use crate::health;
+use crate::history::HistoryEntry;
+use crate::metrics::metrics;
+use crate::AppState;
+
+/// Process-level counters for `/status`, kept apart from the Prometheus
+/// registry so reading them needs no encoding.
+#[derive(Debug)]
+pub struct ServerStats {
+    started: Instant,
+    pushes: AtomicU64,
+    /// Unix time of the last accepted push, or zero before the first.
+    last_push: AtomicI64,
+}
+
+impl ServerStats {
+    pub fn new() -> Self {
+        ServerStats {
+            started: Instant::now(),
+            pushes: AtomicU64::new(0),
+            last_push: AtomicI64::new(0),
+        }
+    }
+
+    /// Count a push accepted at `unix_secs`.
+    pub fn record_push(&self, unix_secs: i64) {
+        self.pushes.fetch_add(1, Ordering::Relaxed);
+        self.last_push.fetch_max(unix_secs, Ordering::Relaxed);
+    }
+}
+
+#[derive(Serialize)]
+struct StatusResponse<'a> {
+    version: &'static str,
+    healthy: bool,
+    /// Why the server is unhealthy, as `/health` reports it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    uptime_seconds: u64,
+    pushes_received: u64,
+    last_push: Option<String>,
+    stations: usize,
+    latest: Option<HistoryEntry<'a>>,
+}
+
+/// Server statistics and the latest reading as JSON, for checking on the
+/// process without a Prometheus server. Not authenticated.
+pub async fn handle_status(state: web::types::State<AppState>) -> web::HttpResponse {
+    let stats = &state.stats;
+    let health = health::check(state.config.stale_threshold);
+    let last_push = stats.last_push.load(Ordering::Relaxed);
+    let latest = state.latest.get();
+    web::HttpResponse::Ok().json(&StatusResponse {
+        version: env!("CARGO_PKG_VERSION"),
+        healthy: health.is_ok(),
+        reason: health.err(),
+        uptime_seconds: stats.started.elapsed().as_secs(),
+        pushes_received: stats.pushes.load(Ordering::Relaxed),
+        last_push: (last_push > 0).then(|| calendar::format_datetime(last_push)),
+        stations: metrics().station_count(),
+        latest: latest
+            .as_ref()
+            .map(|(data, received_at)| {
+                HistoryEntry::new(data, *received_at, state.station_metadata_name(data), &state.config.precision)
+            }),
+    })
+}
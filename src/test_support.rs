@@ -0,0 +1,41 @@
+use std::sync::Once;
+
+use crate::config::{Config, ConfigEnv, ConfigFile};
+use crate::metrics::{self, ExpositionFormat, Metrics};
+use crate::AppState;
+
+/// Config built from `STORMCAST_*` settings as if they were the environment.
+pub fn config(vars: &[(&str, &str)]) -> Config {
+    let vars = vars.iter().map(|&(name, value)| (name.to_string(), value.to_string()));
+    Config::merge(ConfigFile::default(), ConfigEnv::from_vars(vars)).expect("test config is valid")
+}
+
+/// Install the process-wide metrics with the default config. Tests share
+/// them, so each should push under its own station ID.
+pub fn init_metrics() -> &'static Metrics {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| metrics::init(Metrics::new(&config(&[])).expect("metrics register")));
+    metrics::metrics()
+}
+
+/// Handler state for `config`, with the shared metrics installed.
+pub fn state(vars: &[(&str, &str)]) -> AppState {
+    init_metrics();
+    AppState::new(config(vars))
+}
+
+/// Value of the `station` series of metric `name` (with its prefix) in the
+/// encoded exposition, matching the first series carrying that station label.
+pub fn series_value(metrics: &Metrics, name: &str, station: &str) -> Option<f64> {
+    sample(metrics, name, &[("station", station)])
+}
+
+/// Value of the first series of metric `name` carrying all of `labels`.
+pub fn sample(metrics: &Metrics, name: &str, labels: &[(&str, &str)]) -> Option<f64> {
+    let text = String::from_utf8(metrics.encode(ExpositionFormat::Prometheus)).unwrap();
+    let labels: Vec<String> = labels.iter().map(|(name, value)| format!("{}=\"{}\"", name, value)).collect();
+    text.lines()
+        .filter(|line| line.split(['{', ' ']).next() == Some(name))
+        .filter(|line| labels.iter().all(|label| line.contains(label.as_str())))
+        .find_map(|line| line.rsplit(' ').next()?.parse().ok())
+}
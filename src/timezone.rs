@@ -0,0 +1,472 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::{env, fs};
+
+use crate::calendar::{Date, SECS_PER_DAY};
+
+/// Where the system keeps the IANA time zone database, unless `TZDIR` says otherwise.
+const DEFAULT_TZDIR: &str = "/usr/share/zoneinfo";
+/// Default time of day a POSIX TZ rule switches at, 02:00 local.
+const DEFAULT_SWITCH_SECS: i64 = 2 * 3600;
+
+/// The station's local time: an IANA zone such as `America/Los_Angeles`, read
+/// from the system tz database so daylight saving is followed, or a fixed
+/// offset written as `UTC`, `+HH:MM`, `-HH:MM` or `UTC-8`.
+#[derive(Debug, Clone)]
+pub struct TimeZone {
+    name: String,
+    zone: Arc<Zone>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TimeZoneError {
+    #[error("invalid UTC offset {0:?}, expected e.g. \"UTC\", \"+01:00\" or \"-08:00\"")]
+    Offset(String),
+    #[error("unknown time zone {name:?}: {reason}")]
+    Unknown { name: String, reason: String },
+}
+
+/// UTC offsets in effect over time, as described by a TZif file.
+#[derive(Debug, Default)]
+struct Zone {
+    /// Offset in seconds east of UTC before the first transition.
+    initial: i64,
+    /// Unix time of each transition and the offset in effect from then on.
+    transitions: Vec<(i64, i64)>,
+    /// Rule for times after the last transition.
+    rule: Option<PosixRule>,
+}
+
+impl Default for TimeZone {
+    fn default() -> Self {
+        TimeZone::fixed(0)
+    }
+}
+
+impl TimeZone {
+    fn fixed(seconds: i64) -> TimeZone {
+        let sign = if seconds < 0 { '-' } else { '+' };
+        let abs = seconds.abs();
+        TimeZone {
+            name: format!("UTC{}{:02}:{:02}", sign, abs / 3600, abs % 3600 / 60),
+            zone: Arc::new(Zone {
+                initial: seconds,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Load `name` from the tz database in `TZDIR` or `/usr/share/zoneinfo`.
+    fn load(name: &str) -> Result<TimeZone, TimeZoneError> {
+        let unknown = |reason: &str| TimeZoneError::Unknown {
+            name: name.to_string(),
+            reason: reason.to_string(),
+        };
+        let valid_name = !name.starts_with('/')
+            && name.split('/').all(|part| !part.is_empty() && part != "." && part != "..")
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || "/_-+".contains(c));
+        if !valid_name {
+            return Err(unknown("not a zone name"));
+        }
+        let dir = env::var_os("TZDIR").map_or_else(|| PathBuf::from(DEFAULT_TZDIR), PathBuf::from);
+        let bytes = fs::read(dir.join(name)).map_err(|e| unknown(&e.to_string()))?;
+        let zone = parse_tzif(&bytes).ok_or_else(|| unknown("not a valid TZif file"))?;
+        Ok(TimeZone {
+            name: name.to_string(),
+            zone: Arc::new(zone),
+        })
+    }
+
+    /// Offset from UTC in seconds east in effect at `unix_secs`.
+    pub fn offset_at(&self, unix_secs: i64) -> i64 {
+        let zone = &self.zone;
+        let after = zone.transitions.partition_point(|&(at, _)| at <= unix_secs);
+        match (after, &zone.rule) {
+            (n, Some(rule)) if n == zone.transitions.len() => rule.offset_at(unix_secs),
+            (0, _) => zone.initial,
+            (n, _) => zone.transitions[n - 1].1,
+        }
+    }
+
+    /// Days since the epoch in this local time.
+    pub fn local_day(&self, unix_secs: i64) -> i64 {
+        (unix_secs + self.offset_at(unix_secs)).div_euclid(SECS_PER_DAY)
+    }
+
+    /// Calendar date in this local time.
+    pub fn local_date(&self, unix_secs: i64) -> Date {
+        Date::from_days(self.local_day(unix_secs))
+    }
+}
+
+impl FromStr for TimeZone {
+    type Err = TimeZoneError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let rest = value.strip_prefix("UTC").or_else(|| value.strip_prefix("GMT")).unwrap_or(value);
+        if rest.is_empty() || rest == "Z" {
+            return Ok(TimeZone::default());
+        }
+        match rest.as_bytes()[0] {
+            b'+' | b'-' => parse_offset(rest)
+                .filter(|seconds| seconds.abs() <= 14 * 3600)
+                .map(TimeZone::fixed)
+                .ok_or_else(|| TimeZoneError::Offset(value.to_string())),
+            _ => TimeZone::load(value),
+        }
+    }
+}
+
+impl fmt::Display for TimeZone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+/// Parse `[+-]HH[:MM[:SS]]` into seconds, positive for a leading `+` or no sign.
+fn parse_offset(value: &str) -> Option<i64> {
+    let (sign, rest) = match value.as_bytes().first()? {
+        b'+' => (1, &value[1..]),
+        b'-' => (-1, &value[1..]),
+        _ => (1, value),
+    };
+    let mut parts = rest.splitn(3, ':');
+    let hours: i64 = parts.next()?.parse().ok()?;
+    let minutes: i64 = parts.next().map_or(Ok(0), str::parse).ok()?;
+    let seconds: i64 = parts.next().map_or(Ok(0), str::parse).ok()?;
+    if hours > 167 || minutes >= 60 || seconds >= 60 {
+        return None;
+    }
+    Some(sign * (hours * 3600 + minutes * 60 + seconds))
+}
+
+/// Read a TZif file (RFC 8536), preferring the 64-bit data of version 2+.
+fn parse_tzif(bytes: &[u8]) -> Option<Zone> {
+    let (version, counts) = tzif_header(bytes)?;
+    if version == 0 {
+        return parse_tzif_block(bytes.get(44..)?, &counts, 4).map(|(zone, _)| zone);
+    }
+    // Skip the 32-bit block for the header and block that follow it
+    let rest = bytes.get(44 + counts.block_len(4)..)?;
+    let (_, counts) = tzif_header(rest)?;
+    let (mut zone, footer) = parse_tzif_block(rest.get(44..)?, &counts, 8)?;
+    let footer = footer.strip_prefix(b"\n")?;
+    let end = footer.iter().position(|&b| b == b'\n')?;
+    let footer = std::str::from_utf8(&footer[..end]).ok()?;
+    if !footer.is_empty() {
+        zone.rule = Some(PosixRule::parse(footer)?);
+    }
+    Some(zone)
+}
+
+struct TzifCounts {
+    isutcnt: usize,
+    isstdcnt: usize,
+    leapcnt: usize,
+    timecnt: usize,
+    typecnt: usize,
+    charcnt: usize,
+}
+
+impl TzifCounts {
+    /// Length of the data block following the header, with `time_size`-byte times.
+    fn block_len(&self, time_size: usize) -> usize {
+        self.timecnt * (time_size + 1)
+            + self.typecnt * 6
+            + self.charcnt
+            + self.leapcnt * (time_size + 4)
+            + self.isstdcnt
+            + self.isutcnt
+    }
+}
+
+fn tzif_header(bytes: &[u8]) -> Option<(u8, TzifCounts)> {
+    if bytes.get(..4)? != b"TZif" {
+        return None;
+    }
+    let version = match *bytes.get(4)? {
+        0 => 0,
+        v @ b'2'..=b'9' => v - b'0',
+        _ => return None,
+    };
+    let count = |n: usize| {
+        let at = 20 + n * 4;
+        bytes.get(at..at + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize)
+    };
+    let counts = TzifCounts {
+        isutcnt: count(0)?,
+        isstdcnt: count(1)?,
+        leapcnt: count(2)?,
+        timecnt: count(3)?,
+        typecnt: count(4)?,
+        charcnt: count(5)?,
+    };
+    (counts.typecnt > 0).then_some((version, counts))
+}
+
+/// Transitions of one data block, and whatever follows the block.
+fn parse_tzif_block<'a>(block: &'a [u8], counts: &TzifCounts, time_size: usize) -> Option<(Zone, &'a [u8])> {
+    let rest = block.get(counts.block_len(time_size)..)?;
+    let times = &block[..counts.timecnt * time_size];
+    let indices = &block[times.len()..times.len() + counts.timecnt];
+    let types = &block[times.len() + indices.len()..][..counts.typecnt * 6];
+    let offset = |index: usize| {
+        let info = types.get(index * 6..index * 6 + 4)?;
+        Some(i64::from(i32::from_be_bytes([info[0], info[1], info[2], info[3]])))
+    };
+
+    let transitions = times
+        .chunks(time_size)
+        .zip(indices)
+        .map(|(time, &index)| {
+            let at = match *time {
+                [a, b, c, d] => i64::from(i32::from_be_bytes([a, b, c, d])),
+                _ => i64::from_be_bytes(time.try_into().ok()?),
+            };
+            Some((at, offset(index as usize)?))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let zone = Zone {
+        initial: offset(0)?,
+        transitions,
+        rule: None,
+    };
+    Some((zone, rest))
+}
+
+/// Day a POSIX TZ rule switches on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RuleDay {
+    /// `Jn`: day 1–365, never counting February 29th.
+    Julian(i64),
+    /// `n`: day 0–365, counting February 29th.
+    Ordinal(i64),
+    /// `Mm.w.d`: weekday `d` (0 = Sunday) of week `w` (5 = last) of month `m`.
+    MonthWeek { month: u32, week: i64, weekday: i64 },
+}
+
+impl RuleDay {
+    /// Days since the epoch of this day in `year`.
+    fn to_days(self, year: i64) -> i64 {
+        let jan1 = Date { year, month: 1, day: 1 }.to_days();
+        match self {
+            RuleDay::Julian(day) => {
+                let leap_day = Date { year, month: 3, day: 1 }.to_days() - jan1 == 60;
+                jan1 + day - 1 + i64::from(leap_day && day >= 60)
+            }
+            RuleDay::Ordinal(day) => jan1 + day,
+            RuleDay::MonthWeek { month, week, weekday } => {
+                let first = Date { year, month, day: 1 }.to_days();
+                let next_month = match month {
+                    12 => Date { year: year + 1, month: 1, day: 1 },
+                    _ => Date { year, month: month + 1, day: 1 },
+                }
+                .to_days();
+                // 1970-01-01 was a Thursday
+                let first_weekday = (first + 4).rem_euclid(7);
+                let mut day = first + (weekday - first_weekday).rem_euclid(7) + (week - 1) * 7;
+                while day >= next_month {
+                    day -= 7;
+                }
+                day
+            }
+        }
+    }
+}
+
+/// Daylight saving part of a POSIX TZ rule.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct DstRule {
+    offset: i64,
+    start: (RuleDay, i64),
+    end: (RuleDay, i64),
+}
+
+/// A POSIX TZ string such as `PST8PDT,M3.2.0,M11.1.0`, as found at the end
+/// of TZif files to describe times past the last listed transition.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PosixRule {
+    /// Standard offset in seconds east of UTC.
+    std_offset: i64,
+    dst: Option<DstRule>,
+}
+
+impl PosixRule {
+    fn parse(value: &str) -> Option<PosixRule> {
+        let mut rest = skip_zone_name(value)?;
+        let (std_offset, after) = take_posix_offset(rest)?;
+        // POSIX offsets count hours west of UTC
+        let std_offset = -std_offset;
+        rest = after;
+        if rest.is_empty() {
+            return Some(PosixRule { std_offset, dst: None });
+        }
+
+        rest = skip_zone_name(rest)?;
+        let mut dst_offset = std_offset + 3600;
+        if !rest.is_empty() && !rest.starts_with(',') {
+            let (offset, after) = take_posix_offset(rest)?;
+            dst_offset = -offset;
+            rest = after;
+        }
+        // Zones naming a DST abbreviation without rules follow the US rules
+        let rules = rest.strip_prefix(',').unwrap_or("M3.2.0,M11.1.0");
+        let (start, end) = rules.split_once(',')?;
+        Some(PosixRule {
+            std_offset,
+            dst: Some(DstRule {
+                offset: dst_offset,
+                start: parse_rule_switch(start)?,
+                end: parse_rule_switch(end)?,
+            }),
+        })
+    }
+
+    fn offset_at(&self, unix_secs: i64) -> i64 {
+        let Some(dst) = self.dst else {
+            return self.std_offset;
+        };
+        let year = Date::from_days((unix_secs + self.std_offset).div_euclid(SECS_PER_DAY)).year;
+        // The start is given in standard time and the end in daylight time
+        let start = dst.start.0.to_days(year) * SECS_PER_DAY + dst.start.1 - self.std_offset;
+        let end = dst.end.0.to_days(year) * SECS_PER_DAY + dst.end.1 - dst.offset;
+        let in_dst = if start < end {
+            (start..end).contains(&unix_secs)
+        } else {
+            // Southern hemisphere: daylight time spans the new year
+            !(end..start).contains(&unix_secs)
+        };
+        if in_dst {
+            dst.offset
+        } else {
+            self.std_offset
+        }
+    }
+}
+
+/// Skip a zone abbreviation, either alphabetic or quoted as `<...>`.
+fn skip_zone_name(value: &str) -> Option<&str> {
+    if let Some(quoted) = value.strip_prefix('<') {
+        return quoted.split_once('>').map(|(_, rest)| rest);
+    }
+    let len = value.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(value.len());
+    (len >= 3).then(|| &value[len..])
+}
+
+/// Split a leading `[+-]hh[:mm[:ss]]` off `value`.
+fn take_posix_offset(value: &str) -> Option<(i64, &str)> {
+    let len = value
+        .char_indices()
+        .find(|&(i, c)| !(c.is_ascii_digit() || c == ':' || (i == 0 && (c == '+' || c == '-'))))
+        .map_or(value.len(), |(i, _)| i);
+    Some((parse_offset(&value[..len])?, &value[len..]))
+}
+
+/// Parse `date[/time]` of a POSIX TZ rule.
+fn parse_rule_switch(value: &str) -> Option<(RuleDay, i64)> {
+    let (day, time) = match value.split_once('/') {
+        Some((day, time)) => (day, parse_offset(time)?),
+        None => (value, DEFAULT_SWITCH_SECS),
+    };
+    let day = if let Some(day) = day.strip_prefix('J') {
+        RuleDay::Julian(day.parse().ok().filter(|day| (1..=365).contains(day))?)
+    } else if let Some(day) = day.strip_prefix('M') {
+        let mut parts = day.splitn(3, '.').map(str::parse::<i64>);
+        let (month, week, weekday) = (parts.next()?.ok()?, parts.next()?.ok()?, parts.next()?.ok()?);
+        if !(1..=12).contains(&month) || !(1..=5).contains(&week) || !(0..=6).contains(&weekday) {
+            return None;
+        }
+        RuleDay::MonthWeek {
+            month: month as u32,
+            week,
+            weekday,
+        }
+    } else {
+        RuleDay::Ordinal(day.parse().ok().filter(|day| (0..=365).contains(day))?)
+    };
+    Some((day, time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::parse_datetime;
+
+    fn utc(datetime: &str) -> i64 {
+        parse_datetime(datetime).unwrap()
+    }
+
+    #[test]
+    fn fixed_offsets_parse() {
+        for (value, seconds) in [("UTC", 0), ("Z", 0), ("+01:00", 3600), ("UTC-8", -8 * 3600), ("GMT+05:30", 19_800)] {
+            let zone: TimeZone = value.parse().unwrap();
+            assert_eq!(zone.offset_at(0), seconds, "{}", value);
+        }
+        assert_eq!("-08:00".parse::<TimeZone>().unwrap().to_string(), "UTC-08:00");
+        assert!("+15:00".parse::<TimeZone>().is_err());
+        assert!("+1x".parse::<TimeZone>().is_err());
+    }
+
+    #[test]
+    fn unknown_zones_are_rejected() {
+        assert!("Mars/Olympus_Mons".parse::<TimeZone>().is_err());
+        assert!("../etc/passwd".parse::<TimeZone>().is_err());
+        assert!("/etc/localtime".parse::<TimeZone>().is_err());
+    }
+
+    #[test]
+    fn iana_zone_follows_daylight_saving() {
+        let zone: TimeZone = "America/Los_Angeles".parse().unwrap();
+        assert_eq!(zone.to_string(), "America/Los_Angeles");
+        assert_eq!(zone.offset_at(utc("2024-01-15 12:00:00")), -8 * 3600);
+        assert_eq!(zone.offset_at(utc("2024-07-15 12:00:00")), -7 * 3600);
+        // 2024-03-10 02:00 PST is 10:00 UTC
+        assert_eq!(zone.offset_at(utc("2024-03-10 09:59:59")), -8 * 3600);
+        assert_eq!(zone.offset_at(utc("2024-03-10 10:00:00")), -7 * 3600);
+        // Beyond the transitions listed in the file, the footer rule applies
+        assert_eq!(zone.offset_at(utc("2100-07-01 00:00:00")), -7 * 3600);
+        assert_eq!(zone.offset_at(utc("2100-12-01 00:00:00")), -8 * 3600);
+    }
+
+    #[test]
+    fn local_date_uses_local_midnight() {
+        let zone: TimeZone = "America/Los_Angeles".parse().unwrap();
+        // 06:30 UTC is still the previous evening in California
+        let date = zone.local_date(utc("2024-07-02 06:30:00"));
+        assert_eq!((date.month, date.day), (7, 1));
+        let date = zone.local_date(utc("2024-07-02 07:00:00"));
+        assert_eq!((date.month, date.day), (7, 2));
+    }
+
+    #[test]
+    fn posix_rules_parse() {
+        let rule = PosixRule::parse("PST8PDT,M3.2.0,M11.1.0").unwrap();
+        assert_eq!(rule.std_offset, -8 * 3600);
+        assert_eq!(rule.dst.unwrap().offset, -7 * 3600);
+        assert_eq!(PosixRule::parse("<+0530>-5:30").unwrap().std_offset, 19_800);
+        assert!(PosixRule::parse("IST-1GMT0,M10.5.0,M3.5.0/1").is_some());
+        assert!(PosixRule::parse("EST5EDT,M13.1.0,M11.1.0").is_none());
+    }
+
+    #[test]
+    fn southern_hemisphere_rule() {
+        let rule = PosixRule::parse("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        assert_eq!(rule.offset_at(utc("2024-01-15 00:00:00")), 11 * 3600);
+        assert_eq!(rule.offset_at(utc("2024-07-15 00:00:00")), 10 * 3600);
+        // Daylight time ends 2024-04-07 03:00 AEDT, 16:00 UTC the day before
+        assert_eq!(rule.offset_at(utc("2024-04-06 15:59:59")), 11 * 3600);
+        assert_eq!(rule.offset_at(utc("2024-04-06 16:00:00")), 10 * 3600);
+    }
+
+    #[test]
+    fn last_weekday_of_month() {
+        // Last Sunday of March 2024 was the 31st
+        let day = RuleDay::MonthWeek { month: 3, week: 5, weekday: 0 }.to_days(2024);
+        assert_eq!(Date::from_days(day), Date { year: 2024, month: 3, day: 31 });
+        let day = RuleDay::Julian(60).to_days(2024);
+        assert_eq!(Date::from_days(day), Date { year: 2024, month: 3, day: 1 });
+    }
+}
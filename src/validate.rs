@@ -0,0 +1,151 @@
+use crate::WeatherData;
+
+/// Physically plausible range for each push field, inclusive. Absolute
+/// pressure gets a lower floor than relative so high-altitude stations pass.
+const BOUNDS: [(&str, f64, f64); 56] = [
+    ("tempf", -100.0, 160.0),
+    ("tempinf", -100.0, 160.0),
+    ("humidity", 0.0, 100.0),
+    ("humidityin", 0.0, 100.0),
+    ("windspeedmph", 0.0, 250.0),
+    ("windgustmph", 0.0, 250.0),
+    ("maxdailygust", 0.0, 250.0),
+    ("uv", 0.0, 20.0),
+    ("solarradiation", 0.0, 2000.0),
+    ("baromrelin", 25.0, 32.0),
+    ("baromabsin", 15.0, 32.0),
+    ("hourlyrainin", 0.0, f64::INFINITY),
+    ("eventrainin", 0.0, f64::INFINITY),
+    ("dailyrainin", 0.0, f64::INFINITY),
+    ("weeklyrainin", 0.0, f64::INFINITY),
+    ("monthlyrainin", 0.0, f64::INFINITY),
+    ("yearlyrainin", 0.0, f64::INFINITY),
+    ("temp1f", -100.0, 160.0),
+    ("temp2f", -100.0, 160.0),
+    ("temp3f", -100.0, 160.0),
+    ("temp4f", -100.0, 160.0),
+    ("temp5f", -100.0, 160.0),
+    ("temp6f", -100.0, 160.0),
+    ("temp7f", -100.0, 160.0),
+    ("temp8f", -100.0, 160.0),
+    ("humidity1", 0.0, 100.0),
+    ("humidity2", 0.0, 100.0),
+    ("humidity3", 0.0, 100.0),
+    ("humidity4", 0.0, 100.0),
+    ("humidity5", 0.0, 100.0),
+    ("humidity6", 0.0, 100.0),
+    ("humidity7", 0.0, 100.0),
+    ("humidity8", 0.0, 100.0),
+    ("tf_ch1", -100.0, 160.0),
+    ("tf_ch2", -100.0, 160.0),
+    ("tf_ch3", -100.0, 160.0),
+    ("tf_ch4", -100.0, 160.0),
+    ("humi_ch1", 0.0, 100.0),
+    ("humi_ch2", 0.0, 100.0),
+    ("humi_ch3", 0.0, 100.0),
+    ("humi_ch4", 0.0, 100.0),
+    ("leafwetness1", 0.0, 100.0),
+    ("leafwetness2", 0.0, 100.0),
+    ("leafwetness3", 0.0, 100.0),
+    ("leafwetness4", 0.0, 100.0),
+    ("leafwetness5", 0.0, 100.0),
+    ("leafwetness6", 0.0, 100.0),
+    ("leafwetness7", 0.0, 100.0),
+    ("leafwetness8", 0.0, 100.0),
+    ("waterleakage1", 0.0, 1.0),
+    ("waterleakage2", 0.0, 1.0),
+    ("waterleakage3", 0.0, 1.0),
+    ("waterleakage4", 0.0, 1.0),
+    // The WH57 detects strikes up to 40 km away
+    ("lightning", 0.0, 40.0),
+    // CO2 readings above 10,000 ppm are sensor errors
+    ("co2", 0.0, 10_000.0),
+    ("co2_avg_24h", 0.0, 10_000.0),
+];
+
+/// A reading outside the range a working sensor could report.
+#[derive(Debug, thiserror::Error)]
+#[error("{field} = {value} is outside the plausible range {min}..={max}")]
+pub struct ValidationError {
+    pub field: &'static str,
+    pub value: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl WeatherData {
+    /// Check every present field against its plausible range.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let fields = self.fields();
+        for (field, min, max) in BOUNDS {
+            let value = fields.iter().find(|(name, _)| *name == field).and_then(|(_, value)| *value);
+            if let Some(value) = value {
+                if !(min..=max).contains(&value) {
+                    return Err(ValidationError { field, value, min, max });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use ntex::http::StatusCode;
+    use ntex::web::{self, test};
+
+    fn validate(query: &str) -> Result<(), ValidationError> {
+        WeatherData::from_query(query).unwrap().validate()
+    }
+
+    #[test]
+    fn boundaries_are_inclusive() {
+        let cases = [
+            ("tempf", "-100.0", "-100.1", "160.0", "160.1"),
+            ("humidity", "0", "", "100", "101"),
+            ("windspeedmph", "0.0", "-0.1", "250.0", "250.1"),
+            ("uv", "0", "", "20", "21"),
+            ("solarradiation", "0.0", "-0.1", "2000.0", "2000.1"),
+            ("baromrelin", "25.0", "24.9", "32.0", "32.1"),
+            ("dailyrainin", "0.0", "-0.1", "1000.0", ""),
+            ("co2", "0", "", "10000", "10001"),
+            ("co2_avg_24h", "0", "", "10000", "10001"),
+        ];
+        for (field, min, below, max, above) in cases {
+            assert!(validate(&format!("{}={}", field, min)).is_ok(), "{} = {}", field, min);
+            assert!(validate(&format!("{}={}", field, max)).is_ok(), "{} = {}", field, max);
+            for value in [below, above].into_iter().filter(|value| !value.is_empty()) {
+                let err = validate(&format!("{}={}", field, value)).unwrap_err();
+                assert_eq!(err.field, field);
+            }
+        }
+    }
+
+    #[test]
+    fn full_payload_passes() {
+        let query = "PASSKEY=valid&tempf=71.2&tempinf=68.0&humidity=45&humidityin=40&windspeedmph=5.6\
+                     &windgustmph=9.2&maxdailygust=15.0&winddir=270&uv=3&solarradiation=450.5\
+                     &baromrelin=30.01&baromabsin=29.5&hourlyrainin=0.0&dailyrainin=0.12\
+                     &monthlyrainin=1.5&yearlyrainin=12.3&temp1f=65.0&humidity1=50";
+        validate(query).unwrap();
+    }
+
+    #[ntex::test]
+    async fn implausible_push_is_refused() {
+        let app = test::init_service(
+            web::App::new()
+                .state(test_support::state(&[]))
+                .route("/push/", web::get().to(crate::handle_weather_data)),
+        )
+        .await;
+        for query in ["tempf=9999", "humidity=200", "co2=20000"] {
+            let uri = format!("/push/?PASSKEY=implausible&{}", query);
+            let res = test::call_service(&app, test::TestRequest::with_uri(&uri).to_request()).await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{}", query);
+        }
+        let temp = test_support::series_value(crate::metrics::metrics(), "weather_temperature_fahrenheit", "implausible");
+        assert_eq!(temp, None);
+    }
+}
@@ -0,0 +1,239 @@
+use prometheus::{GaugeVec, Opts};
+use serde::Deserialize;
+
+use crate::config::{is_valid_metric_name, ConfigError};
+use crate::metrics::MetricRegistry;
+use crate::WeatherData;
+
+/// A `[[virtual_sensor]]` entry: a gauge computed from other fields.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VirtualSensorConfig {
+    pub name: String,
+    /// Arithmetic over push field names, e.g. `0.6 * tempf + 0.4 * tempinf`.
+    pub expr: String,
+    pub help: String,
+}
+
+/// Parsed arithmetic expression.
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Number(f64),
+    Field(String),
+    Neg(Box<Expr>),
+    Binary(Box<Expr>, char, Box<Expr>),
+}
+
+/// Recursive-descent parser for `+ - * /`, parentheses, numbers and names.
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        let rest = &self.input[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.input[self.pos..].chars().next()
+    }
+
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> &str {
+        let start = self.pos;
+        let len = self.input[start..]
+            .find(|c| !pred(c))
+            .unwrap_or(self.input.len() - start);
+        self.pos += len;
+        &self.input[start..self.pos]
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.term()?;
+        while let Some(op @ ('+' | '-')) = self.peek() {
+            self.pos += 1;
+            lhs = Expr::Binary(Box::new(lhs), op, Box::new(self.term()?));
+        }
+        Ok(lhs)
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.factor()?;
+        while let Some(op @ ('*' | '/')) = self.peek() {
+            self.pos += 1;
+            lhs = Expr::Binary(Box::new(lhs), op, Box::new(self.factor()?));
+        }
+        Ok(lhs)
+    }
+
+    fn factor(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some('-') => {
+                self.pos += 1;
+                Ok(Expr::Neg(Box::new(self.factor()?)))
+            }
+            Some('(') => {
+                self.pos += 1;
+                let inner = self.expr()?;
+                if self.peek() != Some(')') {
+                    return Err(format!("expected ')' at position {}", self.pos));
+                }
+                self.pos += 1;
+                Ok(inner)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let number = self.take_while(|c| c.is_ascii_digit() || c == '.');
+                number
+                    .parse()
+                    .map(Expr::Number)
+                    .map_err(|_| format!("invalid number {:?}", number))
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                let name = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+                Ok(Expr::Field(name.to_string()))
+            }
+            Some(c) => Err(format!("unexpected {:?} at position {}", c, self.pos)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(input: &str) -> Result<Expr, String> {
+        let mut parser = Parser { input, pos: 0 };
+        let expr = parser.expr()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(c) => Err(format!("unexpected {:?} at position {}", c, parser.pos)),
+        }
+    }
+
+    /// Field names the expression refers to.
+    fn fields<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match self {
+            Expr::Number(_) => {}
+            Expr::Field(name) => out.push(name),
+            Expr::Neg(inner) => inner.fields(out),
+            Expr::Binary(lhs, _, rhs) => {
+                lhs.fields(out);
+                rhs.fields(out);
+            }
+        }
+    }
+
+    pub fn eval(&self, field: &impl Fn(&str) -> Option<f64>) -> Option<f64> {
+        Some(match self {
+            Expr::Number(value) => *value,
+            Expr::Field(name) => field(name)?,
+            Expr::Neg(inner) => -inner.eval(field)?,
+            Expr::Binary(lhs, op, rhs) => {
+                let (lhs, rhs) = (lhs.eval(field)?, rhs.eval(field)?);
+                match op {
+                    '+' => lhs + rhs,
+                    '-' => lhs - rhs,
+                    '*' => lhs * rhs,
+                    _ => lhs / rhs,
+                }
+            }
+        })
+    }
+}
+
+/// A configured virtual sensor with its expression parsed and checked.
+#[derive(Debug, Clone)]
+pub struct VirtualSensor {
+    pub name: String,
+    pub help: String,
+    pub expr: Expr,
+}
+
+impl VirtualSensor {
+    pub fn from_config(config: VirtualSensorConfig) -> Result<VirtualSensor, ConfigError> {
+        let invalid = |reason: String| {
+            ConfigError::Invalid(format!("virtual sensor {:?}: {}", config.name, reason))
+        };
+        let expr = Expr::parse(&config.expr).map_err(invalid)?;
+        let known = WeatherData::default().fields().map(|(field, _)| field);
+        let mut fields = Vec::new();
+        expr.fields(&mut fields);
+        if let Some(unknown) = fields.iter().find(|field| !known.contains(field)) {
+            return Err(invalid(format!("unknown field {:?}", unknown)));
+        }
+        let sensor = VirtualSensor {
+            name: config.name,
+            help: config.help,
+            expr,
+        };
+        if !is_valid_metric_name(&sensor.metric_name()) {
+            return Err(ConfigError::Invalid(format!(
+                "virtual sensor name {:?} is not valid in a metric name",
+                sensor.name
+            )));
+        }
+        Ok(sensor)
+    }
+
+    pub fn metric_name(&self) -> String {
+        format!("virtual_{}", self.name)
+    }
+
+    /// Register the sensor's gauge, labelled by station.
+    pub fn register(&self, registry: &MetricRegistry) -> prometheus::Result<GaugeVec> {
+        let gauge = GaugeVec::new(Opts::new(self.metric_name(), self.help.clone()), &["station"])?;
+        registry.register(Box::new(gauge.clone()))?;
+        Ok(gauge)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, ConfigEnv};
+    use crate::metrics::Metrics;
+    use crate::test_support;
+
+    fn sensor(name: &str, expr: &str) -> Result<VirtualSensor, ConfigError> {
+        VirtualSensor::from_config(VirtualSensorConfig {
+            name: name.to_string(),
+            expr: expr.to_string(),
+            help: String::new(),
+        })
+    }
+
+    #[test]
+    fn mean_of_two_channels_is_exported() {
+        let toml = r#"
+            [[virtual_sensor]]
+            name = "channel_mean_fahrenheit"
+            expr = "(temp1f + temp2f) / 2"
+            help = "Mean of channels 1 and 2"
+        "#;
+        let config = Config::merge(toml::from_str(toml).unwrap(), ConfigEnv::default()).unwrap();
+        let metrics = Metrics::new(&config).unwrap();
+        let push = "PASSKEY=virtual&temp1f=60.0&temp2f=70.0";
+        metrics.update(&WeatherData::from_query(push).unwrap()).unwrap();
+
+        let value = test_support::series_value(&metrics, "weather_virtual_channel_mean_fahrenheit", "virtual");
+        assert_eq!(value, Some(65.0));
+    }
+
+    #[test]
+    fn expressions_follow_precedence() {
+        let expr = Expr::parse("2 + 3 * -tempf - (1 - 4) / 3").unwrap();
+        let field = |name: &str| (name == "tempf").then_some(2.0);
+        assert_eq!(expr.eval(&field), Some(-3.0));
+        assert_eq!(expr.eval(&|_: &str| None), None);
+    }
+
+    #[test]
+    fn invalid_sensors_are_rejected() {
+        assert!(sensor("ok", "tempf * 2").is_ok());
+        assert!(sensor("bad", "tempf *").is_err());
+        assert!(sensor("bad", "(tempf").is_err());
+        assert!(sensor("bad", "tempf 2").is_err());
+        assert!(sensor("bad", "nonsense + 1").is_err());
+        assert!(sensor("not a name", "tempf").is_err());
+    }
+}
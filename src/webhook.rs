@@ -0,0 +1,145 @@
+use ntex::http::client::Client;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::calendar;
+use crate::WeatherData;
+
+/// Decides whether a reading differs enough from the previous one to notify.
+pub struct ThresholdFilter;
+
+impl ThresholdFilter {
+    /// Whether any field changed by more than `pct` percent, or appeared.
+    /// A `pct` of 0 treats every reading as significant.
+    pub fn has_significant_change(prev: &WeatherData, curr: &WeatherData, pct: f64) -> bool {
+        if pct <= 0.0 {
+            return true;
+        }
+        prev.fields()
+            .iter()
+            .zip(curr.fields())
+            .any(|((_, prev), (_, curr))| match (prev, curr) {
+                (_, None) => false,
+                (None, Some(_)) => true,
+                (Some(prev), Some(curr)) if *prev == 0.0 => curr != 0.0,
+                (Some(prev), Some(curr)) => ((curr - prev) / prev).abs() * 100.0 > pct,
+            })
+    }
+}
+
+/// POSTs each accepted reading as JSON to a configured URL, skipping
+/// readings that barely differ from the station's previous one.
+#[derive(Debug)]
+pub struct Webhook {
+    url: String,
+    threshold_pct: f64,
+    last: Mutex<HashMap<String, WeatherData>>,
+}
+
+/// JSON body describing a reading.
+fn payload(station: &str, data: &WeatherData) -> Value {
+    let readings: Map<String, Value> = data
+        .fields()
+        .into_iter()
+        .filter_map(|(field, value)| Some((field.to_string(), json!(value?))))
+        .collect();
+    json!({
+        "station": station,
+        "timestamp": data.timestamp().unwrap_or_else(calendar::now),
+        "readings": readings,
+    })
+}
+
+impl Webhook {
+    pub fn new(url: String, threshold_pct: f64) -> Self {
+        Webhook {
+            url,
+            threshold_pct,
+            last: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Send `data` in the background if it changed significantly.
+    pub fn notify(&self, station: &str, data: &WeatherData) {
+        let mut last = self.last.lock().unwrap();
+        if let Some(prev) = last.get(station) {
+            if !ThresholdFilter::has_significant_change(prev, data, self.threshold_pct) {
+                debug!("Skipping webhook for station {}: no significant change", station);
+                return;
+            }
+        }
+        last.insert(station.to_string(), data.clone());
+        drop(last);
+
+        let url = self.url.clone();
+        let body = payload(station, data);
+        ntex::rt::spawn(async move {
+            let client = Client::build().timeout(Duration::from_secs(10)).finish();
+            match client.post(&url).send_json(&body).await {
+                Ok(res) if res.status().is_success() => debug!("Sent webhook to {}", url),
+                Ok(res) => warn!("Webhook {} responded with status {}", url, res.status()),
+                Err(e) => warn!("Failed to send webhook to {}: {}", url, e),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ntex::web::{self, test::server, App};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn reading(query: &str) -> WeatherData {
+        WeatherData::from_query(query).unwrap()
+    }
+
+    #[test]
+    fn changes_beyond_the_threshold_are_significant() {
+        let prev = reading("PASSKEY=t&tempf=50.0&humidity=40");
+        assert!(!ThresholdFilter::has_significant_change(&prev, &prev, 5.0));
+        assert!(ThresholdFilter::has_significant_change(&prev, &prev, 0.0));
+        assert!(!ThresholdFilter::has_significant_change(&prev, &reading("PASSKEY=t&tempf=52.0&humidity=40"), 5.0));
+        assert!(ThresholdFilter::has_significant_change(&prev, &reading("PASSKEY=t&tempf=55.0&humidity=40"), 5.0));
+        assert!(ThresholdFilter::has_significant_change(&prev, &reading("PASSKEY=t&tempf=50.0&humidity=40&uv=1"), 5.0));
+        assert!(!ThresholdFilter::has_significant_change(&prev, &reading("PASSKEY=t&tempf=50.0"), 5.0));
+    }
+
+    /// Wait for the background sends to reach the mock.
+    async fn settle() {
+        ntex::time::sleep(Duration::from_millis(300)).await;
+    }
+
+    #[ntex::test]
+    async fn webhook_fires_only_on_significant_change() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let mock = server(move || {
+            let counter = counter.clone();
+            App::new().route(
+                "/hook",
+                web::post().to(move || {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    async { web::HttpResponse::Ok().finish() }
+                }),
+            )
+        });
+        let webhook = Webhook::new(mock.url("/hook"), 5.0);
+
+        webhook.notify("hook", &reading("PASSKEY=hook&tempf=50.0"));
+        settle().await;
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        webhook.notify("hook", &reading("PASSKEY=hook&tempf=50.0"));
+        settle().await;
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        webhook.notify("hook", &reading("PASSKEY=hook&tempf=55.0"));
+        settle().await;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
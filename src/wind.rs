@@ -0,0 +1,95 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Number of compass sectors wind directions are binned into.
+const SECTORS: usize = 16;
+/// How far back readings count towards the entropy.
+const WINDOW: Duration = Duration::from_secs(3600);
+
+/// Compass sector (0 = N, 1 = NNE, ...) containing `degrees`.
+fn sector(degrees: u16) -> usize {
+    let width = 360.0 / SECTORS as f64;
+    ((f64::from(degrees % 360) + width / 2.0) / width) as usize % SECTORS
+}
+
+/// Shannon entropy of the wind direction over the past hour, in bits: 0 for
+/// a perfectly steady wind, up to 4 when all 16 sectors are equally likely.
+#[derive(Debug, Default)]
+pub struct WindDirectionEntropyCalculator {
+    readings: VecDeque<(Instant, usize)>,
+    counts: [u32; SECTORS],
+}
+
+impl WindDirectionEntropyCalculator {
+    /// Add a reading taken at `now`, dropping readings that left the window.
+    pub fn record(&mut self, degrees: u16, now: Instant) {
+        while let Some(&(at, sector)) = self.readings.front() {
+            if now.saturating_duration_since(at) <= WINDOW {
+                break;
+            }
+            self.readings.pop_front();
+            self.counts[sector] -= 1;
+        }
+        let sector = sector(degrees);
+        self.readings.push_back((now, sector));
+        self.counts[sector] += 1;
+    }
+
+    pub fn entropy(&self) -> f64 {
+        let total = self.readings.len() as f64;
+        self.counts
+            .iter()
+            .filter(|&&count| count > 0)
+            .map(|&count| {
+                let p = f64::from(count) / total;
+                -p * p.log2()
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steady_wind_has_no_entropy() {
+        let mut calculator = WindDirectionEntropyCalculator::default();
+        let start = Instant::now();
+        for n in 0..20 {
+            calculator.record(90, start + Duration::from_secs(n * 60));
+        }
+        assert!(calculator.entropy().abs() < 1e-9);
+    }
+
+    #[test]
+    fn wind_from_every_sector_has_four_bits() {
+        let mut calculator = WindDirectionEntropyCalculator::default();
+        let now = Instant::now();
+        for n in 0..32 {
+            calculator.record((n % 16 * 360 / 16) as u16, now);
+        }
+        assert!((calculator.entropy() - 4.0).abs() < 1e-9, "{}", calculator.entropy());
+    }
+
+    #[test]
+    fn readings_older_than_an_hour_are_dropped() {
+        let mut calculator = WindDirectionEntropyCalculator::default();
+        let start = Instant::now();
+        calculator.record(0, start);
+        calculator.record(180, start);
+        assert!((calculator.entropy() - 1.0).abs() < 1e-9);
+        calculator.record(180, start + WINDOW + Duration::from_secs(1));
+        assert!(calculator.entropy().abs() < 1e-9);
+    }
+
+    #[test]
+    fn sectors_are_centred_on_compass_points() {
+        assert_eq!(sector(0), 0);
+        assert_eq!(sector(11), 0);
+        assert_eq!(sector(12), 1);
+        assert_eq!(sector(348), 15);
+        assert_eq!(sector(355), 0);
+        assert_eq!(sector(360), 0);
+    }
+}